@@ -0,0 +1,3 @@
+ALTER TABLE analysis_jobs
+DROP COLUMN IF EXISTS failure_reason,
+DROP COLUMN IF EXISTS error;
@@ -0,0 +1,3 @@
+ALTER TABLE analysis_jobs
+ADD COLUMN error TEXT,
+ADD COLUMN failure_reason TEXT;
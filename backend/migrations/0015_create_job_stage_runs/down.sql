@@ -0,0 +1,7 @@
+DROP INDEX IF EXISTS idx_job_stage_runs_job_id;
+
+DROP TABLE IF EXISTS job_stage_runs;
+
+ALTER TABLE analysis_jobs
+DROP COLUMN IF EXISTS started_at,
+DROP COLUMN IF EXISTS finished_at;
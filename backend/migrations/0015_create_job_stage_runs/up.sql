@@ -0,0 +1,18 @@
+ALTER TABLE analysis_jobs
+ADD COLUMN started_at TIMESTAMP WITH TIME ZONE,
+ADD COLUMN finished_at TIMESTAMP WITH TIME ZONE;
+
+CREATE TABLE job_stage_runs (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    job_id UUID NOT NULL REFERENCES analysis_jobs(id) ON DELETE CASCADE,
+    stage_id TEXT NOT NULL, -- stage id, or the stage type for stages without an id
+    stage_type TEXT NOT NULL,
+    -- "running", "succeeded", "failed", "skipped", "reused" or "aborted"
+    status TEXT NOT NULL CHECK (status IN ('running', 'succeeded', 'failed', 'skipped', 'reused', 'aborted')),
+    attempt INTEGER NOT NULL DEFAULT 1,
+    error TEXT,
+    started_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    finished_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX idx_job_stage_runs_job_id ON job_stage_runs(job_id);
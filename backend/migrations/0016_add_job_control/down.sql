@@ -0,0 +1,3 @@
+ALTER TABLE analysis_jobs
+DROP COLUMN IF EXISTS resume,
+DROP COLUMN IF EXISTS cancel_requested;
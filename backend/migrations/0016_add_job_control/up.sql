@@ -0,0 +1,3 @@
+ALTER TABLE analysis_jobs
+ADD COLUMN cancel_requested BOOLEAN NOT NULL DEFAULT FALSE,
+ADD COLUMN resume BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,7 @@
+DROP INDEX IF EXISTS idx_analysis_jobs_lease;
+DROP INDEX IF EXISTS idx_analysis_jobs_pending;
+
+ALTER TABLE analysis_jobs
+DROP COLUMN IF EXISTS lease_expires_at,
+DROP COLUMN IF EXISTS lease_owner,
+DROP COLUMN IF EXISTS attempts;
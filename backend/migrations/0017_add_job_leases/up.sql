@@ -0,0 +1,8 @@
+ALTER TABLE analysis_jobs
+ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0,
+ADD COLUMN lease_owner TEXT,
+ADD COLUMN lease_expires_at TIMESTAMP WITH TIME ZONE;
+
+-- Workers claim the oldest pending job and the reaper scans expired leases
+CREATE INDEX idx_analysis_jobs_pending ON analysis_jobs(created_at) WHERE status = 'pending';
+CREATE INDEX idx_analysis_jobs_lease ON analysis_jobs(lease_expires_at) WHERE status = 'in_progress';
@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS idx_analysis_jobs_running_org;
+
+ALTER TABLE org_settings DROP COLUMN IF EXISTS max_concurrent_jobs;
+ALTER TABLE analysis_jobs DROP COLUMN IF EXISTS priority;
+ALTER TABLE pipelines DROP COLUMN IF EXISTS priority;
@@ -0,0 +1,12 @@
+ALTER TABLE pipelines
+ADD COLUMN priority TEXT NOT NULL DEFAULT 'normal' CHECK (priority IN ('high', 'normal', 'bulk'));
+
+ALTER TABLE analysis_jobs
+ADD COLUMN priority TEXT NOT NULL DEFAULT 'normal' CHECK (priority IN ('high', 'normal', 'bulk'));
+
+-- NULL means the organization may run any number of jobs at once
+ALTER TABLE org_settings
+ADD COLUMN max_concurrent_jobs INTEGER CHECK (max_concurrent_jobs > 0);
+
+-- Workers count running jobs per organization when picking the next job
+CREATE INDEX idx_analysis_jobs_running_org ON analysis_jobs(org_id) WHERE status = 'in_progress';
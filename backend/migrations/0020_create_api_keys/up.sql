@@ -0,0 +1,18 @@
+CREATE TABLE api_keys (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    org_id UUID NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    prefix TEXT NOT NULL, -- first characters of the key, shown to identify it
+    key_hash TEXT NOT NULL UNIQUE, -- hex SHA-256 of the key; the key itself is never stored
+    scopes TEXT[] NOT NULL, -- e.g. {"upload", "jobs:read"}
+    created_by UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    last_used_at TIMESTAMP WITH TIME ZONE,
+    expires_at TIMESTAMP WITH TIME ZONE,
+    revoked_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX idx_api_keys_org_id ON api_keys(org_id);
+
+-- Actions performed with an API key are attributed to it
+ALTER TABLE audit_logs ADD COLUMN api_key_id UUID REFERENCES api_keys(id) ON DELETE SET NULL;
@@ -0,0 +1,3 @@
+-- OCR engine of pipeline stages that do not choose one; NULL means tesseract
+ALTER TABLE org_settings
+ADD COLUMN ocr_engine TEXT;
@@ -2,17 +2,26 @@ use anyhow::Result;
 use aws_config::meta::region::RegionProviderChain;
 use aws_sdk_s3::Client as S3Client;
 use backend::config::WorkerConfig;
-use backend::models::{AnalysisJob, Document, OrgSettings, Pipeline};
+use backend::models::analysis_job::JOB_PRIORITIES;
+use backend::models::{AnalysisJob, Document, JobStageRun, OrgSettings, Pipeline};
 use backend::processing;
+use backend::processing::formats::DocumentFormat;
+use backend::processing::ocr_layout::OcrLayout;
+use backend::queue::enqueue_job;
 use backend::worker::metrics::{
-    spawn_metrics_server, JOB_COUNTER, JOB_HISTOGRAM, OCR_HISTOGRAM, RUNNING_JOBS_GAUGE,
-    STAGE_HISTOGRAM,
+    spawn_metrics_server, JOB_COUNTER, JOB_HISTOGRAM, OCR_HISTOGRAM, QUEUE_DEPTH_GAUGE,
+    RUNNING_JOBS_GAUGE, STAGE_HISTOGRAM,
 };
-use backend::worker::{self, Stage, WorkerRuntimeConfig};
+use backend::worker::dag::{self, StageGraph};
+use backend::worker::deliveries::{deliver_due, enqueue_job_event, DeliveryConfig};
+use backend::worker::{self, JobFailure, OnError, Stage, WorkerRuntimeConfig};
+use futures_util::stream::{FuturesUnordered, StreamExt};
 use serde_json::json;
 use serde_json::{self, Value};
 use sqlx::{postgres::PgPoolOptions, PgPool};
 use std::{
+    collections::{HashSet, VecDeque},
+    future::Future,
     path::{Path, PathBuf},
     sync::{
         atomic::{AtomicUsize, Ordering},
@@ -20,7 +29,6 @@ use std::{
     },
     time::{Duration, Instant},
 };
-use tokio::process::Command;
 use tokio::signal;
 use tokio::task::JoinSet;
 use tokio::time::sleep;
@@ -44,133 +52,490 @@ async fn publish_status_event(job_id: Uuid, org_id: Uuid, status: &str) {
     }
 }
 
-/// Execute all stages of a job. Returns `Ok` on success or `Err` on the first stage failure.
-#[tracing::instrument(skip(pool, s3_client, job, doc, stages, org_settings, local, txt_path))]
-async fn run_stages(
-    pool: &PgPool,
-    s3_client: &S3Client,
-    job: &AnalysisJob,
-    doc: &Document,
-    stages: &[Stage],
-    org_settings: Option<&OrgSettings>,
-    bucket: &str,
-    local: &PathBuf,
-    txt_path: &PathBuf,
-) -> Result<()> {
-    let mut json_result = Value::default();
-    for stage in stages {
-        info!(job_id=%job.id, stage=?stage.stage_type, command=?stage.command, prompt_name=?stage.prompt_name, ocr_engine=?stage.ocr_engine, "running stage");
-        let start = Instant::now();
-        let mut break_after = false;
-        let stage_result: Result<(), anyhow::Error> = match stage.stage_type.as_str() {
-            "ocr" => {
-                let engine = stage.ocr_engine.as_deref().unwrap_or("local");
-                let ocr_start = Instant::now();
-                if worker::ocr::handle_ocr_stage(
-                    pool,
-                    s3_client,
-                    job,
-                    stage,
-                    org_settings,
-                    bucket,
-                    local,
-                    txt_path,
-                )
-                .await?
-                {
-                    break_after = true;
-                }
-                let ocr_elapsed = ocr_start.elapsed().as_secs_f64();
-                OCR_HISTOGRAM
-                    .with_label_values(&[engine])
-                    .observe(ocr_elapsed);
-                Ok(())
-            }
-            "parse" => {
-                if !txt_path.exists() {
-                    warn!(job_id=%job.id, stage=%stage.stage_type, "Input text file {:?} not found for parse stage. Skipping.", txt_path);
-                } else {
-                    let text_content = tokio::fs::read_to_string(txt_path).await?;
-                    json_result =
-                        processing::parse::run_parse_stage(&text_content, stage.config.as_ref())
-                            .await?;
-                }
-                if let Ok(b) = serde_json::to_vec_pretty(&json_result) {
-                    let _ = worker::save_stage_output(
-                        pool,
-                        s3_client,
-                        job.id,
-                        &stage.stage_type,
-                        "json",
-                        bucket,
-                        b,
-                        "json",
+/// Shared, read-only state for the stages of one job.
+struct StageContext<'a> {
+    pool: &'a PgPool,
+    s3_client: &'a S3Client,
+    job: &'a AnalysisJob,
+    doc: &'a Document,
+    stages: &'a [Stage],
+    org_settings: Option<&'a OrgSettings>,
+    bucket: &'a str,
+    local: &'a Path,
+}
+
+/// Path of the text file written by the OCR stage at `idx`.
+fn ocr_text_path(local: &Path, idx: usize) -> PathBuf {
+    local.with_extension(format!("{}.txt", idx))
+}
+
+/// Text recognised by the nearest OCR ancestor of a stage.
+#[derive(Clone)]
+struct OcrSource {
+    /// Index of the OCR stage.
+    stage: usize,
+    text: String,
+}
+
+/// Page layout stored by the OCR stage at `idx`, if any.
+async fn load_ocr_layout(ctx: &StageContext<'_>, idx: usize) -> Option<OcrLayout> {
+    let stage = &ctx.stages[idx];
+    match worker::load_stage_output(
+        ctx.pool,
+        ctx.s3_client,
+        ctx.job.id,
+        stage.name(),
+        "ocr_pages",
+    )
+    .await
+    {
+        Ok(bytes) => bytes.and_then(|b| serde_json::from_slice(&b).ok()),
+        Err(e) => {
+            warn!(job_id=%ctx.job.id, stage=%stage.name(), "Failed to load OCR layout: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Run a single stage with the given input and the text of its nearest OCR ancestor.
+async fn execute_stage(
+    ctx: &StageContext<'_>,
+    idx: usize,
+    input: Value,
+    ocr: Option<OcrSource>,
+) -> Result<Value> {
+    let stage = &ctx.stages[idx];
+    let job = ctx.job;
+    let ocr_text = ocr.as_ref().map(|o| o.text.as_str());
+    match stage.stage_type.as_str() {
+        "ocr" => {
+            let engine = worker::ocr::engine_name(stage, ctx.org_settings);
+            let txt_path = ocr_text_path(ctx.local, idx);
+            let ocr_start = Instant::now();
+            let result = worker::ocr::handle_ocr_stage(
+                ctx.pool,
+                ctx.s3_client,
+                job,
+                stage,
+                ctx.org_settings,
+                ctx.bucket,
+                ctx.local,
+                &txt_path,
+            )
+            .await;
+            let _ = tokio::fs::remove_file(&txt_path).await;
+            OCR_HISTOGRAM
+                .with_label_values(&[&engine])
+                .observe(ocr_start.elapsed().as_secs_f64());
+            Ok(Value::String(result?))
+        }
+        "parse" => {
+            let output = match &ocr {
+                Some(ocr) => {
+                    let layout = if processing::parse::uses_layout(stage.config.as_ref()) {
+                        load_ocr_layout(ctx, ocr.stage).await
+                    } else {
+                        None
+                    };
+                    processing::parse::run_parse_stage_with_layout(
+                        &ocr.text,
+                        layout.as_ref(),
+                        stage.config.as_ref(),
                     )
-                    .await;
+                    .await?
                 }
-                Ok(())
-            }
-            "ai" => {
-                json_result = worker::ai::handle_ai_stage(
-                    pool,
-                    s3_client,
-                    job,
-                    stage,
-                    org_settings,
-                    bucket,
-                    json_result.clone(),
-                    local,
+                None => {
+                    warn!(job_id=%job.id, stage=%stage.name(), "No OCR text available for parse stage. Passing input through.");
+                    input
+                }
+            };
+            if let Ok(b) = serde_json::to_vec_pretty(&output) {
+                let _ = worker::save_stage_output(
+                    ctx.pool,
+                    ctx.s3_client,
+                    job.id,
+                    stage.name(),
+                    "json",
+                    ctx.bucket,
+                    b,
+                    "json",
                 )
-                .await?;
-                Ok(())
+                .await;
             }
-            "report" => {
-                worker::report::handle_report_stage(
-                    pool,
-                    s3_client,
-                    job,
-                    doc,
-                    stage,
-                    bucket,
-                    &json_result,
-                    local,
-                )
-                .await?;
-                Ok(())
+            Ok(output)
+        }
+        "ai" => {
+            let request = worker::ai::AiStageRequest {
+                org_settings: ctx.org_settings,
+                input,
+                ocr_text,
+            };
+            let output = worker::ai::handle_ai_stage(
+                ctx.pool,
+                ctx.s3_client,
+                job,
+                stage,
+                ctx.bucket,
+                request,
+            )
+            .await?;
+            Ok(output)
+        }
+        "report" => {
+            worker::report::handle_report_stage(
+                ctx.pool,
+                ctx.s3_client,
+                job,
+                ctx.doc,
+                stage,
+                ctx.bucket,
+                &input,
+                ctx.local,
+            )
+            .await?;
+            Ok(input)
+        }
+        "exec" => {
+            let request = worker::exec::ExecRequest {
+                document: ctx.local,
+                input: &input,
+                ocr_text,
+            };
+            worker::exec::handle_exec_stage(
+                ctx.pool,
+                ctx.s3_client,
+                job,
+                stage,
+                ctx.bucket,
+                request,
+            )
+            .await
+        }
+        "webhook" => {
+            let request = worker::webhook::WebhookStageRequest {
+                document: ctx.doc,
+                local_pdf: ctx.local,
+                input: &input,
+            };
+            worker::webhook::handle_webhook_stage(
+                ctx.pool,
+                ctx.s3_client,
+                job,
+                stage,
+                ctx.bucket,
+                request,
+            )
+            .await
+        }
+        other => Err(anyhow::anyhow!("unsupported stage type '{}'", other)),
+    }
+}
+
+/// Execute a stage, applying its retry policy and timeout.
+///
+/// AI, webhook and external OCR stages pass the policy to their HTTP client;
+/// all other stages are retried as a whole by the worker. Returns the result
+/// together with the number of attempts made by the worker.
+async fn execute_with_retries(
+    ctx: &StageContext<'_>,
+    idx: usize,
+    input: Value,
+    ocr: Option<OcrSource>,
+) -> (Result<Value>, u32) {
+    let stage = &ctx.stages[idx];
+    if stage.retries_in_client(ctx.org_settings) {
+        return (execute_stage(ctx, idx, input, ocr).await, 1);
+    }
+    let policy = stage.retry_policy();
+    let max_attempts = stage.retry.as_ref().map_or(1, |r| r.max_attempts.max(1));
+    let limit = stage.timeout_secs.map(Duration::from_secs);
+    let mut attempt = 1;
+    loop {
+        let fut = execute_stage(ctx, idx, input.clone(), ocr.clone());
+        let result = match limit {
+            Some(limit) => tokio::time::timeout(limit, fut).await.unwrap_or_else(|_| {
+                Err(anyhow::anyhow!("stage timed out after {}s", limit.as_secs()))
+            }),
+            None => fut.await,
+        };
+        match result {
+            Err(e) if attempt < max_attempts => {
+                warn!(job_id=%ctx.job.id, stage=%stage.name(), attempt, "stage attempt failed: {:?}", e);
+                sleep(policy.backoff(attempt)).await;
+                attempt += 1;
+            }
+            other => return (other, attempt),
+        }
+    }
+}
+
+/// Execute a stage and record its duration.
+async fn run_stage(
+    ctx: &StageContext<'_>,
+    idx: usize,
+    input: Value,
+    ocr: Option<OcrSource>,
+) -> Result<Value> {
+    let stage = &ctx.stages[idx];
+    info!(job_id=%ctx.job.id, stage=%stage.name(), stage_type=%stage.stage_type, command=?stage.command, prompt_name=?stage.prompt_name, ocr_engine=?stage.ocr_engine, "running stage");
+    let run = match JobStageRun::start(ctx.pool, ctx.job.id, stage.name(), &stage.stage_type).await
+    {
+        Ok(run) => Some(run),
+        Err(e) => {
+            warn!(job_id=%ctx.job.id, stage=%stage.name(), "Failed to record stage run: {:?}", e);
+            None
+        }
+    };
+    let start = Instant::now();
+    let (result, attempts) = execute_with_retries(ctx, idx, input, ocr).await;
+    let elapsed = start.elapsed().as_secs_f64();
+    STAGE_HISTOGRAM
+        .with_label_values(&[stage.stage_type.as_str()])
+        .observe(elapsed);
+    let error = match &result {
+        Ok(_) => {
+            info!(job_id=%ctx.job.id, stage=%stage.name(), duration=%elapsed, "stage finished");
+            None
+        }
+        Err(e) => {
+            error!(job_id=%ctx.job.id, stage=%stage.name(), duration=%elapsed, "stage failed: {:?}", e);
+            Some(format!("{:#}", e))
+        }
+    };
+    if let Some(run) = run {
+        let status = if error.is_some() { "failed" } else { "succeeded" };
+        if let Err(e) =
+            JobStageRun::finish(ctx.pool, run.id, status, attempts as i32, error.as_deref()).await
+        {
+            warn!(job_id=%ctx.job.id, stage=%stage.name(), "Failed to update stage run: {:?}", e);
+        }
+    }
+    result
+}
+
+/// Record a stage that produced no regular output (skipped or failed) in `job_stage_outputs`.
+async fn record_stage_marker(ctx: &StageContext<'_>, idx: usize, output_type: &str, body: Value) {
+    if let Ok(b) = serde_json::to_vec_pretty(&body) {
+        let _ = worker::save_stage_output(
+            ctx.pool,
+            ctx.s3_client,
+            ctx.job.id,
+            ctx.stages[idx].name(),
+            output_type,
+            ctx.bucket,
+            b,
+            "json",
+        )
+        .await;
+    }
+}
+
+/// Execute all stages of a job following their declared inputs.
+///
+/// Stages start as soon as all of their inputs are available, so independent
+/// branches run concurrently. Stages whose `when` condition does not hold are
+/// skipped. Returns the failure reason on the first failure of a stage with
+/// `on_error: fail` or when the job is cancelled; in-flight stages are dropped,
+/// which aborts their HTTP calls. When the job is resumed, stages that succeeded
+/// in a previous run reuse their stored outputs.
+#[tracing::instrument(skip(ctx), fields(job_id = %ctx.job.id))]
+async fn run_stages(ctx: &StageContext<'_>) -> Result<(), JobFailure> {
+    let graph = StageGraph::build(ctx.stages)
+        .map_err(|e| JobFailure::new("invalid_pipeline", &e.into()))?;
+    let mut outputs: Vec<Option<Value>> = vec![None; graph.len()];
+    let mut skipped = vec![false; graph.len()];
+    let mut pending: Vec<usize> = (0..graph.len())
+        .map(|i| graph.dependencies(i).len())
+        .collect();
+    let mut ready: VecDeque<usize> = graph.roots().into();
+    let mut running = FuturesUnordered::new();
+    // Stage with `on_error: skip_rest` that failed
+    let mut halted_by: Option<usize> = None;
+    let reusable: HashSet<String> = if ctx.job.resume {
+        JobStageRun::succeeded_stage_ids(ctx.pool, ctx.job.id)
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .collect()
+    } else {
+        HashSet::new()
+    };
+
+    loop {
+        while let Some(idx) = ready.pop_front().filter(|_| halted_by.is_none()) {
+            if cancel_requested(ctx).await {
+                return Err(JobFailure::cancelled());
+            }
+            if let Some(reason) = graph.skip_reason(idx, ctx.stages, &outputs, &skipped) {
+                record_skipped(ctx, idx, &reason).await;
+                skipped[idx] = true;
+                release_dependents(&graph, idx, &mut pending, &mut ready);
+                continue;
             }
-            _ => {
-                if let Some(cmd) = stage.command.as_ref() {
-                    let mut parts = cmd.split_whitespace();
-                    if let Some(program) = parts.next() {
-                        let args: Vec<&str> = parts.collect();
-                        Command::new(program).args(args).status().await?;
+            let input = graph.stage_input(idx, ctx.stages, &outputs, &skipped);
+            let stage = &ctx.stages[idx];
+            if reusable.contains(stage.name()) {
+                if let Some(output) = reuse_output(ctx, idx, &input).await {
+                    info!(job_id=%ctx.job.id, stage=%stage.name(), "reusing output of previous run");
+                    if let Err(e) = JobStageRun::record(
+                        ctx.pool,
+                        ctx.job.id,
+                        stage.name(),
+                        &stage.stage_type,
+                        "reused",
+                        None,
+                    )
+                    .await
+                    {
+                        warn!(job_id=%ctx.job.id, stage=%stage.name(), "Failed to record stage run: {:?}", e);
                     }
-                } else {
-                    sleep(Duration::from_secs(1)).await;
+                    outputs[idx] = Some(output);
+                    release_dependents(&graph, idx, &mut pending, &mut ready);
+                    continue;
                 }
-                Ok(())
             }
+            let ocr = graph
+                .nearest_ancestor(idx, |i| ctx.stages[i].stage_type == "ocr")
+                .and_then(|i| {
+                    let text = outputs[i].as_ref()?.as_str()?.to_string();
+                    Some(OcrSource { stage: i, text })
+                });
+            running.push(async move { (idx, run_stage(ctx, idx, input, ocr).await) });
+        }
+        let next = tokio::select! {
+            next = running.next() => next,
+            _ = cancellation(ctx) => return Err(JobFailure::cancelled()),
         };
-        let elapsed = start.elapsed().as_secs_f64();
-        STAGE_HISTOGRAM
-            .with_label_values(&[stage.stage_type.as_str()])
-            .observe(elapsed);
-        match stage_result {
-            Ok(_) => {
-                info!(job_id=%job.id, stage=%stage.stage_type, duration=%elapsed, "stage finished");
-            }
+        let Some((idx, result)) = next else {
+            break;
+        };
+        let output = match result {
+            Ok(output) => output,
             Err(e) => {
-                error!(job_id=%job.id, stage=%stage.stage_type, duration=%elapsed, "stage failed: {:?}", e);
-                return Err(e);
+                let stage = &ctx.stages[idx];
+                if stage.on_error == OnError::Fail {
+                    return Err(JobFailure::stage(stage, &e));
+                }
+                warn!(job_id=%ctx.job.id, stage=%stage.name(), on_error=?stage.on_error, "stage failed, continuing: {:?}", e);
+                record_stage_marker(ctx, idx, "error", json!({"error": format!("{:#}", e)})).await;
+                if stage.on_error == OnError::SkipRest && halted_by.is_none() {
+                    halted_by = Some(idx);
+                }
+                graph.stage_input(idx, ctx.stages, &outputs, &skipped)
+            }
+        };
+        outputs[idx] = Some(output);
+        release_dependents(&graph, idx, &mut pending, &mut ready);
+    }
+    if let Some(failed) = halted_by {
+        let reason = format!("skip_rest after '{}'", ctx.stages[failed].name());
+        for &idx in graph.order() {
+            if outputs[idx].is_none() && !skipped[idx] {
+                record_skipped(ctx, idx, &reason).await;
             }
-        }
-        if break_after {
-            break;
         }
     }
     Ok(())
 }
 
+/// Record that stage `idx` was skipped and why.
+async fn record_skipped(ctx: &StageContext<'_>, idx: usize, reason: &str) {
+    let stage = &ctx.stages[idx];
+    info!(job_id=%ctx.job.id, stage=%stage.name(), reason, "skipping stage");
+    record_stage_marker(
+        ctx,
+        idx,
+        "skipped",
+        json!({"skipped": true, "reason": reason}),
+    )
+    .await;
+    if let Err(e) = JobStageRun::record(
+        ctx.pool,
+        ctx.job.id,
+        stage.name(),
+        &stage.stage_type,
+        "skipped",
+        Some(reason),
+    )
+    .await
+    {
+        warn!(job_id=%ctx.job.id, stage=%stage.name(), "Failed to record stage run: {:?}", e);
+    }
+}
+
+/// How often a running job checks whether it has been cancelled.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether cancellation of the job has been requested.
+async fn cancel_requested(ctx: &StageContext<'_>) -> bool {
+    match AnalysisJob::is_cancel_requested(ctx.pool, ctx.job.id).await {
+        Ok(requested) => requested,
+        Err(e) => {
+            warn!(job_id=%ctx.job.id, "Failed to check cancellation: {:?}", e);
+            false
+        }
+    }
+}
+
+/// Resolve once cancellation of the job has been requested.
+async fn cancellation(ctx: &StageContext<'_>) {
+    loop {
+        sleep(CANCEL_POLL_INTERVAL).await;
+        if cancel_requested(ctx).await {
+            return;
+        }
+    }
+}
+
+/// Load the stored output of a stage that succeeded in a previous run of the job.
+///
+/// OCR text and the JSON of parse, AI, exec and webhook stages are read back
+/// from storage; other stages pass their input through, so it is reused as is.
+async fn reuse_output(ctx: &StageContext<'_>, idx: usize, input: &Value) -> Option<Value> {
+    let stage = &ctx.stages[idx];
+    let output_type = match stage.stage_type.as_str() {
+        "ocr" => "txt",
+        "parse" | "ai" | "exec" | "webhook" => "json",
+        _ => return Some(input.clone()),
+    };
+    let bytes = match worker::load_stage_output(
+        ctx.pool,
+        ctx.s3_client,
+        ctx.job.id,
+        stage.name(),
+        output_type,
+    )
+    .await
+    {
+        Ok(Some(bytes)) => bytes,
+        Ok(None) => return None,
+        Err(e) => {
+            warn!(job_id=%ctx.job.id, stage=%stage.name(), "Failed to load previous output: {:?}", e);
+            return None;
+        }
+    };
+    if output_type == "txt" {
+        Some(Value::String(String::from_utf8_lossy(&bytes).into_owned()))
+    } else {
+        serde_json::from_slice(&bytes).ok()
+    }
+}
+
+/// Mark stage `idx` as done and queue dependents whose inputs are now complete.
+fn release_dependents(
+    graph: &StageGraph,
+    idx: usize,
+    pending: &mut [usize],
+    ready: &mut VecDeque<usize>,
+) {
+    for &next in graph.dependents(idx) {
+        pending[next] -= 1;
+        if pending[next] == 0 {
+            ready.push_back(next);
+        }
+    }
+}
+
 #[tracing::instrument(skip(path))]
 async fn remove_with_retry(path: &Path, job_id: Uuid, desc: &str) {
     const ATTEMPTS: u8 = 2;
@@ -188,70 +553,245 @@ async fn remove_with_retry(path: &Path, job_id: Uuid, desc: &str) {
     }
 }
 
-#[tracing::instrument(skip(pool, s3_client, job, doc, stages, org_settings))]
+/// Lease held by this worker on the jobs it claimed.
+#[derive(Clone)]
+struct Lease {
+    worker_id: Arc<str>,
+    secs: u64,
+}
+
+/// Renew the lease on `job_id` until the task is aborted or the lease is lost.
+/// Returns once the lease is lost.
+async fn keep_lease(pool: Arc<PgPool>, job_id: Uuid, lease: Lease) {
+    let interval = Duration::from_secs((lease.secs / 3).max(1));
+    loop {
+        sleep(interval).await;
+        match AnalysisJob::heartbeat(&pool, job_id, &lease.worker_id, lease.secs).await {
+            Ok(true) => {}
+            Ok(false) => {
+                warn!(job_id=%job_id, "Lost lease on job");
+                return;
+            }
+            Err(e) => warn!(job_id=%job_id, "Failed to renew lease: {:?}", e),
+        }
+    }
+}
+
+/// Periodically return jobs whose worker stopped renewing its lease to the queue.
+async fn reap_expired_leases(pool: Arc<PgPool>, interval: Duration, max_attempts: i32) {
+    loop {
+        sleep(interval).await;
+        let jobs = match AnalysisJob::reap_expired_leases(&pool, max_attempts).await {
+            Ok(jobs) => jobs,
+            Err(e) => {
+                error!("Failed to reap expired leases: {:?}", e);
+                continue;
+            }
+        };
+        for job in jobs {
+            warn!(job_id=%job.id, status=%job.status, attempts=job.attempts, "Reclaimed job with expired lease");
+            let _ = JobStageRun::abort_running(&pool, job.id).await;
+            if job.status == "pending" {
+                enqueue_job(job.id).await;
+            } else {
+                publish_status_event(job.id, job.org_id, "failed").await;
+                enqueue_job_event(&pool, job.id).await;
+                JOB_COUNTER.with_label_values(&["failed"]).inc();
+            }
+        }
+    }
+}
+
+/// Periodically publish the number of pending jobs per priority.
+async fn report_queue_depth(pool: Arc<PgPool>, interval: Duration) {
+    loop {
+        match AnalysisJob::pending_counts(&pool).await {
+            Ok(counts) => {
+                for priority in JOB_PRIORITIES {
+                    let depth = counts
+                        .iter()
+                        .find(|(p, _)| p == priority)
+                        .map_or(0, |(_, n)| *n);
+                    QUEUE_DEPTH_GAUGE.with_label_values(&[priority]).set(depth);
+                }
+            }
+            Err(e) => warn!("Failed to fetch queue depth: {:?}", e),
+        }
+        sleep(interval).await;
+    }
+}
+
+/// Periodically send due outbound webhook deliveries.
+async fn deliver_webhooks(pool: Arc<PgPool>, interval: Duration) {
+    let cfg = DeliveryConfig::from_env();
+    loop {
+        match deliver_due(&pool, &cfg).await {
+            // A non-empty batch may mean more deliveries are due already
+            Ok(n) if n > 0 => continue,
+            Ok(_) => {}
+            Err(e) => error!("Failed to send webhook deliveries: {:?}", e),
+        }
+        sleep(interval).await;
+    }
+}
+
+/// Load the document, pipeline stages and organization settings of a job.
+async fn load_job(
+    pool: &PgPool,
+    job: &AnalysisJob,
+) -> Result<(Document, Vec<Stage>, Option<OrgSettings>), JobFailure> {
+    let org_settings = match OrgSettings::find(pool, job.org_id).await {
+        Ok(settings) => Some(settings),
+        Err(e) => {
+            error!(job_id=%job.id, org_id=%job.org_id, "Failed to fetch org settings: {:?}", e);
+            None
+        }
+    };
+    let doc = sqlx::query_as::<_, Document>("SELECT * FROM documents WHERE id=$1")
+        .bind(job.document_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| JobFailure::new("document_missing", &e.into()))?;
+    let pipeline: Pipeline = sqlx::query_as("SELECT * FROM pipelines WHERE id=$1")
+        .bind(job.pipeline_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| JobFailure::new("invalid_pipeline", &e.into()))?;
+    let mut stages: Vec<Stage> = serde_json::from_value(pipeline.stages)
+        .map_err(|e| JobFailure::new("invalid_pipeline", &e.into()))?;
+    dag::assign_ids(&mut stages);
+    Ok((doc, stages, org_settings))
+}
+
+/// Download the job's document and run its stages.
+///
+/// The stages are stopped when `lease_lost` completes, since another worker
+/// may already be running the job.
+async fn execute_job(
+    pool: &PgPool,
+    s3_client: &S3Client,
+    job: &AnalysisJob,
+    bucket: &str,
+    lease_lost: impl Future<Output = ()>,
+) -> Result<(), JobFailure> {
+    let (doc, stages, org_settings) = load_job(pool, job).await?;
+    // The extension tells the stages which format the document has
+    let extension = DocumentFormat::from_filename(&doc.filename).map_or("pdf", |f| f.extension());
+    let mut local = std::env::temp_dir();
+    local.push(format!("{}-input.{}", job.id, extension));
+    if let Err(e) = processing::ocr::download_pdf(s3_client, bucket, &doc.filename, &local).await {
+        error!(job_id=%job.id, "Failed to download document: {:?}", e);
+        return Err(JobFailure::new("download_failed", &e));
+    }
+
+    let ctx = StageContext {
+        pool,
+        s3_client,
+        job,
+        doc: &doc,
+        stages: &stages,
+        org_settings: org_settings.as_ref(),
+        bucket,
+        local: &local,
+    };
+    let res = tokio::select! {
+        res = run_stages(&ctx) => res,
+        _ = lease_lost => Err(JobFailure::lease_lost()),
+    };
+
+    if local.exists() {
+        remove_with_retry(&local, job.id, "input document").await;
+    }
+    for idx in 0..stages.len() {
+        let txt_path = ocr_text_path(&local, idx);
+        if txt_path.exists() {
+            remove_with_retry(&txt_path, job.id, "text file").await;
+        }
+    }
+    res
+}
+
+/// Whether the final status of a job was stored. `false` means the lease was
+/// lost and another worker may have taken the job over, so the outcome is
+/// left to that worker.
+fn still_leased(marked: sqlx::Result<bool>, job_id: Uuid) -> bool {
+    match marked {
+        Ok(true) => true,
+        Ok(false) => {
+            warn!(job_id=%job_id, "Lease lost before the job finished, discarding its result");
+            false
+        }
+        Err(e) => {
+            error!(job_id=%job_id, "Failed to store job status: {:?}", e);
+            true
+        }
+    }
+}
+
+#[tracing::instrument(skip(pool, s3_client, job, lease))]
 async fn process_job(
     pool: Arc<PgPool>,
     s3_client: Arc<S3Client>,
     job: AnalysisJob,
-    doc: Document,
-    stages: Vec<Stage>,
-    org_settings: Option<OrgSettings>,
     bucket: String,
+    lease: Lease,
 ) {
     RUNNING_JOBS_GAUGE.inc();
-    let mut local = std::env::temp_dir();
-    local.push(format!("{}-input.pdf", job.id));
-    if let Err(e) = processing::ocr::download_pdf(&s3_client, &bucket, &doc.filename, &local).await
-    {
-        error!(job_id=%job.id, "Failed to download PDF: {:?}", e);
-        let _ = AnalysisJob::update_status(&pool, job.id, "failed").await;
-        publish_status_event(job.id, job.org_id, "failed").await;
+    publish_status_event(job.id, job.org_id, "in_progress").await;
+    let worker_id = Arc::clone(&lease.worker_id);
+    let mut heartbeat = tokio::spawn(keep_lease(Arc::clone(&pool), job.id, lease));
+    let job_timer = Instant::now();
+    let lease_lost = async {
+        let _ = (&mut heartbeat).await;
+    };
+    let res = execute_job(&pool, &s3_client, &job, &bucket, lease_lost).await;
+    heartbeat.abort();
+
+    // Only the worker holding the lease stores the outcome and reports it
+    let marked = match &res {
+        Ok(_) => AnalysisJob::mark_completed(&pool, job.id, &worker_id).await,
+        Err(failure) if failure.is_lease_lost() => Ok(false),
+        Err(failure) if failure.is_cancelled() => {
+            AnalysisJob::mark_cancelled(&pool, job.id, &worker_id).await
+        }
+        Err(failure) => {
+            AnalysisJob::mark_failed(&pool, job.id, &worker_id, &failure.reason, &failure.message)
+                .await
+        }
+    };
+    if !still_leased(marked, job.id) {
+        RUNNING_JOBS_GAUGE.dec();
         return;
     }
-    let mut txt_path = local.clone();
-    txt_path.set_extension("txt");
-
-    let job_timer = Instant::now();
-    let res = run_stages(
-        &pool,
-        &s3_client,
-        &job,
-        &doc,
-        &stages,
-        org_settings.as_ref(),
-        &bucket,
-        &local,
-        &txt_path,
-    )
-    .await;
 
     match res {
         Ok(_) => {
-            let _ = AnalysisJob::update_status(&pool, job.id, "completed").await;
             publish_status_event(job.id, job.org_id, "completed").await;
+            enqueue_job_event(&pool, job.id).await;
             JOB_COUNTER.with_label_values(&["success"]).inc();
             JOB_HISTOGRAM
                 .with_label_values(&["success"])
                 .observe(job_timer.elapsed().as_secs_f64());
             info!(job_id=%job.id, "Job processing completed successfully.");
         }
-        Err(e) => {
-            error!(job_id=%job.id, "Job processing failed: {:?}", e);
-            let _ = AnalysisJob::update_status(&pool, job.id, "failed").await;
+        Err(failure) if failure.is_cancelled() => {
+            info!(job_id=%job.id, "Job cancelled.");
+            let _ = JobStageRun::abort_running(&pool, job.id).await;
+            publish_status_event(job.id, job.org_id, "cancelled").await;
+            enqueue_job_event(&pool, job.id).await;
+            JOB_COUNTER.with_label_values(&["cancelled"]).inc();
+        }
+        Err(failure) => {
+            error!(job_id=%job.id, reason=%failure.reason, "Job processing failed: {}", failure.message);
+            let _ = JobStageRun::abort_running(&pool, job.id).await;
             publish_status_event(job.id, job.org_id, "failed").await;
+            enqueue_job_event(&pool, job.id).await;
             JOB_COUNTER.with_label_values(&["failed"]).inc();
             JOB_HISTOGRAM
                 .with_label_values(&["failed"])
                 .observe(job_timer.elapsed().as_secs_f64());
         }
     }
-
-    if local.exists() {
-        remove_with_retry(&local, job.id, "input PDF").await;
-    }
-    if txt_path.exists() {
-        remove_with_retry(&txt_path, job.id, "text file").await;
-    }
     RUNNING_JOBS_GAUGE.dec();
 }
 
@@ -286,7 +826,7 @@ async fn main() -> Result<()> {
 
     let idle_duration = cfg.shutdown_after_idle.map(|m| Duration::from_secs(m * 60));
     let mut last_activity = Instant::now();
-    let blpop_timeout = if idle_duration.is_some() { 60 } else { 0 };
+    let poll_secs = cfg.queue_poll_secs;
 
     let mut shutdown_signal = signal::ctrl_c();
     tokio::pin!(shutdown_signal);
@@ -296,6 +836,29 @@ async fn main() -> Result<()> {
     let runtime_cfg = WorkerRuntimeConfig::from_env();
     let concurrency = Arc::new(AtomicUsize::new(runtime_cfg.concurrency.max(1)));
     tokio::spawn(worker::watch_config_changes(Arc::clone(&concurrency)));
+    let lease = Lease {
+        worker_id: format!(
+            "{}-{}",
+            std::env::var("HOSTNAME").unwrap_or_else(|_| "worker".into()),
+            Uuid::new_v4()
+        )
+        .into(),
+        secs: cfg.job_lease_secs,
+    };
+    info!(worker_id=%lease.worker_id, "Worker started");
+    tokio::spawn(reap_expired_leases(
+        Arc::clone(&pool),
+        Duration::from_secs((cfg.job_lease_secs / 2).max(1)),
+        cfg.job_max_attempts,
+    ));
+    tokio::spawn(report_queue_depth(
+        Arc::clone(&pool),
+        Duration::from_secs(poll_secs),
+    ));
+    tokio::spawn(deliver_webhooks(
+        Arc::clone(&pool),
+        Duration::from_secs(poll_secs),
+    ));
     let mut tasks: JoinSet<()> = JoinSet::new();
 
     'outer: loop {
@@ -312,76 +875,49 @@ async fn main() -> Result<()> {
             continue;
         }
 
-        let mut cmd = redis::cmd("BLPOP");
-        cmd.arg("jobs").arg(blpop_timeout);
-        let job: Option<(String, String)> = tokio::select! {
-            _ = &mut shutdown_signal => {
-                info!("Shutdown signal received");
-                break 'outer;
-            }
-            res = cmd.query_async::<_, Option<(String, String)>>(&mut conn) => {
-                res?
+        // Postgres is the source of truth; Redis only wakes idle workers early.
+        let claimed = match AnalysisJob::claim_next(&pool, &lease.worker_id, lease.secs).await {
+            Ok(job) => job,
+            Err(e) => {
+                error!("Failed to claim job: {:?}", e);
+                None
             }
         };
-
-        let Some((_, job_id_str)) = job else {
+        let Some(job) = claimed else {
             if let Some(d) = idle_duration {
                 if last_activity.elapsed() >= d && tasks.is_empty() {
                     worker::log_idle_shutdown();
                     break 'outer;
                 }
             }
-            if let Some(res) = tasks.join_next().await {
-                if let Err(e) = res {
-                    error!("task failed: {:?}", e);
+            let mut cmd = redis::cmd("BLPOP");
+            cmd.arg("jobs").arg(poll_secs);
+            tokio::select! {
+                _ = &mut shutdown_signal => {
+                    info!("Shutdown signal received");
+                    break 'outer;
+                }
+                res = cmd.query_async::<_, Option<(String, String)>>(&mut conn) => {
+                    if let Err(e) = res {
+                        warn!("Waiting for queue notification failed: {:?}", e);
+                        sleep(Duration::from_secs(poll_secs)).await;
+                    }
+                }
+                Some(res) = tasks.join_next(), if !tasks.is_empty() => {
+                    if let Err(e) = res { error!("task failed: {:?}", e); }
                 }
             }
             continue;
         };
         last_activity = Instant::now();
-        let job_id = match Uuid::parse_str(&job_id_str) {
-            Ok(id) => id,
-            Err(_) => continue,
-        };
-        let job = match AnalysisJob::find(&pool, job_id).await {
-            Ok(j) => j,
-            Err(e) => {
-                error!(job_id=%job_id_str, "Failed to fetch job details: {:?}", e);
-                continue;
-            }
-        };
-
-        let org_settings = match OrgSettings::find(&pool, job.org_id).await {
-            Ok(settings) => Some(settings),
-            Err(e) => {
-                error!(job_id=%job.id, org_id=%job.org_id, "Failed to fetch org settings: {:?}", e);
-                None
-            }
-        };
-
-        let doc = match sqlx::query_as::<_, Document>("SELECT * FROM documents WHERE id=$1")
-            .bind(job.document_id)
-            .fetch_one(&pool)
-            .await
-        {
-            Ok(d) => d,
-            Err(e) => {
-                error!(?e, "document missing");
-                continue;
-            }
-        };
-
-        let pipeline: Pipeline = sqlx::query_as("SELECT * FROM pipelines WHERE id=$1")
-            .bind(job.pipeline_id)
-            .fetch_one(&*pool)
-            .await?;
-        let stages: Vec<Stage> = serde_json::from_value(pipeline.stages)?;
-        let bucket = cfg.s3_bucket.clone();
-        let pool_clone = Arc::clone(&pool);
-        let s3_clone = Arc::clone(&s3_client);
-        tasks.spawn(async move {
-            process_job(pool_clone, s3_clone, job, doc, stages, org_settings, bucket).await;
-        });
+        info!(job_id=%job.id, attempt=job.attempts, "Claimed job");
+        tasks.spawn(process_job(
+            Arc::clone(&pool),
+            Arc::clone(&s3_client),
+            job,
+            cfg.s3_bucket.clone(),
+            lease.clone(),
+        ));
 
         if process_once {
             break;
@@ -46,6 +46,12 @@ pub struct WorkerConfig {
     pub worker_concurrency: usize,
     pub metrics_port: u16,
     pub shutdown_after_idle: Option<u64>,
+    /// Seconds a claimed job stays leased without a heartbeat.
+    pub job_lease_secs: u64,
+    /// Claims after which a job whose worker keeps dying is failed.
+    pub job_max_attempts: i32,
+    /// Seconds between queue polls when no Redis wake-up arrives.
+    pub queue_poll_secs: u64,
 }
 
 impl WorkerConfig {
@@ -67,6 +73,20 @@ impl WorkerConfig {
         let shutdown_after_idle = env::var("SHUTDOWN_AFTER_IDLE")
             .ok()
             .and_then(|v| v.parse().ok());
+        let job_lease_secs = env::var("JOB_LEASE_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(60)
+            .max(1);
+        let job_max_attempts = env::var("JOB_MAX_ATTEMPTS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(3);
+        let queue_poll_secs = env::var("QUEUE_POLL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(5)
+            .max(1);
         Ok(Self {
             database_url,
             redis_url,
@@ -75,6 +95,9 @@ impl WorkerConfig {
             worker_concurrency,
             metrics_port,
             shutdown_after_idle,
+            job_lease_secs,
+            job_max_attempts,
+            queue_poll_secs,
         })
     }
 }
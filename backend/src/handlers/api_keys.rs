@@ -0,0 +1,190 @@
+use crate::error::ApiError;
+use crate::middleware::auth::AuthUser;
+use crate::models::{ApiKey, NewApiKey, API_KEY_SCOPES};
+use crate::utils::log_action;
+use actix_web::{delete, get, http::StatusCode, post, web, HttpResponse, ResponseError};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct ApiKeyInput {
+    pub name: String,
+    pub scopes: Vec<String>,
+    /// Keys without expiry stay valid until they are revoked.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Response of creating or rotating a key, the only ones containing the key.
+#[derive(Serialize)]
+struct IssuedApiKey {
+    #[serde(flatten)]
+    api_key: ApiKey,
+    key: String,
+}
+
+/// Only global admins and organization admins of `org_id` manage API keys.
+fn authorize(user: &AuthUser, org_id: Uuid) -> Result<(), HttpResponse> {
+    if user.can_manage_org(org_id) {
+        return Ok(());
+    }
+    log::warn!(
+        "Unauthorized attempt to manage API keys of org {} by user {} (role: {})",
+        org_id,
+        user.user_id,
+        user.role
+    );
+    Err(HttpResponse::Forbidden().json(
+        serde_json::json!({"error": "You do not have permission to manage API keys for this organization."}),
+    ))
+}
+
+/// Fetch a key and make sure it belongs to `org_id`.
+async fn find_org_key(pool: &PgPool, org_id: Uuid, key_id: Uuid) -> Result<ApiKey, HttpResponse> {
+    match ApiKey::find(pool, key_id).await {
+        Ok(key) if key.org_id == org_id => Ok(key),
+        Ok(_) | Err(sqlx::Error::RowNotFound) => {
+            Err(ApiError::new("API key not found", StatusCode::NOT_FOUND).error_response())
+        }
+        Err(e) => Err(ApiError::from_db("Failed to fetch API key", e).error_response()),
+    }
+}
+
+#[get("/settings/{org_id}/api-keys")]
+#[tracing::instrument(skip(pool, user))]
+async fn list_api_keys(
+    path: web::Path<Uuid>,
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let org_id = path.into_inner();
+    if let Err(resp) = authorize(&user, org_id) {
+        return resp;
+    }
+    match ApiKey::list_for_org(&pool, org_id).await {
+        Ok(keys) => HttpResponse::Ok().json(keys),
+        Err(e) => ApiError::from_db("Failed to fetch API keys", e).error_response(),
+    }
+}
+
+/// Create a key acting for the calling user within `org_id`.
+#[post("/settings/{org_id}/api-keys")]
+#[tracing::instrument(skip(payload, pool, user))]
+async fn create_api_key(
+    path: web::Path<Uuid>,
+    payload: web::Json<ApiKeyInput>,
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let org_id = path.into_inner();
+    if let Err(resp) = authorize(&user, org_id) {
+        return resp;
+    }
+    let input = payload.into_inner();
+    if input.name.trim().is_empty() {
+        return HttpResponse::BadRequest()
+            .json(serde_json::json!({"error": "API key name cannot be empty."}));
+    }
+    let mut scopes = input.scopes;
+    scopes.sort();
+    scopes.dedup();
+    if scopes.is_empty() || scopes.iter().any(|s| !API_KEY_SCOPES.contains(&s.as_str())) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Scopes must be a non-empty list of: {}.", API_KEY_SCOPES.join(", "))
+        }));
+    }
+    if input.expires_at.is_some_and(|t| t <= Utc::now()) {
+        return HttpResponse::BadRequest()
+            .json(serde_json::json!({"error": "Expiry must be in the future."}));
+    }
+    let new = NewApiKey {
+        org_id,
+        name: input.name.trim().to_string(),
+        scopes,
+        created_by: user.user_id,
+        expires_at: input.expires_at,
+    };
+    match ApiKey::create(&pool, new).await {
+        Ok((api_key, key)) => {
+            log_action(
+                &pool,
+                org_id,
+                user.user_id,
+                &format!("api_key_create:{}", api_key.id),
+            )
+            .await;
+            HttpResponse::Ok().json(IssuedApiKey { api_key, key })
+        }
+        Err(e) => ApiError::from_db("Failed to create API key", e).error_response(),
+    }
+}
+
+/// Issue a new key for an existing API key; the old key stops working at once.
+#[post("/settings/{org_id}/api-keys/{key_id}/rotate")]
+#[tracing::instrument(skip(pool, user))]
+async fn rotate_api_key(
+    path: web::Path<(Uuid, Uuid)>,
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let (org_id, key_id) = path.into_inner();
+    if let Err(resp) = authorize(&user, org_id) {
+        return resp;
+    }
+    if let Err(resp) = find_org_key(&pool, org_id, key_id).await {
+        return resp;
+    }
+    match ApiKey::rotate(&pool, key_id).await {
+        Ok(Some((api_key, key))) => {
+            log_action(
+                &pool,
+                org_id,
+                user.user_id,
+                &format!("api_key_rotate:{}", key_id),
+            )
+            .await;
+            HttpResponse::Ok().json(IssuedApiKey { api_key, key })
+        }
+        Ok(None) => ApiError::new("Revoked API keys cannot be rotated", StatusCode::CONFLICT)
+            .error_response(),
+        Err(e) => ApiError::from_db("Failed to rotate API key", e).error_response(),
+    }
+}
+
+/// Revoke a key. It stays listed so audit entries made with it can be traced.
+#[delete("/settings/{org_id}/api-keys/{key_id}")]
+#[tracing::instrument(skip(pool, user))]
+async fn revoke_api_key(
+    path: web::Path<(Uuid, Uuid)>,
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let (org_id, key_id) = path.into_inner();
+    if let Err(resp) = authorize(&user, org_id) {
+        return resp;
+    }
+    if let Err(resp) = find_org_key(&pool, org_id, key_id).await {
+        return resp;
+    }
+    match ApiKey::revoke(&pool, key_id).await {
+        Ok(api_key) => {
+            log_action(
+                &pool,
+                org_id,
+                user.user_id,
+                &format!("api_key_revoke:{}", key_id),
+            )
+            .await;
+            HttpResponse::Ok().json(api_key)
+        }
+        Err(e) => ApiError::from_db("Failed to revoke API key", e).error_response(),
+    }
+}
+
+pub fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_api_keys)
+        .service(create_api_key)
+        .service(rotate_api_key)
+        .service(revoke_api_key);
+}
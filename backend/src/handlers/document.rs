@@ -1,19 +1,21 @@
 use crate::error::ApiError;
-use crate::middleware::auth::AuthUser;
+use crate::middleware::auth::ApiUser;
+use crate::models::analysis_job::is_valid_priority;
+use crate::models::api_key::{SCOPE_JOBS_READ, SCOPE_UPLOAD};
 use crate::models::{
-    AnalysisJob, Document, DocumentError, NewAnalysisJob, NewDocument, OrgSettings,
+    AnalysisJob, Document, DocumentError, NewAnalysisJob, NewDocument, OrgSettings, Pipeline,
 };
-use crate::utils::log_action;
+use crate::processing::formats::DocumentFormat;
+use crate::queue::enqueue_job;
+use crate::utils::log_api_action;
 use actix_multipart::Multipart;
 use actix_web::{delete, get, post, web, HttpResponse, ResponseError};
 use anyhow::Error;
 use async_trait::async_trait;
 use aws_sdk_s3::{presigning::PresigningConfig, Client};
 use futures_util::StreamExt as _;
-use lopdf::Document as PdfDoc;
-use redis::AsyncCommands;
 use sanitize_filename; // Added for sanitizing filenames
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, Transaction};
 use std::path::Path;
 use std::time::Duration;
 use uuid::Uuid;
@@ -48,11 +50,29 @@ pub async fn cleanup_s3_object<S: S3Deleter + Sync>(s3: &S, bucket: &str, key: &
     }
 }
 
+/// Maximum number of documents accepted by one batch analyze request.
+const MAX_BATCH_ANALYZE: usize = 500;
+
+#[derive(serde::Deserialize)]
+pub struct AnalyzeRequest {
+    pub pipeline_id: Uuid,
+    pub priority: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct BatchAnalyzeRequest {
+    pub pipeline_id: Uuid,
+    pub document_ids: Vec<Uuid>,
+    pub priority: Option<String>,
+}
+
 #[derive(serde::Deserialize)]
 pub struct UploadParams {
     pub org_id: Uuid,
     pub pipeline_id: Option<Uuid>,
     pub is_target: Option<bool>,
+    /// Priority of the analysis job; defaults to the pipeline's priority.
+    pub priority: Option<String>,
 }
 
 async fn check_upload_quota(pool: &PgPool, org_id: Uuid) -> Result<(), HttpResponse> {
@@ -83,29 +103,12 @@ async fn check_upload_quota(pool: &PgPool, org_id: Uuid) -> Result<(), HttpRespo
     }
 }
 
-async fn check_analysis_quota(pool: &PgPool, org_id: Uuid) -> Result<(), HttpResponse> {
-    match OrgSettings::find(pool, org_id).await {
-        Ok(settings) => {
-            let (count,): (i64,) = sqlx::query_as(
-                "SELECT COUNT(*) FROM analysis_jobs WHERE org_id=$1 AND created_at >= date_trunc('month', NOW())"
-            )
-            .bind(org_id)
-            .fetch_one(pool)
-            .await
-            .unwrap_or((0,));
-            if count >= settings.monthly_analysis_quota as i64 {
-                return Err(HttpResponse::TooManyRequests().json(serde_json::json!({"error": "Monthly analysis quota exceeded. Document uploaded but not queued for analysis."})));
-            }
-            Ok(())
-        }
-        Err(e) => {
-            log::error!(
-                "Could not verify organization settings for analysis quota (org_id {}): {:?}",
-                org_id,
-                e
-            );
-            Err(HttpResponse::InternalServerError().json(serde_json::json!({"error": "Could not verify organization settings for analysis quota."})))
-        }
+/// Reject priorities other than `high`, `normal` and `bulk`.
+pub(crate) fn validate_priority(priority: Option<&str>) -> Result<(), HttpResponse> {
+    match priority {
+        Some(p) if !is_valid_priority(p) => Err(HttpResponse::BadRequest()
+            .json(serde_json::json!({"error": "Invalid priority. Use high, normal or bulk."}))),
+        _ => Ok(()),
     }
 }
 
@@ -137,10 +140,13 @@ async fn upload_to_s3(
 pub async fn upload(
     mut payload: Multipart,
     params: web::Query<UploadParams>,
-    user: AuthUser,
+    user: ApiUser,
     pool: web::Data<sqlx::PgPool>,
     s3: web::Data<Client>,
 ) -> HttpResponse {
+    if let Err(resp) = user.require_scope(SCOPE_UPLOAD) {
+        return resp;
+    }
     let mut user_provided_filename = String::new();
     let mut file_content_type: Option<String> = None;
     let mut bytes_data = Vec::new();
@@ -186,6 +192,10 @@ pub async fn upload(
         );
     }
 
+    if let Err(resp) = validate_priority(params.priority.as_deref()) {
+        return resp;
+    }
+
     // Quota check (target docs)
     if params.is_target.unwrap_or(false) {
         if let Err(resp) = check_upload_quota(&pool, params.org_id).await {
@@ -228,52 +238,24 @@ pub async fn upload(
         }
     };
 
-    log_action(
-        &pool,
-        user.org_id,
-        user.user_id,
-        &format!("upload:{}", created_document.id),
-    )
-    .await;
+    log_api_action(&pool, &user, &format!("upload:{}", created_document.id)).await;
 
     // Optional: Queue for analysis
     if let Some(pipeline_id) = params.pipeline_id {
-        if let Err(resp) = check_analysis_quota(&pool, params.org_id).await {
-            cleanup_s3_object(s3.get_ref(), &bucket, &s3_key_name).await;
-            return resp;
-        }
         let job_to_create = NewAnalysisJob {
             org_id: params.org_id,
             document_id: created_document.id,
             pipeline_id,
             status: "pending".into(),
         };
-        match AnalysisJob::create(&pool, job_to_create).await {
+        match create_upload_job(&pool, job_to_create, params.priority.as_deref()).await {
             Ok(j) => {
-                log_action(
-                    &pool,
-                    user.org_id,
-                    user.user_id,
-                    &format!("job_created:{}", j.id),
-                )
-                .await;
-                if let Ok(redis_url) = std::env::var("REDIS_URL") {
-                    if let Ok(client) = redis::Client::open(redis_url) {
-                        if let Ok(mut conn) = client.get_async_connection().await {
-                            let _: Result<(), _> = conn.rpush("jobs", j.id.to_string()).await;
-                        } else {
-                            log::error!("Failed to connect to Redis to queue job {}.", j.id);
-                        }
-                    } else {
-                        log::error!("Failed to open Redis client to queue job {}.", j.id);
-                    }
-                } else {
-                    log::warn!("REDIS_URL not set, job {} not queued via Redis.", j.id);
-                }
+                log_api_action(&pool, &user, &format!("job_created:{}", j.id)).await;
+                enqueue_job(j.id).await;
             }
-            Err(e) => {
+            Err(resp) => {
                 cleanup_s3_object(s3.get_ref(), &bucket, &s3_key_name).await;
-                return ApiError::from_db("Failed to queue analysis job.", e).error_response();
+                return resp;
             }
         }
     }
@@ -287,10 +269,13 @@ pub async fn upload(
 #[tracing::instrument(skip(pool, s3, user))]
 pub async fn download(
     path: web::Path<Uuid>,
-    user: AuthUser,
+    user: ApiUser,
     pool: web::Data<PgPool>,
     s3: web::Data<Client>,
 ) -> HttpResponse {
+    if let Err(resp) = user.require_scope(SCOPE_JOBS_READ) {
+        return resp;
+    }
     let document_id = path.into_inner();
 
     let doc = match sqlx::query_as::<_, Document>("SELECT * FROM documents WHERE id=$1")
@@ -317,9 +302,11 @@ pub async fn download(
 
     if let Ok(local_dir) = std::env::var("LOCAL_S3_DIR") {
         let path = Path::new(&local_dir).join(&doc.filename);
+        let content_type = DocumentFormat::from_filename(&doc.filename)
+            .map_or("application/octet-stream", DocumentFormat::mime_type);
         match tokio::fs::read(path).await {
             Ok(bytes) => HttpResponse::Ok()
-                .append_header(("Content-Type", "application/pdf"))
+                .append_header(("Content-Type", content_type))
                 .append_header((
                     "Content-Disposition",
                     format!("attachment; filename=\"{}\"", doc.display_name),
@@ -352,10 +339,13 @@ pub async fn download(
 #[tracing::instrument(skip(pool, s3, user))]
 pub async fn delete_document(
     path: web::Path<Uuid>,
-    user: AuthUser,
+    user: ApiUser,
     pool: web::Data<PgPool>,
     s3: web::Data<Client>,
 ) -> HttpResponse {
+    if let Err(resp) = user.require_scope(SCOPE_UPLOAD) {
+        return resp;
+    }
     let doc_id = path.into_inner();
     let doc = match sqlx::query_as::<_, Document>("SELECT * FROM documents WHERE id=$1")
         .bind(doc_id)
@@ -382,24 +372,253 @@ pub async fn delete_document(
 
     match Document::delete(&pool, doc_id).await {
         Ok(_) => {
-            log_action(
-                &pool,
-                user.org_id,
-                user.user_id,
-                &format!("delete_document:{}", doc_id),
-            )
-            .await;
+            log_api_action(&pool, &user, &format!("delete_document:{}", doc_id)).await;
             HttpResponse::Ok().finish()
         }
         Err(e) => ApiError::from_db("Failed to delete document", e).error_response(),
     }
 }
 
+/// Load a pipeline and make sure it belongs to `org_id`.
+async fn find_org_pipeline(
+    pool: &PgPool,
+    pipeline_id: Uuid,
+    org_id: Uuid,
+) -> Result<Pipeline, HttpResponse> {
+    match sqlx::query_as::<_, Pipeline>("SELECT * FROM pipelines WHERE id=$1")
+        .bind(pipeline_id)
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(Some(p)) if p.org_id == org_id => Ok(p),
+        Ok(_) => {
+            Err(HttpResponse::NotFound().json(serde_json::json!({"error": "Pipeline not found"})))
+        }
+        Err(e) => Err(ApiError::from_db("Failed to fetch pipeline.", e).error_response()),
+    }
+}
+
+/// Create the analysis job of an uploaded document within the monthly quota.
+async fn create_upload_job(
+    pool: &PgPool,
+    new: NewAnalysisJob,
+    priority: Option<&str>,
+) -> Result<AnalysisJob, HttpResponse> {
+    let db_error = |e| ApiError::from_db("Failed to queue analysis job.", e).error_response();
+    let mut tx = pool.begin().await.map_err(db_error)?;
+    reserve_analysis_quota(
+        &mut tx,
+        new.org_id,
+        1,
+        "Monthly analysis quota exceeded. Document uploaded but not queued for analysis.",
+    )
+    .await?;
+    let job = AnalysisJob::create_with_priority(&mut *tx, new, priority)
+        .await
+        .map_err(db_error)?;
+    tx.commit().await.map_err(db_error)?;
+    Ok(job)
+}
+
+/// Create analysis jobs for `docs` and push them to the Redis `jobs` list.
+///
+/// The quota check and all inserts share one transaction, so either every job
+/// is created or none is. `docs` must belong to one organization.
+async fn queue_analysis_jobs(
+    pool: &PgPool,
+    user: &ApiUser,
+    docs: &[Document],
+    pipeline_id: Uuid,
+    priority: Option<&str>,
+) -> Result<Vec<AnalysisJob>, HttpResponse> {
+    let db_error = |e| ApiError::from_db("Failed to queue analysis job.", e).error_response();
+    let org_id = docs[0].org_id;
+    let mut tx = pool.begin().await.map_err(db_error)?;
+    reserve_analysis_quota(&mut tx, org_id, docs.len() as i64, ANALYSIS_QUOTA_EXCEEDED).await?;
+    let mut jobs = Vec::with_capacity(docs.len());
+    for doc in docs {
+        let job_to_create = NewAnalysisJob {
+            org_id: doc.org_id,
+            document_id: doc.id,
+            pipeline_id,
+            status: "pending".into(),
+        };
+        let job = AnalysisJob::create_with_priority(&mut *tx, job_to_create, priority)
+            .await
+            .map_err(db_error)?;
+        jobs.push(job);
+    }
+    tx.commit().await.map_err(db_error)?;
+
+    // Workers only see the jobs once they are committed
+    for job in &jobs {
+        log_api_action(pool, user, &format!("job_created:{}", job.id)).await;
+        enqueue_job(job.id).await;
+    }
+    Ok(jobs)
+}
+
+/// Error returned when a request would exceed the monthly analysis quota.
+pub(crate) const ANALYSIS_QUOTA_EXCEEDED: &str = "Monthly analysis quota exceeded.";
+
+/// Ensure the organization can queue `jobs` more analysis jobs this month,
+/// answering `429` with `exceeded_error` otherwise.
+///
+/// Locks the organization's settings row until `tx` ends, so concurrent
+/// requests cannot both pass the check on the last jobs of the quota. Every
+/// request creating or requeueing jobs goes through here.
+pub(crate) async fn reserve_analysis_quota(
+    tx: &mut Transaction<'_, Postgres>,
+    org_id: Uuid,
+    jobs: i64,
+    exceeded_error: &str,
+) -> Result<(), HttpResponse> {
+    let settings_error = |e: Option<sqlx::Error>| {
+        log::error!(
+            "Could not verify organization settings for analysis quota (org_id {}): {:?}",
+            org_id,
+            e
+        );
+        HttpResponse::InternalServerError().json(serde_json::json!({"error": "Could not verify organization settings for analysis quota."}))
+    };
+    let (quota,): (i32,) = sqlx::query_as(
+        "SELECT monthly_analysis_quota FROM org_settings WHERE org_id=$1 FOR UPDATE",
+    )
+    .bind(org_id)
+    .fetch_optional(&mut **tx)
+    .await
+    .map_err(|e| settings_error(Some(e)))?
+    .ok_or_else(|| settings_error(None))?;
+    let (count,): (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM analysis_jobs WHERE org_id=$1 AND created_at >= date_trunc('month', NOW())"
+    )
+    .bind(org_id)
+    .fetch_one(&mut **tx)
+    .await
+    .map_err(|e| ApiError::from_db("Failed to queue analysis job.", e).error_response())?;
+    if count + jobs > quota as i64 {
+        return Err(
+            HttpResponse::TooManyRequests().json(serde_json::json!({"error": exceeded_error}))
+        );
+    }
+    Ok(())
+}
+
+/// Run an already uploaded document through a pipeline.
+#[post("/documents/{id}/analyze")]
+#[tracing::instrument(skip(pool, user, body))]
+pub async fn analyze_document(
+    path: web::Path<Uuid>,
+    body: web::Json<AnalyzeRequest>,
+    user: ApiUser,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    if let Err(resp) = user.require_scope(SCOPE_UPLOAD) {
+        return resp;
+    }
+    let doc_id = path.into_inner();
+    let doc = match sqlx::query_as::<_, Document>("SELECT * FROM documents WHERE id=$1")
+        .bind(doc_id)
+        .fetch_optional(pool.as_ref())
+        .await
+    {
+        Ok(Some(d)) => d,
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Err(e) => return ApiError::from_db("Failed to fetch document.", e).error_response(),
+    };
+    if doc.org_id != user.org_id && user.role != "admin" {
+        log::warn!(
+            "Unauthorized analyze attempt {} by user {} (org {})",
+            doc_id,
+            user.user_id,
+            user.org_id
+        );
+        return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+    }
+    if let Err(resp) = validate_priority(body.priority.as_deref()) {
+        return resp;
+    }
+    if let Err(resp) = find_org_pipeline(&pool, body.pipeline_id, doc.org_id).await {
+        return resp;
+    }
+    let docs = std::slice::from_ref(&doc);
+    let priority = body.priority.as_deref();
+    match queue_analysis_jobs(&pool, &user, docs, body.pipeline_id, priority).await {
+        Ok(mut jobs) => HttpResponse::Ok().json(jobs.remove(0)),
+        Err(resp) => resp,
+    }
+}
+
+/// Run many already uploaded documents of one organization through a pipeline.
+///
+/// The whole batch is rejected when a document is missing, belongs to another
+/// organization or the analysis quota cannot cover all documents.
+#[post("/documents/analyze")]
+#[tracing::instrument(skip(pool, user, body))]
+pub async fn analyze_documents(
+    body: web::Json<BatchAnalyzeRequest>,
+    user: ApiUser,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    if let Err(resp) = user.require_scope(SCOPE_UPLOAD) {
+        return resp;
+    }
+    let mut ids = body.document_ids.clone();
+    ids.sort();
+    ids.dedup();
+    if ids.is_empty() || ids.len() > MAX_BATCH_ANALYZE {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("document_ids must contain between 1 and {} documents.", MAX_BATCH_ANALYZE)
+        }));
+    }
+    let docs = match sqlx::query_as::<_, Document>(
+        "SELECT * FROM documents WHERE id = ANY($1) ORDER BY upload_date ASC",
+    )
+    .bind(&ids)
+    .fetch_all(pool.as_ref())
+    .await
+    {
+        Ok(d) => d,
+        Err(e) => return ApiError::from_db("Failed to fetch documents.", e).error_response(),
+    };
+    if docs.len() != ids.len() {
+        let missing: Vec<Uuid> = ids
+            .iter()
+            .filter(|id| !docs.iter().any(|d| d.id == **id))
+            .copied()
+            .collect();
+        return HttpResponse::NotFound()
+            .json(serde_json::json!({"error": "Documents not found", "document_ids": missing}));
+    }
+    let org_id = docs[0].org_id;
+    if docs.iter().any(|d| d.org_id != org_id) || (org_id != user.org_id && user.role != "admin") {
+        log::warn!(
+            "Unauthorized batch analyze attempt by user {} (org {})",
+            user.user_id,
+            user.org_id
+        );
+        return HttpResponse::Unauthorized().json(serde_json::json!({"error": "Unauthorized"}));
+    }
+    if let Err(resp) = validate_priority(body.priority.as_deref()) {
+        return resp;
+    }
+    if let Err(resp) = find_org_pipeline(&pool, body.pipeline_id, org_id).await {
+        return resp;
+    }
+    let priority = body.priority.as_deref();
+    match queue_analysis_jobs(&pool, &user, &docs, body.pipeline_id, priority).await {
+        Ok(jobs) => HttpResponse::Ok().json(jobs),
+        Err(resp) => resp,
+    }
+}
+
 /// Configure Actix routes for document-related endpoints.
 pub fn routes(cfg: &mut web::ServiceConfig) {
     cfg.service(upload)
         .service(download)
-        .service(delete_document);
+        .service(delete_document)
+        .service(analyze_documents)
+        .service(analyze_document);
 }
 
 async fn validate_document(
@@ -407,19 +626,17 @@ async fn validate_document(
     file_content_type: &Option<String>,
     bytes_data: &[u8],
 ) -> Result<(String, i32), HttpResponse> {
-    let (base_filename, file_type) =
+    let (base_filename, format) =
         crate::utils::validate_filename_and_type(user_filename, file_content_type, bytes_data)?;
 
-    let pages = if file_type == "pdf" {
-        match PdfDoc::load_mem(bytes_data).map(|d| d.get_pages().len() as i32) {
-            Ok(p) => p,
-            Err(e) => {
-                log::error!("Failed to parse PDF pages for {}: {:?}", user_filename, e);
-                return Err(HttpResponse::BadRequest().json(serde_json::json!({"error": "Corrupt or invalid PDF file. Could not count pages."})));
-            }
+    let pages = match format.page_count(bytes_data) {
+        Ok(p) => p,
+        Err(e) => {
+            log::error!("Failed to count pages of {}: {:?}", user_filename, e);
+            return Err(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Corrupt or invalid {} file. Could not count pages.", format.extension().to_uppercase())
+            })));
         }
-    } else {
-        0
     };
 
     Ok((base_filename, pages))
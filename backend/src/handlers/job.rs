@@ -1,7 +1,14 @@
 use crate::error::ApiError;
-use crate::middleware::auth::AuthUser;
-use crate::models::{AnalysisJob, Document, JobStageOutput, Pipeline};
-use actix_web::{get, http::StatusCode, web, HttpResponse, ResponseError};
+use crate::handlers::document::{
+    reserve_analysis_quota, validate_priority, ANALYSIS_QUOTA_EXCEEDED,
+};
+use crate::middleware::auth::{ApiUser, AuthUser};
+use crate::models::api_key::{SCOPE_JOBS_MANAGE, SCOPE_JOBS_READ};
+use crate::models::{AnalysisJob, Document, JobStageOutput, JobStageRun, NewAnalysisJob, Pipeline};
+use crate::queue::enqueue_job;
+use crate::utils::log_api_action;
+use crate::worker::deliveries::enqueue_job_event;
+use actix_web::{get, http::StatusCode, post, web, Either, HttpResponse, ResponseError};
 use actix_web_lab::sse::{self, ChannelStream, Sse};
 use aws_sdk_s3::presigning::PresigningConfig;
 use futures_util::StreamExt;
@@ -19,7 +26,12 @@ struct JobDetailsResponse {
     document_id: Uuid,
     pipeline_id: Uuid,
     status: String,
+    priority: String,
     job_created_at: chrono::DateTime<chrono::Utc>,
+    error: Option<String>,
+    failure_reason: Option<String>,
+    started_at: Option<chrono::DateTime<chrono::Utc>>,
+    finished_at: Option<chrono::DateTime<chrono::Utc>>,
 
     // From Document
     document_name: String,
@@ -29,6 +41,23 @@ struct JobDetailsResponse {
 
     // From JobStageOutput
     stage_outputs: Vec<JobStageOutput>,
+
+    // From JobStageRun
+    stage_runs: Vec<JobStageRun>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RetryParams {
+    /// Reuse outputs of stages that already succeeded instead of starting over.
+    resume: Option<bool>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RerunParams {
+    /// Pipeline to use instead of the job's original one.
+    pipeline_id: Option<Uuid>,
+    /// Priority of the new job; defaults to the pipeline's priority.
+    priority: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -38,10 +67,46 @@ struct JobEvent {
     status: String,
 }
 
+/// Reject callers outside `org_id`, unless they are global admins.
+fn authorize_org(user: &ApiUser, org_id: Uuid) -> Result<(), HttpResponse> {
+    if user.can_view_org(org_id) {
+        return Ok(());
+    }
+    log::warn!(
+        "Unauthorized attempt to access jobs of org {} by user {} (org {})",
+        org_id,
+        user.user_id,
+        user.org_id
+    );
+    Err(HttpResponse::Unauthorized()
+        .json(serde_json::json!({"error": "You are not authorized to view these jobs"})))
+}
+
+/// Fetch a job the caller may view.
+async fn find_visible_job(
+    pool: &PgPool,
+    job_id: Uuid,
+    user: &ApiUser,
+) -> Result<AnalysisJob, HttpResponse> {
+    match AnalysisJob::find(pool, job_id).await {
+        Ok(job) => authorize_org(user, job.org_id).map(|_| job),
+        Err(sqlx::Error::RowNotFound) => {
+            Err(ApiError::new("Job not found", StatusCode::NOT_FOUND).error_response())
+        }
+        Err(e) => Err(ApiError::from_db("Failed to fetch job", e).error_response()),
+    }
+}
+
 /// Return all jobs for an organization.
 #[get("/jobs/{org_id}")]
-#[tracing::instrument(skip(pool))]
-async fn list_jobs(path: web::Path<Uuid>, pool: web::Data<PgPool>) -> HttpResponse {
+#[tracing::instrument(skip(pool, user))]
+async fn list_jobs(path: web::Path<Uuid>, user: ApiUser, pool: web::Data<PgPool>) -> HttpResponse {
+    if let Err(resp) = user.require_scope(SCOPE_JOBS_READ) {
+        return resp;
+    }
+    if let Err(resp) = authorize_org(&user, *path) {
+        return resp;
+    }
     match AnalysisJob::find_by_org(pool.as_ref(), *path).await {
         Ok(list) => HttpResponse::Ok().json(list),
         Err(_) => {
@@ -52,10 +117,20 @@ async fn list_jobs(path: web::Path<Uuid>, pool: web::Data<PgPool>) -> HttpRespon
 
 /// Server-sent events stream sending job status updates.
 #[get("/jobs/{id}/events")]
-#[tracing::instrument(skip(pool))]
-async fn job_events(path: web::Path<Uuid>, pool: web::Data<PgPool>) -> Sse<ChannelStream> {
-    let (tx, rx) = sse::channel(10);
+#[tracing::instrument(skip(pool, user))]
+async fn job_events(
+    path: web::Path<Uuid>,
+    user: ApiUser,
+    pool: web::Data<PgPool>,
+) -> Either<Sse<ChannelStream>, HttpResponse> {
+    if let Err(resp) = user.require_scope(SCOPE_JOBS_READ) {
+        return Either::Right(resp);
+    }
     let job_id = *path;
+    if let Err(resp) = find_visible_job(&pool, job_id, &user).await {
+        return Either::Right(resp);
+    }
+    let (tx, rx) = sse::channel(10);
     actix_web::rt::spawn(async move {
         loop {
             match AnalysisJob::find(pool.as_ref(), job_id).await {
@@ -63,7 +138,7 @@ async fn job_events(path: web::Path<Uuid>, pool: web::Data<PgPool>) -> Sse<Chann
                     if tx.send(sse::Data::new(job.status.clone())).await.is_err() {
                         break;
                     }
-                    if job.status == "completed" || job.status == "failed" {
+                    if matches!(job.status.as_str(), "completed" | "failed" | "cancelled") {
                         break;
                     }
                 }
@@ -75,29 +150,36 @@ async fn job_events(path: web::Path<Uuid>, pool: web::Data<PgPool>) -> Sse<Chann
             actix_web::rt::time::sleep(Duration::from_secs(2)).await;
         }
     });
-    rx
+    Either::Left(rx)
+}
+
+/// Subscribe to the `job_status` channel, if Redis is configured and reachable.
+async fn subscribe_job_status() -> Option<redis::aio::PubSub> {
+    let redis_url = std::env::var("REDIS_URL").ok()?;
+    let client = redis::Client::open(redis_url).ok()?;
+    let mut conn = client.get_async_connection().await.ok()?.into_pubsub();
+    conn.subscribe("job_status").await.ok()?;
+    Some(conn)
 }
 
 /// Stream status events for a single job via Redis Pub/Sub.
 #[get("/jobs/{id}/detail_events")]
-#[tracing::instrument]
-async fn job_detail_events(path: web::Path<Uuid>) -> Sse<ChannelStream> {
+#[tracing::instrument(skip(pool, user))]
+async fn job_detail_events(
+    path: web::Path<Uuid>,
+    user: ApiUser,
+    pool: web::Data<PgPool>,
+) -> Either<Sse<ChannelStream>, HttpResponse> {
+    if let Err(resp) = user.require_scope(SCOPE_JOBS_READ) {
+        return Either::Right(resp);
+    }
     let job_id = *path;
-    let redis_url = match std::env::var("REDIS_URL") {
-        Ok(u) => u,
-        Err(_) => return sse::channel(0).1,
-    };
-    let client = match redis::Client::open(redis_url) {
-        Ok(c) => c,
-        Err(_) => return sse::channel(0).1,
-    };
-    let mut conn = match client.get_async_connection().await {
-        Ok(c) => c.into_pubsub(),
-        Err(_) => return sse::channel(0).1,
-    };
-    if conn.subscribe("job_status").await.is_err() {
-        return sse::channel(0).1;
+    if let Err(resp) = find_visible_job(&pool, job_id, &user).await {
+        return Either::Right(resp);
     }
+    let Some(mut conn) = subscribe_job_status().await else {
+        return Either::Left(sse::channel(0).1);
+    };
     let (tx, rx) = sse::channel(10);
     actix_web::rt::spawn(async move {
         let mut stream = conn.on_message();
@@ -113,29 +195,26 @@ async fn job_detail_events(path: web::Path<Uuid>) -> Sse<ChannelStream> {
             }
         }
     });
-    rx
+    Either::Left(rx)
 }
 
 /// Stream job status events for an organization via Redis Pub/Sub.
 #[get("/jobs/events/{org_id}")]
-#[tracing::instrument]
-async fn org_job_events(path: web::Path<Uuid>) -> Sse<ChannelStream> {
+#[tracing::instrument(skip(user))]
+async fn org_job_events(
+    path: web::Path<Uuid>,
+    user: ApiUser,
+) -> Either<Sse<ChannelStream>, HttpResponse> {
+    if let Err(resp) = user.require_scope(SCOPE_JOBS_READ) {
+        return Either::Right(resp);
+    }
     let org_id = *path;
-    let redis_url = match std::env::var("REDIS_URL") {
-        Ok(u) => u,
-        Err(_) => return sse::channel(0).1,
-    };
-    let client = match redis::Client::open(redis_url) {
-        Ok(c) => c,
-        Err(_) => return sse::channel(0).1,
-    };
-    let mut conn = match client.get_async_connection().await {
-        Ok(c) => c.into_pubsub(),
-        Err(_) => return sse::channel(0).1,
-    };
-    if conn.subscribe("job_status").await.is_err() {
-        return sse::channel(0).1;
+    if let Err(resp) = authorize_org(&user, org_id) {
+        return Either::Right(resp);
     }
+    let Some(mut conn) = subscribe_job_status().await else {
+        return Either::Left(sse::channel(0).1);
+    };
     let (tx, rx) = sse::channel(10);
     actix_web::rt::spawn(async move {
         let mut stream = conn.on_message();
@@ -151,7 +230,7 @@ async fn org_job_events(path: web::Path<Uuid>) -> Sse<ChannelStream> {
             }
         }
     });
-    rx
+    Either::Left(rx)
 }
 
 /// Retrieve job metadata along with document and pipeline info.
@@ -159,9 +238,12 @@ async fn org_job_events(path: web::Path<Uuid>) -> Sse<ChannelStream> {
 #[tracing::instrument(skip(pool, user))]
 async fn get_job_details(
     path: web::Path<Uuid>,
-    user: AuthUser, // For authorization
+    user: ApiUser,
     pool: web::Data<PgPool>,
 ) -> HttpResponse {
+    if let Err(resp) = user.require_scope(SCOPE_JOBS_READ) {
+        return resp;
+    }
     let job_id = path.into_inner();
 
     // 1. Fetch AnalysisJob
@@ -243,22 +325,210 @@ async fn get_job_details(
         }
     };
 
-    // 6. Construct and return response
+    // 6. Fetch JobStageRuns
+    let stage_runs = match JobStageRun::find_by_job_id(pool.as_ref(), job_id).await {
+        Ok(runs) => runs,
+        Err(e) => {
+            log::error!("Failed to fetch stage runs for job {}: {:?}", job_id, e);
+            vec![]
+        }
+    };
+
+    // 7. Construct and return response
     let response = JobDetailsResponse {
         id: job.id,
         org_id: job.org_id,
         document_id: job.document_id,
         pipeline_id: job.pipeline_id,
         status: job.status,
+        priority: job.priority,
         job_created_at: job.created_at, // Assuming AnalysisJob has created_at
+        error: job.error,
+        failure_reason: job.failure_reason,
+        started_at: job.started_at,
+        finished_at: job.finished_at,
         document_name: document.display_name, // Changed from document.filename
         pipeline_name: pipeline.name,
         stage_outputs,
+        stage_runs,
     };
 
     HttpResponse::Ok().json(response)
 }
 
+/// Fetch a job and make sure it belongs to the user's organization.
+async fn find_org_job(
+    pool: &PgPool,
+    job_id: Uuid,
+    user: &AuthUser,
+) -> Result<AnalysisJob, HttpResponse> {
+    match AnalysisJob::find(pool, job_id).await {
+        Ok(job) if job.org_id == user.org_id => Ok(job),
+        Ok(_) => {
+            log::warn!(
+                "Unauthorized attempt to modify job {} by user {} (org {})",
+                job_id,
+                user.user_id,
+                user.org_id
+            );
+            Err(ApiError::new("Unauthorized", StatusCode::UNAUTHORIZED).error_response())
+        }
+        Err(sqlx::Error::RowNotFound) => {
+            Err(ApiError::new("Job not found", StatusCode::NOT_FOUND).error_response())
+        }
+        Err(e) => Err(ApiError::from_db("Failed to fetch job", e).error_response()),
+    }
+}
+
+/// Re-enqueue a failed or cancelled job, optionally resuming from the failed stage.
+#[post("/jobs/{job_id}/retry")]
+#[tracing::instrument(skip(pool, user))]
+async fn retry_job(
+    path: web::Path<Uuid>,
+    params: web::Query<RetryParams>,
+    user: ApiUser,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    if let Err(resp) = user.require_scope(SCOPE_JOBS_MANAGE) {
+        return resp;
+    }
+    let job_id = path.into_inner();
+    let job = match find_org_job(&pool, job_id, &user).await {
+        Ok(j) => j,
+        Err(resp) => return resp,
+    };
+    let resume = params.resume.unwrap_or(false);
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => return ApiError::from_db("Failed to retry job", e).error_response(),
+    };
+    if let Err(resp) = reserve_analysis_quota(&mut tx, job.org_id, 1, ANALYSIS_QUOTA_EXCEEDED).await
+    {
+        return resp;
+    }
+    let requeued = match AnalysisJob::requeue(&mut *tx, job_id, resume).await {
+        Ok(requeued) => tx.commit().await.map(|_| requeued),
+        Err(e) => Err(e),
+    };
+    match requeued {
+        Ok(Some(job)) => {
+            log_api_action(&pool, &user, &format!("job_retry:{}", job_id)).await;
+            enqueue_job(job.id).await;
+            HttpResponse::Ok().json(job)
+        }
+        Ok(None) => ApiError::new(
+            "Only failed or cancelled jobs can be retried",
+            StatusCode::CONFLICT,
+        )
+        .error_response(),
+        Err(e) => ApiError::from_db("Failed to retry job", e).error_response(),
+    }
+}
+
+/// Run the job's document through its pipeline again as a new job.
+///
+/// The pipeline is loaded when the job runs, so later edits are picked up.
+/// A different pipeline of the same organization can be chosen via `pipeline_id`.
+#[post("/jobs/{job_id}/rerun")]
+#[tracing::instrument(skip(pool, user))]
+async fn rerun_job(
+    path: web::Path<Uuid>,
+    params: web::Query<RerunParams>,
+    user: ApiUser,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    if let Err(resp) = user.require_scope(SCOPE_JOBS_MANAGE) {
+        return resp;
+    }
+    let job_id = path.into_inner();
+    let job = match find_org_job(&pool, job_id, &user).await {
+        Ok(j) => j,
+        Err(resp) => return resp,
+    };
+    if let Err(resp) = validate_priority(params.priority.as_deref()) {
+        return resp;
+    }
+    let pipeline_id = params.pipeline_id.unwrap_or(job.pipeline_id);
+    match sqlx::query_as::<_, Pipeline>("SELECT * FROM pipelines WHERE id=$1")
+        .bind(pipeline_id)
+        .fetch_optional(pool.as_ref())
+        .await
+    {
+        Ok(Some(p)) if p.org_id == job.org_id => {}
+        Ok(_) => {
+            return ApiError::new("Pipeline not found", StatusCode::NOT_FOUND).error_response()
+        }
+        Err(e) => return ApiError::from_db("Failed to fetch pipeline", e).error_response(),
+    }
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => return ApiError::from_db("Failed to queue analysis job", e).error_response(),
+    };
+    if let Err(resp) = reserve_analysis_quota(&mut tx, job.org_id, 1, ANALYSIS_QUOTA_EXCEEDED).await
+    {
+        return resp;
+    }
+    let new_job = NewAnalysisJob {
+        org_id: job.org_id,
+        document_id: job.document_id,
+        pipeline_id,
+        status: "pending".into(),
+    };
+    let created = match AnalysisJob::create_with_priority(
+        &mut *tx,
+        new_job,
+        params.priority.as_deref(),
+    )
+    .await
+    {
+        Ok(created) => tx.commit().await.map(|_| created),
+        Err(e) => Err(e),
+    };
+    match created {
+        Ok(created) => {
+            log_api_action(
+                &pool,
+                &user,
+                &format!("job_rerun:{}:{}", job_id, created.id),
+            )
+            .await;
+            enqueue_job(created.id).await;
+            HttpResponse::Ok().json(created)
+        }
+        Err(e) => ApiError::from_db("Failed to queue analysis job", e).error_response(),
+    }
+}
+
+/// Request cancellation of a pending or running job.
+///
+/// Pending jobs are cancelled immediately; running jobs are stopped by the
+/// worker, which aborts in-flight stages.
+#[post("/jobs/{job_id}/cancel")]
+#[tracing::instrument(skip(pool, user))]
+async fn cancel_job(path: web::Path<Uuid>, user: ApiUser, pool: web::Data<PgPool>) -> HttpResponse {
+    if let Err(resp) = user.require_scope(SCOPE_JOBS_MANAGE) {
+        return resp;
+    }
+    let job_id = path.into_inner();
+    if let Err(resp) = find_org_job(&pool, job_id, &user).await {
+        return resp;
+    }
+    match AnalysisJob::request_cancel(&pool, job_id).await {
+        Ok(Some(job)) => {
+            log_api_action(&pool, &user, &format!("job_cancel:{}", job_id)).await;
+            // Running jobs report the event once the worker stopped them
+            if job.status == "cancelled" {
+                enqueue_job_event(&pool, job.id).await;
+            }
+            HttpResponse::Ok().json(job)
+        }
+        Ok(None) => {
+            ApiError::new("Job has already finished", StatusCode::CONFLICT).error_response()
+        }
+        Err(e) => ApiError::from_db("Failed to cancel job", e).error_response(),
+    }
+}
+
 /// Register job-related endpoints on the Actix configuration.
 pub fn routes(cfg: &mut web::ServiceConfig) {
     cfg.service(list_jobs)
@@ -266,6 +536,9 @@ pub fn routes(cfg: &mut web::ServiceConfig) {
         .service(job_detail_events)
         .service(org_job_events)
         .service(get_job_details)
+        .service(retry_job)
+        .service(rerun_job)
+        .service(cancel_job)
         .service(get_stage_output_download_url);
 }
 
@@ -274,10 +547,13 @@ pub fn routes(cfg: &mut web::ServiceConfig) {
 #[tracing::instrument(skip(pool, s3_client, user))]
 async fn get_stage_output_download_url(
     path: web::Path<Uuid>, // output_id from job_stage_outputs table
-    user: AuthUser,
+    user: ApiUser,
     pool: web::Data<PgPool>,
     s3_client: web::Data<aws_sdk_s3::Client>, // Get S3 client from app data
 ) -> HttpResponse {
+    if let Err(resp) = user.require_scope(SCOPE_JOBS_READ) {
+        return resp;
+    }
     let output_id = path.into_inner();
 
     // 1. Fetch JobStageOutput record
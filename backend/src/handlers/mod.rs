@@ -7,6 +7,8 @@ pub mod pipeline;
 pub mod job;
 pub mod health;
 pub mod settings;
+pub mod webhooks;
+pub mod api_keys;
 pub mod audit;
 pub mod dashboard;
 pub mod admin; // New module
@@ -21,6 +23,8 @@ pub fn init(cfg: &mut web::ServiceConfig) {
         .configure(pipeline::routes)
         .configure(job::routes)
         .configure(settings::routes)
+        .configure(webhooks::routes)
+        .configure(api_keys::routes)
         .configure(audit::routes)
         .configure(dashboard::routes)
         .configure(admin::routes) // Add this line
@@ -1,8 +1,14 @@
 use crate::error::ApiError;
-use crate::middleware::auth::AuthUser;
-use crate::models::{NewPipeline, Pipeline};
+use crate::handlers::document::validate_priority;
+use crate::middleware::auth::ApiUser;
+use crate::models::api_key::SCOPE_PIPELINES_MANAGE;
+use crate::models::{Document, NewPipeline, OrgSettings, Pipeline};
 use crate::pipeline_validation::validate_stages;
+use crate::processing::formats::DocumentFormat;
+use crate::worker::dry_run::{self, DryRunOptions, Sample, StageStatus};
+use crate::worker::Stage;
 use actix_web::{delete, get, http::StatusCode, post, put, web, HttpResponse, ResponseError};
+use aws_sdk_s3::Client as S3Client;
 use dashmap::DashMap;
 use once_cell::sync::Lazy;
 use redis::AsyncCommands;
@@ -60,6 +66,8 @@ pub struct PipelineInput {
     pub org_id: Uuid,
     pub name: String,
     pub stages: serde_json::Value,
+    /// Default priority of the pipeline's jobs: `high`, `normal` or `bulk`.
+    pub priority: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -82,9 +90,12 @@ pub struct PaginatedPipelines {
 #[tracing::instrument(skip(data, pool, user))]
 async fn create_pipeline(
     data: web::Json<PipelineInput>,
-    user: AuthUser,
+    user: ApiUser,
     pool: web::Data<PgPool>,
 ) -> HttpResponse {
+    if let Err(resp) = user.require_scope(SCOPE_PIPELINES_MANAGE) {
+        return resp;
+    }
     // Authorization: Global admin can create for any org_id specified in data.
     // Other users can only create for their own org_id, which must match data.org_id.
     if user.role != "admin" {
@@ -103,6 +114,9 @@ async fn create_pipeline(
     if let Err(resp) = validate_stages(&data.stages) {
         return resp;
     }
+    if let Err(resp) = validate_priority(data.priority.as_deref()) {
+        return resp;
+    }
 
     // If validation passes, proceed to create the pipeline
     let new_pipeline_data = NewPipeline {
@@ -112,7 +126,8 @@ async fn create_pipeline(
         stages: data.stages.clone(), // Clone the validated Value
     };
 
-    match Pipeline::create(&pool, new_pipeline_data).await {
+    let priority = data.priority.as_deref().unwrap_or("normal");
+    match Pipeline::create_with_priority(&pool, new_pipeline_data, priority).await {
         Ok(p) => {
             cache_invalidate(data.org_id).await;
             HttpResponse::Ok().json(p)
@@ -137,9 +152,12 @@ async fn create_pipeline(
 async fn list_pipelines(
     path: web::Path<Uuid>,
     query: web::Query<ListQuery>,
-    user: AuthUser,
+    user: ApiUser,
     pool: web::Data<PgPool>,
 ) -> HttpResponse {
+    if let Err(resp) = user.require_scope(SCOPE_PIPELINES_MANAGE) {
+        return resp;
+    }
     if *path != user.org_id {
         return ApiError::new("Unauthorized", StatusCode::UNAUTHORIZED).error_response();
     }
@@ -219,9 +237,12 @@ async fn list_pipelines(
 async fn update_pipeline(
     path: web::Path<Uuid>,
     data: web::Json<PipelineInput>,
-    user: AuthUser,
+    user: ApiUser,
     pool: web::Data<PgPool>,
 ) -> HttpResponse {
+    if let Err(resp) = user.require_scope(SCOPE_PIPELINES_MANAGE) {
+        return resp;
+    }
     let pipeline_id = path.into_inner();
 
     let existing = match sqlx::query_as::<_, Pipeline>("SELECT * FROM pipelines WHERE id=$1")
@@ -258,8 +279,20 @@ async fn update_pipeline(
     if let Err(resp) = validate_stages(&data.stages) {
         return resp;
     }
+    if let Err(resp) = validate_priority(data.priority.as_deref()) {
+        return resp;
+    }
 
-    match Pipeline::update(&pool, pipeline_id, &data.name, data.stages.clone()).await {
+    let priority = data.priority.as_deref().unwrap_or(&existing.priority);
+    match Pipeline::update(
+        &pool,
+        pipeline_id,
+        &data.name,
+        data.stages.clone(),
+        priority,
+    )
+    .await
+    {
         Ok(p) => {
             cache_invalidate(existing.org_id).await;
             HttpResponse::Ok().json(p)
@@ -279,9 +312,12 @@ async fn update_pipeline(
 #[tracing::instrument(skip(pool, user))]
 async fn delete_pipeline(
     path: web::Path<Uuid>,
-    user: AuthUser,
+    user: ApiUser,
     pool: web::Data<PgPool>,
 ) -> HttpResponse {
+    if let Err(resp) = user.require_scope(SCOPE_PIPELINES_MANAGE) {
+        return resp;
+    }
     let pipeline_id = path.into_inner();
 
     let existing = match sqlx::query_as::<_, Pipeline>("SELECT * FROM pipelines WHERE id=$1")
@@ -326,9 +362,12 @@ async fn delete_pipeline(
 #[tracing::instrument(skip(pool, user))]
 async fn clone_pipeline(
     path: web::Path<Uuid>,
-    user: AuthUser,
+    user: ApiUser,
     pool: web::Data<PgPool>,
 ) -> HttpResponse {
+    if let Err(resp) = user.require_scope(SCOPE_PIPELINES_MANAGE) {
+        return resp;
+    }
     let pipeline_id = path.into_inner();
     let existing = match sqlx::query_as::<_, Pipeline>("SELECT * FROM pipelines WHERE id=$1")
         .bind(pipeline_id)
@@ -358,7 +397,7 @@ async fn clone_pipeline(
         stages: existing.stages.clone(),
     };
 
-    match Pipeline::create(&pool, new_data).await {
+    match Pipeline::create_with_priority(&pool, new_data, &existing.priority).await {
         Ok(p) => {
             cache_invalidate(existing.org_id).await;
             HttpResponse::Ok().json(p)
@@ -374,10 +413,196 @@ async fn clone_pipeline(
     }
 }
 
+#[derive(Deserialize)]
+pub struct TestRunInput {
+    /// Text standing in for the output of OCR stages.
+    pub text: Option<String>,
+    /// Uploaded document of the pipeline's organization to run on instead.
+    pub document_id: Option<Uuid>,
+    /// Recognise the document with the OCR stages' engines instead of
+    /// reading its text layer.
+    #[serde(default)]
+    pub run_ocr: bool,
+    /// Send the AI stages' requests.
+    #[serde(default)]
+    pub run_ai: bool,
+}
+
+#[derive(Deserialize)]
+pub struct UnsavedTestRunInput {
+    pub org_id: Uuid,
+    pub stages: serde_json::Value,
+    #[serde(flatten)]
+    pub sample: TestRunInput,
+}
+
+/// Run `stages` of an organization's pipeline on the sample of `input` and
+/// return each stage's outcome. No job is created and no quota is used.
+async fn test_run(
+    pool: &PgPool,
+    s3: &S3Client,
+    org_id: Uuid,
+    stages: &serde_json::Value,
+    input: &TestRunInput,
+) -> HttpResponse {
+    let stages: Vec<Stage> = match serde_json::from_value(stages.clone()) {
+        Ok(stages) => stages,
+        Err(e) => {
+            return HttpResponse::BadRequest()
+                .json(serde_json::json!({"error": format!("Invalid stages: {}", e)}));
+        }
+    };
+    let org_settings = OrgSettings::find(pool, org_id).await.ok();
+    let options = DryRunOptions {
+        run_ocr: input.run_ocr,
+        run_ai: input.run_ai,
+    };
+
+    let limit = dry_run::time_limit();
+    let run = |sample| {
+        tokio::time::timeout(
+            limit,
+            dry_run::run(&stages, org_settings.as_ref(), sample, options),
+        )
+    };
+
+    let results = match (&input.text, input.document_id) {
+        (Some(text), None) if text.len() > dry_run::MAX_TEXT_BYTES => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Sample text is limited to {} bytes.", dry_run::MAX_TEXT_BYTES)
+            }));
+        }
+        (Some(text), None) => run(Sample::Text(text)).await,
+        (None, Some(document_id)) => {
+            let doc = match sqlx::query_as::<_, Document>("SELECT * FROM documents WHERE id=$1")
+                .bind(document_id)
+                .fetch_optional(pool)
+                .await
+            {
+                Ok(Some(d)) if d.org_id == org_id => d,
+                Ok(_) => {
+                    return HttpResponse::NotFound()
+                        .json(serde_json::json!({"error": "Document not found"}));
+                }
+                Err(e) => {
+                    return ApiError::from_db("Failed to fetch document.", e).error_response();
+                }
+            };
+            if doc.pages > dry_run::MAX_PAGES {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": format!("Test runs are limited to documents of {} pages.", dry_run::MAX_PAGES)
+                }));
+            }
+            let extension =
+                DocumentFormat::from_filename(&doc.filename).map_or("pdf", |f| f.extension());
+            let local = std::env::temp_dir().join(format!("{}-test.{}", Uuid::new_v4(), extension));
+            let bucket = std::env::var("S3_BUCKET").unwrap_or_else(|_| "uploads".into());
+            if let Err(e) =
+                crate::processing::ocr::download_pdf(s3, &bucket, &doc.filename, &local).await
+            {
+                log::error!(
+                    "Failed to download document {} for test run: {:?}",
+                    doc.id,
+                    e
+                );
+                return ApiError::new(
+                    "Failed to download document",
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+                .error_response();
+            }
+            let results = run(Sample::Document(&local)).await;
+            let _ = tokio::fs::remove_file(&local).await;
+            results
+        }
+        _ => {
+            return HttpResponse::BadRequest()
+                .json(serde_json::json!({"error": "Provide either 'text' or 'document_id'."}));
+        }
+    };
+
+    match results {
+        Ok(Ok(stages)) => {
+            let failed = stages.iter().any(|s| s.status == StageStatus::Failed);
+            HttpResponse::Ok().json(serde_json::json!({
+                "status": if failed { "failed" } else { "succeeded" },
+                "stages": stages,
+            }))
+        }
+        Ok(Err(e)) => HttpResponse::BadRequest().json(serde_json::json!({"error": e.to_string()})),
+        Err(_) => HttpResponse::GatewayTimeout().json(serde_json::json!({
+            "error": format!("Test run did not finish within {}s.", limit.as_secs())
+        })),
+    }
+}
+
+/// Run a saved pipeline on pasted text or an uploaded document.
+#[post("/pipelines/{id}/test")]
+#[tracing::instrument(skip(data, pool, s3, user))]
+async fn test_pipeline(
+    path: web::Path<Uuid>,
+    data: web::Json<TestRunInput>,
+    user: ApiUser,
+    pool: web::Data<PgPool>,
+    s3: web::Data<S3Client>,
+) -> HttpResponse {
+    if let Err(resp) = user.require_scope(SCOPE_PIPELINES_MANAGE) {
+        return resp;
+    }
+    let pipeline_id = path.into_inner();
+    let existing = match sqlx::query_as::<_, Pipeline>("SELECT * FROM pipelines WHERE id=$1")
+        .bind(pipeline_id)
+        .fetch_one(pool.as_ref())
+        .await
+    {
+        Ok(p) => p,
+        Err(sqlx::Error::RowNotFound) => {
+            return ApiError::new("Pipeline not found", StatusCode::NOT_FOUND).error_response();
+        }
+        Err(_) => {
+            return ApiError::new(
+                "Failed to fetch pipeline",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+            .error_response();
+        }
+    };
+
+    if user.role != "admin" && existing.org_id != user.org_id {
+        return ApiError::new("Unauthorized", StatusCode::UNAUTHORIZED).error_response();
+    }
+
+    test_run(&pool, &s3, existing.org_id, &existing.stages, &data).await
+}
+
+/// Run stages that have not been saved yet, e.g. while editing a pipeline.
+#[post("/pipelines/test")]
+#[tracing::instrument(skip(data, pool, s3, user))]
+async fn test_unsaved_pipeline(
+    data: web::Json<UnsavedTestRunInput>,
+    user: ApiUser,
+    pool: web::Data<PgPool>,
+    s3: web::Data<S3Client>,
+) -> HttpResponse {
+    if let Err(resp) = user.require_scope(SCOPE_PIPELINES_MANAGE) {
+        return resp;
+    }
+    if user.role != "admin" && data.org_id != user.org_id {
+        return ApiError::new("Unauthorized", StatusCode::UNAUTHORIZED).error_response();
+    }
+    if let Err(resp) = validate_stages(&data.stages) {
+        return resp;
+    }
+
+    test_run(&pool, &s3, data.org_id, &data.stages, &data.sample).await
+}
+
 pub fn routes(cfg: &mut web::ServiceConfig) {
     cfg.service(create_pipeline)
         .service(list_pipelines)
         .service(update_pipeline)
         .service(delete_pipeline)
-        .service(clone_pipeline);
+        .service(clone_pipeline)
+        .service(test_unsaved_pipeline)
+        .service(test_pipeline);
 }
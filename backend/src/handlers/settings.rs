@@ -1,5 +1,6 @@
 use crate::middleware::auth::AuthUser;
 use crate::models::OrgSettings;
+use crate::processing::ocr_engine;
 
 use crate::error::ApiError;
 use actix_web::{get, http::StatusCode, post, web, HttpResponse, ResponseError};
@@ -152,6 +153,22 @@ async fn update_settings(
         }
     }
 
+    if let Some(ref engine) = incoming_settings.ocr_engine {
+        if !engine.is_empty() && ocr_engine::get(engine).is_none() {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!(
+                    "Unknown OCR engine. Available engines: {}.",
+                    ocr_engine::names().join(", ")
+                )
+            }));
+        }
+    }
+
+    if incoming_settings.max_concurrent_jobs.is_some_and(|n| n < 1) {
+        return HttpResponse::BadRequest()
+            .json(serde_json::json!({"error": "Maximum concurrent jobs must be at least 1."}));
+    }
+
     // Now, incoming_settings contains the new values, or original values for keys if "********" was passed.
     match OrgSettings::update(&pool, incoming_settings).await {
         Ok(updated_settings_from_db) => {
@@ -0,0 +1,242 @@
+use crate::error::ApiError;
+use crate::middleware::auth::AuthUser;
+use crate::models::{NewWebhookEndpoint, WebhookDelivery, WebhookEndpoint, WEBHOOK_EVENTS};
+use crate::processing::webhook::check_destination;
+use crate::utils::log_action;
+use actix_web::{delete, get, http::StatusCode, post, web, HttpResponse, ResponseError};
+use rand::Rng;
+use serde::Deserialize;
+use sqlx::PgPool;
+use url::Url;
+use uuid::Uuid;
+
+/// Number of deliveries returned by the delivery log.
+const DELIVERY_LOG_LIMIT: i64 = 100;
+
+#[derive(Deserialize)]
+pub struct WebhookInput {
+    pub url: String,
+    /// Generated when omitted; only returned when the endpoint is created.
+    pub secret: Option<String>,
+    pub event_types: Vec<String>,
+}
+
+/// Only global admins and organization admins of `org_id` manage webhooks.
+fn authorize(user: &AuthUser, org_id: Uuid) -> Result<(), HttpResponse> {
+    if user.can_manage_org(org_id) {
+        return Ok(());
+    }
+    log::warn!(
+        "Unauthorized attempt to manage webhooks of org {} by user {} (role: {})",
+        org_id,
+        user.user_id,
+        user.role
+    );
+    Err(HttpResponse::Forbidden().json(
+        serde_json::json!({"error": "You do not have permission to manage webhooks for this organization."}),
+    ))
+}
+
+fn masked(mut endpoint: WebhookEndpoint) -> WebhookEndpoint {
+    endpoint.secret = "********".to_string();
+    endpoint
+}
+
+/// Fetch an endpoint and make sure it belongs to `org_id`.
+async fn find_org_endpoint(
+    pool: &PgPool,
+    org_id: Uuid,
+    webhook_id: Uuid,
+) -> Result<WebhookEndpoint, HttpResponse> {
+    match WebhookEndpoint::find(pool, webhook_id).await {
+        Ok(endpoint) if endpoint.org_id == org_id => Ok(endpoint),
+        Ok(_) | Err(sqlx::Error::RowNotFound) => {
+            Err(ApiError::new("Webhook not found", StatusCode::NOT_FOUND).error_response())
+        }
+        Err(e) => Err(ApiError::from_db("Failed to fetch webhook", e).error_response()),
+    }
+}
+
+#[get("/settings/{org_id}/webhooks")]
+#[tracing::instrument(skip(pool, user))]
+async fn list_webhooks(
+    path: web::Path<Uuid>,
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let org_id = path.into_inner();
+    if let Err(resp) = authorize(&user, org_id) {
+        return resp;
+    }
+    match WebhookEndpoint::list_for_org(&pool, org_id).await {
+        Ok(endpoints) => {
+            HttpResponse::Ok().json(endpoints.into_iter().map(masked).collect::<Vec<_>>())
+        }
+        Err(e) => ApiError::from_db("Failed to fetch webhooks", e).error_response(),
+    }
+}
+
+/// Register an endpoint. The response is the only one containing the secret.
+#[post("/settings/{org_id}/webhooks")]
+#[tracing::instrument(skip(payload, pool, user))]
+async fn create_webhook(
+    path: web::Path<Uuid>,
+    payload: web::Json<WebhookInput>,
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let org_id = path.into_inner();
+    if let Err(resp) = authorize(&user, org_id) {
+        return resp;
+    }
+    let input = payload.into_inner();
+    match Url::parse(&input.url) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {}
+        _ => {
+            return HttpResponse::BadRequest()
+                .json(serde_json::json!({"error": "Webhook URL must be an http or https URL."}))
+        }
+    }
+    if let Err(e) = check_destination(&input.url).await {
+        return HttpResponse::BadRequest()
+            .json(serde_json::json!({"error": format!("Webhook URL is not allowed: {}", e)}));
+    }
+    let mut event_types = input.event_types;
+    event_types.sort();
+    event_types.dedup();
+    if event_types.is_empty()
+        || event_types
+            .iter()
+            .any(|e| !WEBHOOK_EVENTS.contains(&e.as_str()))
+    {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Event types must be a non-empty list of: {}.", WEBHOOK_EVENTS.join(", "))
+        }));
+    }
+    let secret = match input.secret {
+        Some(s) if s.trim().is_empty() => {
+            return HttpResponse::BadRequest()
+                .json(serde_json::json!({"error": "Webhook secret cannot be empty."}))
+        }
+        Some(s) => s,
+        None => rand::thread_rng()
+            .sample_iter(&rand::distributions::Alphanumeric)
+            .take(32)
+            .map(char::from)
+            .collect(),
+    };
+    let new = NewWebhookEndpoint {
+        org_id,
+        url: input.url,
+        secret,
+        event_types,
+    };
+    match WebhookEndpoint::create(&pool, new).await {
+        Ok(endpoint) => {
+            log_action(
+                &pool,
+                org_id,
+                user.user_id,
+                &format!("webhook_create:{}", endpoint.id),
+            )
+            .await;
+            HttpResponse::Ok().json(endpoint)
+        }
+        Err(e) => ApiError::from_db("Failed to create webhook", e).error_response(),
+    }
+}
+
+#[delete("/settings/{org_id}/webhooks/{webhook_id}")]
+#[tracing::instrument(skip(pool, user))]
+async fn delete_webhook(
+    path: web::Path<(Uuid, Uuid)>,
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let (org_id, webhook_id) = path.into_inner();
+    if let Err(resp) = authorize(&user, org_id) {
+        return resp;
+    }
+    if let Err(resp) = find_org_endpoint(&pool, org_id, webhook_id).await {
+        return resp;
+    }
+    match WebhookEndpoint::delete(&pool, webhook_id).await {
+        Ok(()) => {
+            log_action(
+                &pool,
+                org_id,
+                user.user_id,
+                &format!("webhook_delete:{}", webhook_id),
+            )
+            .await;
+            HttpResponse::NoContent().finish()
+        }
+        Err(e) => ApiError::from_db("Failed to delete webhook", e).error_response(),
+    }
+}
+
+/// Delivery log of an endpoint, newest first.
+#[get("/settings/{org_id}/webhooks/{webhook_id}/deliveries")]
+#[tracing::instrument(skip(pool, user))]
+async fn list_deliveries(
+    path: web::Path<(Uuid, Uuid)>,
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let (org_id, webhook_id) = path.into_inner();
+    if let Err(resp) = authorize(&user, org_id) {
+        return resp;
+    }
+    if let Err(resp) = find_org_endpoint(&pool, org_id, webhook_id).await {
+        return resp;
+    }
+    match WebhookDelivery::list_for_endpoint(&pool, webhook_id, DELIVERY_LOG_LIMIT).await {
+        Ok(deliveries) => HttpResponse::Ok().json(deliveries),
+        Err(e) => ApiError::from_db("Failed to fetch webhook deliveries", e).error_response(),
+    }
+}
+
+/// Send the payload of an earlier delivery again as a new delivery.
+#[post("/settings/{org_id}/webhooks/{webhook_id}/deliveries/{delivery_id}/redeliver")]
+#[tracing::instrument(skip(pool, user))]
+async fn redeliver(
+    path: web::Path<(Uuid, Uuid, Uuid)>,
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let (org_id, webhook_id, delivery_id) = path.into_inner();
+    if let Err(resp) = authorize(&user, org_id) {
+        return resp;
+    }
+    if let Err(resp) = find_org_endpoint(&pool, org_id, webhook_id).await {
+        return resp;
+    }
+    match WebhookDelivery::find(&pool, delivery_id).await {
+        Ok(delivery) if delivery.endpoint_id == webhook_id => {}
+        Ok(_) | Err(sqlx::Error::RowNotFound) => {
+            return ApiError::new("Delivery not found", StatusCode::NOT_FOUND).error_response()
+        }
+        Err(e) => return ApiError::from_db("Failed to fetch delivery", e).error_response(),
+    }
+    match WebhookDelivery::redeliver(&pool, delivery_id).await {
+        Ok(delivery) => {
+            log_action(
+                &pool,
+                org_id,
+                user.user_id,
+                &format!("webhook_redeliver:{}:{}", delivery_id, delivery.id),
+            )
+            .await;
+            HttpResponse::Ok().json(delivery)
+        }
+        Err(e) => ApiError::from_db("Failed to redeliver webhook", e).error_response(),
+    }
+}
+
+pub fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_webhooks)
+        .service(create_webhook)
+        .service(delete_webhook)
+        .service(list_deliveries)
+        .service(redeliver);
+}
@@ -9,3 +9,4 @@ pub mod config;
 pub mod error;
 pub mod metrics;
 pub mod pipeline_validation;
+pub mod queue;
@@ -1,8 +1,16 @@
-use actix_web::{FromRequest, HttpRequest, dev::Payload, error::ErrorUnauthorized};
-use futures_util::future::{ready, Ready};
+use actix_web::{FromRequest, HttpRequest, HttpResponse, dev::Payload, error::ErrorUnauthorized, web};
+use futures_util::future::{ready, LocalBoxFuture, Ready};
+use sqlx::PgPool;
+use std::ops::Deref;
 use uuid::Uuid;
 
 use crate::middleware::jwt::verify_jwt;
+use crate::models::ApiKey;
+
+/// Header carrying an organization API key.
+pub const API_KEY_HEADER: &str = "X-API-Key";
+/// Role of the [`AuthUser`] of requests authenticated with an API key.
+pub const API_KEY_ROLE: &str = "api_key";
 
 pub struct AuthUser {
     pub user_id: Uuid,
@@ -10,6 +18,18 @@ pub struct AuthUser {
     pub role: String,
 }
 
+impl AuthUser {
+    /// Global admins manage every organization, organization admins their own.
+    pub fn can_manage_org(&self, org_id: Uuid) -> bool {
+        self.role == "admin" || (self.role == "org_admin" && self.org_id == org_id)
+    }
+
+    /// Global admins see every organization, everyone else only their own.
+    pub fn can_view_org(&self, org_id: Uuid) -> bool {
+        self.role == "admin" || self.org_id == org_id
+    }
+}
+
 impl FromRequest for AuthUser {
     type Error = actix_web::Error;
     type Future = Ready<Result<Self, Self::Error>>;
@@ -35,3 +55,77 @@ impl FromRequest for AuthUser {
         ready(Err(ErrorUnauthorized("Unauthorized")))
     }
 }
+
+/// Caller of endpoints that accept organization API keys besides user logins.
+///
+/// Requests with a valid JWT behave as with [`AuthUser`]. Otherwise the key in
+/// the `X-API-Key` header is looked up; such callers act for the user who
+/// created the key, are limited to its organization and have to hold the scope
+/// handlers ask for with [`ApiUser::require_scope`].
+pub struct ApiUser {
+    user: AuthUser,
+    pub api_key: Option<ApiKey>,
+}
+
+impl ApiUser {
+    pub fn api_key_id(&self) -> Option<Uuid> {
+        self.api_key.as_ref().map(|k| k.id)
+    }
+
+    /// Reject API keys without `scope`. Logged-in users are not restricted.
+    pub fn require_scope(&self, scope: &str) -> Result<(), HttpResponse> {
+        match &self.api_key {
+            Some(key) if !key.has_scope(scope) => {
+                log::warn!("API key {} used without the '{}' scope", key.id, scope);
+                Err(HttpResponse::Forbidden().json(
+                    serde_json::json!({"error": format!("API key lacks the '{}' scope.", scope)}),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Deref for ApiUser {
+    type Target = AuthUser;
+
+    fn deref(&self) -> &AuthUser {
+        &self.user
+    }
+}
+
+impl FromRequest for ApiUser {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        if let Ok(user) = AuthUser::from_request(req, payload).into_inner() {
+            return Box::pin(ready(Ok(ApiUser { user, api_key: None })));
+        }
+        let key = req
+            .headers()
+            .get(API_KEY_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let pool = req.app_data::<web::Data<PgPool>>().cloned();
+        Box::pin(async move {
+            let (Some(key), Some(pool)) = (key, pool) else {
+                return Err(ErrorUnauthorized("Unauthorized"));
+            };
+            match ApiKey::authenticate(&pool, &key).await {
+                Ok(Some(api_key)) => Ok(ApiUser {
+                    user: AuthUser {
+                        user_id: api_key.created_by,
+                        org_id: api_key.org_id,
+                        role: API_KEY_ROLE.to_string(),
+                    },
+                    api_key: Some(api_key),
+                }),
+                Ok(None) => Err(ErrorUnauthorized("Unauthorized")),
+                Err(e) => {
+                    log::error!("Failed to look up API key: {:?}", e);
+                    Err(actix_web::error::ErrorInternalServerError("Failed to authenticate"))
+                }
+            }
+        })
+    }
+}
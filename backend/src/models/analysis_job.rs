@@ -1,8 +1,16 @@
 use serde::Serialize;
-use sqlx::{FromRow, PgPool};
+use sqlx::{FromRow, PgExecutor, PgPool};
 use uuid::Uuid;
 
-#[derive(Serialize, FromRow, Debug)]
+/// Job priorities, highest first. Workers always drain higher priorities first.
+pub const JOB_PRIORITIES: [&str; 3] = ["high", "normal", "bulk"];
+
+/// Whether `priority` is one of [`JOB_PRIORITIES`].
+pub fn is_valid_priority(priority: &str) -> bool {
+    JOB_PRIORITIES.contains(&priority)
+}
+
+#[derive(Serialize, FromRow, Debug, Default)]
 pub struct AnalysisJob {
     pub id: Uuid,
     pub org_id: Uuid,
@@ -10,6 +18,26 @@ pub struct AnalysisJob {
     pub pipeline_id: Uuid,
     pub status: String,
     pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Error message of the stage that failed the job.
+    pub error: Option<String>,
+    /// Machine readable failure reason such as `ocr_failed`.
+    pub failure_reason: Option<String>,
+    /// When a worker started processing the job.
+    pub started_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// When the job completed or failed.
+    pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Set by `POST /jobs/{id}/cancel`; the worker stops the job when it sees it.
+    pub cancel_requested: bool,
+    /// Reuse outputs of stages that succeeded in a previous run of this job.
+    pub resume: bool,
+    /// Number of times a worker claimed the job.
+    pub attempts: i32,
+    /// Worker currently holding the job.
+    pub lease_owner: Option<String>,
+    /// The job is handed to another worker when the lease is not renewed in time.
+    pub lease_expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// `high`, `normal` or `bulk`.
+    pub priority: String,
 }
 
 pub struct NewAnalysisJob {
@@ -28,20 +56,36 @@ pub struct JobWithNames {
     pub pipeline_id: Uuid,
     pub status: String,
     pub created_at: chrono::DateTime<chrono::Utc>,
+    pub priority: String,
     pub document_name: String,
     pub pipeline_name: String,
 }
 
 impl AnalysisJob {
     pub async fn create(pool: &PgPool, new: NewAnalysisJob) -> sqlx::Result<AnalysisJob> {
-        sqlx::query_as::<_, AnalysisJob>("INSERT INTO analysis_jobs (id, org_id, document_id, pipeline_id, status) VALUES ($1,$2,$3,$4,$5) RETURNING *")
-            .bind(Uuid::new_v4())
-            .bind(new.org_id)
-            .bind(new.document_id)
-            .bind(new.pipeline_id)
-            .bind(new.status)
-            .fetch_one(pool)
-            .await
+        Self::create_with_priority(pool, new, None).await
+    }
+
+    /// Insert a job with an explicit priority. Without one the job inherits the
+    /// priority of its pipeline.
+    pub async fn create_with_priority(
+        executor: impl PgExecutor<'_>,
+        new: NewAnalysisJob,
+        priority: Option<&str>,
+    ) -> sqlx::Result<AnalysisJob> {
+        sqlx::query_as::<_, AnalysisJob>(
+            "INSERT INTO analysis_jobs (id, org_id, document_id, pipeline_id, status, priority) \
+             VALUES ($1,$2,$3,$4,$5, COALESCE($6, (SELECT priority FROM pipelines WHERE id=$4), 'normal')) \
+             RETURNING *",
+        )
+        .bind(Uuid::new_v4())
+        .bind(new.org_id)
+        .bind(new.document_id)
+        .bind(new.pipeline_id)
+        .bind(new.status)
+        .bind(priority)
+        .fetch_one(executor)
+        .await
     }
 
     pub async fn next_pending(pool: &PgPool) -> sqlx::Result<Option<AnalysisJob>> {
@@ -52,6 +96,142 @@ impl AnalysisJob {
         .await
     }
 
+    /// Atomically claim the next pending job for `worker_id`.
+    ///
+    /// Higher priorities are served first. Within a priority the job of the
+    /// organization with the fewest running jobs wins, so one tenant's backlog
+    /// cannot starve the others, and organizations at their
+    /// `max_concurrent_jobs` limit are passed over. Uses `FOR UPDATE SKIP
+    /// LOCKED`, so concurrent workers never claim the same job, and an advisory
+    /// lock per organization, so concurrent claims cannot exceed its limit. The
+    /// claim holds a lease that must be renewed with [`Self::heartbeat`].
+    pub async fn claim_next(
+        pool: &PgPool,
+        worker_id: &str,
+        lease_secs: u64,
+    ) -> sqlx::Result<Option<AnalysisJob>> {
+        // Organizations found at their limit once the lock was held
+        let mut full_orgs: Vec<Uuid> = Vec::new();
+        loop {
+            let mut tx = pool.begin().await?;
+            let candidate: Option<(Uuid, Uuid)> = sqlx::query_as(
+                r#"
+                WITH running AS (
+                    SELECT org_id, COUNT(*) AS jobs FROM analysis_jobs
+                    WHERE status='in_progress' GROUP BY org_id
+                )
+                SELECT j.id, j.org_id FROM analysis_jobs j
+                LEFT JOIN running r ON r.org_id = j.org_id
+                LEFT JOIN org_settings s ON s.org_id = j.org_id
+                WHERE j.status='pending' AND NOT (j.org_id = ANY($1))
+                  AND (s.max_concurrent_jobs IS NULL OR COALESCE(r.jobs, 0) < s.max_concurrent_jobs)
+                ORDER BY CASE j.priority WHEN 'high' THEN 0 WHEN 'normal' THEN 1 ELSE 2 END,
+                    COALESCE(r.jobs, 0), j.created_at
+                LIMIT 1
+                FOR UPDATE OF j SKIP LOCKED
+                "#,
+            )
+            .bind(&full_orgs)
+            .fetch_optional(&mut *tx)
+            .await?;
+            let Some((job_id, org_id)) = candidate else {
+                return Ok(None);
+            };
+
+            // Held until commit, so the running jobs counted below include
+            // every claim made for the organization in the meantime.
+            sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1::text))")
+                .bind(org_id)
+                .execute(&mut *tx)
+                .await?;
+            let claimed = sqlx::query_as::<_, AnalysisJob>(
+                r#"
+                UPDATE analysis_jobs SET status='in_progress', attempts=attempts+1,
+                    lease_owner=$1, lease_expires_at=NOW() + make_interval(secs => $2),
+                    started_at=NOW(), finished_at=NULL, error=NULL, failure_reason=NULL
+                WHERE id=$3 AND NOT EXISTS (
+                    SELECT 1 FROM org_settings s
+                    WHERE s.org_id=$4 AND s.max_concurrent_jobs IS NOT NULL
+                      AND s.max_concurrent_jobs <= (
+                          SELECT COUNT(*) FROM analysis_jobs
+                          WHERE org_id=$4 AND status='in_progress'
+                      )
+                )
+                RETURNING *
+                "#,
+            )
+            .bind(worker_id)
+            .bind(lease_secs as f64)
+            .bind(job_id)
+            .bind(org_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+            match claimed {
+                Some(job) => {
+                    tx.commit().await?;
+                    return Ok(Some(job));
+                }
+                None => full_orgs.push(org_id),
+            }
+        }
+    }
+
+    /// Number of pending jobs per priority.
+    pub async fn pending_counts(pool: &PgPool) -> sqlx::Result<Vec<(String, i64)>> {
+        sqlx::query_as(
+            "SELECT priority, COUNT(*) FROM analysis_jobs WHERE status='pending' GROUP BY priority",
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Extend the lease of a running job. Returns `false` when the worker no
+    /// longer holds it, e.g. because the reaper handed the job to another worker.
+    pub async fn heartbeat(
+        pool: &PgPool,
+        id: Uuid,
+        worker_id: &str,
+        lease_secs: u64,
+    ) -> sqlx::Result<bool> {
+        let res = sqlx::query(
+            "UPDATE analysis_jobs SET lease_expires_at=NOW() + make_interval(secs => $3) \
+             WHERE id=$1 AND lease_owner=$2 AND status='in_progress'",
+        )
+        .bind(id)
+        .bind(worker_id)
+        .bind(lease_secs as f64)
+        .execute(pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Release running jobs whose lease expired because their worker died.
+    ///
+    /// Jobs are put back to `pending`, or failed with `worker_lost` once they
+    /// were claimed `max_attempts` times. Returns the affected jobs.
+    pub async fn reap_expired_leases(
+        pool: &PgPool,
+        max_attempts: i32,
+    ) -> sqlx::Result<Vec<AnalysisJob>> {
+        sqlx::query_as::<_, AnalysisJob>(
+            r#"
+            UPDATE analysis_jobs SET
+                status = CASE WHEN attempts >= $1 THEN 'failed' ELSE 'pending' END,
+                failure_reason = CASE WHEN attempts >= $1 THEN 'worker_lost' ELSE NULL END,
+                error = CASE WHEN attempts >= $1
+                    THEN 'Worker stopped responding after ' || attempts || ' attempts'
+                    ELSE NULL END,
+                finished_at = CASE WHEN attempts >= $1 THEN NOW() ELSE NULL END,
+                lease_owner=NULL, lease_expires_at=NULL
+            WHERE status='in_progress' AND lease_expires_at < NOW()
+            RETURNING *
+            "#,
+        )
+        .bind(max_attempts)
+        .fetch_all(pool)
+        .await
+    }
+
     pub async fn update_status(pool: &PgPool, id: Uuid, status: &str) -> sqlx::Result<()> {
         sqlx::query("UPDATE analysis_jobs SET status=$1 WHERE id=$2")
             .bind(status)
@@ -62,6 +242,109 @@ impl AnalysisJob {
         Ok(())
     }
 
+    /// Mark a job as successfully completed. Returns `false` when `worker_id`
+    /// no longer holds the lease, in which case the job is left alone.
+    pub async fn mark_completed(pool: &PgPool, id: Uuid, worker_id: &str) -> sqlx::Result<bool> {
+        let res = sqlx::query(
+            "UPDATE analysis_jobs SET status='completed', finished_at=NOW(), \
+             lease_owner=NULL, lease_expires_at=NULL WHERE id=$1 AND lease_owner=$2",
+        )
+        .bind(id)
+        .bind(worker_id)
+        .execute(pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Mark a job as cancelled after the worker stopped it. Returns `false`
+    /// when `worker_id` no longer holds the lease.
+    pub async fn mark_cancelled(pool: &PgPool, id: Uuid, worker_id: &str) -> sqlx::Result<bool> {
+        let res = sqlx::query(
+            "UPDATE analysis_jobs SET status='cancelled', finished_at=NOW(), \
+             lease_owner=NULL, lease_expires_at=NULL WHERE id=$1 AND lease_owner=$2",
+        )
+        .bind(id)
+        .bind(worker_id)
+        .execute(pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Request cancellation of a pending or running job.
+    ///
+    /// Pending jobs are cancelled right away, running jobs are stopped by the
+    /// worker. Returns `None` when the job has already finished.
+    pub async fn request_cancel(pool: &PgPool, id: Uuid) -> sqlx::Result<Option<AnalysisJob>> {
+        sqlx::query_as::<_, AnalysisJob>(
+            r#"
+            UPDATE analysis_jobs SET cancel_requested=true,
+                status = CASE WHEN status='pending' THEN 'cancelled' ELSE status END,
+                finished_at = CASE WHEN status='pending' THEN NOW() ELSE finished_at END
+            WHERE id=$1 AND status IN ('pending', 'in_progress')
+            RETURNING *
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Whether cancellation of the job has been requested.
+    pub async fn is_cancel_requested(pool: &PgPool, id: Uuid) -> sqlx::Result<bool> {
+        let (requested,): (bool,) =
+            sqlx::query_as("SELECT cancel_requested FROM analysis_jobs WHERE id=$1")
+                .bind(id)
+                .fetch_one(pool)
+                .await?;
+        Ok(requested)
+    }
+
+    /// Put a failed or cancelled job back into the `pending` state.
+    ///
+    /// With `resume` the worker reuses the outputs of stages that already
+    /// succeeded. Returns `None` when the job is not failed or cancelled.
+    pub async fn requeue(
+        executor: impl PgExecutor<'_>,
+        id: Uuid,
+        resume: bool,
+    ) -> sqlx::Result<Option<AnalysisJob>> {
+        sqlx::query_as::<_, AnalysisJob>(
+            r#"
+            UPDATE analysis_jobs SET status='pending', resume=$2, cancel_requested=false,
+                error=NULL, failure_reason=NULL, started_at=NULL, finished_at=NULL,
+                attempts=0, lease_owner=NULL, lease_expires_at=NULL
+            WHERE id=$1 AND status IN ('failed', 'cancelled')
+            RETURNING *
+            "#,
+        )
+        .bind(id)
+        .bind(resume)
+        .fetch_optional(executor)
+        .await
+    }
+
+    /// Mark a job as failed and store why. Returns `false` when `worker_id`
+    /// no longer holds the lease.
+    pub async fn mark_failed(
+        pool: &PgPool,
+        id: Uuid,
+        worker_id: &str,
+        reason: &str,
+        error: &str,
+    ) -> sqlx::Result<bool> {
+        let res = sqlx::query(
+            "UPDATE analysis_jobs SET status='failed', failure_reason=$1, error=$2, finished_at=NOW(), \
+             lease_owner=NULL, lease_expires_at=NULL WHERE id=$3 AND lease_owner=$4",
+        )
+        .bind(reason)
+        .bind(error)
+        .bind(id)
+        .bind(worker_id)
+        .execute(pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
     pub async fn find_by_org(pool: &PgPool, org: Uuid) -> sqlx::Result<Vec<JobWithNames>> {
         sqlx::query_as::<_, JobWithNames>(
             r#"
@@ -0,0 +1,176 @@
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+/// Upload documents and queue them for analysis.
+pub const SCOPE_UPLOAD: &str = "upload";
+/// Read jobs, their outputs and the analyzed documents.
+pub const SCOPE_JOBS_READ: &str = "jobs:read";
+/// Retry, rerun and cancel jobs.
+pub const SCOPE_JOBS_MANAGE: &str = "jobs:manage";
+/// List, create, update and delete pipelines.
+pub const SCOPE_PIPELINES_MANAGE: &str = "pipelines:manage";
+
+/// Scopes an API key can be granted.
+pub const API_KEY_SCOPES: [&str; 4] = [
+    SCOPE_UPLOAD,
+    SCOPE_JOBS_READ,
+    SCOPE_JOBS_MANAGE,
+    SCOPE_PIPELINES_MANAGE,
+];
+
+/// Characters of a key kept as its displayed prefix, including `KEY_MARKER`.
+const PREFIX_LEN: usize = 11;
+/// Marks values as API keys of this service, e.g. in secret scanners.
+const KEY_MARKER: &str = "dk_";
+
+/// Key used by other systems to call the API on behalf of an organization.
+///
+/// Only a hash of the key is stored; the key itself is returned once when the
+/// key is created or rotated.
+#[derive(Serialize, FromRow, Debug, Clone)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub org_id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    #[serde(skip_serializing)]
+    pub key_hash: String,
+    pub scopes: Vec<String>,
+    /// User the key acts for, e.g. as owner of uploaded documents.
+    pub created_by: Uuid,
+    pub created_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+pub struct NewApiKey {
+    pub org_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_by: Uuid,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Hex SHA-256 of a key, as stored in `api_keys.key_hash`.
+pub fn hash_api_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+fn generate_key() -> String {
+    let random: String = rand::thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(40)
+        .map(char::from)
+        .collect();
+    format!("{}{}", KEY_MARKER, random)
+}
+
+impl ApiKey {
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == scope)
+    }
+
+    /// Create a key and return it together with the plaintext key.
+    pub async fn create(pool: &PgPool, new: NewApiKey) -> sqlx::Result<(ApiKey, String)> {
+        let key = generate_key();
+        let created = sqlx::query_as::<_, ApiKey>(
+            "INSERT INTO api_keys (org_id, name, prefix, key_hash, scopes, created_by, expires_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *",
+        )
+        .bind(new.org_id)
+        .bind(new.name)
+        .bind(&key[..PREFIX_LEN])
+        .bind(hash_api_key(&key))
+        .bind(new.scopes)
+        .bind(new.created_by)
+        .bind(new.expires_at)
+        .fetch_one(pool)
+        .await?;
+        Ok((created, key))
+    }
+
+    pub async fn list_for_org(pool: &PgPool, org_id: Uuid) -> sqlx::Result<Vec<ApiKey>> {
+        sqlx::query_as::<_, ApiKey>("SELECT * FROM api_keys WHERE org_id=$1 ORDER BY created_at")
+            .bind(org_id)
+            .fetch_all(pool)
+            .await
+    }
+
+    pub async fn find(pool: &PgPool, id: Uuid) -> sqlx::Result<ApiKey> {
+        sqlx::query_as::<_, ApiKey>("SELECT * FROM api_keys WHERE id=$1")
+            .bind(id)
+            .fetch_one(pool)
+            .await
+    }
+
+    /// Replace the key of a non-revoked API key, invalidating the old one.
+    /// Returns `None` when the key was revoked.
+    pub async fn rotate(pool: &PgPool, id: Uuid) -> sqlx::Result<Option<(ApiKey, String)>> {
+        let key = generate_key();
+        let rotated = sqlx::query_as::<_, ApiKey>(
+            "UPDATE api_keys SET prefix=$2, key_hash=$3, last_used_at=NULL \
+             WHERE id=$1 AND revoked_at IS NULL RETURNING *",
+        )
+        .bind(id)
+        .bind(&key[..PREFIX_LEN])
+        .bind(hash_api_key(&key))
+        .fetch_optional(pool)
+        .await?;
+        Ok(rotated.map(|k| (k, key)))
+    }
+
+    /// Revoke a key. The row is kept so audit entries still name it.
+    pub async fn revoke(pool: &PgPool, id: Uuid) -> sqlx::Result<ApiKey> {
+        sqlx::query_as::<_, ApiKey>(
+            "UPDATE api_keys SET revoked_at=COALESCE(revoked_at, NOW()) WHERE id=$1 RETURNING *",
+        )
+        .bind(id)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Look up the active key matching `key` and record its use.
+    /// Returns `None` for unknown, revoked and expired keys and for keys
+    /// created by deactivated users. `last_used_at` is updated at most once a
+    /// minute, so busy keys do not write on every request.
+    pub async fn authenticate(pool: &PgPool, key: &str) -> sqlx::Result<Option<ApiKey>> {
+        let found = sqlx::query_as::<_, ApiKey>(
+            "SELECT k.* FROM api_keys k JOIN users u ON u.id = k.created_by \
+             WHERE k.key_hash=$1 AND k.revoked_at IS NULL \
+             AND (k.expires_at IS NULL OR k.expires_at > NOW()) AND u.is_active",
+        )
+        .bind(hash_api_key(key))
+        .fetch_optional(pool)
+        .await?;
+        if let Some(key) = &found {
+            sqlx::query(
+                "UPDATE api_keys SET last_used_at=NOW() WHERE id=$1 \
+                 AND (last_used_at IS NULL OR last_used_at < NOW() - INTERVAL '1 minute')",
+            )
+            .bind(key.id)
+            .execute(pool)
+            .await?;
+        }
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_keys_are_prefixed_and_hashed() {
+        let key = generate_key();
+        assert!(key.starts_with(KEY_MARKER));
+        assert_eq!(key.len(), KEY_MARKER.len() + 40);
+        assert_eq!(hash_api_key(&key), hash_api_key(&key));
+        assert_eq!(hash_api_key(&key).len(), 64);
+        assert_ne!(hash_api_key(&key), hash_api_key(&generate_key()));
+    }
+}
@@ -9,6 +9,8 @@ pub struct AuditLog {
     pub org_id: Uuid,
     pub user_id: Uuid,
     pub action: String,
+    /// API key the action was performed with, if any.
+    pub api_key_id: Option<Uuid>,
     pub created_at: DateTime<Utc>,
 }
 
@@ -16,17 +18,19 @@ pub struct NewAuditLog {
     pub org_id: Uuid,
     pub user_id: Uuid,
     pub action: String,
+    pub api_key_id: Option<Uuid>,
 }
 
 impl AuditLog {
     pub async fn create(pool: &PgPool, new: NewAuditLog) -> sqlx::Result<AuditLog> {
         sqlx::query_as::<_, AuditLog>(
-            "INSERT INTO audit_logs (id, org_id, user_id, action) VALUES ($1,$2,$3,$4) RETURNING *",
+            "INSERT INTO audit_logs (id, org_id, user_id, action, api_key_id) VALUES ($1,$2,$3,$4,$5) RETURNING *",
         )
         .bind(Uuid::new_v4())
         .bind(new.org_id)
         .bind(new.user_id)
         .bind(new.action)
+        .bind(new.api_key_id)
         .fetch_one(pool)
         .await
     }
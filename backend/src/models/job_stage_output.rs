@@ -46,4 +46,22 @@ impl JobStageOutput {
         .fetch_all(pool)
         .await
     }
+
+    /// Most recent output of a stage with the given type.
+    pub async fn find_latest(
+        pool: &PgPool,
+        job_id: Uuid,
+        stage_name: &str,
+        output_type: &str,
+    ) -> sqlx::Result<Option<JobStageOutput>> {
+        sqlx::query_as::<_, JobStageOutput>(
+            "SELECT * FROM job_stage_outputs WHERE job_id = $1 AND stage_name = $2 AND output_type = $3 \
+             ORDER BY created_at DESC LIMIT 1"
+        )
+        .bind(job_id)
+        .bind(stage_name)
+        .bind(output_type)
+        .fetch_optional(pool)
+        .await
+    }
 }
@@ -0,0 +1,115 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+/// Execution record of a single pipeline stage within a job.
+#[derive(Serialize, FromRow, Debug)]
+pub struct JobStageRun {
+    pub id: Uuid,
+    pub job_id: Uuid,
+    pub stage_id: String,
+    pub stage_type: String,
+    pub status: String, // "running", "succeeded", "failed", "skipped", "reused" or "aborted"
+    /// Number of attempts made by the worker.
+    pub attempt: i32,
+    pub error: Option<String>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl JobStageRun {
+    /// Insert a `running` record for a stage that is about to start.
+    pub async fn start(
+        pool: &PgPool,
+        job_id: Uuid,
+        stage_id: &str,
+        stage_type: &str,
+    ) -> sqlx::Result<JobStageRun> {
+        sqlx::query_as::<_, JobStageRun>(
+            "INSERT INTO job_stage_runs (job_id, stage_id, stage_type, status) \
+             VALUES ($1, $2, $3, 'running') RETURNING *",
+        )
+        .bind(job_id)
+        .bind(stage_id)
+        .bind(stage_type)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Set the final status of a run and stamp its finish time.
+    pub async fn finish(
+        pool: &PgPool,
+        id: Uuid,
+        status: &str,
+        attempt: i32,
+        error: Option<&str>,
+    ) -> sqlx::Result<()> {
+        sqlx::query(
+            "UPDATE job_stage_runs SET status=$1, attempt=$2, error=$3, finished_at=NOW() \
+             WHERE id=$4",
+        )
+        .bind(status)
+        .bind(attempt)
+        .bind(error)
+        .bind(id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Record a stage that finished without running, i.e. `skipped` or `reused`.
+    pub async fn record(
+        pool: &PgPool,
+        job_id: Uuid,
+        stage_id: &str,
+        stage_type: &str,
+        status: &str,
+        note: Option<&str>,
+    ) -> sqlx::Result<JobStageRun> {
+        sqlx::query_as::<_, JobStageRun>(
+            "INSERT INTO job_stage_runs (job_id, stage_id, stage_type, status, attempt, error, finished_at) \
+             VALUES ($1, $2, $3, $4, 0, $5, NOW()) RETURNING *",
+        )
+        .bind(job_id)
+        .bind(stage_id)
+        .bind(stage_type)
+        .bind(status)
+        .bind(note)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Ids of stages that succeeded in an earlier run of the job.
+    pub async fn succeeded_stage_ids(pool: &PgPool, job_id: Uuid) -> sqlx::Result<Vec<String>> {
+        sqlx::query_scalar(
+            "SELECT DISTINCT stage_id FROM job_stage_runs \
+             WHERE job_id = $1 AND status IN ('succeeded', 'reused')",
+        )
+        .bind(job_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Mark runs still in progress as `aborted`, e.g. sibling branches that were
+    /// dropped after another stage failed the job or the job was cancelled.
+    pub async fn abort_running(pool: &PgPool, job_id: Uuid) -> sqlx::Result<()> {
+        sqlx::query(
+            "UPDATE job_stage_runs SET status='aborted', finished_at=NOW() \
+             WHERE job_id=$1 AND status='running'",
+        )
+        .bind(job_id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn find_by_job_id(pool: &PgPool, job_id: Uuid) -> sqlx::Result<Vec<JobStageRun>> {
+        sqlx::query_as::<_, JobStageRun>(
+            "SELECT * FROM job_stage_runs WHERE job_id = $1 ORDER BY started_at ASC",
+        )
+        .bind(job_id)
+        .fetch_all(pool)
+        .await
+    }
+}
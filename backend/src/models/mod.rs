@@ -1,17 +1,23 @@
 pub mod analysis_job;
+pub mod api_key;
 pub mod audit_log;
 pub mod document;
 pub mod job_stage_output;
+pub mod job_stage_run;
 pub mod organization;
 pub mod pipeline;
 pub mod settings;
 pub mod user; // Added new module
+pub mod webhook;
 
 pub use analysis_job::{AnalysisJob, JobWithNames, NewAnalysisJob};
+pub use api_key::{ApiKey, NewApiKey, API_KEY_SCOPES};
 pub use audit_log::{AuditLog, NewAuditLog};
 pub use document::{Document, NewDocument, DocumentError};
 pub use job_stage_output::{JobStageOutput, NewJobStageOutput};
+pub use job_stage_run::JobStageRun;
 pub use organization::{NewOrganization, Organization};
 pub use pipeline::{NewPipeline, Pipeline};
 pub use settings::{NewOrgSettings, OrgSettings};
 pub use user::{NewUser, User}; // Added new pub use
+pub use webhook::{NewWebhookEndpoint, WebhookDelivery, WebhookEndpoint, WEBHOOK_EVENTS};
@@ -9,6 +9,13 @@ pub struct Pipeline {
     pub org_id: Uuid,
     pub name: String,
     pub stages: serde_json::Value,
+    /// Default priority of jobs created for this pipeline.
+    #[serde(default = "default_priority")]
+    pub priority: String,
+}
+
+fn default_priority() -> String {
+    "normal".into()
 }
 
 /// Information needed to create a pipeline.
@@ -21,20 +28,27 @@ pub struct NewPipeline {
 impl Pipeline {
     /// Insert a new pipeline and return it.
     pub async fn create(pool: &PgPool, new: NewPipeline) -> sqlx::Result<Pipeline> {
-        sqlx::query_as::<_, Pipeline>("INSERT INTO pipelines (id, org_id, name, stages) VALUES ($1,$2,$3,$4) RETURNING *")
+        Self::create_with_priority(pool, new, "normal").await
+    }
+
+    /// Insert a new pipeline whose jobs default to `priority`.
+    pub async fn create_with_priority(pool: &PgPool, new: NewPipeline, priority: &str) -> sqlx::Result<Pipeline> {
+        sqlx::query_as::<_, Pipeline>("INSERT INTO pipelines (id, org_id, name, stages, priority) VALUES ($1,$2,$3,$4,$5) RETURNING *")
             .bind(Uuid::new_v4())
             .bind(new.org_id)
             .bind(new.name)
             .bind(new.stages)
+            .bind(priority)
             .fetch_one(pool)
             .await
     }
 
-    /// Update an existing pipeline's name and stages.
-    pub async fn update(pool: &PgPool, id: Uuid, name: &str, stages: serde_json::Value) -> sqlx::Result<Pipeline> {
-        sqlx::query_as::<_, Pipeline>("UPDATE pipelines SET name=$1, stages=$2 WHERE id=$3 RETURNING *")
+    /// Update an existing pipeline's name, stages and priority.
+    pub async fn update(pool: &PgPool, id: Uuid, name: &str, stages: serde_json::Value, priority: &str) -> sqlx::Result<Pipeline> {
+        sqlx::query_as::<_, Pipeline>("UPDATE pipelines SET name=$1, stages=$2, priority=$3 WHERE id=$4 RETURNING *")
             .bind(name)
             .bind(stages)
+            .bind(priority)
             .bind(id)
             .fetch_one(pool)
             .await
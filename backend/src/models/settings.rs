@@ -16,6 +16,10 @@ pub struct OrgSettings {
     pub ocr_api_key: Option<String>,
     pub prompt_templates: Option<serde_json::Value>,
     pub ai_custom_headers: Option<serde_json::Value>, // New field
+    /// Maximum number of jobs the workers run for this organization at once.
+    pub max_concurrent_jobs: Option<i32>,
+    /// OCR engine of stages that do not choose one; tesseract when unset.
+    pub ocr_engine: Option<String>,
 }
 
 /// Wrapper for creating default settings for an organization.
@@ -54,8 +58,10 @@ impl OrgSettings {
              ocr_api_endpoint=$6, \
              ocr_api_key=$7, \
              prompt_templates=$8, \
-             ai_custom_headers=$9 \
-             WHERE org_id=$10 RETURNING *",
+             ai_custom_headers=$9, \
+             max_concurrent_jobs=$10, \
+             ocr_engine=$11 \
+             WHERE org_id=$12 RETURNING *",
         )
         .bind(settings.monthly_upload_quota)
         .bind(settings.monthly_analysis_quota)
@@ -66,7 +72,9 @@ impl OrgSettings {
         .bind(settings.ocr_api_key)
         .bind(settings.prompt_templates)
         .bind(settings.ai_custom_headers) // New binding
-        .bind(settings.org_id) // org_id is now $10
+        .bind(settings.max_concurrent_jobs)
+        .bind(settings.ocr_engine)
+        .bind(settings.org_id) // org_id is now $12
         .fetch_one(pool)
         .await
     }
@@ -0,0 +1,217 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+/// Events organizations can subscribe webhook endpoints to.
+pub const WEBHOOK_EVENTS: [&str; 3] = ["job.completed", "job.failed", "job.cancelled"];
+
+/// Endpoint registered by an organization to receive job events.
+#[derive(Serialize, FromRow, Debug, Clone)]
+pub struct WebhookEndpoint {
+    pub id: Uuid,
+    pub org_id: Uuid,
+    pub url: String,
+    /// Key for the HMAC signature of deliveries; masked in API responses.
+    pub secret: String,
+    pub event_types: Vec<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+pub struct NewWebhookEndpoint {
+    pub org_id: Uuid,
+    pub url: String,
+    pub secret: String,
+    pub event_types: Vec<String>,
+}
+
+/// One event sent to one endpoint, together with the outcome of the last attempt.
+#[derive(Serialize, FromRow, Debug, Clone)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub endpoint_id: Uuid,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    pub status: String, // "pending", "delivered" or "failed"
+    pub attempts: i32,
+    /// HTTP status of the last response, if the endpoint answered.
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub next_attempt_at: DateTime<Utc>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+/// Delivery claimed by a worker, joined with its endpoint.
+#[derive(FromRow, Debug)]
+pub struct DueDelivery {
+    pub id: Uuid,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    /// Attempts including the current one.
+    pub attempts: i32,
+    pub url: String,
+    pub secret: String,
+}
+
+impl WebhookEndpoint {
+    pub async fn create(pool: &PgPool, new: NewWebhookEndpoint) -> sqlx::Result<WebhookEndpoint> {
+        sqlx::query_as::<_, WebhookEndpoint>(
+            "INSERT INTO webhook_endpoints (org_id, url, secret, event_types) \
+             VALUES ($1, $2, $3, $4) RETURNING *",
+        )
+        .bind(new.org_id)
+        .bind(new.url)
+        .bind(new.secret)
+        .bind(new.event_types)
+        .fetch_one(pool)
+        .await
+    }
+
+    pub async fn list_for_org(pool: &PgPool, org_id: Uuid) -> sqlx::Result<Vec<WebhookEndpoint>> {
+        sqlx::query_as::<_, WebhookEndpoint>(
+            "SELECT * FROM webhook_endpoints WHERE org_id=$1 ORDER BY created_at",
+        )
+        .bind(org_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn find(pool: &PgPool, id: Uuid) -> sqlx::Result<WebhookEndpoint> {
+        sqlx::query_as::<_, WebhookEndpoint>("SELECT * FROM webhook_endpoints WHERE id=$1")
+            .bind(id)
+            .fetch_one(pool)
+            .await
+    }
+
+    /// Delete an endpoint together with its delivery log.
+    pub async fn delete(pool: &PgPool, id: Uuid) -> sqlx::Result<()> {
+        sqlx::query("DELETE FROM webhook_endpoints WHERE id=$1")
+            .bind(id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+}
+
+impl WebhookDelivery {
+    /// Queue `payload` for every endpoint of the organization subscribed to `event_type`.
+    pub async fn enqueue(
+        pool: &PgPool,
+        org_id: Uuid,
+        event_type: &str,
+        payload: &serde_json::Value,
+    ) -> sqlx::Result<Vec<WebhookDelivery>> {
+        sqlx::query_as::<_, WebhookDelivery>(
+            "INSERT INTO webhook_deliveries (endpoint_id, event_type, payload) \
+             SELECT id, $2, $3 FROM webhook_endpoints WHERE org_id=$1 AND $2 = ANY(event_types) \
+             RETURNING *",
+        )
+        .bind(org_id)
+        .bind(event_type)
+        .bind(payload)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn find(pool: &PgPool, id: Uuid) -> sqlx::Result<WebhookDelivery> {
+        sqlx::query_as::<_, WebhookDelivery>("SELECT * FROM webhook_deliveries WHERE id=$1")
+            .bind(id)
+            .fetch_one(pool)
+            .await
+    }
+
+    /// Most recent deliveries of an endpoint, newest first.
+    pub async fn list_for_endpoint(
+        pool: &PgPool,
+        endpoint_id: Uuid,
+        limit: i64,
+    ) -> sqlx::Result<Vec<WebhookDelivery>> {
+        sqlx::query_as::<_, WebhookDelivery>(
+            "SELECT * FROM webhook_deliveries WHERE endpoint_id=$1 \
+             ORDER BY created_at DESC LIMIT $2",
+        )
+        .bind(endpoint_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Queue the payload of an earlier delivery again as a new delivery.
+    pub async fn redeliver(pool: &PgPool, id: Uuid) -> sqlx::Result<WebhookDelivery> {
+        sqlx::query_as::<_, WebhookDelivery>(
+            "INSERT INTO webhook_deliveries (endpoint_id, event_type, payload) \
+             SELECT endpoint_id, event_type, payload FROM webhook_deliveries WHERE id=$1 \
+             RETURNING *",
+        )
+        .bind(id)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Claim up to `limit` due deliveries and count the attempt.
+    ///
+    /// Claimed deliveries are hidden from other workers for `lease_secs`, so a
+    /// worker that dies mid-request only delays the next attempt.
+    pub async fn claim_due(
+        pool: &PgPool,
+        limit: i64,
+        lease_secs: u64,
+    ) -> sqlx::Result<Vec<DueDelivery>> {
+        sqlx::query_as::<_, DueDelivery>(
+            r#"
+            WITH due AS (
+                SELECT id FROM webhook_deliveries
+                WHERE status='pending' AND next_attempt_at <= NOW()
+                ORDER BY next_attempt_at
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            UPDATE webhook_deliveries d SET
+                attempts = d.attempts + 1,
+                next_attempt_at = NOW() + make_interval(secs => $2)
+            FROM due, webhook_endpoints e
+            WHERE d.id = due.id AND e.id = d.endpoint_id
+            RETURNING d.id, d.event_type, d.payload, d.attempts, e.url, e.secret
+            "#,
+        )
+        .bind(limit)
+        .bind(lease_secs as f64)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn mark_delivered(pool: &PgPool, id: Uuid, response_status: i32) -> sqlx::Result<()> {
+        sqlx::query(
+            "UPDATE webhook_deliveries SET status='delivered', response_status=$2, \
+             last_error=NULL, delivered_at=NOW() WHERE id=$1",
+        )
+        .bind(id)
+        .bind(response_status)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Record a failed attempt. Without `retry_in_secs` the delivery is given up.
+    pub async fn mark_attempt_failed(
+        pool: &PgPool,
+        id: Uuid,
+        response_status: Option<i32>,
+        error: &str,
+        retry_in_secs: Option<f64>,
+    ) -> sqlx::Result<()> {
+        sqlx::query(
+            "UPDATE webhook_deliveries SET response_status=$2, last_error=$3, \
+             status = CASE WHEN $4::FLOAT8 IS NULL THEN 'failed' ELSE 'pending' END, \
+             next_attempt_at = NOW() + make_interval(secs => COALESCE($4, 0)) WHERE id=$1",
+        )
+        .bind(id)
+        .bind(response_status)
+        .bind(error)
+        .bind(retry_in_secs)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
@@ -1,6 +1,71 @@
+use crate::processing::ocr_engine;
+use crate::processing::parse::{self, ConfigError};
+use crate::worker::ocr::{OcrConfig, AUTO_ENGINE};
+use crate::worker::{dag::StageGraph, webhook::WebhookConfig, Stage};
 use actix_web::HttpResponse;
 use std::collections::HashSet;
 
+const MAX_STAGE_ATTEMPTS: u32 = 10;
+const MAX_BACKOFF_MS: u64 = 60_000;
+const MAX_TIMEOUT_SECS: u64 = 3_600;
+const MAX_STAGE_ID_LEN: usize = 64;
+
+/// Stage ids end up in file paths, storage keys and output names, so they are
+/// limited to ASCII letters, digits, `_` and `-`.
+fn valid_stage_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_STAGE_ID_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+/// Check the retry and timeout settings of a parsed stage.
+fn validate_execution_policy(stage: &Stage) -> Result<(), String> {
+    if let Some(retry) = &stage.retry {
+        if retry.max_attempts == 0 || retry.max_attempts > MAX_STAGE_ATTEMPTS {
+            return Err(format!(
+                "'retry.max_attempts' must be between 1 and {}.",
+                MAX_STAGE_ATTEMPTS
+            ));
+        }
+        if retry.backoff_ms > MAX_BACKOFF_MS {
+            return Err(format!(
+                "'retry.backoff_ms' must not exceed {}.",
+                MAX_BACKOFF_MS
+            ));
+        }
+    }
+    if let Some(timeout) = stage.timeout_secs {
+        if timeout == 0 || timeout > MAX_TIMEOUT_SECS {
+            return Err(format!(
+                "'timeout_secs' must be between 1 and {}.",
+                MAX_TIMEOUT_SECS
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Bad request listing every problem of a parse stage's config, with paths
+/// like `stages[1].config.parameters.patterns[0].regex`.
+fn parse_config_error(index: usize, errors: &[ConfigError]) -> HttpResponse {
+    let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+    let details: Vec<serde_json::Value> = errors
+        .iter()
+        .map(|e| {
+            serde_json::json!({
+                "path": format!("stages[{}].config.{}", index, e.path),
+                "message": e.message,
+            })
+        })
+        .collect();
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "error": format!("Stage {} (parse): invalid 'config': {}", index, messages.join("; ")),
+        "details": details,
+    }))
+}
+
 pub fn validate_stages(stages: &serde_json::Value) -> Result<(), HttpResponse> {
     if let Some(stages_array) = stages.as_array() {
         if stages_array.is_empty() {
@@ -10,7 +75,15 @@ pub fn validate_stages(stages: &serde_json::Value) -> Result<(), HttpResponse> {
         let mut seen_ids = HashSet::new();
         for (index, stage_val) in stages_array.iter().enumerate() {
             if let Some(stage_obj) = stage_val.as_object() {
-                if let Some(id_val) = stage_obj.get("id").and_then(|v| v.as_str()) {
+                if let Some(id_val) = stage_obj.get("id").filter(|v| !v.is_null()) {
+                    let Some(id_val) = id_val.as_str().filter(|id| valid_stage_id(id)) else {
+                        return Err(HttpResponse::BadRequest().json(serde_json::json!({
+                            "error": format!(
+                                "Stage {} 'id' must be 1 to {} letters, digits, '_' or '-'.",
+                                index, MAX_STAGE_ID_LEN
+                            )
+                        })));
+                    };
                     if !seen_ids.insert(id_val.to_string()) {
                         return Err(HttpResponse::BadRequest().json(serde_json::json!({
                             "error": format!("Duplicate stage id '{}'", id_val)
@@ -88,35 +161,45 @@ pub fn validate_stages(stages: &serde_json::Value) -> Result<(), HttpResponse> {
                         if let Some(engine_val) = stage_obj.get("ocr_engine") {
                             if !engine_val.is_null() {
                                 if let Some(engine_str) = engine_val.as_str() {
-                                    if engine_str != "default" && engine_str != "external" {
+                                    let engine = ocr_engine::get(engine_str);
+                                    if engine_str != "default" && engine_str != AUTO_ENGINE && engine.is_none() {
                                         return Err(HttpResponse::BadRequest().json(serde_json::json!({
-                                            "error": format!("Stage {} (OCR): 'ocr_engine' must be 'default', 'external', or null.", index)
+                                            "error": format!("Stage {} (OCR): 'ocr_engine' must be 'default', 'auto', one of the engines {}, or null.", index, ocr_engine::names().join(", "))
                                         })));
                                     }
-                                    if engine_str == "external" {
+                                    if engine_str == "auto" {
+                                        if let Some(endpoint_val) = stage_obj.get("ocr_stage_endpoint") {
+                                            if !endpoint_val.is_null() && endpoint_val.as_str().is_none_or(|s| s.trim().is_empty()) {
+                                                return Err(HttpResponse::BadRequest().json(serde_json::json!({
+                                                    "error": format!("Stage {} (OCR): 'ocr_stage_endpoint' must be a non-empty string or null.", index)
+                                                })));
+                                            }
+                                        }
+                                    }
+                                    if engine.is_some_and(|e| e.is_remote()) {
                                         if let Some(endpoint_val) =
                                             stage_obj.get("ocr_stage_endpoint")
                                         {
                                             if let Some(endpoint_str) = endpoint_val.as_str() {
                                                 if endpoint_str.trim().is_empty() {
                                                     return Err(HttpResponse::BadRequest().json(serde_json::json!({
-                                                        "error": format!("Stage {} (OCR): 'ocr_stage_endpoint' must be a non-empty string when ocr_engine is 'external'.", index)
+                                                        "error": format!("Stage {} (OCR): 'ocr_stage_endpoint' must be a non-empty string when ocr_engine is '{}'.", index, engine_str)
                                                     })));
                                                 }
                                             } else {
                                                 return Err(HttpResponse::BadRequest().json(serde_json::json!({
-                                                    "error": format!("Stage {} (OCR): 'ocr_stage_endpoint' must be a non-empty string when ocr_engine is 'external'.", index)
+                                                    "error": format!("Stage {} (OCR): 'ocr_stage_endpoint' must be a non-empty string when ocr_engine is '{}'.", index, engine_str)
                                                 })));
                                             }
                                         } else {
                                             return Err(HttpResponse::BadRequest().json(serde_json::json!({
-                                                "error": format!("Stage {} (OCR): 'ocr_stage_endpoint' is required when ocr_engine is 'external'.", index)
+                                                "error": format!("Stage {} (OCR): 'ocr_stage_endpoint' is required when ocr_engine is '{}'.", index, engine_str)
                                             })));
                                         }
                                         if let Some(key_val) = stage_obj.get("ocr_stage_key") {
                                             if !key_val.is_string() && !key_val.is_null() {
                                                 return Err(HttpResponse::BadRequest().json(serde_json::json!({
-                                                    "error": format!("Stage {} (OCR): 'ocr_stage_key' for external engine must be a string or null.", index)
+                                                    "error": format!("Stage {} (OCR): 'ocr_stage_key' for remote engines must be a string or null.", index)
                                                 }))); 
                                             }
                                         }
@@ -145,14 +228,14 @@ pub fn validate_stages(stages: &serde_json::Value) -> Result<(), HttpResponse> {
                             }
                         }
                         if let Some(key_val) = stage_obj.get("ocr_stage_key") {
-                            if stage_obj
-                                .get("ocr_engine")
-                                .and_then(|v| v.as_str())
-                                != Some("external")
-                                && !key_val.is_null()
-                            {
+                            let engine_str = stage_obj.get("ocr_engine").and_then(|v| v.as_str());
+                            let takes_key = engine_str == Some(AUTO_ENGINE)
+                                || engine_str
+                                    .and_then(ocr_engine::get)
+                                    .is_some_and(|e| e.is_remote());
+                            if !takes_key && !key_val.is_null() {
                                 return Err(HttpResponse::BadRequest().json(serde_json::json!({
-                                    "error": format!("Stage {} (OCR): 'ocr_stage_key' can only be set when ocr_engine is 'external'.", index)
+                                    "error": format!("Stage {} (OCR): 'ocr_stage_key' can only be set for remote engines or 'auto'.", index)
                                 }))); 
                             }
                             if !key_val.is_string() && !key_val.is_null() {
@@ -161,6 +244,16 @@ pub fn validate_stages(stages: &serde_json::Value) -> Result<(), HttpResponse> {
                                 }))); 
                             }
                         }
+                        if let Some(config) = stage_obj.get("config").filter(|c| !c.is_null()) {
+                            let result = serde_json::from_value::<OcrConfig>(config.clone())
+                                .map_err(|e| e.to_string())
+                                .and_then(|cfg| cfg.validate());
+                            if let Err(e) = result {
+                                return Err(HttpResponse::BadRequest().json(serde_json::json!({
+                                    "error": format!("Stage {} (OCR): invalid 'config': {}", index, e)
+                                })));
+                            }
+                        }
                     }
                     "parse" | "report" => {
                         if command_missing {
@@ -168,8 +261,40 @@ pub fn validate_stages(stages: &serde_json::Value) -> Result<(), HttpResponse> {
                                 "error": format!("Stage {} ({}): 'command' is required.", index, stage_type_str)
                             })));
                         }
+                        if stage_type_str == "parse" {
+                            if let Some(config) = stage_obj.get("config").filter(|c| !c.is_null()) {
+                                if let Err(errors) = parse::validate_config(config) {
+                                    return Err(parse_config_error(index, &errors));
+                                }
+                            }
+                        }
+                    }
+                    "exec" => {
+                        if command_missing {
+                            return Err(HttpResponse::BadRequest().json(serde_json::json!({
+                                "error": format!("Stage {} (exec): 'command' naming an allowlisted executable is required.", index)
+                            })));
+                        }
+                    }
+                    "webhook" => {
+                        let config = stage_obj
+                            .get("config")
+                            .cloned()
+                            .unwrap_or(serde_json::Value::Null);
+                        let result = serde_json::from_value::<WebhookConfig>(config)
+                            .map_err(|e| e.to_string())
+                            .and_then(|cfg| cfg.validate());
+                        if let Err(e) = result {
+                            return Err(HttpResponse::BadRequest().json(serde_json::json!({
+                                "error": format!("Stage {} (webhook): invalid 'config': {}", index, e)
+                            })));
+                        }
+                    }
+                    other => {
+                        return Err(HttpResponse::BadRequest().json(serde_json::json!({
+                            "error": format!("Stage {}: unknown type '{}'. Use ocr, parse, ai, report, exec or webhook.", index, other)
+                        })));
                     }
-                    _ => {}
                 }
             } else {
                 return Err(HttpResponse::BadRequest().json(serde_json::json!({
@@ -181,6 +306,27 @@ pub fn validate_stages(stages: &serde_json::Value) -> Result<(), HttpResponse> {
         return Err(HttpResponse::BadRequest()
             .json(serde_json::json!({"error": "'stages' must be an array."})));
     }
+    let parsed: Vec<Stage> = serde_json::from_value(stages.clone()).map_err(|e| {
+        HttpResponse::BadRequest()
+            .json(serde_json::json!({"error": format!("Invalid stage definition: {}", e)}))
+    })?;
+    for (index, stage) in parsed.iter().enumerate() {
+        if let Err(e) = validate_execution_policy(stage) {
+            return Err(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Stage {} ({}): {}", index, stage.stage_type, e)
+            })));
+        }
+        if let Some(condition) = &stage.when {
+            if let Err(e) = condition.validate() {
+                return Err(HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": format!("Stage {} ({}): invalid 'when': {}", index, stage.stage_type, e)
+                })));
+            }
+        }
+    }
+    StageGraph::build(&parsed).map_err(|e| {
+        HttpResponse::BadRequest().json(serde_json::json!({"error": e.to_string()}))
+    })?;
     Ok(())
 }
 
@@ -213,4 +359,55 @@ mod tests {
         ]);
         assert!(validate_stages(&stages).is_err());
     }
+
+    #[test]
+    fn registered_ocr_engines_accepted() {
+        let tesseract = json!([{"type": "ocr", "ocr_engine": "tesseract", "command": "run"}]);
+        assert!(validate_stages(&tesseract).is_ok());
+        let json_engine = json!([{"type": "ocr", "ocr_engine": "external_json", "command": "run",
+            "ocr_stage_endpoint": "http://ocr", "ocr_stage_key": "k"}]);
+        assert!(validate_stages(&json_engine).is_ok());
+        let no_endpoint = json!([{"type": "ocr", "ocr_engine": "external_json", "command": "run"}]);
+        assert!(validate_stages(&no_endpoint).is_err());
+        let local_key = json!([{"type": "ocr", "ocr_engine": "tesseract", "command": "run", "ocr_stage_key": "k"}]);
+        assert!(validate_stages(&local_key).is_err());
+    }
+
+    #[test]
+    fn auto_ocr_engine_accepts_optional_endpoint() {
+        assert!(validate_stages(&json!([{"type": "ocr", "ocr_engine": "auto", "command": "run"}])).is_ok());
+        let with_endpoint = json!([{"type": "ocr", "ocr_engine": "auto", "command": "run",
+            "ocr_stage_endpoint": "http://ocr", "ocr_stage_key": "k", "config": {"min_text_chars": 50}}]);
+        assert!(validate_stages(&with_endpoint).is_ok());
+        let empty_endpoint = json!([{"type": "ocr", "ocr_engine": "auto", "command": "run", "ocr_stage_endpoint": " "}]);
+        assert!(validate_stages(&empty_endpoint).is_err());
+        let bad_coverage = json!([{"type": "ocr", "ocr_engine": "auto", "command": "run", "config": {"min_text_coverage": 2}}]);
+        assert!(validate_stages(&bad_coverage).is_err());
+    }
+
+    #[test]
+    fn ocr_tesseract_options_validated() {
+        let ok = json!([{"type": "ocr", "command": "run", "config": {"languages": "deu+eng", "psm": 6, "dpi": 300}}]);
+        assert!(validate_stages(&ok).is_ok());
+        for config in [json!({"languages": "deu -c x"}), json!({"psm": 14}), json!({"dpi": 5})] {
+            let stages = json!([{"type": "ocr", "command": "run", "config": config}]);
+            assert!(validate_stages(&stages).is_err());
+        }
+    }
+
+    #[test]
+    fn exec_requires_command_and_unknown_types_rejected() {
+        assert!(validate_stages(&json!([{"type": "exec", "command": "tool --fast"}])).is_ok());
+        assert!(validate_stages(&json!([{"type": "exec"}])).is_err());
+        assert!(validate_stages(&json!([{"type": "shell", "command": "rm -rf /"}])).is_err());
+    }
+
+    #[test]
+    fn webhook_requires_valid_config() {
+        let ok = json!([{"type": "webhook", "config": {"url": "https://classifier.local/run", "extract": "$.label"}}]);
+        assert!(validate_stages(&ok).is_ok());
+        assert!(validate_stages(&json!([{"type": "webhook"}])).is_err());
+        let bad_url = json!([{"type": "webhook", "config": {"url": "file:///etc/passwd"}}]);
+        assert!(validate_stages(&bad_url).is_err());
+    }
 }
@@ -1,4 +1,4 @@
-use std::time::Duration;
+use crate::processing::retry::RetryPolicy;
 use reqwest::header::{HeaderName, HeaderValue, CONTENT_TYPE};
 use serde::Deserialize;
 
@@ -29,22 +29,34 @@ impl std::fmt::Display for AiClientError {
 
 impl std::error::Error for AiClientError {}
 
-const MAX_RETRIES: u32 = 3;
-const BASE_DELAY_MS: u64 = 500;
-
-fn exponential_backoff(attempt: u32) -> Duration {
-    Duration::from_millis(BASE_DELAY_MS * (1 << (attempt - 1)))
+/// Send `input` to the AI endpoint using the default [`RetryPolicy`].
+pub async fn run_ai(
+    input: &serde_json::Value,
+    api_endpoint: &str,
+    api_key: &str,
+    custom_headers_json: Option<&serde_json::Value>,
+) -> Result<serde_json::Value, AiClientError> {
+    run_ai_with_policy(
+        input,
+        api_endpoint,
+        api_key,
+        custom_headers_json,
+        &RetryPolicy::default(),
+    )
+    .await
 }
 
+/// Send `input` to the AI endpoint, retrying failed requests according to `policy`.
 #[tracing::instrument(skip(input, custom_headers_json))]
-pub async fn run_ai(
+pub async fn run_ai_with_policy(
     input: &serde_json::Value,
     api_endpoint: &str,
     api_key: &str,
     custom_headers_json: Option<&serde_json::Value>,
+    policy: &RetryPolicy,
 ) -> Result<serde_json::Value, AiClientError> {
     let client = reqwest::Client::new();
-    let mut request_builder = client.post(api_endpoint).timeout(Duration::from_secs(10));
+    let mut request_builder = client.post(api_endpoint).timeout(policy.timeout);
     if !api_key.is_empty() {
         request_builder = request_builder.bearer_auth(api_key);
     }
@@ -81,7 +93,7 @@ pub async fn run_ai(
         }
     }
     request_builder = request_builder.header(CONTENT_TYPE, "application/json");
-    let mut attempts = 0;
+    let mut attempts = 1;
     loop {
         let builder = request_builder
             .try_clone()
@@ -91,15 +103,15 @@ pub async fn run_ai(
             Ok(resp) => {
                 if resp.status().is_success() {
                     return resp.json().await.map_err(AiClientError::Request);
-                } else if attempts < MAX_RETRIES {
-                    attempts += 1;
+                } else if policy.should_retry(attempts) {
                     let status = resp.status();
                     let msg = resp
                         .text()
                         .await
                         .unwrap_or_else(|_| "unknown".into());
                     log::warn!("AI request failed status {} attempt {}: {}", status, attempts, msg);
-                    tokio::time::sleep(exponential_backoff(attempts)).await;
+                    tokio::time::sleep(policy.backoff(attempts)).await;
+                    attempts += 1;
                 } else {
                     let status = resp.status();
                     let msg = resp
@@ -110,10 +122,10 @@ pub async fn run_ai(
                 }
             }
             Err(e) => {
-                if attempts < MAX_RETRIES {
-                    attempts += 1;
+                if policy.should_retry(attempts) {
                     log::warn!("AI request error attempt {}: {:?}", attempts, e);
-                    tokio::time::sleep(exponential_backoff(attempts)).await;
+                    tokio::time::sleep(policy.backoff(attempts)).await;
+                    attempts += 1;
                 } else {
                     return Err(AiClientError::Request(e));
                 }
@@ -0,0 +1,436 @@
+//! Values next to labels like `Invoice No.` or `Rechnungsnummer:`.
+//!
+//! A value is the text to the right of a label on the same line, or the text
+//! under the label on one of the next lines. When the OCR layout has word
+//! positions, right and under are decided geometrically; otherwise the text
+//! is split into lines and cells like tables are.
+
+use crate::processing::ocr_layout::{page_at, BBox, OcrLayout, OcrPage, OcrWord, PAGE_BREAK};
+use crate::processing::table::{split_cells, Chunk, DEFAULT_DELIMITER};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+static CELL_DELIMITER: Lazy<Regex> = Lazy::new(|| Regex::new(DEFAULT_DELIMITER).unwrap());
+
+/// Separators skipped between a label and its value.
+const SEPARATORS: [char; 2] = [':', '#'];
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum Direction {
+    /// Right of the label, or else below it.
+    #[default]
+    Any,
+    Right,
+    Below,
+}
+
+fn default_max_lines_below() -> usize {
+    1
+}
+
+/// Where a value may be found relative to its label.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AnchorOptions {
+    #[serde(default)]
+    pub direction: Direction,
+    /// Most characters between the label and a value to its right.
+    #[serde(default)]
+    pub max_gap: Option<usize>,
+    /// Most lines between the label and a value below it.
+    #[serde(default = "default_max_lines_below")]
+    pub max_lines_below: usize,
+}
+
+impl Default for AnchorOptions {
+    fn default() -> Self {
+        Self {
+            direction: Direction::default(),
+            max_gap: None,
+            max_lines_below: default_max_lines_below(),
+        }
+    }
+}
+
+/// Field of the `anchorExtraction` strategy.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AnchorField {
+    pub name: String,
+    /// The label and its synonyms, tried in order.
+    pub labels: Vec<String>,
+    #[serde(flatten)]
+    pub options: AnchorOptions,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct AnchorMatch {
+    pub value: String,
+    /// Label as written in the document.
+    pub label: String,
+    pub page: u32,
+    /// Box around the value, when found from the OCR layout.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bbox: Option<BBox>,
+}
+
+/// Value next to the first of `labels` found in the document. Uses the word
+/// positions of `layout` when it has any, otherwise `text`.
+pub fn find_value(
+    text: &str,
+    layout: Option<&OcrLayout>,
+    labels: &[String],
+    options: &AnchorOptions,
+) -> Option<AnchorMatch> {
+    let layout = layout.filter(|l| has_positions(l));
+    labels
+        .iter()
+        .map(|label| label.trim())
+        .filter(|label| !label.is_empty())
+        .find_map(|label| match layout {
+            Some(layout) => find_in_layout(layout, label, options),
+            None => find_in_text(text, label, options),
+        })
+}
+
+fn has_positions(layout: &OcrLayout) -> bool {
+    layout
+        .pages
+        .iter()
+        .flat_map(|p| &p.lines)
+        .flat_map(|l| &l.words)
+        .any(|w| w.bbox.is_some())
+}
+
+/// Labels match case-insensitively and, when they start with a letter or
+/// digit, only at the start of a word, so `Total` does not match `Subtotal`.
+fn label_regex(label: &str) -> Option<Regex> {
+    let boundary = if label.starts_with(|c: char| c.is_alphanumeric()) {
+        r"\b"
+    } else {
+        ""
+    };
+    Regex::new(&format!("(?i){}{}", boundary, regex::escape(label))).ok()
+}
+
+fn find_in_text(text: &str, label: &str, options: &AnchorOptions) -> Option<AnchorMatch> {
+    let re = label_regex(label)?;
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    for line in text.split('\n') {
+        lines.push((offset, line));
+        offset += line.len() + 1;
+    }
+    for (i, (offset, line)) in lines.iter().enumerate() {
+        for m in re.find_iter(line) {
+            let found = |value: String| AnchorMatch {
+                value,
+                label: m.as_str().to_string(),
+                page: page_at(text, offset + m.start()),
+                bbox: None,
+            };
+            if options.direction != Direction::Below {
+                if let Some(value) = text_right_of(&line[m.end()..], options.max_gap) {
+                    return Some(found(value));
+                }
+            }
+            if options.direction != Direction::Right {
+                let start = line[..m.start()].chars().count();
+                let column = start..start + m.as_str().chars().count();
+                let below = lines[i + 1..].iter().map(|(_, l)| *l);
+                if let Some(value) = text_below(below, column, options.max_lines_below) {
+                    return Some(found(value));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// First cell of `rest`, the part of a line after a label.
+fn text_right_of(rest: &str, max_gap: Option<usize>) -> Option<String> {
+    let value = rest
+        .trim_start()
+        .trim_start_matches(SEPARATORS)
+        .trim_start();
+    let gap = rest.chars().count() - value.chars().count();
+    if max_gap.is_some_and(|max| gap > max) {
+        return None;
+    }
+    split_cells(value, &CELL_DELIMITER)
+        .into_iter()
+        .next()
+        .map(|cell| cell.text)
+}
+
+/// Cell under `column` in the next `max_lines` non-empty lines, or the only
+/// cell of such a line. Stops at the end of the page.
+fn text_below<'a>(
+    lines: impl Iterator<Item = &'a str>,
+    column: Range<usize>,
+    max_lines: usize,
+) -> Option<String> {
+    let overlaps = |cell: &Chunk| cell.span.start < column.end && column.start < cell.span.end;
+    lines
+        .take_while(|line| !line.contains(PAGE_BREAK))
+        .filter(|line| !line.trim().is_empty())
+        .take(max_lines)
+        .find_map(|line| {
+            let mut cells = split_cells(line, &CELL_DELIMITER);
+            if cells.len() == 1 {
+                return cells.pop();
+            }
+            cells.into_iter().find(overlaps)
+        })
+        .map(|cell| cell.text)
+}
+
+/// Words are compared to labels case-insensitively and without surrounding
+/// separators or dots, so `No.:` matches the label `No`.
+fn token(text: &str) -> String {
+    text.trim_matches(|c| SEPARATORS.contains(&c) || c == '.')
+        .to_lowercase()
+}
+
+fn find_in_layout(layout: &OcrLayout, label: &str, options: &AnchorOptions) -> Option<AnchorMatch> {
+    let tokens: Vec<String> = label.split_whitespace().map(token).collect();
+    for page in &layout.pages {
+        for line in &page.lines {
+            let words = &line.words;
+            for start in 0..words.len().saturating_sub(tokens.len() - 1) {
+                let label_words = &words[start..start + tokens.len()];
+                if !label_words
+                    .iter()
+                    .zip(&tokens)
+                    .all(|(w, t)| token(&w.text) == *t)
+                {
+                    continue;
+                }
+                let Some(label_box) = union(label_words) else {
+                    continue;
+                };
+                let label_text = label_words
+                    .iter()
+                    .map(|w| w.text.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let char_width = label_box[2] as f64 / label_text.chars().count().max(1) as f64;
+                let value = match options.direction {
+                    Direction::Right => words_right_of(page, &label_box, char_width, options),
+                    Direction::Below => words_below(page, &label_box, char_width, options),
+                    Direction::Any => words_right_of(page, &label_box, char_width, options)
+                        .or_else(|| words_below(page, &label_box, char_width, options)),
+                };
+                if let Some(value) = value {
+                    return Some(AnchorMatch {
+                        value: value
+                            .iter()
+                            .map(|w| w.text.as_str())
+                            .collect::<Vec<_>>()
+                            .join(" "),
+                        label: label_text,
+                        page: page.page,
+                        bbox: union(&value),
+                    });
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Words right of `label` whose vertical centre lies within it. The first
+/// word has to start within `max_gap` characters of the label; the value
+/// ends at a gap wider than a space.
+fn words_right_of(
+    page: &OcrPage,
+    label: &BBox,
+    char_width: f64,
+    options: &AnchorOptions,
+) -> Option<Vec<OcrWord>> {
+    let right = label[0] + label[2];
+    let mut candidates: Vec<&OcrWord> = positioned_words(page)
+        .filter(|(_, b)| {
+            let centre = b[1] + b[3] / 2;
+            b[0] >= right && centre >= label[1] && centre <= label[1] + label[3]
+        })
+        .filter(|(w, _)| !token(&w.text).is_empty())
+        .map(|(w, _)| w)
+        .collect();
+    candidates.sort_by_key(|w| w.bbox.map(|b| b[0]));
+    let first = candidates.first()?.bbox?;
+    let gap = (first[0] - right) as f64 / char_width;
+    if options.max_gap.is_some_and(|max| gap > max as f64) {
+        return None;
+    }
+    Some(adjacent(&candidates, char_width))
+}
+
+/// Words under `label` on the first line below it, at most `max_lines_below`
+/// line heights away, continued to the right up to a gap wider than a space.
+fn words_below(
+    page: &OcrPage,
+    label: &BBox,
+    char_width: f64,
+    options: &AnchorOptions,
+) -> Option<Vec<OcrWord>> {
+    let bottom = label[1] + label[3];
+    let max_distance = options.max_lines_below as f64 * label[3] as f64 * 1.5;
+    let overlaps = |b: &BBox| b[0] < label[0] + label[2] && label[0] < b[0] + b[2];
+    let mut lines: Vec<(u32, Vec<&OcrWord>)> = page
+        .lines
+        .iter()
+        .filter_map(|line| {
+            let mut words: Vec<&OcrWord> = line
+                .words
+                .iter()
+                .filter(|w| w.bbox.is_some_and(|b| b[1] >= bottom))
+                .collect();
+            words.sort_by_key(|w| w.bbox.map(|b| b[0]));
+            let start = words
+                .iter()
+                .position(|w| w.bbox.is_some_and(|b| overlaps(&b)))?;
+            let top = words[start].bbox?[1];
+            ((top - bottom) as f64 <= max_distance).then(|| (top, words.split_off(start)))
+        })
+        .collect();
+    lines.sort_by_key(|(top, _)| *top);
+    let (_, words) = lines.into_iter().next()?;
+    Some(adjacent(&words, char_width))
+}
+
+fn positioned_words(page: &OcrPage) -> impl Iterator<Item = (&OcrWord, BBox)> {
+    page.lines
+        .iter()
+        .flat_map(|l| &l.words)
+        .filter_map(|w| w.bbox.map(|b| (w, b)))
+}
+
+/// Leading words of `words`, sorted left to right, up to a gap wider than
+/// one and a half characters.
+fn adjacent(words: &[&OcrWord], char_width: f64) -> Vec<OcrWord> {
+    let mut value: Vec<OcrWord> = Vec::new();
+    for word in words {
+        let (Some(b), Some(prev)) = (word.bbox, value.last().and_then(|w| w.bbox)) else {
+            value.push((*word).clone());
+            continue;
+        };
+        if b[0].saturating_sub(prev[0] + prev[2]) as f64 > 1.5 * char_width {
+            break;
+        }
+        value.push((*word).clone());
+    }
+    value
+}
+
+/// Smallest box around the boxes of `words`.
+fn union<'a>(words: impl IntoIterator<Item = &'a OcrWord>) -> Option<BBox> {
+    let boxes: Vec<BBox> = words.into_iter().filter_map(|w| w.bbox).collect();
+    let left = boxes.iter().map(|b| b[0]).min()?;
+    let top = boxes.iter().map(|b| b[1]).min()?;
+    let right = boxes.iter().map(|b| b[0] + b[2]).max()?;
+    let bottom = boxes.iter().map(|b| b[1] + b[3]).max()?;
+    Some([left, top, right - left, bottom - top])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::ocr_layout::OcrLine;
+
+    fn labels(labels: &[&str]) -> Vec<String> {
+        labels.iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn values_right_of_or_below_labels_in_text() {
+        let text = "Rechnung\n\
+            Rechnungsnummer:  RE-42      Datum: 12.03.2026\n\
+            Kunde                       Lieferdatum\n\
+            ACME GmbH                   10.03.2026\n\
+            \x0c\n\
+            Subtotal 90,00\n\
+            Total    100,00";
+        let options = AnchorOptions::default();
+        let found = find_value(
+            text,
+            None,
+            &labels(&["Invoice No.", "Rechnungsnummer"]),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            (found.value.as_str(), found.label.as_str()),
+            ("RE-42", "Rechnungsnummer")
+        );
+        let found = find_value(text, None, &labels(&["lieferdatum"]), &options).unwrap();
+        assert_eq!(found.value, "10.03.2026");
+        let found = find_value(text, None, &labels(&["Total"]), &options).unwrap();
+        assert_eq!((found.value.as_str(), found.page), ("100,00", 2));
+
+        let near = AnchorOptions {
+            direction: Direction::Right,
+            max_gap: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            find_value(text, None, &labels(&["Rechnungsnummer"]), &near),
+            None
+        );
+        let below = AnchorOptions {
+            direction: Direction::Below,
+            ..Default::default()
+        };
+        let found = find_value(text, None, &labels(&["Kunde"]), &below).unwrap();
+        assert_eq!(found.value, "ACME GmbH");
+    }
+
+    fn word(text: &str, bbox: BBox) -> OcrWord {
+        OcrWord {
+            text: text.into(),
+            confidence: None,
+            bbox: Some(bbox),
+        }
+    }
+
+    #[test]
+    fn values_follow_word_positions_in_layout() {
+        let line = |words: Vec<OcrWord>| OcrLine {
+            words,
+            ..Default::default()
+        };
+        let layout = OcrLayout {
+            pages: vec![OcrPage {
+                page: 1,
+                lines: vec![
+                    line(vec![
+                        word("Invoice", [100, 100, 70, 20]),
+                        word("No.:", [180, 100, 40, 20]),
+                        word("2024", [260, 102, 40, 20]),
+                        word("-17", [305, 102, 30, 20]),
+                        word("Date", [600, 100, 40, 20]),
+                    ]),
+                    line(vec![word("Customer", [100, 160, 80, 20])]),
+                    line(vec![
+                        word("ACME", [100, 190, 50, 20]),
+                        word("GmbH", [158, 190, 50, 20]),
+                        word("12.03.2026", [600, 130, 100, 20]),
+                    ]),
+                ],
+                ..Default::default()
+            }],
+        };
+        let options = AnchorOptions::default();
+        let found = find_value("", Some(&layout), &labels(&["invoice no"]), &options).unwrap();
+        assert_eq!(found.value, "2024 -17");
+        assert_eq!(found.label, "Invoice No.:");
+        assert_eq!(found.bbox, Some([260, 102, 75, 20]));
+        let found = find_value("", Some(&layout), &labels(&["Customer"]), &options).unwrap();
+        assert_eq!(found.value, "ACME GmbH");
+        let found = find_value("", Some(&layout), &labels(&["Date"]), &options).unwrap();
+        assert_eq!(found.value, "12.03.2026");
+    }
+}
@@ -0,0 +1,619 @@
+//! File formats accepted for upload and how text is obtained from them.
+//!
+//! PDFs and image scans go through OCR. DOCX, HTML, Markdown and plain text
+//! are converted directly, and emails contribute their headers and body plus
+//! the text of their attachments.
+
+use anyhow::{bail, Context, Result};
+use lopdf::Document as PdfDoc;
+use mail_parser::{MessageParser, MimeHeaders};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::io::{Cursor, Read};
+
+/// Format of an uploaded document, detected from its file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentFormat {
+    Pdf,
+    Png,
+    Jpeg,
+    Tiff,
+    Docx,
+    Html,
+    Email,
+    Markdown,
+    Text,
+}
+
+const ALL_FORMATS: [DocumentFormat; 9] = [
+    DocumentFormat::Pdf,
+    DocumentFormat::Png,
+    DocumentFormat::Jpeg,
+    DocumentFormat::Tiff,
+    DocumentFormat::Docx,
+    DocumentFormat::Html,
+    DocumentFormat::Email,
+    DocumentFormat::Markdown,
+    DocumentFormat::Text,
+];
+
+/// Attachments nested deeper than this are ignored.
+const MAX_EMAIL_DEPTH: usize = 3;
+/// Uncompressed size of a DOCX part that is read, so small archives cannot
+/// expand into huge allocations.
+const MAX_ZIP_ENTRY_BYTES: u64 = 64 * 1024 * 1024;
+
+impl DocumentFormat {
+    /// File extensions of the format; the first one is used for local copies.
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            DocumentFormat::Pdf => &["pdf"],
+            DocumentFormat::Png => &["png"],
+            DocumentFormat::Jpeg => &["jpg", "jpeg"],
+            DocumentFormat::Tiff => &["tif", "tiff"],
+            DocumentFormat::Docx => &["docx"],
+            DocumentFormat::Html => &["html", "htm"],
+            DocumentFormat::Email => &["eml"],
+            DocumentFormat::Markdown => &["md"],
+            DocumentFormat::Text => &["txt"],
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        self.extensions()[0]
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            DocumentFormat::Pdf => "application/pdf",
+            DocumentFormat::Png => "image/png",
+            DocumentFormat::Jpeg => "image/jpeg",
+            DocumentFormat::Tiff => "image/tiff",
+            DocumentFormat::Docx => {
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+            }
+            DocumentFormat::Html => "text/html",
+            DocumentFormat::Email => "message/rfc822",
+            DocumentFormat::Markdown => "text/markdown",
+            DocumentFormat::Text => "text/plain",
+        }
+    }
+
+    /// Detect the format from the extension of `filename`.
+    pub fn from_filename(filename: &str) -> Option<Self> {
+        let (_, ext) = filename.rsplit_once('.')?;
+        let ext = ext.to_ascii_lowercase();
+        ALL_FORMATS
+            .into_iter()
+            .find(|f| f.extensions().contains(&ext.as_str()))
+    }
+
+    /// Detect the format from a MIME type such as `image/jpeg`.
+    pub fn from_mime_type(mime: &str) -> Option<Self> {
+        let mime = mime.to_ascii_lowercase();
+        ALL_FORMATS
+            .into_iter()
+            .find(|f| f.mime_type() == mime || f.content_types().contains(&mime.as_str()))
+    }
+
+    /// Comma separated list of accepted extensions, e.g. for error messages.
+    pub fn supported_extensions() -> String {
+        ALL_FORMATS
+            .iter()
+            .flat_map(|f| f.extensions())
+            .map(|ext| format!(".{}", ext))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Upload content types accepted besides the canonical MIME type.
+    fn content_types(self) -> &'static [&'static str] {
+        match self {
+            DocumentFormat::Jpeg => &["image/jpg", "image/pjpeg"],
+            DocumentFormat::Tiff => &["image/tif"],
+            DocumentFormat::Docx => &["application/zip"],
+            DocumentFormat::Email => &["application/vnd.ms-outlook"],
+            DocumentFormat::Markdown => &["text/plain", "text/x-markdown"],
+            _ => &[],
+        }
+    }
+
+    /// Whether an upload declared as `content_type` may hold this format.
+    pub fn accepts_content_type(self, content_type: &str) -> bool {
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        mime.starts_with("application/octet-stream")
+            || mime == self.mime_type()
+            || self.content_types().contains(&mime.as_str())
+    }
+
+    /// Text based formats, whose declared content type varies between clients.
+    pub fn is_text(self) -> bool {
+        matches!(
+            self,
+            DocumentFormat::Html
+                | DocumentFormat::Email
+                | DocumentFormat::Markdown
+                | DocumentFormat::Text
+        )
+    }
+
+    /// Formats whose text is obtained through OCR.
+    pub fn needs_ocr(self) -> bool {
+        matches!(
+            self,
+            DocumentFormat::Pdf | DocumentFormat::Png | DocumentFormat::Jpeg | DocumentFormat::Tiff
+        )
+    }
+
+    /// Check the leading bytes of `bytes` against the signature of the format.
+    pub fn matches_signature(self, bytes: &[u8]) -> bool {
+        match self {
+            DocumentFormat::Pdf => bytes.starts_with(b"%PDF-"),
+            DocumentFormat::Png => bytes.starts_with(b"\x89PNG\r\n\x1a\n"),
+            DocumentFormat::Jpeg => bytes.starts_with(&[0xFF, 0xD8, 0xFF]),
+            DocumentFormat::Tiff => bytes.starts_with(b"II*\0") || bytes.starts_with(b"MM\0*"),
+            DocumentFormat::Docx => bytes.starts_with(b"PK\x03\x04"),
+            DocumentFormat::Html => {
+                let head = leading_text(bytes).to_ascii_lowercase();
+                ["<!doctype html", "<html", "<head", "<body"]
+                    .iter()
+                    .any(|tag| head.contains(tag))
+            }
+            DocumentFormat::Email => EMAIL_HEADER_RE.is_match(&leading_text(bytes)),
+            DocumentFormat::Markdown | DocumentFormat::Text => true,
+        }
+    }
+
+    /// Number of pages of the document.
+    ///
+    /// Text, Markdown and HTML are not paginated and count as 0 pages. Images
+    /// count as one page, TIFFs as one page per frame, emails as the pages of
+    /// their attachments.
+    pub fn page_count(self, bytes: &[u8]) -> Result<i32> {
+        match self {
+            DocumentFormat::Pdf => Ok(PdfDoc::load_mem(bytes)
+                .context("could not read PDF")?
+                .get_pages()
+                .len() as i32),
+            DocumentFormat::Png | DocumentFormat::Jpeg => Ok(1),
+            DocumentFormat::Tiff => tiff_frame_count(bytes),
+            DocumentFormat::Docx => docx_page_count(bytes),
+            DocumentFormat::Email => email_page_count(bytes, 0),
+            DocumentFormat::Html | DocumentFormat::Markdown | DocumentFormat::Text => Ok(0),
+        }
+    }
+}
+
+/// Header line such as `From: ...` at the start of an email.
+static EMAIL_HEADER_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^[A-Za-z][A-Za-z0-9-]*:[ \t]").unwrap());
+
+/// Up to the first KiB of `bytes` as text, without leading whitespace or BOM.
+fn leading_text(bytes: &[u8]) -> String {
+    let head = &bytes[..bytes.len().min(1024)];
+    String::from_utf8_lossy(head)
+        .trim_start_matches(['\u{feff}', ' ', '\t', '\r', '\n'])
+        .to_string()
+}
+
+/// Count the image file directories (frames) of a TIFF.
+fn tiff_frame_count(bytes: &[u8]) -> Result<i32> {
+    let little_endian = bytes.starts_with(b"II");
+    let read_u16 = |at: usize| -> Option<u16> {
+        let b: [u8; 2] = bytes.get(at..at + 2)?.try_into().ok()?;
+        Some(if little_endian {
+            u16::from_le_bytes(b)
+        } else {
+            u16::from_be_bytes(b)
+        })
+    };
+    let read_u32 = |at: usize| -> Option<u32> {
+        let b: [u8; 4] = bytes.get(at..at + 4)?.try_into().ok()?;
+        Some(if little_endian {
+            u32::from_le_bytes(b)
+        } else {
+            u32::from_be_bytes(b)
+        })
+    };
+    let mut offset = read_u32(4).context("truncated TIFF header")? as usize;
+    let mut frames = 0;
+    while offset != 0 {
+        // Offsets must move forward, which also rules out cycles
+        let entries = read_u16(offset).context("invalid TIFF directory offset")? as usize;
+        let next = read_u32(offset + 2 + entries * 12).context("truncated TIFF directory")?;
+        frames += 1;
+        if (next as usize) <= offset {
+            break;
+        }
+        offset = next as usize;
+    }
+    if frames == 0 {
+        bail!("TIFF contains no images");
+    }
+    Ok(frames)
+}
+
+/// Read the part `name` of a DOCX, refusing parts larger than `limit` bytes.
+fn read_zip_entry(bytes: &[u8], name: &str, limit: u64) -> Result<Option<String>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).context("could not read DOCX")?;
+    let entry = match archive.by_name(name) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e).context("could not read DOCX"),
+    };
+    if entry.size() > limit {
+        bail!("{} of DOCX is larger than {} bytes", name, limit);
+    }
+    // The size in the archive may be wrong, so the read is capped as well.
+    let mut content = String::new();
+    entry
+        .take(limit + 1)
+        .read_to_string(&mut content)
+        .with_context(|| format!("could not read {} of DOCX", name))?;
+    if content.len() as u64 > limit {
+        bail!("{} of DOCX is larger than {} bytes", name, limit);
+    }
+    Ok(Some(content))
+}
+
+fn docx_document_xml(bytes: &[u8]) -> Result<String> {
+    read_zip_entry(bytes, "word/document.xml", MAX_ZIP_ENTRY_BYTES)?
+        .context("DOCX has no word/document.xml")
+}
+
+static DOCX_PAGES_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"<Pages>(\d+)</Pages>").unwrap());
+static DOCX_PAGE_BREAK_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"<w:br [^>]*w:type="page"|<w:lastRenderedPageBreak/>"#).unwrap());
+
+/// Page count saved by the editor in `docProps/app.xml`, or one page more than
+/// the page breaks of the body when it is missing.
+fn docx_page_count(bytes: &[u8]) -> Result<i32> {
+    let body = docx_document_xml(bytes)?;
+    if let Some(pages) = read_zip_entry(bytes, "docProps/app.xml", MAX_ZIP_ENTRY_BYTES)?
+        .as_deref()
+        .and_then(|app| DOCX_PAGES_RE.captures(app))
+        .and_then(|c| c[1].parse::<i32>().ok())
+        .filter(|p| *p > 0)
+    {
+        return Ok(pages);
+    }
+    Ok(1 + DOCX_PAGE_BREAK_RE.find_iter(&body).count() as i32)
+}
+
+static DOCX_TOKEN_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"<w:t(?:\s[^>]*)?>([^<]*)</w:t>|<w:tab/>|<w:br(?:\s[^>]*)?/>|<w:cr/>|</w:p>|</w:tc>|</w:tr>")
+        .unwrap()
+});
+
+fn decode_xml_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Text of the body of a DOCX. Paragraphs end with a newline and table cells
+/// are separated by tabs, one row per line.
+pub fn docx_text(bytes: &[u8]) -> Result<String> {
+    let xml = docx_document_xml(bytes)?;
+    let mut text = String::new();
+    for token in DOCX_TOKEN_RE.captures_iter(&xml) {
+        if let Some(run) = token.get(1) {
+            text.push_str(&decode_xml_entities(run.as_str()));
+            continue;
+        }
+        match &token[0] {
+            "<w:tab/>" => text.push('\t'),
+            "</w:p>" => text.push('\n'),
+            "</w:tc>" => {
+                if text.ends_with('\n') {
+                    text.pop();
+                }
+                text.push('\t');
+            }
+            "</w:tr>" => {
+                if text.ends_with('\t') {
+                    text.pop();
+                }
+                text.push('\n');
+            }
+            // <w:br/> and <w:cr/>
+            _ => text.push('\n'),
+        }
+    }
+    Ok(text)
+}
+
+/// Readable text of an HTML document.
+pub fn html_text(html: &str) -> String {
+    mail_parser::decoders::html::html_to_text(html)
+}
+
+/// File attached to an email in one of the supported formats.
+#[derive(Debug)]
+pub struct EmailAttachment {
+    pub filename: String,
+    pub format: DocumentFormat,
+    pub bytes: Vec<u8>,
+}
+
+/// Email split into its readable text and its supported attachments.
+#[derive(Debug)]
+pub struct Email {
+    /// Main headers followed by the body.
+    pub text: String,
+    pub attachments: Vec<EmailAttachment>,
+    /// Names of attachments that were dropped because of their format.
+    pub skipped: Vec<String>,
+}
+
+/// Parse an `.eml` file.
+pub fn parse_email(bytes: &[u8]) -> Result<Email> {
+    let message = MessageParser::default()
+        .parse(bytes)
+        .context("could not parse email")?;
+    let mut text = String::new();
+    if let Some(from) = message.from().and_then(|a| a.first()) {
+        let address = from.address().unwrap_or_default();
+        match from.name() {
+            Some(name) => text.push_str(&format!("From: {} <{}>\n", name, address)),
+            None => text.push_str(&format!("From: {}\n", address)),
+        }
+    }
+    if let Some(to) = message.to() {
+        let recipients: Vec<&str> = to.iter().filter_map(|a| a.address()).collect();
+        text.push_str(&format!("To: {}\n", recipients.join(", ")));
+    }
+    if let Some(date) = message.date() {
+        text.push_str(&format!("Date: {}\n", date.to_rfc3339()));
+    }
+    if let Some(subject) = message.subject() {
+        text.push_str(&format!("Subject: {}\n", subject));
+    }
+    text.push('\n');
+    for idx in 0..message.text_body_count() {
+        if let Some(body) = message.body_text(idx) {
+            text.push_str(body.trim_end());
+            text.push('\n');
+        }
+    }
+
+    let mut attachments = Vec::new();
+    let mut skipped = Vec::new();
+    for (idx, part) in message.attachments().enumerate() {
+        let declared = part
+            .content_type()
+            .map(|ct| match ct.subtype() {
+                Some(sub) => format!("{}/{}", ct.ctype(), sub),
+                None => ct.ctype().to_string(),
+            })
+            .unwrap_or_default();
+        let format = if part.is_message() {
+            Some(DocumentFormat::Email)
+        } else {
+            part.attachment_name()
+                .and_then(DocumentFormat::from_filename)
+                .or_else(|| DocumentFormat::from_mime_type(&declared))
+        };
+        let filename = part
+            .attachment_name()
+            .map(str::to_string)
+            .unwrap_or_else(|| match format {
+                Some(f) => format!("attachment-{}.{}", idx + 1, f.extension()),
+                None => format!("attachment-{}", idx + 1),
+            });
+        match format {
+            Some(format) if format.matches_signature(part.contents()) => {
+                attachments.push(EmailAttachment {
+                    filename,
+                    format,
+                    bytes: part.contents().to_vec(),
+                })
+            }
+            _ => skipped.push(filename),
+        }
+    }
+    Ok(Email {
+        text,
+        attachments,
+        skipped,
+    })
+}
+
+fn email_page_count(bytes: &[u8], depth: usize) -> Result<i32> {
+    let email = parse_email(bytes)?;
+    let mut pages = 0;
+    for attachment in &email.attachments {
+        pages += match attachment.format {
+            DocumentFormat::Email if depth >= MAX_EMAIL_DEPTH => 0,
+            DocumentFormat::Email => email_page_count(&attachment.bytes, depth + 1)?,
+            // A broken attachment should not reject the whole email
+            format => format.page_count(&attachment.bytes).unwrap_or(0),
+        };
+    }
+    Ok(pages)
+}
+
+/// Text of a document in a format that does not need OCR. Attachments of
+/// emails are not included; see [`parse_email`].
+pub fn extract_text(format: DocumentFormat, bytes: &[u8]) -> Result<String> {
+    match format {
+        DocumentFormat::Docx => docx_text(bytes),
+        DocumentFormat::Html => Ok(html_text(&String::from_utf8_lossy(bytes))),
+        DocumentFormat::Email => Ok(parse_email(bytes)?.text),
+        DocumentFormat::Markdown | DocumentFormat::Text => {
+            Ok(String::from_utf8_lossy(bytes).into_owned())
+        }
+        format => bail!("{} documents need OCR", format.extension()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn docx(document_xml: &str, app_xml: Option<&str>) -> Vec<u8> {
+        let mut buf = Cursor::new(Vec::new());
+        {
+            let mut zip = zip::ZipWriter::new(&mut buf);
+            let options = zip::write::FileOptions::default();
+            zip.start_file("word/document.xml", options).unwrap();
+            zip.write_all(document_xml.as_bytes()).unwrap();
+            if let Some(app) = app_xml {
+                zip.start_file("docProps/app.xml", options).unwrap();
+                zip.write_all(app.as_bytes()).unwrap();
+            }
+            zip.finish().unwrap();
+        }
+        buf.into_inner()
+    }
+
+    fn tiff(frames: u32) -> Vec<u8> {
+        // Header followed by empty directories chained through their next offsets
+        let mut bytes = b"II*\0".to_vec();
+        bytes.extend_from_slice(&8u32.to_le_bytes());
+        for i in 0..frames {
+            let next = if i + 1 == frames { 0 } else { 8 + (i + 1) * 6 };
+            bytes.extend_from_slice(&0u16.to_le_bytes());
+            bytes.extend_from_slice(&next.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn detects_formats_by_extension_and_signature() {
+        assert_eq!(
+            DocumentFormat::from_filename("Scan.JPEG"),
+            Some(DocumentFormat::Jpeg)
+        );
+        assert_eq!(
+            DocumentFormat::from_filename("mail.eml"),
+            Some(DocumentFormat::Email)
+        );
+        assert_eq!(DocumentFormat::from_filename("archive.zip"), None);
+        assert_eq!(
+            DocumentFormat::from_mime_type("image/png"),
+            Some(DocumentFormat::Png)
+        );
+        assert!(DocumentFormat::Png.matches_signature(b"\x89PNG\r\n\x1a\n...."));
+        assert!(!DocumentFormat::Png.matches_signature(b"%PDF-1.4"));
+        assert!(DocumentFormat::Html.matches_signature(b"\n<!DOCTYPE html><html></html>"));
+        assert!(!DocumentFormat::Html.matches_signature(b"just text"));
+        assert!(DocumentFormat::Email.matches_signature(b"From: a@example.com\r\n"));
+        assert!(DocumentFormat::Jpeg.accepts_content_type("image/jpg"));
+        assert!(!DocumentFormat::Jpeg.accepts_content_type("image/png"));
+    }
+
+    #[test]
+    fn counts_pages_per_format() {
+        assert_eq!(DocumentFormat::Tiff.page_count(&tiff(3)).unwrap(), 3);
+        assert!(DocumentFormat::Tiff.page_count(b"II*\0").is_err());
+        let body = r#"<w:body><w:p><w:r><w:t>a</w:t></w:r></w:p><w:p><w:r><w:br w:type="page"/></w:r></w:p></w:body>"#;
+        assert_eq!(
+            DocumentFormat::Docx.page_count(&docx(body, None)).unwrap(),
+            2
+        );
+        let app = "<Properties><Pages>5</Pages></Properties>";
+        assert_eq!(
+            DocumentFormat::Docx
+                .page_count(&docx(body, Some(app)))
+                .unwrap(),
+            5
+        );
+        assert_eq!(
+            DocumentFormat::Html.page_count(b"<html></html>").unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn extracts_docx_paragraphs_and_tables() {
+        let body = concat!(
+            r#"<w:body><w:p><w:r><w:t xml:space="preserve">Invoice &amp; </w:t></w:r><w:r><w:t>Co</w:t></w:r></w:p>"#,
+            "<w:tbl><w:tr><w:tc><w:p><w:r><w:t>Item</w:t></w:r></w:p></w:tc>",
+            "<w:tc><w:p><w:r><w:t>Total</w:t></w:r></w:p></w:tc></w:tr></w:tbl></w:body>"
+        );
+        assert_eq!(
+            docx_text(&docx(body, None)).unwrap(),
+            "Invoice & Co\nItem\tTotal\n"
+        );
+    }
+
+    #[test]
+    fn refuses_docx_parts_over_the_size_limit() {
+        let bytes = docx("<w:body></w:body>", None);
+        assert!(read_zip_entry(&bytes, "word/document.xml", 1024)
+            .unwrap()
+            .is_some());
+        assert!(read_zip_entry(&bytes, "word/document.xml", 8).is_err());
+    }
+
+    #[test]
+    fn parses_email_body_and_attachments() {
+        let eml = concat!(
+            "From: Supplier <billing@supplier.example>\r\n",
+            "To: ap@example.com\r\n",
+            "Subject: Invoice 42\r\n",
+            "MIME-Version: 1.0\r\n",
+            "Content-Type: multipart/mixed; boundary=\"b\"\r\n\r\n",
+            "--b\r\nContent-Type: text/plain\r\n\r\nPlease find the invoice attached.\r\n",
+            "--b\r\nContent-Type: application/pdf; name=\"invoice.pdf\"\r\n",
+            "Content-Disposition: attachment; filename=\"invoice.pdf\"\r\n",
+            "Content-Transfer-Encoding: base64\r\n\r\nJVBERi0xLjQ=\r\n",
+            "--b\r\nContent-Type: application/zip; name=\"data.zip\"\r\n",
+            "Content-Disposition: attachment; filename=\"data.zip\"\r\n\r\nPK\r\n",
+            "--b--\r\n"
+        );
+        let email = parse_email(eml.as_bytes()).unwrap();
+        assert!(email
+            .text
+            .contains("From: Supplier <billing@supplier.example>"));
+        assert!(email.text.contains("Subject: Invoice 42"));
+        assert!(email.text.contains("Please find the invoice attached."));
+        assert_eq!(email.attachments.len(), 1);
+        assert_eq!(email.attachments[0].filename, "invoice.pdf");
+        assert_eq!(email.attachments[0].format, DocumentFormat::Pdf);
+        assert_eq!(email.attachments[0].bytes, b"%PDF-1.4");
+        assert_eq!(email.skipped, vec!["data.zip".to_string()]);
+    }
+}
@@ -1,4 +1,14 @@
+pub mod anchor;
+pub mod formats;
 pub mod ocr;
+pub mod ocr_engine;
+pub mod ocr_layout;
 pub mod parse;
 pub mod report;
+pub mod table;
 pub mod ai_client;
+pub mod retry;
+pub mod schema;
+pub mod template;
+pub mod values;
+pub mod webhook;
@@ -1,10 +1,15 @@
+use crate::processing::formats::DocumentFormat;
+use crate::processing::ocr_layout::OcrLayout;
+use crate::processing::retry::RetryPolicy;
 use crate::worker::metrics::S3_ERROR_COUNTER;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use aws_sdk_s3::Client as S3Client;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use lopdf::Document as PdfDoc;
 use reqwest::header::{HeaderValue, AUTHORIZATION};
 use reqwest::multipart;
-use std::path::Path;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
 use tokio::process::Command;
 
 #[derive(Debug)]
@@ -24,14 +29,7 @@ impl std::fmt::Display for OcrError {
 
 impl std::error::Error for OcrError {}
 
-const MAX_RETRIES: u32 = 3;
-const BASE_DELAY_MS: u64 = 500;
-
-fn backoff(attempt: u32) -> Duration {
-    Duration::from_millis(BASE_DELAY_MS * (1 << (attempt - 1)))
-}
-
-/// Download a PDF from S3 (or from `LOCAL_S3_DIR` when set) and write it to `path`.
+/// Download a document from S3 (or from `LOCAL_S3_DIR` when set) and write it to `path`.
 ///
 /// * `s3` - AWS S3 client for fetching the object.
 /// * `bucket` - Source bucket name.
@@ -65,43 +63,115 @@ pub async fn download_pdf(s3: &S3Client, bucket: &str, key: &str, path: &Path) -
     Ok(())
 }
 
-/// Send a PDF to an external OCR service and return the resulting text.
-#[tracing::instrument(skip(file_bytes))]
+/// Send a PDF or image to an external OCR service and return the resulting text.
 pub async fn run_external_ocr(
     api_endpoint: &str,
     api_key: Option<&str>,
     file_bytes: Vec<u8>,
     original_filename: &str,
+) -> Result<String, OcrError> {
+    run_external_ocr_with_policy(
+        api_endpoint,
+        api_key,
+        file_bytes,
+        original_filename,
+        &RetryPolicy::default(),
+    )
+    .await
+}
+
+/// Send a PDF or image to an external OCR service, retrying failed requests according to `policy`.
+/// The content type of the upload follows the extension of `original_filename`.
+#[tracing::instrument(skip(file_bytes))]
+pub async fn run_external_ocr_with_policy(
+    api_endpoint: &str,
+    api_key: Option<&str>,
+    file_bytes: Vec<u8>,
+    original_filename: &str,
+    policy: &RetryPolicy,
 ) -> Result<String, OcrError> {
     let client = reqwest::Client::new();
-    let mut attempts = 0;
-    loop {
+    let mime_type = mime_type(original_filename);
+    send_with_retries(policy, || {
         let file_part = multipart::Part::bytes(file_bytes.clone())
             .file_name(original_filename.to_string())
-            .mime_str("application/pdf")
+            .mime_str(mime_type)
             .map_err(OcrError::Request)?;
-        let mut request_builder = client
+        let request_builder = client
             .post(api_endpoint)
-            .timeout(Duration::from_secs(10))
+            .timeout(policy.timeout)
             .multipart(multipart::Form::new().part("file", file_part));
-        if let Some(key_str) = api_key.filter(|k| !k.trim().is_empty()) {
-            if key_str.to_lowercase().starts_with("bearer ") {
-                let token_part = key_str.split_at("bearer ".len()).1;
-                if let Ok(mut header_val) = HeaderValue::from_str(token_part) {
-                    header_val.set_sensitive(true);
-                    request_builder = request_builder.header(AUTHORIZATION, header_val);
-                }
-            } else if let Ok(mut header_val) = HeaderValue::from_str(key_str) {
+        Ok(with_api_key(request_builder, api_key))
+    })
+    .await
+}
+
+/// Send a PDF or image to an external OCR service as JSON, with the file
+/// base64 encoded in `content` next to its `filename` and `content_type`.
+/// Failed requests are retried according to `policy`.
+#[tracing::instrument(skip(file_bytes))]
+pub async fn run_external_json_ocr_with_policy(
+    api_endpoint: &str,
+    api_key: Option<&str>,
+    file_bytes: &[u8],
+    original_filename: &str,
+    policy: &RetryPolicy,
+) -> Result<String, OcrError> {
+    let client = reqwest::Client::new();
+    let body = serde_json::json!({
+        "filename": original_filename,
+        "content_type": mime_type(original_filename),
+        "content": BASE64.encode(file_bytes),
+    });
+    send_with_retries(policy, || {
+        let request_builder = client
+            .post(api_endpoint)
+            .timeout(policy.timeout)
+            .json(&body);
+        Ok(with_api_key(request_builder, api_key))
+    })
+    .await
+}
+
+/// Content type of an upload, following the extension of `filename`.
+fn mime_type(filename: &str) -> &'static str {
+    DocumentFormat::from_filename(filename).map_or("application/pdf", DocumentFormat::mime_type)
+}
+
+/// Authenticate a request with `api_key`: keys starting with `Bearer ` are
+/// sent as `Authorization` header, others as `X-API-KEY`.
+fn with_api_key(
+    mut request_builder: reqwest::RequestBuilder,
+    api_key: Option<&str>,
+) -> reqwest::RequestBuilder {
+    if let Some(key_str) = api_key.filter(|k| !k.trim().is_empty()) {
+        if key_str.to_lowercase().starts_with("bearer ") {
+            let token_part = key_str.split_at("bearer ".len()).1;
+            if let Ok(mut header_val) = HeaderValue::from_str(token_part) {
                 header_val.set_sensitive(true);
-                request_builder = request_builder.header("X-API-KEY", header_val);
+                request_builder = request_builder.header(AUTHORIZATION, header_val);
             }
+        } else if let Ok(mut header_val) = HeaderValue::from_str(key_str) {
+            header_val.set_sensitive(true);
+            request_builder = request_builder.header("X-API-KEY", header_val);
         }
-        match request_builder.send().await {
+    }
+    request_builder
+}
+
+/// Send the request built by `build` until it succeeds or `policy` gives up,
+/// and return the response body.
+async fn send_with_retries(
+    policy: &RetryPolicy,
+    build: impl Fn() -> Result<reqwest::RequestBuilder, OcrError>,
+) -> Result<String, OcrError> {
+    let mut attempts = 1;
+    loop {
+        match build()?.send().await {
             Ok(resp) => {
                 if resp.status().is_success() {
                     return resp.text().await.map_err(OcrError::Request);
-                } else if attempts < MAX_RETRIES {
-                    attempts += 1;
+                } else if policy.should_retry(attempts) {
                     let status = resp.status();
                     let msg = resp.text().await.unwrap_or_else(|_| "unknown".into());
                     log::warn!(
@@ -110,7 +180,8 @@ pub async fn run_external_ocr(
                         attempts,
                         msg
                     );
-                    tokio::time::sleep(backoff(attempts)).await;
+                    tokio::time::sleep(policy.backoff(attempts)).await;
+                    attempts += 1;
                 } else {
                     let status = resp.status();
                     let msg = resp.text().await.unwrap_or_else(|_| "unknown".into());
@@ -118,10 +189,10 @@ pub async fn run_external_ocr(
                 }
             }
             Err(e) => {
-                if attempts < MAX_RETRIES {
-                    attempts += 1;
+                if policy.should_retry(attempts) {
                     log::warn!("OCR request error attempt {}: {:?}", attempts, e);
-                    tokio::time::sleep(backoff(attempts)).await;
+                    tokio::time::sleep(policy.backoff(attempts)).await;
+                    attempts += 1;
                 } else {
                     return Err(OcrError::Request(e));
                 }
@@ -130,15 +201,137 @@ pub async fn run_external_ocr(
     }
 }
 
-/// Run the Tesseract OCR command locally.
-pub async fn run_ocr(input: &Path, output: &Path) -> Result<()> {
+/// Command line options passed to tesseract.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TesseractOptions {
+    /// Language packs, e.g. `deu+eng`.
+    pub languages: Option<String>,
+    /// Page segmentation mode.
+    pub psm: Option<u8>,
+    /// Resolution of the input image.
+    pub dpi: Option<u32>,
+}
+
+impl TesseractOptions {
+    fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(languages) = &self.languages {
+            args.extend(["-l".to_string(), languages.clone()]);
+        }
+        if let Some(psm) = self.psm {
+            args.extend(["--psm".to_string(), psm.to_string()]);
+        }
+        if let Some(dpi) = self.dpi {
+            args.extend(["--dpi".to_string(), dpi.to_string()]);
+        }
+        args
+    }
+}
+
+/// Run the Tesseract OCR command locally and return the recognised layout.
+///
+/// Tesseract writes its TSV output to `{output_base}.tsv`, which is removed
+/// after reading.
+pub async fn run_ocr(
+    input: &Path,
+    output_base: &Path,
+    options: &TesseractOptions,
+) -> Result<OcrLayout> {
     let status = Command::new("tesseract")
         .arg(input)
-        .arg(output)
+        .arg(output_base)
+        .args(options.args())
+        .arg("tsv")
+        .kill_on_drop(true)
         .status()
         .await?;
     if !status.success() {
         anyhow::bail!("tesseract failed");
     }
-    Ok(())
+    let tsv_path = with_suffix(output_base, ".tsv");
+    let tsv = tokio::fs::read_to_string(&tsv_path)
+        .await
+        .context("Failed to read tesseract output")?;
+    let _ = tokio::fs::remove_file(&tsv_path).await;
+    OcrLayout::from_tesseract_tsv(&tsv)
+}
+
+/// `path` with `suffix` appended to its file name.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Embedded text of every page of a PDF, in page order.
+///
+/// Pages whose content cannot be decoded come back empty so they are treated
+/// like scanned pages.
+pub fn pdf_page_texts(bytes: &[u8]) -> Result<Vec<String>> {
+    let doc = PdfDoc::load_mem(bytes).context("Failed to read PDF")?;
+    Ok(doc
+        .get_pages()
+        .keys()
+        .map(|page| doc.extract_text(&[*page]).unwrap_or_default())
+        .collect())
+}
+
+/// Share of the visible characters of `text` that are readable letters,
+/// digits or punctuation. Text layers of fonts without a usable encoding
+/// decode to control characters and private use glyphs and score low.
+pub fn text_coverage(text: &str) -> f64 {
+    let mut visible = 0usize;
+    let mut readable = 0usize;
+    for c in text.chars().filter(|c| !c.is_whitespace()) {
+        visible += 1;
+        let private_use = ('\u{E000}'..='\u{F8FF}').contains(&c);
+        if !c.is_control() && !private_use && c != char::REPLACEMENT_CHARACTER {
+            readable += 1;
+        }
+    }
+    if visible == 0 {
+        0.0
+    } else {
+        readable as f64 / visible as f64
+    }
+}
+
+/// Copy of a PDF that only contains page `page` (1-based).
+pub fn pdf_single_page(bytes: &[u8], page: u32) -> Result<Vec<u8>> {
+    let mut doc = PdfDoc::load_mem(bytes).context("Failed to read PDF")?;
+    let others: Vec<u32> = doc.get_pages().into_keys().filter(|p| *p != page).collect();
+    doc.delete_pages(&others);
+    doc.prune_objects();
+    let mut out = Vec::new();
+    doc.save_to(&mut out)
+        .context("Failed to write single page PDF")?;
+    Ok(out)
+}
+
+/// Number of pages of a PDF.
+pub fn pdf_page_count(bytes: &[u8]) -> Result<u32> {
+    let doc = PdfDoc::load_mem(bytes).context("Failed to read PDF")?;
+    Ok(doc.get_pages().len() as u32)
+}
+
+/// Render page `page` of the PDF at `input` to `{output_prefix}.png` at `dpi`
+/// with `pdftoppm`, since tesseract cannot read PDFs itself.
+pub async fn render_pdf_page(
+    input: &Path,
+    page: u32,
+    dpi: u32,
+    output_prefix: &Path,
+) -> Result<PathBuf> {
+    let (page, dpi) = (page.to_string(), dpi.to_string());
+    let status = Command::new("pdftoppm")
+        .args(["-f", &page, "-l", &page, "-r", &dpi, "-png", "-singlefile"])
+        .arg(input)
+        .arg(output_prefix)
+        .kill_on_drop(true)
+        .status()
+        .await?;
+    if !status.success() {
+        anyhow::bail!("pdftoppm failed");
+    }
+    Ok(with_suffix(output_prefix, ".png"))
 }
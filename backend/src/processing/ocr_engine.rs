@@ -0,0 +1,212 @@
+//! OCR engines selectable by name.
+//!
+//! OCR stages choose an engine with `ocr_engine`, organizations choose the
+//! engine of stages that leave it out. The built-in engines are registered
+//! on first use; further engines can be added with [`register`] and are then
+//! accepted by pipeline validation and reported in metrics like the others.
+
+use crate::processing::ocr::{self, TesseractOptions};
+use crate::processing::ocr_layout::{parse_external_response, OcrLayout};
+use crate::processing::retry::RetryPolicy;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+/// Engine used when neither the stage nor the organization choose one.
+pub const DEFAULT_ENGINE: &str = "tesseract";
+
+/// Remote OCR service documents are sent to.
+#[derive(Debug, Clone, Default)]
+pub struct OcrService {
+    pub endpoint: String,
+    pub api_key: Option<String>,
+    pub policy: RetryPolicy,
+}
+
+/// A document, or a single page of one, to recognise.
+pub struct OcrInput<'a> {
+    /// PDF or image on disk.
+    pub path: &'a Path,
+    /// File name reported to remote services.
+    pub filename: &'a str,
+    /// Base path for temporary files of the engine.
+    pub output_base: &'a Path,
+    pub options: &'a TesseractOptions,
+    pub service: &'a OcrService,
+}
+
+#[async_trait]
+pub trait OcrEngine: Send + Sync {
+    /// Name stages select the engine by. Also used as metrics label and as
+    /// `method` of the pages it recognised.
+    fn name(&self) -> &str;
+
+    /// Whether documents are sent to a remote service, which then needs an
+    /// endpoint. Remote engines apply the stage's retry policy themselves.
+    fn is_remote(&self) -> bool;
+
+    /// Whether PDF pages have to be rendered to images first.
+    fn needs_images(&self) -> bool {
+        false
+    }
+
+    /// Text and layout of `input`.
+    async fn recognise(&self, input: &OcrInput<'_>) -> Result<(String, OcrLayout)>;
+}
+
+/// Local `tesseract`, reading images.
+pub struct Tesseract;
+
+#[async_trait]
+impl OcrEngine for Tesseract {
+    fn name(&self) -> &str {
+        "tesseract"
+    }
+
+    fn is_remote(&self) -> bool {
+        false
+    }
+
+    fn needs_images(&self) -> bool {
+        true
+    }
+
+    async fn recognise(&self, input: &OcrInput<'_>) -> Result<(String, OcrLayout)> {
+        let layout = ocr::run_ocr(input.path, input.output_base, input.options)
+            .await
+            .context("Local OCR failed")?;
+        Ok((layout.text(), layout))
+    }
+}
+
+/// HTTP service receiving the document as multipart upload in `file`.
+pub struct MultipartHttp;
+
+#[async_trait]
+impl OcrEngine for MultipartHttp {
+    fn name(&self) -> &str {
+        "external"
+    }
+
+    fn is_remote(&self) -> bool {
+        true
+    }
+
+    async fn recognise(&self, input: &OcrInput<'_>) -> Result<(String, OcrLayout)> {
+        let file_bytes = read_input(input).await?;
+        let body = ocr::run_external_ocr_with_policy(
+            &input.service.endpoint,
+            input.service.api_key.as_deref(),
+            file_bytes,
+            input.filename,
+            &input.service.policy,
+        )
+        .await
+        .context("External OCR request failed")?;
+        Ok(parse_external_response(&body, self.name()))
+    }
+}
+
+/// HTTP service receiving the document base64 encoded in a JSON body, see
+/// [`ocr::run_external_json_ocr_with_policy`].
+pub struct JsonHttp;
+
+#[async_trait]
+impl OcrEngine for JsonHttp {
+    fn name(&self) -> &str {
+        "external_json"
+    }
+
+    fn is_remote(&self) -> bool {
+        true
+    }
+
+    async fn recognise(&self, input: &OcrInput<'_>) -> Result<(String, OcrLayout)> {
+        let file_bytes = read_input(input).await?;
+        let body = ocr::run_external_json_ocr_with_policy(
+            &input.service.endpoint,
+            input.service.api_key.as_deref(),
+            &file_bytes,
+            input.filename,
+            &input.service.policy,
+        )
+        .await
+        .context("External OCR request failed")?;
+        Ok(parse_external_response(&body, self.name()))
+    }
+}
+
+async fn read_input(input: &OcrInput<'_>) -> Result<Vec<u8>> {
+    tokio::fs::read(input.path)
+        .await
+        .context("Failed to read input document for external OCR")
+}
+
+static ENGINES: Lazy<RwLock<BTreeMap<String, Arc<dyn OcrEngine>>>> = Lazy::new(|| {
+    let builtin: [Arc<dyn OcrEngine>; 3] = [
+        Arc::new(Tesseract),
+        Arc::new(MultipartHttp),
+        Arc::new(JsonHttp),
+    ];
+    RwLock::new(
+        builtin
+            .into_iter()
+            .map(|engine| (engine.name().to_string(), engine))
+            .collect(),
+    )
+});
+
+/// Make `engine` available under its name, replacing an engine of the same
+/// name.
+pub fn register(engine: Arc<dyn OcrEngine>) {
+    ENGINES
+        .write()
+        .unwrap()
+        .insert(engine.name().to_string(), engine);
+}
+
+/// Registered engine called `name`.
+pub fn get(name: &str) -> Option<Arc<dyn OcrEngine>> {
+    ENGINES.read().unwrap().get(name).cloned()
+}
+
+/// Names of all registered engines, sorted.
+pub fn names() -> Vec<String> {
+    ENGINES.read().unwrap().keys().cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixed;
+
+    #[async_trait]
+    impl OcrEngine for Fixed {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        fn is_remote(&self) -> bool {
+            false
+        }
+
+        async fn recognise(&self, _input: &OcrInput<'_>) -> Result<(String, OcrLayout)> {
+            Ok(("fixed".into(), OcrLayout::from_text("fixed", self.name())))
+        }
+    }
+
+    #[test]
+    fn builtin_and_registered_engines_are_found_by_name() {
+        assert!(get("tesseract").is_some_and(|e| !e.is_remote() && e.needs_images()));
+        assert!(get("external").is_some_and(|e| e.is_remote()));
+        assert!(get("external_json").is_some_and(|e| e.is_remote()));
+        assert!(get("fixed").is_none());
+        register(Arc::new(Fixed));
+        assert!(get("fixed").is_some());
+        assert!(names().contains(&"fixed".to_string()));
+    }
+}
@@ -0,0 +1,306 @@
+//! Page-aware OCR results.
+//!
+//! OCR stages store an [`OcrLayout`] next to their text: the pages of the
+//! document with their lines and words, including confidence and bounding
+//! boxes where the engine provides them. In the text itself pages are
+//! separated by a line holding a single form feed, so stages that only see
+//! the text can still tell which page a match came from.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Character marking the start of a new page in OCR text.
+pub const PAGE_BREAK: char = '\x0c';
+/// Separator placed between the texts of two pages.
+pub const PAGE_SEPARATOR: &str = "\n\x0c\n";
+
+/// Bounding box as `[left, top, width, height]` in pixels of the page image.
+pub type BBox = [u32; 4];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct OcrWord {
+    pub text: String,
+    /// Recognition confidence between 0 and 100.
+    #[serde(default)]
+    pub confidence: Option<f32>,
+    #[serde(default)]
+    pub bbox: Option<BBox>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct OcrLine {
+    pub text: String,
+    /// Mean confidence of the words of the line.
+    #[serde(default)]
+    pub confidence: Option<f32>,
+    #[serde(default)]
+    pub bbox: Option<BBox>,
+    #[serde(default)]
+    pub words: Vec<OcrWord>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct OcrPage {
+    /// 1-based page number.
+    pub page: u32,
+    /// How the page was read: `text` for text that needed no OCR, otherwise
+    /// the OCR engine.
+    #[serde(default)]
+    pub method: String,
+    /// Share of readable characters in the PDF text layer, for pages checked
+    /// by the `auto` engine.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coverage: Option<f64>,
+    #[serde(default)]
+    pub width: Option<u32>,
+    #[serde(default)]
+    pub height: Option<u32>,
+    #[serde(default)]
+    pub lines: Vec<OcrLine>,
+}
+
+impl OcrPage {
+    /// Lines of the page joined by newlines.
+    pub fn text(&self) -> String {
+        self.lines
+            .iter()
+            .map(|l| l.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct OcrLayout {
+    pub pages: Vec<OcrPage>,
+}
+
+impl OcrLayout {
+    /// Layout of plain text without positions. Form feeds start new pages.
+    pub fn from_text(text: &str, method: &str) -> Self {
+        let text = text.trim_end_matches(|c: char| c == PAGE_BREAK || c.is_whitespace());
+        let pages = text
+            .split(PAGE_BREAK)
+            .enumerate()
+            .map(|(idx, page_text)| OcrPage {
+                page: idx as u32 + 1,
+                method: method.to_string(),
+                lines: page_text
+                    .lines()
+                    .map(str::trim)
+                    .filter(|l| !l.is_empty())
+                    .map(|l| OcrLine {
+                        text: l.to_string(),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            })
+            .collect();
+        Self { pages }
+    }
+
+    /// Parse the TSV output of `tesseract <input> <base> tsv`.
+    ///
+    /// Word rows (level 5) are grouped into lines by their page, block,
+    /// paragraph and line numbers; page rows (level 1) give the page size.
+    pub fn from_tesseract_tsv(tsv: &str) -> Result<Self> {
+        let mut pages: BTreeMap<u32, OcrPage> = BTreeMap::new();
+        let mut lines: BTreeMap<(u32, u32, u32, u32), OcrLine> = BTreeMap::new();
+        for (idx, row) in tsv.lines().enumerate().skip(1) {
+            let cols: Vec<&str> = row.splitn(12, '\t').collect();
+            if cols.len() < 11 {
+                continue;
+            }
+            let num = |i: usize| -> Result<u32> {
+                cols[i]
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("invalid tesseract TSV in row {}", idx + 1))
+            };
+            let (level, page) = (num(0)?, num(1)?);
+            let bbox = [num(6)?, num(7)?, num(8)?, num(9)?];
+            let page_entry = pages.entry(page).or_insert_with(|| OcrPage {
+                page,
+                method: "tesseract".into(),
+                ..Default::default()
+            });
+            if level == 1 {
+                page_entry.width = Some(bbox[2]);
+                page_entry.height = Some(bbox[3]);
+                continue;
+            }
+            let text = cols.get(11).map_or("", |t| t.trim());
+            if level != 5 || text.is_empty() {
+                continue;
+            }
+            let confidence = cols[10].trim().parse::<f32>().ok().filter(|c| *c >= 0.0);
+            let line = lines.entry((page, num(2)?, num(3)?, num(4)?)).or_default();
+            line.words.push(OcrWord {
+                text: text.to_string(),
+                confidence,
+                bbox: Some(bbox),
+            });
+        }
+        for ((page, ..), mut line) in lines {
+            line.text = line
+                .words
+                .iter()
+                .map(|w| w.text.as_str())
+                .collect::<Vec<_>>()
+                .join(" ");
+            let confidences: Vec<f32> = line.words.iter().filter_map(|w| w.confidence).collect();
+            if !confidences.is_empty() {
+                line.confidence = Some(confidences.iter().sum::<f32>() / confidences.len() as f32);
+            }
+            line.bbox = union(line.words.iter().filter_map(|w| w.bbox));
+            if let Some(p) = pages.get_mut(&page) {
+                p.lines.push(line);
+            }
+        }
+        Ok(Self {
+            pages: pages.into_values().collect(),
+        })
+    }
+
+    /// Text of all pages, separated by [`PAGE_SEPARATOR`].
+    pub fn text(&self) -> String {
+        self.pages
+            .iter()
+            .map(OcrPage::text)
+            .collect::<Vec<_>>()
+            .join(PAGE_SEPARATOR)
+    }
+
+    /// Set the method of every page.
+    pub fn with_method(mut self, method: &str) -> Self {
+        for page in &mut self.pages {
+            page.method = method.to_string();
+        }
+        self
+    }
+}
+
+/// Text and layout of an external OCR response, with pages read by `method`.
+///
+/// Services may answer with plain text, or with JSON holding `pages` in the
+/// [`OcrLayout`] format and/or a `text`.
+pub fn parse_external_response(body: &str, method: &str) -> (String, OcrLayout) {
+    #[derive(Deserialize)]
+    struct Structured {
+        text: Option<String>,
+        #[serde(default)]
+        pages: Vec<OcrPage>,
+    }
+    match serde_json::from_str::<Structured>(body) {
+        Ok(Structured {
+            text: Some(text),
+            pages,
+        }) if pages.is_empty() => {
+            let layout = OcrLayout::from_text(&text, method);
+            (text, layout)
+        }
+        Ok(structured) if !structured.pages.is_empty() => {
+            let layout = OcrLayout {
+                pages: structured.pages,
+            }
+            .with_method(method);
+            let text = structured.text.unwrap_or_else(|| layout.text());
+            (text, layout)
+        }
+        _ => (body.to_string(), OcrLayout::from_text(body, method)),
+    }
+}
+
+/// Smallest box containing all `boxes`.
+fn union(boxes: impl Iterator<Item = BBox>) -> Option<BBox> {
+    boxes.fold(None, |acc, [left, top, width, height]| {
+        let (right, bottom) = (left + width, top + height);
+        Some(match acc {
+            None => [left, top, width, height],
+            Some([l, t, w, h]) => {
+                let (l2, t2) = (l.min(left), t.min(top));
+                [l2, t2, (l + w).max(right) - l2, (t + h).max(bottom) - t2]
+            }
+        })
+    })
+}
+
+/// Join the texts of consecutive pages with [`PAGE_SEPARATOR`].
+pub fn join_pages<S: AsRef<str>>(pages: &[S]) -> String {
+    pages
+        .iter()
+        .map(|p| p.as_ref().trim_end())
+        .collect::<Vec<_>>()
+        .join(PAGE_SEPARATOR)
+}
+
+/// 1-based page of the byte `offset` in OCR text.
+pub fn page_at(text: &str, offset: usize) -> u32 {
+    let end = offset.min(text.len());
+    1 + text.as_bytes()[..end]
+        .iter()
+        .filter(|b| **b == PAGE_BREAK as u8)
+        .count() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tesseract_tsv_is_grouped_into_pages_and_lines() {
+        let tsv = "level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\tleft\ttop\twidth\theight\tconf\ttext\n\
+            1\t1\t0\t0\t0\t0\t0\t0\t2480\t3508\t-1\t\n\
+            4\t1\t1\t1\t1\t0\t100\t200\t400\t40\t-1\t\n\
+            5\t1\t1\t1\t1\t1\t100\t200\t150\t40\t96.5\tInvoice\n\
+            5\t1\t1\t1\t1\t2\t300\t205\t200\t30\t90.5\t2024-17\n\
+            5\t1\t1\t1\t2\t1\t100\t300\t100\t30\t-1\t \n\
+            1\t2\t0\t0\t0\t0\t0\t0\t2480\t3508\t-1\t\n\
+            5\t2\t1\t1\t1\t1\t50\t60\t70\t20\t80\tTotal\n";
+        let layout = OcrLayout::from_tesseract_tsv(tsv).unwrap();
+        assert_eq!(layout.pages.len(), 2);
+        let first = &layout.pages[0];
+        assert_eq!((first.width, first.height), (Some(2480), Some(3508)));
+        assert_eq!(first.lines.len(), 1);
+        assert_eq!(first.lines[0].text, "Invoice 2024-17");
+        assert_eq!(first.lines[0].confidence, Some(93.5));
+        assert_eq!(first.lines[0].bbox, Some([100, 200, 400, 40]));
+        assert_eq!(first.lines[0].words[1].bbox, Some([300, 205, 200, 30]));
+        assert_eq!(layout.pages[1].method, "tesseract");
+        assert_eq!(layout.text(), "Invoice 2024-17\n\x0c\nTotal");
+    }
+
+    #[test]
+    fn plain_text_pages_follow_form_feeds() {
+        let layout = OcrLayout::from_text("a\n\nb\n\x0cc\n\x0c", "external");
+        assert_eq!(layout.pages.len(), 2);
+        assert_eq!(layout.pages[0].text(), "a\nb");
+        assert_eq!(layout.pages[1].page, 2);
+        assert_eq!(layout.pages[1].method, "external");
+
+        let text = join_pages(&["one\n", "two"]);
+        assert_eq!(text, "one\n\x0c\ntwo");
+        assert_eq!(page_at(&text, 0), 1);
+        assert_eq!(page_at(&text, text.find("two").unwrap()), 2);
+    }
+
+    #[test]
+    fn external_responses_may_be_text_or_layout() {
+        let (text, layout) = parse_external_response("Total 5", "external");
+        assert_eq!(text, "Total 5");
+        assert_eq!(layout.pages[0].lines[0].text, "Total 5");
+
+        let body = r#"{"pages": [{"page": 1, "lines": [{"text": "Total 5", "confidence": 88.0,
+            "bbox": [1, 2, 3, 4], "words": []}]}]}"#;
+        let (text, layout) = parse_external_response(body, "external");
+        assert_eq!(text, "Total 5");
+        assert_eq!(layout.pages[0].method, "external");
+        assert_eq!(layout.pages[0].lines[0].bbox, Some([1, 2, 3, 4]));
+
+        let (text, layout) = parse_external_response(r#"{"text": "Total 5"}"#, "external_json");
+        assert_eq!(text, "Total 5");
+        assert_eq!(layout.pages[0].method, "external_json");
+    }
+}
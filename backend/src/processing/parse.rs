@@ -1,30 +1,86 @@
+use crate::processing::anchor::{find_value, AnchorField};
+use crate::processing::ocr_layout::{page_at, OcrLayout};
+use crate::processing::schema::{extract_fields, FieldSpec, FieldType};
+use crate::processing::table::{find_tables, TableOptions, DEFAULT_DELIMITER};
+use crate::processing::values::Locale;
 use anyhow::{anyhow, Result};
 use regex::Regex;
-use serde::Deserialize;
-use std::collections::HashMap;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 
-#[derive(Deserialize, Debug)]
-#[serde(tag = "strategy", content = "parameters", rename_all = "camelCase")]
+/// `config` of a parse stage. Strategies are accepted in camelCase and
+/// PascalCase, parameters in camelCase and snake_case.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(
+    tag = "strategy",
+    content = "parameters",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
 enum ParseConfig {
+    #[serde(alias = "KeywordExtraction")]
     KeywordExtraction {
         keywords: Vec<String>,
-        #[serde(default)]
+        #[serde(default, alias = "case_sensitive")]
         case_sensitive: bool,
     },
+    #[serde(alias = "RegexExtraction")]
     RegexExtraction {
         patterns: Vec<RegexPattern>,
+        /// Return each match as `{"value", "page"}` instead of a plain string.
+        #[serde(default, alias = "include_pages")]
+        include_pages: bool,
     },
+    #[serde(alias = "SimpleTableExtraction")]
     SimpleTableExtraction {
+        #[serde(alias = "header_keywords")]
         header_keywords: Vec<String>,
+        #[serde(default, alias = "stop_keywords")]
         stop_keywords: Option<Vec<String>>,
-        #[serde(default)]
+        #[serde(default, alias = "delimiter_regex")]
         delimiter_regex: Option<String>,
-        #[serde(default)]
+        #[serde(default, alias = "numeric_summary")]
         numeric_summary: bool,
+        /// Language tag like `de-DE` deciding how numbers and dates are read.
+        #[serde(default)]
+        locale: Option<String>,
+        /// Headers of columns whose empty cells repeat the row above.
+        #[serde(default, alias = "fill_down")]
+        fill_down: Vec<String>,
     },
+    /// Typed fields, see [`crate::processing::schema`].
+    #[serde(alias = "SchemaExtraction")]
+    SchemaExtraction {
+        fields: Vec<FieldSpec>,
+        #[serde(default)]
+        locale: Option<String>,
+    },
+    /// Values next to labels, see [`crate::processing::anchor`].
+    #[serde(alias = "AnchorExtraction")]
+    AnchorExtraction { fields: Vec<AnchorField> },
+    /// Several strategies over the same text, each result stored under the
+    /// name of its step. With `firstMatch` only the result of the first step
+    /// that found something is kept.
+    #[serde(alias = "Composite")]
+    Composite {
+        steps: Vec<CompositeStep>,
+        #[serde(default, alias = "first_match")]
+        first_match: bool,
+    },
+    #[serde(alias = "Passthrough")]
     Passthrough {},
 }
 
+/// Step of a composite strategy: a name plus `strategy` and `parameters`.
+#[derive(Deserialize, Debug, Clone)]
+struct CompositeStep {
+    name: String,
+    #[serde(flatten)]
+    config: ParseConfig,
+}
+
 fn default_capture_group_index() -> usize {
     1
 }
@@ -38,16 +94,327 @@ struct RegexPattern {
     capture_group_index: usize,
 }
 
-#[tracing::instrument(skip(text_content, config_json))]
+/// Whether the stage reads word positions, so the OCR layout should be
+/// passed to [`run_parse_stage_with_layout`].
+pub fn uses_layout(config_json: Option<&serde_json::Value>) -> bool {
+    let config: Option<ParseConfig> =
+        config_json.and_then(|c_val| serde_json::from_value(c_val.clone()).ok());
+    config.is_some_and(|c| c.uses_layout())
+}
+
+impl ParseConfig {
+    fn uses_layout(&self) -> bool {
+        match self {
+            ParseConfig::AnchorExtraction { .. } => true,
+            ParseConfig::SchemaExtraction { fields, .. } => {
+                fields.iter().any(|f| !f.labels.is_empty())
+            }
+            ParseConfig::Composite { steps, .. } => steps.iter().any(|s| s.config.uses_layout()),
+            _ => false,
+        }
+    }
+
+    /// Whether `result` of this strategy found anything, for `firstMatch`.
+    fn matched(&self, result: &serde_json::Value) -> bool {
+        match self {
+            ParseConfig::KeywordExtraction { .. } => result
+                .as_object()
+                .is_some_and(|counts| counts.values().any(|c| c.as_u64() > Some(0))),
+            ParseConfig::SimpleTableExtraction { .. } => result["status"] == "ok",
+            ParseConfig::SchemaExtraction { .. } => result["fields"]
+                .as_object()
+                .is_some_and(|fields| fields.values().any(|v| !v.is_null())),
+            ParseConfig::Passthrough {} => result["lines"]
+                .as_array()
+                .is_some_and(|lines| lines.iter().any(|l| l != "")),
+            ParseConfig::RegexExtraction { .. }
+            | ParseConfig::AnchorExtraction { .. }
+            | ParseConfig::Composite { .. } => {
+                result.as_object().is_some_and(|found| !found.is_empty())
+            }
+        }
+    }
+}
+
 pub async fn run_parse_stage(
     text_content: &str,
     config_json: Option<&serde_json::Value>,
 ) -> Result<serde_json::Value> {
-    let config: Option<ParseConfig> =
-        config_json.and_then(|c_val| serde_json::from_value(c_val.clone()).ok());
+    run_parse_stage_with_layout(text_content, None, config_json).await
+}
+
+/// Run a parse stage on OCR text. Strategies locating values by labels use
+/// the word positions of `layout` when it has any.
+#[tracing::instrument(skip(text_content, layout, config_json))]
+pub async fn run_parse_stage_with_layout(
+    text_content: &str,
+    layout: Option<&OcrLayout>,
+    config_json: Option<&serde_json::Value>,
+) -> Result<serde_json::Value> {
+    let Some(config_json) = config_json.filter(|c| !c.is_null()) else {
+        return Ok(lines_result(text_content, "Default (Lines)"));
+    };
+    let config = parse_config(config_json).map_err(|errors| {
+        let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        anyhow!("Invalid parse config: {}", errors.join("; "))
+    })?;
+    run_strategy(config, text_content, layout)
+}
+
+/// Problem with a parse stage's `config`, at a path like
+/// `parameters.patterns[0].regex`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    pub path: String,
+    pub message: String,
+}
+
+impl ConfigError {
+    fn new(path: impl Into<String>, message: impl fmt::Display) -> Self {
+        Self {
+            path: path.into(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Check the `config` of a parse stage: the strategy has to be known, its
+/// parameters complete and all regexes valid.
+pub fn validate_config(config: &serde_json::Value) -> Result<(), Vec<ConfigError>> {
+    parse_config(config).map(drop)
+}
+
+fn parse_config(config: &serde_json::Value) -> Result<ParseConfig, Vec<ConfigError>> {
+    let mut errors = Vec::new();
+    let parsed = deserialize_config(config, "", &mut errors);
+    if let Some(parsed) = &parsed {
+        parsed.validate("", &mut errors);
+    }
+    match parsed {
+        Some(parsed) if errors.is_empty() => Ok(parsed),
+        _ => Err(errors),
+    }
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn deserialize_config(
+    config: &serde_json::Value,
+    path: &str,
+    errors: &mut Vec<ConfigError>,
+) -> Option<ParseConfig> {
+    let err = match serde_json::from_value::<ParseConfig>(config.clone()) {
+        Ok(parsed) => return Some(parsed),
+        Err(err) => err,
+    };
+    if !config["strategy"].is_string() {
+        errors.push(ConfigError::new(
+            join_path(path, "strategy"),
+            "must name a parse strategy",
+        ));
+    } else if !item_errors(config, path, errors) {
+        let message = err.to_string();
+        let at = if message.starts_with("unknown variant") {
+            "strategy"
+        } else {
+            "parameters"
+        };
+        errors.push(ConfigError::new(join_path(path, at), message));
+    }
+    None
+}
+
+/// Errors in the items of list parameters, which serde reports without
+/// telling which item is wrong. Returns whether any were found.
+fn item_errors(config: &serde_json::Value, path: &str, errors: &mut Vec<ConfigError>) -> bool {
+    fn check<T: DeserializeOwned>(item: &serde_json::Value) -> Result<(), String> {
+        serde_json::from_value::<T>(item.clone())
+            .map(drop)
+            .map_err(|e| e.to_string())
+    }
+    let strategy = config["strategy"]
+        .as_str()
+        .unwrap_or_default()
+        .to_lowercase();
+    let key = match strategy.as_str() {
+        "regexextraction" => "patterns",
+        "schemaextraction" | "anchorextraction" => "fields",
+        "composite" => "steps",
+        _ => return false,
+    };
+    let Some(items) = config["parameters"][key].as_array() else {
+        return false;
+    };
+    let found = errors.len();
+    for (idx, item) in items.iter().enumerate() {
+        let item_path = format!("{}.{}[{}]", join_path(path, "parameters"), key, idx);
+        let result = match strategy.as_str() {
+            "regexextraction" => check::<RegexPattern>(item),
+            "schemaextraction" => check::<FieldSpec>(item),
+            "anchorextraction" => check::<AnchorField>(item),
+            _ => {
+                if !item["name"].is_string() {
+                    errors.push(ConfigError::new(
+                        join_path(&item_path, "name"),
+                        "must be a string",
+                    ));
+                }
+                // Steps that are well-formed still get their own checks.
+                if let Some(step) = deserialize_config(item, &item_path, errors) {
+                    step.validate(&item_path, errors);
+                }
+                continue;
+            }
+        };
+        if let Err(message) = result {
+            errors.push(ConfigError::new(item_path, message));
+        }
+    }
+    errors.len() > found
+}
+
+fn check_regex(regex: &str, path: String, errors: &mut Vec<ConfigError>) {
+    if let Err(e) = Regex::new(regex) {
+        errors.push(ConfigError::new(path, e));
+    }
+}
 
+/// Names have to be non-empty and unique among their siblings.
+fn check_name(name: &str, path: String, seen: &mut HashSet<String>, errors: &mut Vec<ConfigError>) {
+    if name.trim().is_empty() {
+        errors.push(ConfigError::new(path, "must not be empty"));
+    } else if !seen.insert(name.to_string()) {
+        errors.push(ConfigError::new(path, format!("duplicate name '{}'", name)));
+    }
+}
+
+impl ParseConfig {
+    /// Checks serde cannot express, reported relative to `path`.
+    fn validate(&self, path: &str, errors: &mut Vec<ConfigError>) {
+        let params = join_path(path, "parameters");
+        match self {
+            ParseConfig::KeywordExtraction { keywords, .. } => {
+                if keywords.is_empty() {
+                    errors.push(ConfigError::new(
+                        format!("{}.keywords", params),
+                        "must list at least one keyword",
+                    ));
+                }
+                for (idx, keyword) in keywords.iter().enumerate() {
+                    if keyword.is_empty() {
+                        errors.push(ConfigError::new(
+                            format!("{}.keywords[{}]", params, idx),
+                            "must not be empty",
+                        ));
+                    }
+                }
+            }
+            ParseConfig::RegexExtraction { patterns, .. } => {
+                for (idx, pattern) in patterns.iter().enumerate() {
+                    let at = format!("{}.patterns[{}]", params, idx);
+                    if pattern.name.trim().is_empty() {
+                        errors.push(ConfigError::new(
+                            format!("{}.name", at),
+                            "must not be empty",
+                        ));
+                    }
+                    check_regex(&pattern.regex, format!("{}.regex", at), errors);
+                }
+            }
+            ParseConfig::SimpleTableExtraction {
+                header_keywords,
+                delimiter_regex,
+                ..
+            } => {
+                if header_keywords.iter().all(|k| k.trim().is_empty()) {
+                    errors.push(ConfigError::new(
+                        format!("{}.headerKeywords", params),
+                        "must list at least one keyword",
+                    ));
+                }
+                if let Some(delimiter) = delimiter_regex.as_deref().filter(|d| !d.is_empty()) {
+                    check_regex(delimiter, format!("{}.delimiterRegex", params), errors);
+                }
+            }
+            ParseConfig::SchemaExtraction { fields, .. } => {
+                let mut names = HashSet::new();
+                for (idx, field) in fields.iter().enumerate() {
+                    let at = format!("{}.fields[{}]", params, idx);
+                    check_name(&field.name, format!("{}.name", at), &mut names, errors);
+                    match field.regex.as_deref() {
+                        Some(regex) => check_regex(regex, format!("{}.regex", at), errors),
+                        None if field.labels.is_empty() => {
+                            errors.push(ConfigError::new(at.clone(), "needs a regex or labels"));
+                        }
+                        None => {}
+                    }
+                    if field.field_type == FieldType::Enum && field.values.is_empty() {
+                        errors.push(ConfigError::new(
+                            format!("{}.values", at),
+                            "must list the allowed values of an enum field",
+                        ));
+                    }
+                }
+            }
+            ParseConfig::AnchorExtraction { fields } => {
+                let mut names = HashSet::new();
+                for (idx, field) in fields.iter().enumerate() {
+                    let at = format!("{}.fields[{}]", params, idx);
+                    check_name(&field.name, format!("{}.name", at), &mut names, errors);
+                    if field.labels.iter().all(|l| l.trim().is_empty()) {
+                        errors.push(ConfigError::new(
+                            format!("{}.labels", at),
+                            "must list at least one label",
+                        ));
+                    }
+                }
+            }
+            ParseConfig::Composite { steps, .. } => {
+                if steps.is_empty() {
+                    errors.push(ConfigError::new(
+                        format!("{}.steps", params),
+                        "must list at least one step",
+                    ));
+                }
+                let mut names = HashSet::new();
+                for (idx, step) in steps.iter().enumerate() {
+                    let at = format!("{}.steps[{}]", params, idx);
+                    check_name(&step.name, format!("{}.name", at), &mut names, errors);
+                    step.config.validate(&at, errors);
+                }
+            }
+            ParseConfig::Passthrough {} => {}
+        }
+    }
+}
+
+fn lines_result(text_content: &str, strategy_used: &str) -> serde_json::Value {
+    let lines: Vec<&str> = text_content.lines().map(|l| l.trim()).collect();
+    serde_json::json!({
+        "strategy_used": strategy_used,
+        "lines": lines,
+    })
+}
+
+fn run_strategy(
+    config: ParseConfig,
+    text_content: &str,
+    layout: Option<&OcrLayout>,
+) -> Result<serde_json::Value> {
     match config {
-        Some(ParseConfig::KeywordExtraction { keywords, case_sensitive }) => {
+        ParseConfig::KeywordExtraction { keywords, case_sensitive } => {
             let mut counts = HashMap::new();
             for keyword_orig in keywords {
                 let keyword_to_search = if case_sensitive {
@@ -65,7 +432,10 @@ pub async fn run_parse_stage(
             }
             Ok(serde_json::to_value(counts)?)
         }
-        Some(ParseConfig::RegexExtraction { patterns }) => {
+        ParseConfig::RegexExtraction {
+            patterns,
+            include_pages,
+        } => {
             let mut extractions = HashMap::new();
             for pattern_def in patterns {
                 match Regex::new(&pattern_def.regex) {
@@ -73,8 +443,14 @@ pub async fn run_parse_stage(
                         let mut field_matches = Vec::new();
                         for cap in re.captures_iter(text_content) {
                             match cap.get(pattern_def.capture_group_index) {
+                                Some(capture_match) if include_pages => {
+                                    field_matches.push(serde_json::json!({
+                                        "value": capture_match.as_str(),
+                                        "page": page_at(text_content, capture_match.start()),
+                                    }));
+                                }
                                 Some(capture_match) => {
-                                    field_matches.push(capture_match.as_str().to_string());
+                                    field_matches.push(capture_match.as_str().into());
                                 }
                                 None => {
                                     if pattern_def.capture_group_index != 0 {
@@ -83,7 +459,7 @@ pub async fn run_parse_stage(
                                                 "Capture group index {} out of bounds for regex '{}' (pattern: {}). Using full match instead.",
                                                 pattern_def.capture_group_index, pattern_def.regex, pattern_def.name
                                             );
-                                            field_matches.push(full_match.as_str().to_string());
+                                            field_matches.push(full_match.as_str().into());
                                         } else {
                                             log::error!(
                                                 "Critical error: No group 0 (full match) found for a regex match. Regex: '{}', Pattern: {}",
@@ -104,148 +480,94 @@ pub async fn run_parse_stage(
                         }
                     }
                     Err(e) => {
-                        log::warn!(
-                            "Invalid regex pattern '{}' for field '{}': {:?}. Skipping.",
+                        return Err(anyhow!(
+                            "Invalid regex '{}' for field '{}': {}",
                             pattern_def.regex,
-                            pattern_def.name.clone(),
+                            pattern_def.name,
                             e
-                        );
-                        extractions.insert(
-                            pattern_def.name.clone(),
-                            vec![format!("Regex Compile Error: {}", e)],
-                        );
+                        ));
                     }
                 }
             }
             Ok(serde_json::to_value(extractions)?)
         }
-        Some(ParseConfig::SimpleTableExtraction {
+        ParseConfig::SimpleTableExtraction {
             header_keywords,
             stop_keywords,
             delimiter_regex,
             numeric_summary,
-        }) => {
-            let lines: Vec<&str> = text_content.lines().collect();
-            let mut header_index = None;
-            for (idx, line) in lines.iter().enumerate() {
-                let lower = line.to_lowercase();
-                if header_keywords.iter().all(|kw| lower.contains(&kw.to_lowercase())) {
-                    header_index = Some(idx);
-                    break;
-                }
+            locale,
+            fill_down,
+        } => {
+            let regex_pattern = delimiter_regex
+                .as_deref()
+                .filter(|d| !d.is_empty())
+                .unwrap_or(DEFAULT_DELIMITER);
+            let delim_re = Regex::new(regex_pattern)
+                .map_err(|e| anyhow!("Invalid delimiter regex '{}': {}", regex_pattern, e))?;
+            let tables = find_tables(
+                text_content,
+                &TableOptions {
+                    header_keywords: &header_keywords,
+                    stop_keywords: stop_keywords.as_deref().unwrap_or_default(),
+                    delimiter: &delim_re,
+                    locale: Locale::from_tag(locale.as_deref()),
+                    fill_down: &fill_down,
+                },
+            );
+            if tables.is_empty() {
+                return Ok(serde_json::json!({
+                    "status": "header_not_found"
+                }));
             }
-            if let Some(h_idx) = header_index {
-                let regex_pattern = delimiter_regex.as_deref().unwrap_or(r"\s{2,}|\t|\s*\|\s*");
-                let delim_re = match Regex::new(regex_pattern) {
-                    Ok(re) => re,
-                    Err(e) => {
-                        log::warn!(
-                            "Invalid delimiter regex '{}': {:?}. Using default delimiter.",
-                            regex_pattern,
-                            e
-                        );
-                        Regex::new(r"\s{2,}|\t|\s*\|\s*")
-                            .map_err(|err| anyhow!("Failed to compile fallback delimiter regex: {}", err))?
-                    }
-                };
-                let headers: Vec<String> = delim_re
-                    .split(lines[h_idx].trim())
-                    .filter(|s| !s.is_empty())
-                    .map(|s| s.trim().to_string())
-                    .collect();
-                let mut rows: Vec<Vec<String>> = Vec::new();
-                for line in lines.iter().skip(h_idx + 1) {
-                    let trimmed = line.trim();
-                    if trimmed.is_empty() {
-                        continue;
-                    }
-                    let lower = trimmed.to_lowercase();
-                    if let Some(stops) = &stop_keywords {
-                        if stops.iter().any(|kw| lower.contains(&kw.to_lowercase())) {
-                            break;
-                        }
-                    }
-                    let row: Vec<String> = delim_re
-                        .split(trimmed)
-                        .filter(|s| !s.is_empty())
-                        .map(|s| s.trim().to_string())
-                        .collect();
-                    if !row.is_empty() {
-                        rows.push(row);
-                    }
-                }
-                let mut result = serde_json::json!({
-                    "status": "ok",
-                    "headers": headers,
-                    "rows": rows,
-                });
+            let mut tables_json = Vec::new();
+            for table in &tables {
+                let mut table_json = serde_json::to_value(table)?;
                 if numeric_summary {
-                    if let (Some(h), Some(r)) = (result.get("headers"), result.get("rows")) {
-                        let headers_vec = match h.as_array() {
-                            Some(arr) => arr,
-                            None => {
-                                log::error!("Expected headers to be an array");
-                                return Ok(result);
-                            }
-                        };
-                        let rows_vec = match r.as_array() {
-                            Some(arr) => arr,
-                            None => {
-                                log::error!("Expected rows to be an array");
-                                return Ok(result);
-                            }
-                        };
-                        let mut summary = serde_json::Map::new();
-                        for (col_idx, header_val) in headers_vec.iter().enumerate() {
-                            if let Some(header_str) = header_val.as_str() {
-                                let mut numeric: Vec<f64> = Vec::new();
-                                for row in rows_vec {
-                                    if let Some(cell) = row.get(col_idx) {
-                                        let normalized = cell.as_str().unwrap_or("").replace(',', ".");
-                                        if let Ok(n) = normalized.parse::<f64>() {
-                                            numeric.push(n);
-                                        } else {
-                                            numeric.clear();
-                                            break;
-                                        }
-                                    } else {
-                                        numeric.clear();
-                                        break;
-                                    }
-                                }
-                                if !numeric.is_empty() {
-                                    let sum: f64 = numeric.iter().sum();
-                                    let avg = sum / numeric.len() as f64;
-                                    summary.insert(
-                                        header_str.to_string(),
-                                        serde_json::json!({"sum": sum, "avg": avg}),
-                                    );
-                                }
-                            }
-                        }
-                        if !summary.is_empty() {
-                            if let Some(obj) = result.as_object_mut() {
-                                obj.insert(
-                                    "numeric_summary".to_string(),
-                                    serde_json::Value::Object(summary),
-                                );
-                            }
-                        }
+                    let summary = table.numeric_summary();
+                    if !summary.is_empty() {
+                        table_json["numeric_summary"] = serde_json::Value::Object(summary);
                     }
                 }
-                Ok(result)
-            } else {
-                Ok(serde_json::json!({
-                    "status": "header_not_found"
-                }))
+                tables_json.push(table_json);
+            }
+            // The first table is also returned at the top level, as before
+            // multiple tables were supported.
+            let mut result = tables_json[0].clone();
+            result["status"] = "ok".into();
+            result["tables"] = tables_json.into();
+            Ok(result)
+        }
+        ParseConfig::SchemaExtraction { fields, locale } => Ok(extract_fields(
+            text_content,
+            layout,
+            &fields,
+            &Locale::from_tag(locale.as_deref()),
+        )),
+        ParseConfig::AnchorExtraction { fields } => {
+            let mut extractions = serde_json::Map::new();
+            for field in &fields {
+                if let Some(found) = find_value(text_content, layout, &field.labels, &field.options)
+                {
+                    extractions.insert(field.name.clone(), serde_json::to_value(found)?);
+                }
             }
+            Ok(serde_json::Value::Object(extractions))
         }
-        Some(ParseConfig::Passthrough {}) | None => {
-            let lines: Vec<&str> = text_content.lines().map(|l| l.trim()).collect();
-            Ok(serde_json::json!({
-                "strategy_used": if config.is_some() { "Passthrough" } else { "Default (Lines)" },
-                "lines": lines,
-            }))
+        ParseConfig::Composite { steps, first_match } => {
+            let mut results = serde_json::Map::new();
+            for step in steps {
+                let result = run_strategy(step.config.clone(), text_content, layout)?;
+                let matched = step.config.matched(&result);
+                if !first_match || matched {
+                    results.insert(step.name, result);
+                }
+                if first_match && matched {
+                    break;
+                }
+            }
+            Ok(serde_json::Value::Object(results))
         }
+        ParseConfig::Passthrough {} => Ok(lines_result(text_content, "Passthrough")),
     }
 }
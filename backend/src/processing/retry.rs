@@ -0,0 +1,55 @@
+use std::time::Duration;
+
+/// Retry and timeout settings for outbound requests made by a stage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one.
+    pub max_attempts: u32,
+    /// Base delay for exponential backoff between attempts.
+    pub backoff_ms: u64,
+    /// Timeout applied to every single attempt.
+    pub timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    /// One attempt plus three retries, 500ms base backoff and a 10s timeout.
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            backoff_ms: 500,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `attempt` (starting at 1), doubling each time.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u64 << attempt.saturating_sub(1).min(16);
+        Duration::from_millis(self.backoff_ms.saturating_mul(factor))
+    }
+
+    /// Whether another attempt is allowed after `attempts` failed ones.
+    pub fn should_retry(&self, attempts: u32) -> bool {
+        attempts < self.max_attempts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RetryPolicy;
+    use std::time::Duration;
+
+    #[test]
+    fn backoff_doubles() {
+        let p = RetryPolicy {
+            max_attempts: 3,
+            backoff_ms: 100,
+            timeout: Duration::from_secs(1),
+        };
+        assert_eq!(p.backoff(1), Duration::from_millis(100));
+        assert_eq!(p.backoff(3), Duration::from_millis(400));
+        assert!(p.should_retry(2));
+        assert!(!p.should_retry(3));
+    }
+}
@@ -0,0 +1,190 @@
+//! Typed fields declared by a pipeline.
+//!
+//! Each field is found by a regex or after one of its labels, normalized and
+//! converted to its type. Fields that are missing or do not convert are
+//! `null` in the result and reported in `validation_errors`.
+
+use crate::processing::anchor::{find_value, AnchorOptions};
+use crate::processing::ocr_layout::OcrLayout;
+use crate::processing::values::{parse_amount, parse_date, parse_iban, parse_number, Locale};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    #[default]
+    String,
+    /// JSON number, read from a plain number or a currency amount.
+    Decimal,
+    /// Date as `YYYY-MM-DD`.
+    Date,
+    /// IBAN with valid check digits, upper-cased and without spaces.
+    Iban,
+    /// One of the field's `values`, matched case-insensitively.
+    Enum,
+}
+
+/// Applied to the found text before it is converted, in the order listed.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Normalization {
+    Uppercase,
+    Lowercase,
+    CollapseWhitespace,
+    RemoveWhitespace,
+}
+
+fn default_capture_group_index() -> usize {
+    1
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldSpec {
+    pub name: String,
+    #[serde(default, rename = "type")]
+    pub field_type: FieldType,
+    /// Regex whose capture group holds the value. Tried before `labels`.
+    #[serde(default)]
+    pub regex: Option<String>,
+    #[serde(default = "default_capture_group_index", alias = "capture_group_index")]
+    pub capture_group_index: usize,
+    /// Labels like `Invoice date` the value is next to, see
+    /// [`crate::processing::anchor`].
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Where the value may be relative to its label.
+    #[serde(flatten)]
+    pub anchor: AnchorOptions,
+    #[serde(default)]
+    pub required: bool,
+    /// Allowed values of `enum` fields.
+    #[serde(default)]
+    pub values: Vec<String>,
+    #[serde(default)]
+    pub normalize: Vec<Normalization>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    pub field: String,
+    pub message: String,
+    /// Text found for the field, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+/// Extract `fields` from `text` into
+/// `{"fields": {name: value}, "validation_errors": [...]}`. Labels are looked
+/// up in `layout` when it has word positions.
+pub fn extract_fields(
+    text: &str,
+    layout: Option<&OcrLayout>,
+    fields: &[FieldSpec],
+    locale: &Locale,
+) -> serde_json::Value {
+    let mut values = serde_json::Map::new();
+    let mut errors = Vec::new();
+    for field in fields {
+        let value = match find_text(text, layout, field) {
+            Ok(Some(found)) => {
+                let found = normalize(&found, &field.normalize);
+                match convert(&found, field, locale) {
+                    Ok(value) => value,
+                    Err(message) => {
+                        errors.push(ValidationError {
+                            field: field.name.clone(),
+                            message,
+                            value: Some(found),
+                        });
+                        serde_json::Value::Null
+                    }
+                }
+            }
+            Ok(None) => {
+                if field.required {
+                    errors.push(ValidationError {
+                        field: field.name.clone(),
+                        message: "Required field not found".into(),
+                        value: None,
+                    });
+                }
+                serde_json::Value::Null
+            }
+            Err(message) => {
+                errors.push(ValidationError {
+                    field: field.name.clone(),
+                    message,
+                    value: None,
+                });
+                serde_json::Value::Null
+            }
+        };
+        values.insert(field.name.clone(), value);
+    }
+    serde_json::json!({
+        "fields": values,
+        "validation_errors": errors,
+    })
+}
+
+/// Text of the first match of the field's regex, or else the value next to
+/// the first of its labels found.
+fn find_text(
+    text: &str,
+    layout: Option<&OcrLayout>,
+    field: &FieldSpec,
+) -> Result<Option<String>, String> {
+    if let Some(pattern) = field.regex.as_deref() {
+        let re = Regex::new(pattern).map_err(|e| format!("Invalid regex: {}", e))?;
+        if let Some(caps) = re.captures(text) {
+            let found = caps
+                .get(field.capture_group_index)
+                .or_else(|| caps.get(0))
+                .map(|m| m.as_str().trim().to_string())
+                .filter(|s| !s.is_empty());
+            if found.is_some() {
+                return Ok(found);
+            }
+        }
+    }
+    Ok(find_value(text, layout, &field.labels, &field.anchor).map(|found| found.value))
+}
+
+fn normalize(text: &str, steps: &[Normalization]) -> String {
+    let mut text = text.trim().to_string();
+    for step in steps {
+        text = match step {
+            Normalization::Uppercase => text.to_uppercase(),
+            Normalization::Lowercase => text.to_lowercase(),
+            Normalization::CollapseWhitespace => {
+                text.split_whitespace().collect::<Vec<_>>().join(" ")
+            }
+            Normalization::RemoveWhitespace => text.split_whitespace().collect(),
+        };
+    }
+    text
+}
+
+fn convert(text: &str, field: &FieldSpec, locale: &Locale) -> Result<serde_json::Value, String> {
+    match field.field_type {
+        FieldType::String => Ok(text.into()),
+        FieldType::Decimal => parse_number(text, locale)
+            .or_else(|| parse_amount(text, locale).map(|(value, _)| value))
+            .map(Into::into)
+            .ok_or_else(|| "Not a decimal number".to_string()),
+        FieldType::Date => parse_date(text, locale)
+            .map(|date| date.format("%Y-%m-%d").to_string().into())
+            .ok_or_else(|| "Not a date".to_string()),
+        FieldType::Iban => parse_iban(text)
+            .map(Into::into)
+            .ok_or_else(|| "Not a valid IBAN".to_string()),
+        FieldType::Enum => field
+            .values
+            .iter()
+            .find(|allowed| allowed.to_lowercase() == text.to_lowercase())
+            .map(|allowed| allowed.as_str().into())
+            .ok_or_else(|| format!("Not one of {}", field.values.join(", "))),
+    }
+}
@@ -0,0 +1,435 @@
+//! Tables in OCR text.
+//!
+//! A table starts at a header line containing all header keywords and ends
+//! at a stop keyword, at the header of a different table, after two blank
+//! lines or at the end of the text. The header repeated at the top of the
+//! next page continues the table.
+//!
+//! Cells are separated by the delimiter regex. Rows that do not split into
+//! as many cells as there are headers are aligned to the header by character
+//! position, which handles fixed-width layouts and empty or merged cells.
+//! Lines holding only some text cells continue the previous row, e.g. a
+//! wrapped description.
+
+use crate::processing::ocr_layout::PAGE_BREAK;
+use crate::processing::values::{parse_value, Locale, TypedValue};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+use std::ops::Range;
+
+/// Delimiter used when the stage sets none: two or more spaces, a tab or a
+/// pipe.
+pub const DEFAULT_DELIMITER: &str = r"\s{2,}|\t|\s*\|\s*";
+
+/// Text of a line between two delimiters, with its position in characters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub text: String,
+    pub span: Range<usize>,
+}
+
+/// Split `line` at `delimiter`, keeping the character position of each
+/// non-empty part.
+pub fn split_cells(line: &str, delimiter: &Regex) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut push = |from: usize, to: usize| {
+        let part = &line[from..to];
+        let text = part.trim();
+        if !text.is_empty() {
+            let lead = part.len() - part.trim_start().len();
+            let begin = line[..from + lead].chars().count();
+            chunks.push(Chunk {
+                text: text.to_string(),
+                span: begin..begin + text.chars().count(),
+            });
+        }
+    };
+    for m in delimiter.find_iter(line) {
+        push(start, m.start());
+        start = m.end();
+    }
+    push(start, line.len());
+    chunks
+}
+
+/// Split `line` at whitespace.
+fn split_words(line: &str) -> Vec<Chunk> {
+    static WHITESPACE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\s+").unwrap());
+    split_cells(line, &WHITESPACE)
+}
+
+/// Cell of a table with the value recognised in it.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Cell {
+    pub text: String,
+    #[serde(flatten)]
+    pub value: TypedValue,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Table {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    /// `rows` with the number, currency amount or date of each cell.
+    pub cells: Vec<Vec<Cell>>,
+    /// Page of the header line.
+    pub page: u32,
+    /// Page of each row.
+    pub row_pages: Vec<u32>,
+}
+
+impl Table {
+    /// Sum and average of every column holding only numbers or amounts,
+    /// keyed by the lower-cased header. Empty cells are left out.
+    pub fn numeric_summary(&self) -> serde_json::Map<String, serde_json::Value> {
+        let mut summary = serde_json::Map::new();
+        for (col, header) in self.headers.iter().enumerate() {
+            let values: Vec<Option<f64>> = self
+                .cells
+                .iter()
+                .filter_map(|row| row.get(col))
+                .filter(|cell| !cell.text.is_empty())
+                .map(|cell| cell.value.as_f64())
+                .collect();
+            if values.is_empty() || values.iter().any(Option::is_none) {
+                continue;
+            }
+            let sum: f64 = values.iter().flatten().sum();
+            summary.insert(
+                header.to_lowercase(),
+                serde_json::json!({"sum": sum, "avg": sum / values.len() as f64}),
+            );
+        }
+        summary
+    }
+}
+
+/// What to look for in [`find_tables`].
+pub struct TableOptions<'a> {
+    pub header_keywords: &'a [String],
+    pub stop_keywords: &'a [String],
+    pub delimiter: &'a Regex,
+    pub locale: Locale,
+    /// Headers of columns whose empty cells repeat the value of the row
+    /// above, for cells merged across rows.
+    pub fill_down: &'a [String],
+}
+
+/// Columns of a table, from the positions of the header cells.
+struct Columns {
+    starts: Vec<usize>,
+    /// Header cells are separated by single spaces only, so rows are too.
+    by_words: bool,
+}
+
+impl Columns {
+    fn from_header(line: &str, delimiter: &Regex) -> (Vec<String>, Self) {
+        let mut chunks = split_cells(line, delimiter);
+        let by_words = chunks.len() < 2;
+        if by_words {
+            chunks = split_words(line);
+        }
+        let headers = chunks.iter().map(|c| c.text.clone()).collect();
+        let starts = chunks.iter().map(|c| c.span.start).collect();
+        (headers, Self { starts, by_words })
+    }
+
+    fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// Column whose span overlaps `span` the most, or whose start is nearest.
+    fn column_of(&self, span: &Range<usize>) -> usize {
+        let overlap = |col: usize| {
+            let end = self.starts.get(col + 1).copied().unwrap_or(usize::MAX);
+            span.end
+                .min(end)
+                .saturating_sub(span.start.max(self.starts[col]))
+        };
+        let best = (0..self.len()).max_by_key(|&col| (overlap(col), std::cmp::Reverse(col)));
+        match best {
+            Some(col) if overlap(col) > 0 => col,
+            _ => (0..self.len())
+                .min_by_key(|&col| self.starts[col].abs_diff(span.start))
+                .unwrap_or(0),
+        }
+    }
+
+    /// Cells of a row line, in order when it splits into one part per
+    /// column, otherwise aligned by position.
+    fn place(&self, line: &str, delimiter: &Regex) -> Vec<String> {
+        let chunks = split_cells(line, delimiter);
+        if chunks.len() == self.len() {
+            return chunks.into_iter().map(|c| c.text).collect();
+        }
+        let words = split_words(line);
+        if self.by_words && words.len() == self.len() {
+            return words.into_iter().map(|c| c.text).collect();
+        }
+        let mut cells = vec![String::new(); self.len()];
+        for chunk in if self.by_words { words } else { chunks } {
+            let cell = &mut cells[self.column_of(&chunk.span)];
+            if !cell.is_empty() {
+                cell.push(' ');
+            }
+            cell.push_str(&chunk.text);
+        }
+        cells
+    }
+}
+
+fn contains_any(line: &str, keywords: &[String]) -> bool {
+    let lower = line.to_lowercase();
+    keywords.iter().any(|kw| lower.contains(&kw.to_lowercase()))
+}
+
+fn is_header(line: &str, keywords: &[String]) -> bool {
+    let lower = line.to_lowercase();
+    !keywords.is_empty() && keywords.iter().all(|kw| lower.contains(&kw.to_lowercase()))
+}
+
+/// All tables in `text`, in document order.
+pub fn find_tables(text: &str, options: &TableOptions) -> Vec<Table> {
+    let lines: Vec<&str> = text.lines().collect();
+    // Page of each line, counted from the page breaks of OCR text
+    let line_pages: Vec<u32> = lines
+        .iter()
+        .scan(1, |page, line| {
+            let current = *page;
+            *page += line.matches(PAGE_BREAK).count() as u32;
+            Some(current)
+        })
+        .collect();
+    let mut tables = Vec::new();
+    let mut idx = 0;
+    while idx < lines.len() {
+        if !is_header(lines[idx], options.header_keywords) {
+            idx += 1;
+            continue;
+        }
+        let (table, next) = read_table(&lines, &line_pages, idx, options);
+        tables.push(table);
+        idx = next;
+    }
+    tables
+}
+
+/// Read the table whose header is at `header_idx` and return it with the
+/// index of the first line after it.
+fn read_table(
+    lines: &[&str],
+    line_pages: &[u32],
+    header_idx: usize,
+    options: &TableOptions,
+) -> (Table, usize) {
+    let (headers, columns) = Columns::from_header(lines[header_idx], options.delimiter);
+    let mut table = Table {
+        headers,
+        rows: Vec::new(),
+        cells: Vec::new(),
+        page: line_pages[header_idx],
+        row_pages: Vec::new(),
+    };
+    let fill_down: Vec<usize> = table
+        .headers
+        .iter()
+        .enumerate()
+        .filter(|(_, h)| options.fill_down.iter().any(|f| f.eq_ignore_ascii_case(h)))
+        .map(|(col, _)| col)
+        .collect();
+    let mut blank_lines = 0;
+    // Whether a blank line or page break separates the line from the last row
+    let mut gap = false;
+    let mut idx = header_idx + 1;
+    while idx < lines.len() {
+        let line = lines[idx];
+        if line.trim().is_empty() {
+            if !line.contains(PAGE_BREAK) {
+                blank_lines += 1;
+                if blank_lines >= 2 {
+                    break;
+                }
+            }
+            gap = true;
+            idx += 1;
+            continue;
+        }
+        blank_lines = 0;
+        if contains_any(line, options.stop_keywords) {
+            break;
+        }
+        if is_header(line, options.header_keywords) {
+            let (headers, _) = Columns::from_header(line, options.delimiter);
+            let last_page = table.row_pages.last().copied().unwrap_or(table.page);
+            if headers == table.headers && line_pages[idx] > last_page {
+                // Header repeated on the next page
+                idx += 1;
+                continue;
+            }
+            break;
+        }
+        let texts = columns.place(line, options.delimiter);
+        let cells: Vec<Cell> = texts
+            .iter()
+            .map(|text| Cell {
+                text: text.clone(),
+                value: if text.is_empty() {
+                    TypedValue::Text
+                } else {
+                    parse_value(text, &options.locale)
+                },
+            })
+            .collect();
+        let filled = texts.iter().filter(|t| !t.is_empty()).count();
+        let only_text = cells.iter().all(|c| c.value == TypedValue::Text);
+        let continues = !gap
+            && only_text
+            && table
+                .rows
+                .last()
+                .is_some_and(|row| filled < row.iter().filter(|t| !t.is_empty()).count());
+        if continues {
+            let last = table.rows.len() - 1;
+            for (col, text) in texts.iter().enumerate().filter(|(_, t)| !t.is_empty()) {
+                let cell = &mut table.rows[last][col];
+                if !cell.is_empty() {
+                    cell.push(' ');
+                }
+                cell.push_str(text);
+                table.cells[last][col] = Cell {
+                    text: cell.clone(),
+                    value: parse_value(cell, &options.locale),
+                };
+            }
+        } else {
+            let (mut texts, mut cells) = (texts, cells);
+            if let (Some(prev_texts), Some(prev_cells)) = (table.rows.last(), table.cells.last()) {
+                for &col in &fill_down {
+                    if texts[col].is_empty() {
+                        texts[col] = prev_texts[col].clone();
+                        cells[col] = prev_cells[col].clone();
+                    }
+                }
+            }
+            table.rows.push(texts);
+            table.cells.push(cells);
+            table.row_pages.push(line_pages[idx]);
+        }
+        gap = false;
+        idx += 1;
+    }
+    (table, idx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options<'a>(
+        header: &'a [String],
+        stop: &'a [String],
+        delimiter: &'a Regex,
+    ) -> TableOptions<'a> {
+        TableOptions {
+            header_keywords: header,
+            stop_keywords: stop,
+            delimiter,
+            locale: Locale::default(),
+            fill_down: &[],
+        }
+    }
+
+    #[test]
+    fn chunks_keep_character_positions() {
+        let delimiter = Regex::new(DEFAULT_DELIMITER).unwrap();
+        let chunks = split_cells("  Büro   12,50 €| x", &delimiter);
+        let spans: Vec<_> = chunks
+            .iter()
+            .map(|c| (c.text.as_str(), c.span.clone()))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![("Büro", 2..6), ("12,50 €", 9..16), ("x", 18..19)]
+        );
+    }
+
+    #[test]
+    fn fixed_width_rows_are_aligned_and_wrapped_cells_joined() {
+        let text = "\
+Description            Qty      Amount
+Consulting services      1    1.200,00
+  for March
+Travel                            80,50
+Total                         1.280,50";
+        let delimiter = Regex::new(DEFAULT_DELIMITER).unwrap();
+        let header = vec!["description".to_string(), "amount".to_string()];
+        let stop = vec!["total".to_string()];
+        let tables = find_tables(text, &options(&header, &stop, &delimiter));
+        assert_eq!(tables.len(), 1);
+        let table = &tables[0];
+        assert_eq!(table.headers, vec!["Description", "Qty", "Amount"]);
+        assert_eq!(
+            table.rows,
+            vec![
+                vec!["Consulting services for March", "1", "1.200,00"],
+                vec!["Travel", "", "80,50"],
+            ]
+        );
+        assert_eq!(table.cells[0][2].value.as_f64(), Some(1200.0));
+        let summary = table.numeric_summary();
+        assert_eq!(summary["amount"]["sum"], 1280.5);
+        assert_eq!(summary["qty"]["sum"], 1.0);
+    }
+
+    #[test]
+    fn tables_continue_across_pages_and_several_are_found() {
+        let text = "\
+Item | Price
+Apple | 1.00
+\x0c
+Item | Price
+Pear | 2.00
+
+
+Notes about the order.
+Item | Price | Date
+Plum | 3.00 | 01.02.2024";
+        let delimiter = Regex::new(DEFAULT_DELIMITER).unwrap();
+        let header = vec!["item".to_string(), "price".to_string()];
+        let tables = find_tables(text, &options(&header, &[], &delimiter));
+        assert_eq!(tables.len(), 2);
+        assert_eq!(
+            tables[0].rows,
+            vec![vec!["Apple", "1.00"], vec!["Pear", "2.00"]]
+        );
+        assert_eq!(tables[0].row_pages, vec![1, 2]);
+        assert_eq!(tables[1].page, 2);
+        assert_eq!(
+            tables[1].cells[0][2].value,
+            TypedValue::Date {
+                value: "2024-02-01".into()
+            }
+        );
+    }
+
+    #[test]
+    fn merged_cells_fill_down() {
+        let text = "\
+Category   Item    Amount
+Food       Apple     1.00
+           Pear      2.00";
+        let delimiter = Regex::new(DEFAULT_DELIMITER).unwrap();
+        let header = vec!["category".to_string(), "amount".to_string()];
+        let fill_down = vec!["Category".to_string()];
+        let tables = find_tables(
+            text,
+            &TableOptions {
+                fill_down: &fill_down,
+                ..options(&header, &[], &delimiter)
+            },
+        );
+        assert_eq!(tables[0].rows[1], vec!["Food", "Pear", "2.00"]);
+    }
+}
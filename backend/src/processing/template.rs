@@ -0,0 +1,61 @@
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use serde_json::Value;
+
+static PLACEHOLDER_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\{\{\s*([\w.-]+)\s*\}\}").expect("valid placeholder regex"));
+
+/// Resolve a dotted path such as `input.totals.0.amount` inside `data`.
+/// Numeric segments index into arrays.
+fn resolve_path<'a>(data: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(data, |current, segment| match current {
+        Value::Object(map) => map.get(segment),
+        Value::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+        _ => None,
+    })
+}
+
+/// Render `{{placeholders}}` in `template` using values from `context`.
+///
+/// String values are inserted as-is, other JSON values are serialized.
+/// Placeholders that cannot be resolved are replaced with an empty string.
+pub fn render_template(template: &str, context: &Value) -> String {
+    PLACEHOLDER_RE
+        .replace_all(template, |caps: &Captures| {
+            let path = &caps[1];
+            match resolve_path(context, path) {
+                Some(Value::String(s)) => s.clone(),
+                Some(Value::Null) | None => {
+                    log::warn!("Unresolved template placeholder '{}'", path);
+                    String::new()
+                }
+                Some(other) => serde_json::to_string_pretty(other).unwrap_or_default(),
+            }
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render_template;
+    use serde_json::json;
+
+    #[test]
+    fn renders_strings_objects_and_paths() {
+        let ctx = json!({
+            "input": {"total": 12.5, "items": [{"name": "Apple"}]},
+            "ocr_text": "Invoice 42"
+        });
+        let out = render_template(
+            "Text: {{ ocr_text }} | first: {{input.items.0.name}} | total: {{input.total}}",
+            &ctx,
+        );
+        assert_eq!(out, "Text: Invoice 42 | first: Apple | total: 12.5");
+    }
+
+    #[test]
+    fn unknown_placeholders_become_empty() {
+        let out = render_template("a{{missing.key}}b", &json!({}));
+        assert_eq!(out, "ab");
+    }
+}
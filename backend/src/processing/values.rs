@@ -0,0 +1,344 @@
+//! Typed values in document text.
+//!
+//! Numbers, currency amounts and dates are recognised following the number
+//! and date conventions of a [`Locale`]. Without a locale the decimal
+//! separator is guessed for each number: `1.234,56` and `1,234.56` both read
+//! as 1234.56, `12,50` as 12.5.
+
+use chrono::NaiveDate;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+static NUMERIC_DATE_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(\d{1,4})([./-])(\d{1,2})([./-])(\d{1,4})$").unwrap());
+
+/// Currency symbols and the codes they stand for.
+const CURRENCY_SYMBOLS: [(&str, &str); 4] =
+    [("€", "EUR"), ("$", "USD"), ("£", "GBP"), ("¥", "JPY")];
+
+/// ISO 4217 codes recognised next to amounts.
+const CURRENCY_CODES: [&str; 24] = [
+    "EUR", "USD", "GBP", "CHF", "JPY", "CNY", "CAD", "AUD", "NZD", "SEK", "NOK", "DKK", "PLN",
+    "CZK", "HUF", "INR", "BRL", "MXN", "ZAR", "SGD", "HKD", "KRW", "TRY", "RUB",
+];
+
+/// Languages writing decimals with a comma, e.g. `1.234,56`.
+const COMMA_DECIMAL_LANGUAGES: [&str; 18] = [
+    "de", "fr", "es", "it", "nl", "pt", "da", "sv", "nb", "nn", "no", "fi", "pl", "cs", "sk", "ru",
+    "tr", "id",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DecimalSeparator {
+    /// Decide per number, see the module documentation.
+    #[default]
+    Auto,
+    Point,
+    Comma,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DateOrder {
+    #[default]
+    DayFirst,
+    MonthFirst,
+}
+
+/// Number and date conventions of a document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Locale {
+    pub decimal: DecimalSeparator,
+    /// Order of day and month in dates like `03/04/2024`. Dates separated by
+    /// dots are always read day first.
+    pub date_order: DateOrder,
+}
+
+impl Locale {
+    /// Conventions of a language tag like `de-DE` or `en-US`. Without a tag
+    /// the decimal separator is guessed per number.
+    pub fn from_tag(tag: Option<&str>) -> Self {
+        let Some(tag) = tag.map(str::trim).filter(|t| !t.is_empty()) else {
+            return Self::default();
+        };
+        let tag = tag.replace('_', "-").to_lowercase();
+        let language = tag.split('-').next().unwrap_or_default();
+        Self {
+            decimal: if COMMA_DECIMAL_LANGUAGES.contains(&language) {
+                DecimalSeparator::Comma
+            } else {
+                DecimalSeparator::Point
+            },
+            date_order: if tag == "en-us" {
+                DateOrder::MonthFirst
+            } else {
+                DateOrder::DayFirst
+            },
+        }
+    }
+}
+
+/// Value recognised in a piece of text.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum TypedValue {
+    Number {
+        value: f64,
+    },
+    Currency {
+        value: f64,
+        currency: String,
+    },
+    /// Date as `YYYY-MM-DD`.
+    Date {
+        value: String,
+    },
+    Text,
+}
+
+impl TypedValue {
+    /// Numeric value of numbers and currency amounts.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            TypedValue::Number { value } | TypedValue::Currency { value, .. } => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+/// Recognise a date, currency amount or number in `text`, in that order.
+pub fn parse_value(text: &str, locale: &Locale) -> TypedValue {
+    let text = text.trim();
+    if let Some(date) = parse_date(text, locale) {
+        return TypedValue::Date {
+            value: date.format("%Y-%m-%d").to_string(),
+        };
+    }
+    if let Some(value) = parse_number(text, locale) {
+        return TypedValue::Number { value };
+    }
+    match parse_amount(text, locale) {
+        Some((value, currency)) => TypedValue::Currency { value, currency },
+        None => TypedValue::Text,
+    }
+}
+
+/// Amount with a currency symbol or code before or after it, like
+/// `1.234,56 EUR` or `-$12.00`.
+pub fn parse_amount(text: &str, locale: &Locale) -> Option<(f64, String)> {
+    let text = text.trim();
+    if let Some(rest) = text.strip_prefix(['-', '−']) {
+        return parse_amount(rest, locale).map(|(value, currency)| (-value, currency));
+    }
+    for (symbol, code) in CURRENCY_SYMBOLS {
+        if let Some(rest) = text
+            .strip_prefix(symbol)
+            .or_else(|| text.strip_suffix(symbol))
+        {
+            return parse_number(rest, locale).map(|value| (value, code.to_string()));
+        }
+    }
+    for code in CURRENCY_CODES {
+        if let Some(rest) = text.strip_prefix(code).or_else(|| text.strip_suffix(code)) {
+            // The code must be separated from the amount or directly next to a digit
+            if rest.starts_with(|c: char| c.is_ascii_digit() || c.is_whitespace() || c == '-')
+                || rest.ends_with(|c: char| c.is_ascii_digit() || c.is_whitespace())
+            {
+                return parse_number(rest, locale).map(|value| (value, code.to_string()));
+            }
+        }
+    }
+    None
+}
+
+/// Number with optional sign, thousands separators and decimals. Negative
+/// numbers may also be written in parentheses or with a trailing minus.
+pub fn parse_number(text: &str, locale: &Locale) -> Option<f64> {
+    let text = text.trim();
+    let (negative, digits) =
+        if let Some(inner) = text.strip_prefix('(').and_then(|t| t.strip_suffix(')')) {
+            (true, inner.trim())
+        } else if let Some(rest) = text.strip_prefix(['-', '−']) {
+            (true, rest.trim_start())
+        } else if let Some(rest) = text.strip_suffix('-') {
+            (true, rest)
+        } else {
+            (false, text.strip_prefix('+').unwrap_or(text))
+        };
+    let is_group = |c: char| matches!(c, ' ' | '\'' | '\u{a0}' | '\u{202f}');
+    if !digits.starts_with(|c: char| c.is_ascii_digit())
+        || !digits.ends_with(|c: char| c.is_ascii_digit())
+        || !digits
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == '.' || c == ',' || is_group(c))
+    {
+        return None;
+    }
+    let decimal = match locale.decimal {
+        DecimalSeparator::Point => Some('.'),
+        DecimalSeparator::Comma => Some(','),
+        DecimalSeparator::Auto => guess_decimal(digits),
+    };
+    let (integer, fraction) = match decimal.and_then(|d| digits.rfind(d)) {
+        Some(pos) => (&digits[..pos], Some(&digits[pos + 1..])),
+        None => (digits, None),
+    };
+    if fraction.is_some_and(|f| !f.chars().all(|c| c.is_ascii_digit())) {
+        return None;
+    }
+    // Every group after the first needs three digits
+    let groups: Vec<&str> = integer
+        .split(|c: char| c == '.' || c == ',' || is_group(c))
+        .collect();
+    if groups.len() > 1 && (groups[0].is_empty() || groups[1..].iter().any(|g| g.len() != 3)) {
+        return None;
+    }
+    let mut plain = groups.concat();
+    if let Some(fraction) = fraction {
+        plain.push('.');
+        plain.push_str(fraction);
+    }
+    let value: f64 = plain.parse().ok()?;
+    Some(if negative { -value } else { value })
+}
+
+/// Decimal separator of `digits` when the locale is unknown: the last of
+/// `.` and `,` when both occur. Otherwise a single `.` is a decimal point, a
+/// single `,` too unless exactly three digits follow it, and repeated
+/// separators group thousands.
+fn guess_decimal(digits: &str) -> Option<char> {
+    match (digits.rfind('.'), digits.rfind(',')) {
+        (Some(point), Some(comma)) => Some(if point > comma { '.' } else { ',' }),
+        (Some(_), None) if digits.matches('.').count() == 1 => Some('.'),
+        (None, Some(pos)) if digits.matches(',').count() == 1 && digits.len() - pos - 1 != 3 => {
+            Some(',')
+        }
+        _ => None,
+    }
+}
+
+/// Date written as `2024-03-31`, `31.03.2024`, `31/03/2024` or `03/31/2024`.
+/// Two-digit years are taken as 20xx.
+pub fn parse_date(text: &str, locale: &Locale) -> Option<NaiveDate> {
+    let caps = NUMERIC_DATE_RE.captures(text.trim())?;
+    let (sep, sep2) = (&caps[2], &caps[4]);
+    if sep != sep2 {
+        return None;
+    }
+    let (first, second, third) = (&caps[1], &caps[3], &caps[5]);
+    let num = |s: &str| s.parse::<u32>().ok();
+    if first.len() == 4 {
+        return NaiveDate::from_ymd_opt(first.parse().ok()?, num(second)?, num(third)?);
+    }
+    let year: i32 = match third.len() {
+        4 => third.parse().ok()?,
+        2 => 2000 + third.parse::<i32>().ok()?,
+        _ => return None,
+    };
+    let (a, b) = (num(first)?, num(second)?);
+    let month_first =
+        sep != "." && (b > 12 || (a <= 12 && locale.date_order == DateOrder::MonthFirst));
+    let (day, month) = if month_first { (b, a) } else { (a, b) };
+    NaiveDate::from_ymd_opt(year, month, day)
+}
+
+/// IBAN with a valid length and check digits, upper-cased and without
+/// spaces.
+pub fn parse_iban(text: &str) -> Option<String> {
+    let iban: String = text
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase();
+    if !(15..=34).contains(&iban.len())
+        || !iban.chars().all(|c| c.is_ascii_alphanumeric())
+        || !iban[..2].chars().all(|c| c.is_ascii_alphabetic())
+        || !iban[2..4].chars().all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+    // ISO 7064 mod 97 over the rearranged IBAN, letters counting as 10 to 35
+    let mut remainder = 0;
+    for c in iban[4..].chars().chain(iban[..4].chars()) {
+        let digit = c.to_digit(36)?;
+        let shift = if digit < 10 { 10 } else { 100 };
+        remainder = (remainder * shift + digit) % 97;
+    }
+    (remainder == 1).then_some(iban)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_follow_locale_or_guessed_separator() {
+        let auto = Locale::default();
+        let de = Locale::from_tag(Some("de-DE"));
+        let en = Locale::from_tag(Some("en"));
+        assert_eq!(parse_number("1.234,56", &auto), Some(1234.56));
+        assert_eq!(parse_number("1,234.56", &auto), Some(1234.56));
+        assert_eq!(parse_number("12,50", &auto), Some(12.5));
+        assert_eq!(parse_number("1,234", &auto), Some(1234.0));
+        assert_eq!(parse_number("1.234", &de), Some(1234.0));
+        assert_eq!(parse_number("1.234", &en), Some(1.234));
+        assert_eq!(parse_number("1 234 567,8", &de), Some(1234567.8));
+        assert_eq!(parse_number("(12.00)", &auto), Some(-12.0));
+        assert_eq!(parse_number("12.00-", &auto), Some(-12.0));
+        assert_eq!(parse_number("1,23,4", &auto), None);
+        assert_eq!(parse_number("12a", &auto), None);
+    }
+
+    #[test]
+    fn values_are_typed() {
+        let auto = Locale::default();
+        assert_eq!(
+            parse_value("1.234,56 EUR", &auto),
+            TypedValue::Currency {
+                value: 1234.56,
+                currency: "EUR".into()
+            }
+        );
+        assert_eq!(
+            parse_value("-$5.00", &auto),
+            TypedValue::Currency {
+                value: -5.0,
+                currency: "USD".into()
+            }
+        );
+        assert_eq!(parse_value("€ 12,50", &auto).as_f64(), Some(12.5));
+        assert_eq!(
+            parse_value("31.03.2024", &auto),
+            TypedValue::Date {
+                value: "2024-03-31".into()
+            }
+        );
+        let us = Locale::from_tag(Some("en-US"));
+        assert_eq!(
+            parse_value("03/04/24", &us),
+            TypedValue::Date {
+                value: "2024-03-04".into()
+            }
+        );
+        assert_eq!(
+            parse_value("03/04/24", &auto),
+            TypedValue::Date {
+                value: "2024-04-03".into()
+            }
+        );
+        assert_eq!(parse_value("NET 30", &auto), TypedValue::Text);
+        assert_eq!(parse_value("Apple", &auto), TypedValue::Text);
+        assert_eq!(parse_value("42", &auto), TypedValue::Number { value: 42.0 });
+    }
+
+    #[test]
+    fn ibans_are_checked() {
+        assert_eq!(
+            parse_iban("de89 3704 0044 0532 0130 00").as_deref(),
+            Some("DE89370400440532013000")
+        );
+        assert!(parse_iban("GB82 WEST 1234 5698 7654 32").is_some());
+        assert!(parse_iban("DE89 3704 0044 0532 0130 01").is_none());
+        assert!(parse_iban("DE89 3704").is_none());
+    }
+}
@@ -0,0 +1,352 @@
+use crate::processing::formats::DocumentFormat;
+use crate::processing::retry::RetryPolicy;
+use hmac::{Hmac, Mac};
+use hyper::client::connect::dns::Name;
+use once_cell::sync::Lazy;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use reqwest::header::{HeaderName, HeaderValue, CONTENT_TYPE};
+use sha2::Sha256;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Header carrying the Unix timestamp the signature was computed with.
+pub const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+/// Header carrying `sha256=<hex HMAC>` of `<timestamp>.<payload JSON>`.
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+/// Client shared by all webhook calls. Building a client loads the TLS roots,
+/// which blocks the runtime long enough to time out concurrent requests.
+/// Redirects are not followed, so they cannot lead to hosts the worker
+/// refuses to call.
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .dns_resolver(Arc::new(PublicResolver))
+        .build()
+        .expect("webhook client configuration is valid")
+});
+
+#[derive(Debug)]
+pub enum WebhookError {
+    Request(reqwest::Error),
+    InvalidHeader(String),
+    HttpError(reqwest::StatusCode, String),
+    /// The URL is invalid or its host resolves to an internal address.
+    Destination(String),
+}
+
+impl std::fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebhookError::Request(e) => write!(f, "request error: {e}"),
+            WebhookError::InvalidHeader(name) => write!(f, "invalid header '{name}'"),
+            WebhookError::HttpError(status, msg) => write!(f, "http error {status}: {msg}"),
+            WebhookError::Destination(msg) => write!(f, "refused destination: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for WebhookError {}
+
+/// Outgoing webhook call.
+pub struct WebhookRequest<'a> {
+    pub url: &'a str,
+    /// Additional headers, already rendered.
+    pub headers: &'a [(String, String)],
+    /// JSON payload; sent as the body or as the `payload` part with a document.
+    pub payload: &'a serde_json::Value,
+    /// Document sent as the `document` part of a multipart body.
+    pub document: Option<(&'a str, &'a [u8])>,
+    /// Secret used to sign the payload.
+    pub signing_secret: Option<&'a str>,
+}
+
+/// Whether `ip` belongs to the loopback, private, link-local (including cloud
+/// metadata services) or another non-public range.
+fn is_internal_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                // Shared address space 100.64.0.0/10
+                || (ip.octets()[0] == 100 && ip.octets()[1] & 0xc0 == 64)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(v4) => is_internal_address(IpAddr::V4(v4)),
+            None => {
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local()
+            }
+        },
+    }
+}
+
+/// Whether `WEBHOOK_ALLOW_PRIVATE_NETWORKS=true` lets webhooks call internal addresses.
+fn private_networks_allowed() -> bool {
+    std::env::var("WEBHOOK_ALLOW_PRIVATE_NETWORKS")
+        .is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+}
+
+/// Resolve `host` and refuse it when any of its addresses is internal.
+async fn resolve_public(host: &str, port: u16) -> Result<Vec<SocketAddr>, String> {
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| format!("failed to resolve '{host}': {e}"))?
+        .collect();
+    if !private_networks_allowed() {
+        if let Some(addr) = addrs.iter().find(|a| is_internal_address(a.ip())) {
+            return Err(format!(
+                "host '{host}' resolves to internal address {}; set WEBHOOK_ALLOW_PRIVATE_NETWORKS to allow it",
+                addr.ip()
+            ));
+        }
+    }
+    Ok(addrs)
+}
+
+/// DNS resolver of the webhook client.
+///
+/// Host names are checked while the request connects, so the addresses that
+/// passed the check are the ones connected to, even when the DNS answer
+/// changes after [`check_destination`] looked at it.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs = resolve_public(name.as_str(), 0).await?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Parse a webhook URL, refusing schemes other than http(s) and internal IP
+/// addresses. Host names are checked by [`PublicResolver`].
+fn check_url(url: &str) -> Result<url::Url, WebhookError> {
+    let parsed = url::Url::parse(url)
+        .map_err(|e| WebhookError::Destination(format!("invalid url '{url}': {e}")))?;
+    if parsed.scheme() != "http" && parsed.scheme() != "https" {
+        return Err(WebhookError::Destination(format!(
+            "url '{url}' must use http or https"
+        )));
+    }
+    let ip = match parsed.host() {
+        Some(url::Host::Ipv4(ip)) => IpAddr::V4(ip),
+        Some(url::Host::Ipv6(ip)) => IpAddr::V6(ip),
+        Some(url::Host::Domain(_)) => return Ok(parsed),
+        None => {
+            return Err(WebhookError::Destination(format!(
+                "url '{url}' has no host"
+            )))
+        }
+    };
+    if is_internal_address(ip) && !private_networks_allowed() {
+        return Err(WebhookError::Destination(format!(
+            "address {ip} is internal; set WEBHOOK_ALLOW_PRIVATE_NETWORKS to allow it"
+        )));
+    }
+    Ok(parsed)
+}
+
+/// Refuse webhook URLs that are not http(s) or whose host resolves to an
+/// internal address, unless `WEBHOOK_ALLOW_PRIVATE_NETWORKS=true` is set.
+pub async fn check_destination(url: &str) -> Result<(), WebhookError> {
+    let parsed = check_url(url)?;
+    if let Some(url::Host::Domain(host)) = parsed.host() {
+        let port = parsed.port_or_known_default().unwrap_or(80);
+        resolve_public(host, port)
+            .await
+            .map_err(WebhookError::Destination)?;
+    }
+    Ok(())
+}
+
+/// HMAC-SHA256 signature of `<timestamp>.<body>` as `sha256=<hex>`.
+pub fn sign_payload(secret: &str, timestamp: u64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+fn build_request(
+    client: &reqwest::Client,
+    request: &WebhookRequest<'_>,
+    body: &[u8],
+    policy: &RetryPolicy,
+) -> Result<reqwest::RequestBuilder, WebhookError> {
+    let mut builder = client.post(request.url).timeout(policy.timeout);
+    for (name, value) in request.headers {
+        let header_name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| WebhookError::InvalidHeader(name.clone()))?;
+        let header_value =
+            HeaderValue::from_str(value).map_err(|_| WebhookError::InvalidHeader(name.clone()))?;
+        builder = builder.header(header_name, header_value);
+    }
+    if let Some(secret) = request.signing_secret {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        builder = builder
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(SIGNATURE_HEADER, sign_payload(secret, timestamp, body));
+    }
+    Ok(match request.document {
+        Some((filename, bytes)) => {
+            let payload = reqwest::multipart::Part::bytes(body.to_vec())
+                .mime_str("application/json")
+                .map_err(WebhookError::Request)?;
+            let mime_type = DocumentFormat::from_filename(filename)
+                .map_or("application/octet-stream", DocumentFormat::mime_type);
+            let document = reqwest::multipart::Part::bytes(bytes.to_vec())
+                .file_name(filename.to_string())
+                .mime_str(mime_type)
+                .map_err(WebhookError::Request)?;
+            let form = reqwest::multipart::Form::new()
+                .part("payload", payload)
+                .part("document", document);
+            builder.multipart(form)
+        }
+        None => builder
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.to_vec()),
+    })
+}
+
+/// POST the webhook, retrying failed requests according to `policy`, and
+/// return the JSON response. An empty response body yields `null`.
+pub async fn send_webhook(
+    request: &WebhookRequest<'_>,
+    policy: &RetryPolicy,
+) -> Result<serde_json::Value, WebhookError> {
+    let (status, bytes) = post_webhook(request, policy).await?;
+    if bytes.iter().all(u8::is_ascii_whitespace) {
+        return Ok(serde_json::Value::Null);
+    }
+    serde_json::from_slice(&bytes)
+        .map_err(|e| WebhookError::HttpError(status, format!("response is not JSON: {e}")))
+}
+
+/// POST the webhook, retrying failed requests according to `policy`, and
+/// return the status and body of the first successful response.
+#[tracing::instrument(skip(request), fields(url = request.url))]
+pub async fn post_webhook(
+    request: &WebhookRequest<'_>,
+    policy: &RetryPolicy,
+) -> Result<(reqwest::StatusCode, bytes::Bytes), WebhookError> {
+    check_url(request.url)?;
+    let client = &*CLIENT;
+    let body = serde_json::to_vec(request.payload).unwrap_or_default();
+    let mut attempts = 1;
+    loop {
+        // Multipart bodies cannot be cloned, so every attempt builds a fresh request.
+        let builder = build_request(client, request, &body, policy)?;
+        match builder.send().await {
+            Ok(resp) if resp.status().is_success() => {
+                let status = resp.status();
+                let bytes = resp.bytes().await.map_err(WebhookError::Request)?;
+                return Ok((status, bytes));
+            }
+            Ok(resp) => {
+                let status = resp.status();
+                let msg = resp.text().await.unwrap_or_else(|_| "unknown".into());
+                if !policy.should_retry(attempts) {
+                    return Err(WebhookError::HttpError(status, msg));
+                }
+                log::warn!(
+                    "Webhook request failed status {} attempt {}: {}",
+                    status,
+                    attempts,
+                    msg
+                );
+            }
+            Err(e) => {
+                if !policy.should_retry(attempts) {
+                    return Err(WebhookError::Request(e));
+                }
+                log::warn!("Webhook request error attempt {}: {:?}", attempts, e);
+            }
+        }
+        tokio::time::sleep(policy.backoff(attempts)).await;
+        attempts += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_destination, post_webhook, sign_payload, WebhookRequest};
+    use crate::processing::retry::RetryPolicy;
+    use serial_test::serial;
+    use std::time::Duration;
+    use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn signature_covers_timestamp_and_body() {
+        let sig = sign_payload("secret", 1700000000, b"{}");
+        assert!(sig.starts_with("sha256="));
+        assert_eq!(sig.len(), "sha256=".len() + 64);
+        assert_ne!(sig, sign_payload("secret", 1700000001, b"{}"));
+        assert_ne!(sig, sign_payload("other", 1700000000, b"{}"));
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn internal_destinations_are_refused() {
+        std::env::remove_var("WEBHOOK_ALLOW_PRIVATE_NETWORKS");
+        for url in [
+            "http://127.0.0.1:8080/hook",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://10.1.2.3/",
+            "http://[::1]/",
+            "http://[::ffff:192.168.0.1]/",
+            "ftp://93.184.216.34/",
+        ] {
+            assert!(check_destination(url).await.is_err(), "{}", url);
+        }
+        assert!(check_destination("http://93.184.216.34/").await.is_ok());
+        std::env::set_var("WEBHOOK_ALLOW_PRIVATE_NETWORKS", "true");
+        assert!(check_destination("http://127.0.0.1:8080/hook")
+            .await
+            .is_ok());
+        std::env::remove_var("WEBHOOK_ALLOW_PRIVATE_NETWORKS");
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn host_names_are_checked_when_connecting() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&server)
+            .await;
+        // A host name rather than an address, so only the resolver sees 127.0.0.1
+        let url = format!("http://localhost:{}/hook", server.address().port());
+        let payload = serde_json::json!({});
+        let request = WebhookRequest {
+            url: &url,
+            headers: &[],
+            payload: &payload,
+            document: None,
+            signing_secret: None,
+        };
+        let policy = RetryPolicy {
+            max_attempts: 1,
+            backoff_ms: 0,
+            timeout: Duration::from_secs(5),
+        };
+        std::env::remove_var("WEBHOOK_ALLOW_PRIVATE_NETWORKS");
+        assert!(post_webhook(&request, &policy).await.is_err());
+        assert!(server.received_requests().await.unwrap().is_empty());
+        std::env::set_var("WEBHOOK_ALLOW_PRIVATE_NETWORKS", "true");
+        assert!(post_webhook(&request, &policy).await.is_ok());
+        std::env::remove_var("WEBHOOK_ALLOW_PRIVATE_NETWORKS");
+    }
+}
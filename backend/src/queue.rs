@@ -0,0 +1,21 @@
+use redis::AsyncCommands;
+use uuid::Uuid;
+
+/// Push a job onto the Redis `jobs` list consumed by the workers.
+///
+/// Failures are logged; the job stays `pending` in the database.
+pub async fn enqueue_job(job_id: Uuid) {
+    if let Ok(redis_url) = std::env::var("REDIS_URL") {
+        if let Ok(client) = redis::Client::open(redis_url) {
+            if let Ok(mut conn) = client.get_async_connection().await {
+                let _: Result<(), _> = conn.rpush("jobs", job_id.to_string()).await;
+            } else {
+                log::error!("Failed to connect to Redis to queue job {}.", job_id);
+            }
+        } else {
+            log::error!("Failed to open Redis client to queue job {}.", job_id);
+        }
+    } else {
+        log::warn!("REDIS_URL not set, job {} not queued via Redis.", job_id);
+    }
+}
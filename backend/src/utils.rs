@@ -1,19 +1,31 @@
+use crate::middleware::auth::ApiUser;
 use crate::models::{AuditLog, NewAuditLog};
+use crate::processing::formats::DocumentFormat;
 use sqlx::PgPool;
 use uuid::Uuid;
 
 pub async fn log_action(pool: &PgPool, org_id: Uuid, user_id: Uuid, action: &str) {
-    let _ = AuditLog::create(pool, NewAuditLog { org_id, user_id, action: action.to_string() }).await;
+    let _ = AuditLog::create(pool, NewAuditLog { org_id, user_id, action: action.to_string(), api_key_id: None }).await;
+}
+
+/// Like [`log_action`], attributing the action to the API key of the request, if any.
+pub async fn log_api_action(pool: &PgPool, user: &ApiUser, action: &str) {
+    let new = NewAuditLog {
+        org_id: user.org_id,
+        user_id: user.user_id,
+        action: action.to_string(),
+        api_key_id: user.api_key_id(),
+    };
+    let _ = AuditLog::create(pool, new).await;
 }
 
-const PDF_MAGIC_BYTES: &[u8] = b"%PDF-";
 pub const MAX_FILE_SIZE: usize = 200 * 1024 * 1024; // 200MB
 
 pub fn validate_filename_and_type(
     user_filename: &str,
     file_content_type: &Option<String>,
     bytes_data: &[u8],
-) -> Result<(String, String), actix_web::HttpResponse> {
+) -> Result<(String, DocumentFormat), actix_web::HttpResponse> {
     use actix_web::HttpResponse;
     use std::path::Path;
 
@@ -40,57 +52,42 @@ pub fn validate_filename_and_type(
         ));
     }
 
-    let lower_filename = base_filename.to_lowercase();
-    let detected_file_type = if lower_filename.ends_with(".pdf") {
-        if let Some(ref ct) = file_content_type {
-            if ct != "application/pdf" && !ct.starts_with("application/octet-stream") {
-                log::warn!(
-                    "PDF upload for '{}': Mismatch Content-Type: {:?}, expected application/pdf or application/octet-stream",
-                    user_filename,
-                    file_content_type
-                );
-                return Err(HttpResponse::BadRequest().json(serde_json::json!({
-                    "error": "Invalid Content-Type for PDF file. Expected 'application/pdf'."
-                })));
-            }
-        }
-        if !bytes_data.starts_with(PDF_MAGIC_BYTES) {
-            log::warn!("Invalid PDF magic bytes for file '{}'", user_filename);
-            return Err(HttpResponse::BadRequest().json(
-                serde_json::json!({"error": "Invalid PDF file format (magic bytes mismatch)."}),
-            ));
-        }
-        "pdf"
-    } else if lower_filename.ends_with(".md") {
-        if file_content_type.as_deref().map_or(false, |ct| {
-            ct != "text/markdown"
-                && ct != "text/plain"
-                && !ct.starts_with("application/octet-stream")
-        }) {
+    let Some(format) = DocumentFormat::from_filename(&base_filename) else {
+        return Err(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Unsupported file type. Only {} are allowed.", DocumentFormat::supported_extensions())
+        })));
+    };
+
+    if let Some(ct) = file_content_type.as_deref().filter(|ct| !format.accepts_content_type(ct)) {
+        // Clients disagree on content types of text files, so only binary formats are strict
+        if format.is_text() {
             log::warn!(
-                "MD upload for '{}': Suspicious Content-Type: {:?}. Allowing.",
+                "{} upload for '{}': Suspicious Content-Type: {:?}. Allowing.",
+                format.extension().to_uppercase(),
                 user_filename,
-                file_content_type
+                ct
             );
-        }
-        "md"
-    } else if lower_filename.ends_with(".txt") {
-        if file_content_type.as_deref().map_or(false, |ct| {
-            ct != "text/plain" && !ct.starts_with("application/octet-stream")
-        }) {
+        } else {
             log::warn!(
-                "TXT upload for '{}': Suspicious Content-Type: {:?}. Allowing.",
+                "{} upload for '{}': Mismatch Content-Type: {:?}, expected {} or application/octet-stream",
+                format.extension().to_uppercase(),
                 user_filename,
-                file_content_type
+                ct,
+                format.mime_type()
             );
+            return Err(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Invalid Content-Type for {} file. Expected '{}'.", format.extension().to_uppercase(), format.mime_type())
+            })));
         }
-        "txt"
-    } else {
+    }
+
+    if !format.matches_signature(bytes_data) {
+        log::warn!("Invalid {} magic bytes for file '{}'", format.extension(), user_filename);
         return Err(HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Unsupported file type. Only .pdf, .md, .txt are allowed."
+            "error": format!("Invalid {} file format (magic bytes mismatch).", format.extension().to_uppercase())
         })));
-    };
+    }
 
-    Ok((base_filename, detected_file_type.to_string()))
+    Ok((base_filename, format))
 }
 
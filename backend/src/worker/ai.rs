@@ -1,29 +1,62 @@
 use crate::models::{AnalysisJob, OrgSettings};
 use crate::processing;
-use crate::worker::{metrics::API_ERROR_COUNTER, save_stage_output, Stage};
-use anyhow::Result;
+use crate::worker::{metrics::API_ERROR_COUNTER, save_stage_output, PromptTemplate, Stage};
+use anyhow::{anyhow, Result};
 use aws_sdk_s3::Client as S3Client;
 use sqlx::PgPool;
 use std::env;
 use tracing::{error, warn, info};
 
-/// Execute an AI stage and return the resulting JSON.
-#[tracing::instrument(skip(pool, s3, job, stage, org_settings, current_json, local_pdf))]
-pub async fn handle_ai_stage(
-    pool: &PgPool,
-    s3: &S3Client,
-    job: &AnalysisJob,
+/// Look up a prompt template by name in the organization's `prompt_templates`.
+pub fn find_prompt_template(
+    org_settings: Option<&OrgSettings>,
+    name: &str,
+) -> Option<PromptTemplate> {
+    let templates = org_settings.and_then(|s| s.prompt_templates.as_ref())?;
+    match serde_json::from_value::<Vec<PromptTemplate>>(templates.clone()) {
+        Ok(list) => list.into_iter().find(|t| t.name == name),
+        Err(e) => {
+            warn!("Failed to parse prompt_templates: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Build the request body for the AI endpoint.
+///
+/// Without a `prompt_name` the previous stage output is sent unchanged. With a
+/// prompt, the named template is rendered with `{{input}}` (previous stage JSON)
+/// and `{{ocr_text}}` and sent as `{"prompt": ..., "input": ...}`.
+pub fn build_ai_request(
     stage: &Stage,
     org_settings: Option<&OrgSettings>,
-    bucket: &str,
-    current_json: serde_json::Value,
-    local_pdf: &std::path::Path,
+    input_json: &serde_json::Value,
+    ocr_text: Option<&str>,
 ) -> Result<serde_json::Value> {
-    info!(job_id=%job.id, stage=%stage.stage_type, "start ai stage");
-    let timer = crate::worker::metrics::STAGE_HISTOGRAM
-        .with_label_values(&[stage.stage_type.as_str()])
-        .start_timer();
-    let (endpoint, key) = if let Some(settings) = org_settings {
+    let Some(prompt_name) = stage.prompt_name.as_deref() else {
+        return Ok(input_json.clone());
+    };
+    let template = find_prompt_template(org_settings, prompt_name).ok_or_else(|| {
+        anyhow!(
+            "Prompt template '{}' not found in organization settings",
+            prompt_name
+        )
+    })?;
+    let context = serde_json::json!({
+        "input": input_json,
+        "ocr_text": ocr_text.unwrap_or_default(),
+    });
+    let prompt = processing::template::render_template(&template.text, &context);
+    Ok(serde_json::json!({
+        "prompt": prompt,
+        "input": input_json,
+    }))
+}
+
+/// Endpoint and key of the organization's AI service, falling back to
+/// `AI_API_URL` and `AI_API_KEY`.
+pub fn ai_service(org_settings: Option<&OrgSettings>) -> (String, String) {
+    if let Some(settings) = org_settings {
         let ep = settings
             .ai_api_endpoint
             .clone()
@@ -38,7 +71,39 @@ pub async fn handle_ai_stage(
             env::var("AI_API_URL").unwrap_or_default(),
             env::var("AI_API_KEY").unwrap_or_default(),
         )
-    };
+    }
+}
+
+/// Data passed to an `ai` stage.
+pub struct AiStageRequest<'a> {
+    /// Settings of the job's organization, overriding the AI service and prompts.
+    pub org_settings: Option<&'a OrgSettings>,
+    /// Output of the upstream stages.
+    pub input: serde_json::Value,
+    /// Text of the nearest OCR stage, if any.
+    pub ocr_text: Option<&'a str>,
+}
+
+/// Execute an AI stage and return the resulting JSON.
+#[tracing::instrument(skip(pool, s3, job, stage, request))]
+pub async fn handle_ai_stage(
+    pool: &PgPool,
+    s3: &S3Client,
+    job: &AnalysisJob,
+    stage: &Stage,
+    bucket: &str,
+    request: AiStageRequest<'_>,
+) -> Result<serde_json::Value> {
+    let AiStageRequest {
+        org_settings,
+        input: current_json,
+        ocr_text,
+    } = request;
+    info!(job_id=%job.id, stage=%stage.name(), "start ai stage");
+    let timer = crate::worker::metrics::STAGE_HISTOGRAM
+        .with_label_values(&[stage.stage_type.as_str()])
+        .start_timer();
+    let (endpoint, key) = ai_service(org_settings);
 
     if endpoint.is_empty() {
         error!(job_id=%job.id, "AI endpoint missing");
@@ -46,12 +111,18 @@ pub async fn handle_ai_stage(
         return Err(anyhow::anyhow!("AI endpoint missing"));
     }
 
-    let input_json = current_json.clone();
-    // prompt handling skipped
+    let input_json = match build_ai_request(stage, org_settings, &current_json, ocr_text) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(job_id=%job.id, "{}", e);
+            timer.observe_duration();
+            return Err(e);
+        }
+    };
 
     // Save AI input
     if let Ok(bytes) = serde_json::to_vec_pretty(&input_json) {
-        let name = format!("{}_input", stage.stage_type);
+        let name = format!("{}_input", stage.name());
         if let Err(e) =
             save_stage_output(pool, s3, job.id, &name, "json", bucket, bytes, "json").await
         {
@@ -62,7 +133,15 @@ pub async fn handle_ai_stage(
     let headers = org_settings.and_then(|s| s.ai_custom_headers.as_ref());
 
     // Kombiniert beide Logiken: Counter und die richtige run_ai-Methode!
-    let result = match processing::ai_client::run_ai(&input_json, &endpoint, &key, headers).await {
+    let result = match processing::ai_client::run_ai_with_policy(
+        &input_json,
+        &endpoint,
+        &key,
+        headers,
+        &stage.retry_policy(),
+    )
+    .await
+    {
         Ok(r) => r,
         Err(e) => {
             API_ERROR_COUNTER.with_label_values(&["ai"]).inc();
@@ -77,7 +156,7 @@ pub async fn handle_ai_stage(
             pool,
             s3,
             job.id,
-            &stage.stage_type,
+            stage.name(),
             "json",
             bucket,
             bytes,
@@ -89,12 +168,129 @@ pub async fn handle_ai_stage(
         }
     }
 
-    // remove local pdf if something went wrong
-    if !local_pdf.exists() {
-        let _ = local_pdf; // keep lint happy
-    }
-
-    info!(job_id=%job.id, stage=%stage.stage_type, "finished ai stage");
+    info!(job_id=%job.id, stage=%stage.name(), "finished ai stage");
     timer.observe_duration();
     Ok(result)
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_config::meta::region::RegionProviderChain;
+    use serial_test::serial;
+    use sqlx::postgres::PgPoolOptions;
+    use tempfile::tempdir;
+    use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+    fn ai_stage(prompt_name: Option<&str>) -> Stage {
+        Stage {
+            stage_type: "ai".into(),
+            command: None,
+            prompt_name: prompt_name.map(String::from),
+            ocr_engine: None,
+            ocr_stage_endpoint: None,
+            ocr_stage_key: None,
+            config: None,
+            ..Default::default()
+        }
+    }
+
+    fn dummy_job() -> AnalysisJob {
+        AnalysisJob {
+            id: uuid::Uuid::new_v4(),
+            org_id: uuid::Uuid::new_v4(),
+            document_id: uuid::Uuid::new_v4(),
+            pipeline_id: uuid::Uuid::new_v4(),
+            status: "pending".into(),
+            created_at: chrono::Utc::now(),
+            ..Default::default()
+        }
+    }
+
+    fn settings(org_id: uuid::Uuid, endpoint: String) -> OrgSettings {
+        OrgSettings {
+            org_id,
+            monthly_upload_quota: 100,
+            monthly_analysis_quota: 100,
+            accent_color: "#fff".into(),
+            ai_api_endpoint: Some(endpoint),
+            ai_api_key: None,
+            ocr_api_endpoint: None,
+            ocr_api_key: None,
+            prompt_templates: Some(serde_json::json!([
+                {"name": "summary", "text": "Summarize {{ocr_text}} with total {{input.total}}"}
+            ])),
+            ai_custom_headers: None,
+            max_concurrent_jobs: None,
+            ocr_engine: None,
+        }
+    }
+
+    async fn dummy_clients() -> (sqlx::Pool<sqlx::Postgres>, S3Client) {
+        let pool = PgPoolOptions::new()
+            .connect_lazy("postgres://user@localhost/db")
+            .unwrap();
+        let rp = RegionProviderChain::default_provider().or_else("us-east-1");
+        let shared = aws_config::from_env().region(rp).load().await;
+        let cfg = aws_sdk_s3::config::Builder::from(&shared)
+            .endpoint_url("http://localhost")
+            .force_path_style(true)
+            .build();
+        (pool, S3Client::from_conf(cfg))
+    }
+
+    #[test]
+    fn request_without_prompt_sends_input_unchanged() {
+        let input = serde_json::json!({"total": 3});
+        let body = build_ai_request(&ai_stage(None), None, &input, Some("text")).unwrap();
+        assert_eq!(body, input);
+    }
+
+    #[test]
+    fn missing_template_is_an_error() {
+        let s = settings(uuid::Uuid::new_v4(), "http://ai".into());
+        let err = build_ai_request(
+            &ai_stage(Some("unknown")),
+            Some(&s),
+            &serde_json::json!({}),
+            None,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("'unknown' not found"));
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn ai_stage_sends_rendered_prompt() {
+        std::env::set_var("SKIP_DB", "1");
+        let dir = tempdir().unwrap();
+        std::env::set_var("LOCAL_S3_DIR", dir.path());
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({"ok": true})))
+            .mount(&server)
+            .await;
+        let (pool, s3) = dummy_clients().await;
+        let job = dummy_job();
+        let s = settings(job.org_id, server.uri());
+        let res = handle_ai_stage(
+            &pool,
+            &s3,
+            &job,
+            &ai_stage(Some("summary")),
+            "bucket",
+            AiStageRequest {
+                org_settings: Some(&s),
+                input: serde_json::json!({"total": 7}),
+                ocr_text: Some("invoice text"),
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(res["ok"], true);
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 1);
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(body["prompt"], "Summarize invoice text with total 7");
+        assert_eq!(body["input"]["total"], 7);
+    }
+}
@@ -0,0 +1,104 @@
+use jsonpath_rust::JsonPath;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Comparison applied to the values selected by a [`StageCondition`].
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ConditionOp {
+    /// Any selected value equals `value`.
+    #[default]
+    Eq,
+    /// No selected value equals `value`.
+    Ne,
+    /// The path selects at least one non-null value.
+    Exists,
+    /// The path selects nothing or only nulls.
+    NotExists,
+    /// Any selected string contains `value` or any selected array contains `value`.
+    Contains,
+}
+
+/// Optional `when` expression of a stage, evaluated against previous outputs.
+///
+/// The JSONPath is resolved against an object keyed by the names of the
+/// stage's upstream stages, e.g. `$.totals.status`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct StageCondition {
+    pub path: String,
+    #[serde(default)]
+    pub op: ConditionOp,
+    pub value: Option<Value>,
+}
+
+impl StageCondition {
+    /// Check the JSONPath syntax and that comparison operators have a value.
+    pub fn validate(&self) -> Result<(), String> {
+        jsonpath_rust::parser::parse_json_path(&self.path)
+            .map_err(|e| format!("invalid JSONPath '{}': {}", self.path, e))?;
+        match self.op {
+            ConditionOp::Eq | ConditionOp::Ne | ConditionOp::Contains if self.value.is_none() => {
+                Err(format!("'value' is required for operator {:?}", self.op))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Evaluate the condition. An unparsable path evaluates to `false`.
+    pub fn evaluate(&self, context: &Value) -> bool {
+        let matches = match context.query(&self.path) {
+            Ok(m) => m,
+            Err(e) => {
+                tracing::warn!(path=%self.path, "Failed to evaluate stage condition: {}", e);
+                return false;
+            }
+        };
+        let expected = self.value.as_ref().unwrap_or(&Value::Null);
+        match self.op {
+            ConditionOp::Eq => matches.contains(&expected),
+            ConditionOp::Ne => !matches.contains(&expected),
+            ConditionOp::Exists => matches.iter().any(|v| !v.is_null()),
+            ConditionOp::NotExists => matches.iter().all(|v| v.is_null()),
+            ConditionOp::Contains => matches.iter().any(|v| match (v, expected) {
+                (Value::String(s), Value::String(needle)) => s.contains(needle.as_str()),
+                (Value::Array(items), _) => items.contains(expected),
+                _ => false,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn cond(value: Value) -> StageCondition {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn equality_against_parse_status() {
+        let ctx = json!({"totals": {"status": "ok", "summary": {"Total": 3}}});
+        assert!(cond(json!({"path": "$.totals.status", "value": "ok"})).evaluate(&ctx));
+        assert!(!cond(json!({"path": "$.totals.status", "value": "header_not_found"})).evaluate(&ctx));
+        assert!(cond(json!({"path": "$.totals.status", "op": "ne", "value": "header_not_found"}))
+            .evaluate(&ctx));
+    }
+
+    #[test]
+    fn existence_and_contains() {
+        let ctx = json!({"ai": {"tags": ["invoice", "eur"], "note": "late payment"}});
+        assert!(cond(json!({"path": "$.ai.tags", "op": "exists"})).evaluate(&ctx));
+        assert!(cond(json!({"path": "$.ai.missing", "op": "not_exists"})).evaluate(&ctx));
+        assert!(cond(json!({"path": "$.ai.tags", "op": "contains", "value": "eur"})).evaluate(&ctx));
+        assert!(cond(json!({"path": "$.ai.note", "op": "contains", "value": "late"})).evaluate(&ctx));
+    }
+
+    #[test]
+    fn validation_rejects_bad_path_and_missing_value() {
+        assert!(cond(json!({"path": "$.a[", "value": 1})).validate().is_err());
+        assert!(cond(json!({"path": "$.a"})).validate().is_err());
+        assert!(cond(json!({"path": "$.a", "op": "exists"})).validate().is_ok());
+    }
+}
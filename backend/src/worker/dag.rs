@@ -0,0 +1,410 @@
+use crate::worker::Stage;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+
+/// Errors detected while building the dependency graph of a pipeline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DagError {
+    DuplicateId(String),
+    UnknownInput { stage: String, input: String },
+    Cycle(Vec<String>),
+}
+
+impl fmt::Display for DagError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DagError::DuplicateId(id) => write!(f, "Duplicate stage id '{}'", id),
+            DagError::UnknownInput { stage, input } => {
+                write!(f, "Stage '{}' references unknown input '{}'", stage, input)
+            }
+            DagError::Cycle(stages) => {
+                write!(f, "Pipeline contains a cycle between stages: {}", stages.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for DagError {}
+
+/// Give stages without an `id` a unique one before a pipeline runs.
+///
+/// Stage runs, stored outputs and merged inputs are keyed by [`Stage::name`],
+/// which falls back to the stage type. The first stage of a type keeps that
+/// name; later ones become `<type>_<position>`, so two id-less stages of the
+/// same type are never mixed up.
+pub fn assign_ids(stages: &mut [Stage]) {
+    let mut taken: HashSet<String> = stages.iter().filter_map(|s| s.id.clone()).collect();
+    for (idx, stage) in stages.iter_mut().enumerate() {
+        if stage.id.is_some() {
+            continue;
+        }
+        let mut id = stage.stage_type.clone();
+        let mut position = idx + 1;
+        while taken.contains(&id) {
+            id = format!("{}_{}", stage.stage_type, position);
+            position += 1;
+        }
+        taken.insert(id.clone());
+        stage.id = Some(id);
+    }
+}
+
+/// Dependency graph of a pipeline's stages, indexed by stage position.
+///
+/// Stages without an `inputs` list depend on the stage directly before them,
+/// which keeps linear pipelines working unchanged. `inputs: []` marks a root.
+#[derive(Debug, Clone)]
+pub struct StageGraph {
+    deps: Vec<Vec<usize>>,
+    dependents: Vec<Vec<usize>>,
+    order: Vec<usize>,
+}
+
+impl StageGraph {
+    /// Build and validate the graph. Rejects duplicate ids, unknown inputs and cycles.
+    pub fn build(stages: &[Stage]) -> Result<Self, DagError> {
+        let mut ids: HashMap<&str, usize> = HashMap::new();
+        for (idx, stage) in stages.iter().enumerate() {
+            if let Some(id) = stage.id.as_deref() {
+                if ids.insert(id, idx).is_some() {
+                    return Err(DagError::DuplicateId(id.to_string()));
+                }
+            }
+        }
+
+        let mut deps = Vec::with_capacity(stages.len());
+        for (idx, stage) in stages.iter().enumerate() {
+            let stage_deps = match &stage.inputs {
+                Some(inputs) => {
+                    let mut resolved = Vec::with_capacity(inputs.len());
+                    for input in inputs {
+                        let dep = *ids.get(input.as_str()).ok_or_else(|| DagError::UnknownInput {
+                            stage: stage.name().to_string(),
+                            input: input.clone(),
+                        })?;
+                        if !resolved.contains(&dep) {
+                            resolved.push(dep);
+                        }
+                    }
+                    resolved
+                }
+                None if idx > 0 => vec![idx - 1],
+                None => Vec::new(),
+            };
+            deps.push(stage_deps);
+        }
+
+        let mut dependents = vec![Vec::new(); stages.len()];
+        for (idx, stage_deps) in deps.iter().enumerate() {
+            for &dep in stage_deps {
+                dependents[dep].push(idx);
+            }
+        }
+
+        // Kahn's algorithm; whatever is left unvisited is part of a cycle.
+        let mut pending: Vec<usize> = deps.iter().map(Vec::len).collect();
+        let mut queue: VecDeque<usize> = (0..stages.len()).filter(|&i| pending[i] == 0).collect();
+        let mut order = Vec::with_capacity(stages.len());
+        while let Some(idx) = queue.pop_front() {
+            order.push(idx);
+            for &next in &dependents[idx] {
+                pending[next] -= 1;
+                if pending[next] == 0 {
+                    queue.push_back(next);
+                }
+            }
+        }
+        if order.len() != stages.len() {
+            let cyclic = (0..stages.len())
+                .filter(|i| pending[*i] > 0)
+                .map(|i| stages[i].name().to_string())
+                .collect();
+            return Err(DagError::Cycle(cyclic));
+        }
+
+        Ok(Self {
+            deps,
+            dependents,
+            order,
+        })
+    }
+
+    /// Number of stages in the graph.
+    pub fn len(&self) -> usize {
+        self.deps.len()
+    }
+
+    /// Whether the graph has no stages.
+    pub fn is_empty(&self) -> bool {
+        self.deps.is_empty()
+    }
+
+    /// Stage indices in a valid execution order.
+    pub fn order(&self) -> &[usize] {
+        &self.order
+    }
+
+    /// Stages whose outputs feed into `idx`.
+    pub fn dependencies(&self, idx: usize) -> &[usize] {
+        &self.deps[idx]
+    }
+
+    /// Stages that consume the output of `idx`.
+    pub fn dependents(&self, idx: usize) -> &[usize] {
+        &self.dependents[idx]
+    }
+
+    /// Stages that can start immediately.
+    pub fn roots(&self) -> Vec<usize> {
+        (0..self.len()).filter(|&i| self.deps[i].is_empty()).collect()
+    }
+
+    /// Find the closest upstream stage matching `pred`, searching breadth-first.
+    pub fn nearest_ancestor(&self, idx: usize, pred: impl Fn(usize) -> bool) -> Option<usize> {
+        let mut seen = vec![false; self.len()];
+        let mut queue: VecDeque<usize> = self.deps[idx].iter().copied().collect();
+        while let Some(current) = queue.pop_front() {
+            if std::mem::replace(&mut seen[current], true) {
+                continue;
+            }
+            if pred(current) {
+                return Some(current);
+            }
+            queue.extend(self.deps[current].iter().copied());
+        }
+        None
+    }
+
+    /// All transitive upstream stages of `idx`.
+    pub fn ancestors(&self, idx: usize) -> Vec<usize> {
+        let mut seen = vec![false; self.len()];
+        let mut stack: Vec<usize> = self.deps[idx].clone();
+        while let Some(current) = stack.pop() {
+            if !std::mem::replace(&mut seen[current], true) {
+                stack.extend(self.deps[current].iter().copied());
+            }
+        }
+        (0..self.len()).filter(|&i| seen[i]).collect()
+    }
+
+    /// Outputs of all upstream stages of `idx`, keyed by stage name.
+    /// Used as the evaluation context of `when` conditions.
+    pub fn upstream_outputs(&self, idx: usize, stages: &[Stage], outputs: &[Option<Value>]) -> Value {
+        Value::Object(
+            self.ancestors(idx)
+                .into_iter()
+                .filter_map(|dep| {
+                    outputs[dep]
+                        .clone()
+                        .map(|out| (stages[dep].name().to_string(), out))
+                })
+                .collect(),
+        )
+    }
+
+    /// Decide whether stage `idx` should be skipped once its inputs are done.
+    ///
+    /// A stage is skipped when all of its declared `inputs` were skipped or when
+    /// its `when` condition does not hold for the upstream outputs. A stage
+    /// without `inputs` that follows a skipped stage still runs; see
+    /// [`StageGraph::stage_input`].
+    pub fn skip_reason(
+        &self,
+        idx: usize,
+        stages: &[Stage],
+        outputs: &[Option<Value>],
+        skipped: &[bool],
+    ) -> Option<String> {
+        let deps = &self.deps[idx];
+        if stages[idx].inputs.is_some() && !deps.is_empty() && deps.iter().all(|&d| skipped[d]) {
+            return Some("all inputs were skipped".to_string());
+        }
+        let condition = stages[idx].when.as_ref()?;
+        if condition.evaluate(&self.upstream_outputs(idx, stages, outputs)) {
+            None
+        } else {
+            Some(format!("condition on '{}' not met", condition.path))
+        }
+    }
+
+    /// Compose the input value for stage `idx` from the outputs of its dependencies.
+    ///
+    /// A single input is passed through unchanged, multiple inputs are merged into
+    /// an object keyed by stage name and a root stage receives `null`. A stage
+    /// without `inputs` whose previous stage was skipped receives the input of
+    /// that stage, like after a failed stage with `on_error: continue`.
+    pub fn stage_input(
+        &self,
+        idx: usize,
+        stages: &[Stage],
+        outputs: &[Option<Value>],
+        skipped: &[bool],
+    ) -> Value {
+        match self.deps[idx].as_slice() {
+            [] => Value::Null,
+            [dep] if stages[idx].inputs.is_none() && skipped[*dep] => {
+                self.stage_input(*dep, stages, outputs, skipped)
+            }
+            [dep] => outputs[*dep].clone().unwrap_or(Value::Null),
+            many => Value::Object(
+                many.iter()
+                    .map(|&dep| {
+                        (
+                            stages[dep].name().to_string(),
+                            outputs[dep].clone().unwrap_or(Value::Null),
+                        )
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn stages(value: Value) -> Vec<Stage> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn linear_pipeline_without_inputs() {
+        let s = stages(json!([
+            {"type": "ocr"}, {"type": "parse"}, {"type": "ai"}
+        ]));
+        let g = StageGraph::build(&s).unwrap();
+        assert_eq!(g.order(), &[0, 1, 2]);
+        assert_eq!(g.dependencies(2), &[1]);
+        assert_eq!(g.roots(), vec![0]);
+    }
+
+    #[test]
+    fn branches_are_merged_by_name() {
+        let s = stages(json!([
+            {"id": "ocr1", "type": "ocr"},
+            {"id": "totals", "type": "parse", "inputs": ["ocr1"]},
+            {"id": "lines", "type": "parse", "inputs": ["ocr1"]},
+            {"id": "merge", "type": "ai", "inputs": ["totals", "lines"]}
+        ]));
+        let g = StageGraph::build(&s).unwrap();
+        assert_eq!(g.dependents(0), &[1, 2]);
+        assert_eq!(g.nearest_ancestor(3, |i| s[i].stage_type == "ocr"), Some(0));
+        let outputs = vec![
+            Some(json!("text")),
+            Some(json!({"total": 1})),
+            Some(json!({"lines": []})),
+            None,
+        ];
+        let skipped = vec![false; 4];
+        assert_eq!(
+            g.stage_input(3, &s, &outputs, &skipped),
+            json!({"totals": {"total": 1}, "lines": {"lines": []}})
+        );
+        assert_eq!(g.stage_input(1, &s, &outputs, &skipped), json!("text"));
+        assert_eq!(g.ancestors(3), vec![0, 1, 2]);
+        assert_eq!(
+            g.upstream_outputs(1, &s, &outputs),
+            json!({"ocr1": "text"})
+        );
+    }
+
+    #[test]
+    fn stages_without_id_get_unique_names() {
+        let mut s = stages(json!([
+            {"type": "ocr"},
+            {"type": "parse"},
+            {"type": "parse"},
+            {"id": "parse_3", "type": "ai"},
+            {"type": "parse"}
+        ]));
+        assign_ids(&mut s);
+        let names: Vec<_> = s.iter().map(Stage::name).collect();
+        assert_eq!(names, ["ocr", "parse", "parse_4", "parse_3", "parse_5"]);
+
+        let g = StageGraph::build(&s).unwrap();
+        let outputs = vec![Some(json!("text")), Some(json!(1)), Some(json!(2)), None, None];
+        assert_eq!(
+            g.upstream_outputs(3, &s, &outputs),
+            json!({"ocr": "text", "parse": 1, "parse_4": 2})
+        );
+    }
+
+    #[test]
+    fn conditions_and_skipped_inputs() {
+        let s = stages(json!([
+            {"id": "totals", "type": "parse"},
+            {"id": "ai", "type": "ai", "inputs": ["totals"],
+             "when": {"path": "$.totals.status", "value": "ok"}},
+            {"id": "ocr2", "type": "ocr", "inputs": ["totals"],
+             "when": {"path": "$.totals.status", "value": "header_not_found"}},
+            {"id": "ai2", "type": "ai", "inputs": ["ocr2"]}
+        ]));
+        let g = StageGraph::build(&s).unwrap();
+        let outputs = vec![Some(json!({"status": "ok"})), None, None, None];
+        let mut skipped = vec![false; 4];
+        assert_eq!(g.skip_reason(1, &s, &outputs, &skipped), None);
+        assert!(g.skip_reason(2, &s, &outputs, &skipped).is_some());
+        skipped[2] = true;
+        assert_eq!(
+            g.skip_reason(3, &s, &outputs, &skipped).as_deref(),
+            Some("all inputs were skipped")
+        );
+    }
+
+    #[test]
+    fn skipped_stage_passes_its_input_to_the_next_stage() {
+        let s = stages(json!([
+            {"type": "ocr"},
+            {"type": "parse"},
+            {"type": "ai", "when": {"path": "$.parse.status", "value": "ok"}},
+            {"type": "report"}
+        ]));
+        let g = StageGraph::build(&s).unwrap();
+        let outputs = vec![
+            Some(json!("text")),
+            Some(json!({"status": "header_not_found"})),
+            None,
+            None,
+        ];
+        let mut skipped = vec![false; 4];
+        assert!(g.skip_reason(2, &s, &outputs, &skipped).is_some());
+        skipped[2] = true;
+        assert_eq!(g.skip_reason(3, &s, &outputs, &skipped), None);
+        assert_eq!(
+            g.stage_input(3, &s, &outputs, &skipped),
+            json!({"status": "header_not_found"})
+        );
+    }
+
+    #[test]
+    fn unknown_input_rejected() {
+        let s = stages(json!([
+            {"id": "a", "type": "ocr"},
+            {"id": "b", "type": "parse", "inputs": ["missing"]}
+        ]));
+        assert_eq!(
+            StageGraph::build(&s).unwrap_err(),
+            DagError::UnknownInput {
+                stage: "b".into(),
+                input: "missing".into()
+            }
+        );
+    }
+
+    #[test]
+    fn cycle_rejected() {
+        let s = stages(json!([
+            {"id": "a", "type": "parse", "inputs": ["c"]},
+            {"id": "b", "type": "parse", "inputs": ["a"]},
+            {"id": "c", "type": "parse", "inputs": ["b"]},
+            {"id": "d", "type": "report", "inputs": []}
+        ]));
+        match StageGraph::build(&s).unwrap_err() {
+            DagError::Cycle(names) => assert_eq!(names, vec!["a", "b", "c"]),
+            other => panic!("unexpected error {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//! Outbound webhooks notifying organizations about finished jobs.
+//!
+//! When a job completes, fails or is cancelled a delivery is queued for every
+//! endpoint of the organization subscribed to the event. Workers send due
+//! deliveries in the background; failed attempts are retried with exponential
+//! backoff and the outcome of each delivery is kept as a delivery log.
+
+use crate::models::webhook::DueDelivery;
+use crate::models::{AnalysisJob, WebhookDelivery};
+use crate::processing::retry::RetryPolicy;
+use crate::processing::webhook::{post_webhook, WebhookError, WebhookRequest};
+use crate::worker::metrics::API_ERROR_COUNTER;
+use futures_util::future::join_all;
+use serde_json::json;
+use sqlx::PgPool;
+use std::time::Duration;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Header naming the event of a delivery, e.g. `job.completed`.
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+/// Header carrying the id of the delivery, which changes on redelivery.
+pub const DELIVERY_HEADER: &str = "X-Webhook-Delivery";
+/// Deliveries sent per poll.
+const BATCH_SIZE: i64 = 50;
+
+/// Retry settings of outbound job webhooks, read from the worker environment.
+#[derive(Debug, Clone)]
+pub struct DeliveryConfig {
+    /// Attempts before a delivery is marked failed (`WEBHOOK_MAX_ATTEMPTS`).
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for every further one (`WEBHOOK_BACKOFF_SECS`).
+    pub backoff: Duration,
+    /// Timeout of a single request (`WEBHOOK_TIMEOUT_SECS`).
+    pub timeout: Duration,
+}
+
+fn env_u64(name: &str, default: u64) -> u64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(default)
+}
+
+impl DeliveryConfig {
+    pub fn from_env() -> Self {
+        Self {
+            max_attempts: env_u64("WEBHOOK_MAX_ATTEMPTS", 8) as u32,
+            backoff: Duration::from_secs(env_u64("WEBHOOK_BACKOFF_SECS", 30)),
+            timeout: Duration::from_secs(env_u64("WEBHOOK_TIMEOUT_SECS", 10)),
+        }
+    }
+
+    fn policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.max_attempts,
+            backoff_ms: self.backoff.as_millis() as u64,
+            timeout: self.timeout,
+        }
+    }
+}
+
+/// Event type reported for a job that ended with `status`.
+pub fn job_event_type(status: &str) -> Option<&'static str> {
+    match status {
+        "completed" => Some("job.completed"),
+        "failed" => Some("job.failed"),
+        "cancelled" => Some("job.cancelled"),
+        _ => None,
+    }
+}
+
+/// Queue the event of a finished job for the organization's webhook endpoints.
+pub async fn enqueue_job_event(pool: &PgPool, job_id: Uuid) {
+    let job = match AnalysisJob::find(pool, job_id).await {
+        Ok(job) => job,
+        Err(e) => {
+            warn!(job_id=%job_id, "Failed to load job for webhook event: {:?}", e);
+            return;
+        }
+    };
+    let Some(event_type) = job_event_type(&job.status) else {
+        return;
+    };
+    let payload = json!({
+        "id": Uuid::new_v4(),
+        "type": event_type,
+        "created_at": chrono::Utc::now(),
+        "data": {
+            "job_id": job.id,
+            "org_id": job.org_id,
+            "document_id": job.document_id,
+            "pipeline_id": job.pipeline_id,
+            "status": job.status,
+            "failure_reason": job.failure_reason,
+            "error": job.error,
+            "started_at": job.started_at,
+            "finished_at": job.finished_at,
+        },
+    });
+    match WebhookDelivery::enqueue(pool, job.org_id, event_type, &payload).await {
+        Ok(deliveries) if !deliveries.is_empty() => {
+            info!(job_id=%job.id, event_type, count=deliveries.len(), "Queued webhook deliveries");
+        }
+        Ok(_) => {}
+        Err(e) => warn!(job_id=%job.id, "Failed to queue webhook deliveries: {:?}", e),
+    }
+}
+
+/// Send all due deliveries once and return how many were attempted.
+///
+/// The claimed batch is sent concurrently, so every request ends within one
+/// request timeout and before the lease of the claim runs out.
+pub async fn deliver_due(pool: &PgPool, cfg: &DeliveryConfig) -> sqlx::Result<usize> {
+    // Hide claimed deliveries from other workers for longer than a request may take.
+    let lease_secs = cfg.timeout.as_secs() * 2 + 5;
+    let due = WebhookDelivery::claim_due(pool, BATCH_SIZE, lease_secs).await?;
+    join_all(due.iter().map(|delivery| deliver(pool, cfg, delivery)))
+        .await
+        .into_iter()
+        .collect::<sqlx::Result<Vec<()>>>()?;
+    Ok(due.len())
+}
+
+/// Make one attempt to send a claimed delivery and record its outcome.
+async fn deliver(pool: &PgPool, cfg: &DeliveryConfig, delivery: &DueDelivery) -> sqlx::Result<()> {
+    let policy = cfg.policy();
+    let single_attempt = RetryPolicy {
+        max_attempts: 1,
+        ..policy
+    };
+    let headers = vec![
+        (EVENT_HEADER.to_string(), delivery.event_type.clone()),
+        (DELIVERY_HEADER.to_string(), delivery.id.to_string()),
+    ];
+    let request = WebhookRequest {
+        url: &delivery.url,
+        headers: &headers,
+        payload: &delivery.payload,
+        document: None,
+        signing_secret: Some(&delivery.secret),
+    };
+    match post_webhook(&request, &single_attempt).await {
+        Ok((status, _)) => {
+            WebhookDelivery::mark_delivered(pool, delivery.id, status.as_u16() as i32).await
+        }
+        Err(e) => {
+            API_ERROR_COUNTER.with_label_values(&["job_webhook"]).inc();
+            let attempts = delivery.attempts.max(1) as u32;
+            let retry_in = policy
+                .should_retry(attempts)
+                .then(|| policy.backoff(attempts).as_secs_f64());
+            let status = match &e {
+                WebhookError::HttpError(status, _) => Some(status.as_u16() as i32),
+                _ => None,
+            };
+            warn!(delivery_id=%delivery.id, attempts, "Webhook delivery failed: {}", e);
+            WebhookDelivery::mark_attempt_failed(
+                pool,
+                delivery.id,
+                status,
+                &e.to_string(),
+                retry_in,
+            )
+            .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_final_statuses_have_events() {
+        assert_eq!(job_event_type("completed"), Some("job.completed"));
+        assert_eq!(job_event_type("cancelled"), Some("job.cancelled"));
+        assert_eq!(job_event_type("in_progress"), None);
+    }
+}
@@ -0,0 +1,359 @@
+//! Test runs of a pipeline against sample text or a stored document.
+//!
+//! The stages run one after another in the calling process, without an
+//! analysis job: nothing is stored, no stage runs are recorded and no quota is
+//! used. Parse stages always run, OCR and AI stages only when asked to. Report,
+//! exec and webhook stages have side effects and pass their input on instead.
+
+use crate::models::OrgSettings;
+use crate::processing;
+use crate::processing::formats::{self, DocumentFormat};
+use crate::processing::ocr_layout::{join_pages, OcrLayout};
+use crate::worker::dag::{self, DagError, StageGraph};
+use crate::worker::{ai, ocr, OnError, Stage};
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Pages of a stored document a test run accepts.
+pub const MAX_PAGES: i32 = 20;
+/// Bytes of sample text, or of text read from a document, a test run works on.
+pub const MAX_TEXT_BYTES: usize = 256 * 1024;
+
+/// Longest a test run may take, from `PIPELINE_TEST_TIMEOUT_SECS` (default 60).
+pub fn time_limit() -> Duration {
+    let secs = std::env::var("PIPELINE_TEST_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(60);
+    Duration::from_secs(secs)
+}
+
+/// What the stages run on.
+#[derive(Debug, Clone, Copy)]
+pub enum Sample<'a> {
+    /// Text OCR stages output instead of recognising a document.
+    Text(&'a str),
+    /// Document on disk, whose format follows from its extension.
+    Document(&'a Path),
+}
+
+/// Stages that only run when asked to.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DryRunOptions {
+    /// Recognise the document with the stage's OCR engine. Otherwise OCR
+    /// stages read the text layer of PDFs and the text of text documents.
+    pub run_ocr: bool,
+    /// Send requests to the AI service.
+    pub run_ai: bool,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StageStatus {
+    Succeeded,
+    Failed,
+    /// The stage's `when` condition did not hold or all its inputs were skipped.
+    Skipped,
+    /// The stage was not run and passed its input, or the sample text, on.
+    NotRun,
+}
+
+/// Outcome of one stage of a test run.
+#[derive(Serialize, Debug, Clone)]
+pub struct StageResult {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub stage_type: String,
+    pub status: StageStatus,
+    /// Output passed on to the following stages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<Value>,
+    /// Why the stage was skipped or not run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+impl StageResult {
+    fn new(stage: &Stage, status: StageStatus) -> Self {
+        Self {
+            id: stage.name().to_string(),
+            stage_type: stage.stage_type.clone(),
+            status,
+            output: None,
+            reason: None,
+            error: None,
+            duration_ms: 0,
+        }
+    }
+}
+
+/// Run `stages` on `sample` and return the outcome of each stage in pipeline
+/// order.
+///
+/// Stages run in dependency order and get their inputs like in the worker.
+/// A failed stage with `on_error: continue` passes its input on; other
+/// failures stop the run and the remaining stages are reported as not run.
+pub async fn run(
+    stages: &[Stage],
+    org_settings: Option<&OrgSettings>,
+    sample: Sample<'_>,
+    options: DryRunOptions,
+) -> Result<Vec<StageResult>, DagError> {
+    let mut stages = stages.to_vec();
+    dag::assign_ids(&mut stages);
+    let stages = stages.as_slice();
+    let graph = StageGraph::build(stages)?;
+    let test = TestRun {
+        org_settings,
+        sample,
+        options,
+    };
+    let mut outputs: Vec<Option<Value>> = vec![None; graph.len()];
+    let mut layouts: Vec<Option<OcrLayout>> = vec![None; graph.len()];
+    let mut skipped = vec![false; graph.len()];
+    let mut results: Vec<Option<StageResult>> = vec![None; graph.len()];
+    let mut stopped_by: Option<&str> = None;
+
+    for &idx in graph.order() {
+        let stage = &stages[idx];
+        if let Some(failed) = stopped_by {
+            let mut result = StageResult::new(stage, StageStatus::NotRun);
+            result.reason = Some(format!("stage '{}' failed", failed));
+            results[idx] = Some(result);
+            continue;
+        }
+        if let Some(reason) = graph.skip_reason(idx, stages, &outputs, &skipped) {
+            let mut result = StageResult::new(stage, StageStatus::Skipped);
+            result.reason = Some(reason);
+            results[idx] = Some(result);
+            skipped[idx] = true;
+            continue;
+        }
+        let input = graph.stage_input(idx, stages, &outputs, &skipped);
+        let ocr = graph.nearest_ancestor(idx, |i| stages[i].stage_type == "ocr");
+        let ocr_text = ocr.and_then(|i| outputs[i].as_ref()?.as_str());
+        let layout = ocr.and_then(|i| layouts[i].as_ref());
+
+        let start = Instant::now();
+        let fut = test.run_stage(stage, idx, input.clone(), ocr_text, layout);
+        let outcome = match stage.timeout_secs.map(Duration::from_secs) {
+            Some(limit) => tokio::time::timeout(limit, fut)
+                .await
+                .unwrap_or_else(|_| Err(anyhow!("stage timed out after {}s", limit.as_secs()))),
+            None => fut.await,
+        };
+        let duration_ms = start.elapsed().as_millis() as u64;
+        let result = match outcome {
+            Ok(Outcome::Ran(output, layout)) => {
+                layouts[idx] = layout;
+                StageResult {
+                    output: Some(output),
+                    duration_ms,
+                    ..StageResult::new(stage, StageStatus::Succeeded)
+                }
+            }
+            Ok(Outcome::NotRun(output, reason)) => StageResult {
+                output: Some(output),
+                reason: Some(reason.to_string()),
+                ..StageResult::new(stage, StageStatus::NotRun)
+            },
+            Err(e) => {
+                if stage.on_error != OnError::Continue {
+                    stopped_by = Some(stage.name());
+                }
+                StageResult {
+                    output: (stage.on_error == OnError::Continue).then(|| input.clone()),
+                    error: Some(format!("{:#}", e)),
+                    duration_ms,
+                    ..StageResult::new(stage, StageStatus::Failed)
+                }
+            }
+        };
+        outputs[idx] = result.output.clone();
+        results[idx] = Some(result);
+    }
+    Ok(results.into_iter().flatten().collect())
+}
+
+enum Outcome {
+    /// Output of the stage and, for OCR stages, the layout of the pages.
+    Ran(Value, Option<OcrLayout>),
+    /// Value passed on by a stage that was not run.
+    NotRun(Value, &'static str),
+}
+
+struct TestRun<'a> {
+    org_settings: Option<&'a OrgSettings>,
+    sample: Sample<'a>,
+    options: DryRunOptions,
+}
+
+impl TestRun<'_> {
+    async fn run_stage(
+        &self,
+        stage: &Stage,
+        idx: usize,
+        input: Value,
+        ocr_text: Option<&str>,
+        layout: Option<&OcrLayout>,
+    ) -> Result<Outcome> {
+        let org_settings = self.org_settings;
+        match stage.stage_type.as_str() {
+            "ocr" => match self.sample {
+                Sample::Text(text) => Ok(Outcome::NotRun(
+                    text.into(),
+                    "sample text used as OCR output",
+                )),
+                Sample::Document(path) if self.options.run_ocr => {
+                    let txt_path = path.with_extension(format!("{}.txt", idx));
+                    let result =
+                        ocr::recognise_document(stage, org_settings, path, &txt_path).await;
+                    let _ = tokio::fs::remove_file(&txt_path).await;
+                    let (text, layout) = result?;
+                    Ok(Outcome::Ran(text.into(), Some(layout)))
+                }
+                Sample::Document(path) => Ok(Outcome::NotRun(
+                    document_text(path).await?.into(),
+                    "text of the document used as OCR output",
+                )),
+            },
+            "parse" => match ocr_text {
+                Some(text) => {
+                    let layout =
+                        layout.filter(|_| processing::parse::uses_layout(stage.config.as_ref()));
+                    let output = processing::parse::run_parse_stage_with_layout(
+                        text,
+                        layout,
+                        stage.config.as_ref(),
+                    )
+                    .await?;
+                    Ok(Outcome::Ran(output, None))
+                }
+                None => Ok(Outcome::NotRun(
+                    input,
+                    "no OCR stage before the parse stage",
+                )),
+            },
+            "ai" if self.options.run_ai => {
+                let (endpoint, key) = ai::ai_service(org_settings);
+                if endpoint.is_empty() {
+                    return Err(anyhow!("AI endpoint missing"));
+                }
+                let request = ai::build_ai_request(stage, org_settings, &input, ocr_text)?;
+                let headers = org_settings.and_then(|s| s.ai_custom_headers.as_ref());
+                let output = processing::ai_client::run_ai_with_policy(
+                    &request,
+                    &endpoint,
+                    &key,
+                    headers,
+                    &stage.retry_policy(),
+                )
+                .await?;
+                Ok(Outcome::Ran(output, None))
+            }
+            "ai" => Ok(Outcome::NotRun(input, "AI stages only run with run_ai")),
+            _ => Ok(Outcome::NotRun(input, "stage type is not run in tests")),
+        }
+    }
+}
+
+/// Text of a document without OCR: the text layer of PDFs or the text of
+/// text based formats, cut to [`MAX_TEXT_BYTES`].
+async fn document_text(path: &Path) -> Result<String> {
+    let format =
+        DocumentFormat::from_filename(&path.to_string_lossy()).unwrap_or(DocumentFormat::Pdf);
+    let bytes = tokio::fs::read(path)
+        .await
+        .context("Failed to read input document")?;
+    let mut text = if format == DocumentFormat::Pdf {
+        let mut pages = processing::ocr::pdf_page_texts(&bytes)?;
+        pages.truncate(MAX_PAGES as usize);
+        join_pages(&pages)
+    } else {
+        formats::extract_text(format, &bytes).context("Set run_ocr to recognise the document")?
+    };
+    if text.len() > MAX_TEXT_BYTES {
+        let mut end = MAX_TEXT_BYTES;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn stages(value: Value) -> Vec<Stage> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn parse_stages_run_on_sample_text() {
+        let s = stages(json!([
+            {"id": "ocr", "type": "ocr"},
+            {"id": "ids", "type": "parse", "config": {
+                "strategy": "RegexExtraction",
+                "parameters": {"patterns": [{"name": "invoice", "regex": "(INV-\\d+)"}]}
+            }},
+            {"id": "big", "type": "parse", "inputs": ["ids"],
+             "when": {"path": "$.ids.invoice", "op": "not_exists"}},
+            {"id": "summary", "type": "ai", "inputs": ["ids"]}
+        ]));
+        let results = run(
+            &s,
+            None,
+            Sample::Text("Invoice INV-42"),
+            DryRunOptions::default(),
+        )
+        .await
+        .unwrap();
+        let statuses: Vec<_> = results.iter().map(|r| r.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                StageStatus::NotRun,
+                StageStatus::Succeeded,
+                StageStatus::Skipped,
+                StageStatus::NotRun
+            ]
+        );
+        assert_eq!(results[0].output, Some(json!("Invoice INV-42")));
+        assert_eq!(results[1].output.as_ref().unwrap()["invoice"][0], "INV-42");
+        assert_eq!(results[3].output, results[1].output);
+    }
+
+    #[actix_rt::test]
+    async fn failed_stage_stops_the_run_unless_it_continues() {
+        let bad_config = json!({"strategy": "tableExtraction", "parameters": {}});
+        let s = stages(json!([
+            {"id": "ocr", "type": "ocr"},
+            {"id": "first", "type": "parse", "config": bad_config, "on_error": "continue"},
+            {"id": "second", "type": "parse", "inputs": ["ocr"], "config": bad_config},
+            {"id": "report", "type": "report", "inputs": ["second"]}
+        ]));
+        let results = run(&s, None, Sample::Text("text"), DryRunOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(results[1].status, StageStatus::Failed);
+        assert_eq!(results[1].output, Some(json!("text")));
+        assert!(results[2]
+            .error
+            .as_ref()
+            .unwrap()
+            .contains("unknown variant"));
+        assert_eq!(results[2].output, None);
+        assert_eq!(results[3].status, StageStatus::NotRun);
+        assert_eq!(results[3].reason.as_deref(), Some("stage 'second' failed"));
+    }
+}
@@ -0,0 +1,399 @@
+//! `exec` stages run an allowlisted executable in a restricted environment.
+//!
+//! The program receives a JSON envelope on stdin describing the job, the path
+//! of a private copy of the document and the stage input. The same input is
+//! also written to `input.json` next to the document for tools that prefer
+//! files. The program must print a single JSON value on stdout, which becomes
+//! the stage output.
+
+use crate::models::AnalysisJob;
+use crate::worker::{save_stage_output, Stage};
+use anyhow::{anyhow, bail, Context, Result};
+use aws_sdk_s3::Client as S3Client;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+use tracing::{info, warn};
+
+/// `PATH` handed to sandboxed programs; nothing else is inherited from the worker.
+const SANDBOX_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+/// Bytes of stderr kept for logs and error messages.
+const MAX_STDERR_BYTES: u64 = 64 * 1024;
+/// Maximum number of open file descriptors of a sandboxed program.
+const MAX_OPEN_FILES: u64 = 256;
+
+/// Sandbox settings of `exec` stages, read from the worker environment.
+#[derive(Debug, Clone)]
+pub struct ExecConfig {
+    /// Absolute paths of the executables stages may run (`EXEC_ALLOWLIST`).
+    pub allowlist: Vec<PathBuf>,
+    /// Wall clock limit when the stage sets no `timeout_secs` (`EXEC_TIMEOUT_SECS`).
+    pub timeout: Duration,
+    /// Address space limit in megabytes (`EXEC_MEMORY_MB`).
+    pub memory_mb: u64,
+    /// CPU time limit in seconds (`EXEC_CPU_SECS`).
+    pub cpu_secs: u64,
+    /// Largest file the program may write, in megabytes (`EXEC_MAX_FILE_MB`).
+    pub max_file_mb: u64,
+    /// Largest accepted stdout (`EXEC_MAX_OUTPUT_BYTES`).
+    pub max_output_bytes: u64,
+}
+
+fn env_u64(name: &str, default: u64) -> u64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(default)
+}
+
+impl ExecConfig {
+    /// Load the sandbox settings. Without `EXEC_ALLOWLIST` no program may run.
+    pub fn from_env() -> Self {
+        let allowlist = std::env::var("EXEC_ALLOWLIST")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(PathBuf::from)
+            .collect();
+        let timeout_secs = env_u64("EXEC_TIMEOUT_SECS", 60);
+        Self {
+            allowlist,
+            timeout: Duration::from_secs(timeout_secs),
+            memory_mb: env_u64("EXEC_MEMORY_MB", 512),
+            cpu_secs: env_u64("EXEC_CPU_SECS", timeout_secs),
+            max_file_mb: env_u64("EXEC_MAX_FILE_MB", 100),
+            max_output_bytes: env_u64("EXEC_MAX_OUTPUT_BYTES", 10 * 1024 * 1024),
+        }
+    }
+
+    /// Resolve `program` against the allowlist. Bare names match the file name
+    /// of an allowlisted path, absolute paths must be listed verbatim.
+    pub fn resolve(&self, program: &str) -> Result<PathBuf> {
+        let requested = Path::new(program);
+        let found = if requested.is_absolute() {
+            self.allowlist.iter().find(|p| p.as_path() == requested)
+        } else if requested.components().count() == 1 {
+            self.allowlist
+                .iter()
+                .find(|p| p.file_name() == requested.file_name())
+        } else {
+            None
+        };
+        found
+            .filter(|p| p.is_absolute())
+            .cloned()
+            .ok_or_else(|| anyhow!("executable '{}' is not in EXEC_ALLOWLIST", program))
+    }
+}
+
+/// Data passed to an `exec` stage.
+pub struct ExecRequest<'a> {
+    /// Local copy of the job's document.
+    pub document: &'a Path,
+    /// Output of the upstream stages.
+    pub input: &'a Value,
+    /// Text of the nearest OCR stage, if any.
+    pub ocr_text: Option<&'a str>,
+}
+
+/// Apply resource limits in the child process before it executes the program.
+#[cfg(unix)]
+fn limit_resources(cmd: &mut Command, cfg: &ExecConfig) {
+    let limits = [
+        (libc::RLIMIT_AS, cfg.memory_mb * 1024 * 1024),
+        (libc::RLIMIT_CPU, cfg.cpu_secs),
+        (libc::RLIMIT_FSIZE, cfg.max_file_mb * 1024 * 1024),
+        (libc::RLIMIT_NOFILE, MAX_OPEN_FILES),
+        (libc::RLIMIT_CORE, 0),
+    ];
+    // SAFETY: the closure only calls setrlimit, which is async-signal-safe.
+    unsafe {
+        cmd.pre_exec(move || {
+            for (resource, value) in limits {
+                let limit = libc::rlimit {
+                    rlim_cur: value as libc::rlim_t,
+                    rlim_max: value as libc::rlim_t,
+                };
+                if libc::setrlimit(resource, &limit) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(unix))]
+fn limit_resources(_cmd: &mut Command, _cfg: &ExecConfig) {}
+
+/// Run the program of an `exec` stage and store its JSON output.
+#[tracing::instrument(skip(pool, s3, job, stage, request))]
+pub async fn handle_exec_stage(
+    pool: &PgPool,
+    s3: &S3Client,
+    job: &AnalysisJob,
+    stage: &Stage,
+    bucket: &str,
+    request: ExecRequest<'_>,
+) -> Result<Value> {
+    let cfg = ExecConfig::from_env();
+    let command = stage
+        .command
+        .as_deref()
+        .ok_or_else(|| anyhow!("exec stage requires a command"))?;
+    let mut parts = command.split_whitespace();
+    let program = cfg.resolve(parts.next().unwrap_or_default())?;
+    let args: Vec<&str> = parts.collect();
+
+    // A fresh directory with a random name, so the stage id never ends up in
+    // the path that is removed afterwards.
+    let work_dir = tempfile::Builder::new()
+        .prefix(&format!("{}-exec-", job.id))
+        .tempdir()?
+        .keep();
+    let result = run_sandboxed(&cfg, &program, &args, &work_dir, job, stage, &request).await;
+    if let Err(e) = tokio::fs::remove_dir_all(&work_dir).await {
+        warn!(job_id=%job.id, stage=%stage.name(), "Failed to remove exec work dir: {:?}", e);
+    }
+    let output = result?;
+
+    let bytes = serde_json::to_vec_pretty(&output)?;
+    save_stage_output(
+        pool,
+        s3,
+        job.id,
+        stage.name(),
+        "json",
+        bucket,
+        bytes,
+        "json",
+    )
+    .await?;
+    Ok(output)
+}
+
+async fn run_sandboxed(
+    cfg: &ExecConfig,
+    program: &Path,
+    args: &[&str],
+    work_dir: &Path,
+    job: &AnalysisJob,
+    stage: &Stage,
+    request: &ExecRequest<'_>,
+) -> Result<Value> {
+    // Keep the extension so programs can tell the format of the document
+    let extension = request
+        .document
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("pdf");
+    let document = work_dir.join(format!("document.{}", extension));
+    tokio::fs::copy(request.document, &document)
+        .await
+        .context("failed to copy document for exec stage")?;
+    let input_path = work_dir.join("input.json");
+    tokio::fs::write(&input_path, serde_json::to_vec(request.input)?).await?;
+    let envelope = json!({
+        "job_id": job.id,
+        "document_id": job.document_id,
+        "stage_id": stage.name(),
+        "document_path": document,
+        "input_path": input_path,
+        "input": request.input,
+        "ocr_text": request.ocr_text,
+    });
+
+    let mut cmd = Command::new(program);
+    cmd.args(args)
+        .current_dir(work_dir)
+        .env_clear()
+        .env("PATH", SANDBOX_PATH)
+        .env("HOME", work_dir)
+        .env("TMPDIR", work_dir)
+        .env("EXEC_DOCUMENT", &document)
+        .env("EXEC_INPUT", &input_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    limit_resources(&mut cmd, cfg);
+
+    info!(job_id=%job.id, stage=%stage.name(), program=%program.display(), "starting exec stage");
+    let mut child = cmd
+        .spawn()
+        .with_context(|| format!("failed to start {}", program.display()))?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let payload = serde_json::to_vec(&envelope)?;
+
+    let limit = stage.timeout_secs.map_or(cfg.timeout, Duration::from_secs);
+    let run = async {
+        let write = async {
+            // Programs reading only the input file may close stdin early.
+            let _ = stdin.write_all(&payload).await;
+            drop(stdin);
+        };
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let mut stdout = stdout.take(cfg.max_output_bytes + 1);
+        let mut stderr = stderr.take(MAX_STDERR_BYTES);
+        let read_out = stdout.read_to_end(&mut out);
+        let read_err = stderr.read_to_end(&mut err);
+        let (_, out_res, err_res) = tokio::join!(write, read_out, read_err);
+        out_res?;
+        err_res?;
+        if out.len() as u64 > cfg.max_output_bytes {
+            bail!("output exceeds {} bytes", cfg.max_output_bytes);
+        }
+        let status = child.wait().await?;
+        Ok::<_, anyhow::Error>((status, out, err))
+    };
+    let (status, out, err) = match tokio::time::timeout(limit, run).await {
+        Ok(res) => res?,
+        Err(_) => bail!("program timed out after {}s", limit.as_secs()),
+    };
+
+    let stderr_text = String::from_utf8_lossy(&err);
+    if !status.success() {
+        bail!(
+            "{} exited with {}: {}",
+            program.display(),
+            status,
+            stderr_text.trim()
+        );
+    }
+    if !stderr_text.trim().is_empty() {
+        info!(job_id=%job.id, stage=%stage.name(), stderr=%stderr_text.trim(), "exec stage stderr");
+    }
+    serde_json::from_slice(&out).context("exec stage did not print valid JSON on stdout")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_config::meta::region::RegionProviderChain;
+    use serial_test::serial;
+    use sqlx::postgres::PgPoolOptions;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::tempdir;
+
+    fn exec_stage(command: &str) -> Stage {
+        Stage {
+            id: Some("tool".into()),
+            stage_type: "exec".into(),
+            command: Some(command.into()),
+            ..Default::default()
+        }
+    }
+
+    fn dummy_job() -> AnalysisJob {
+        AnalysisJob {
+            id: uuid::Uuid::new_v4(),
+            status: "in_progress".into(),
+            created_at: chrono::Utc::now(),
+            ..Default::default()
+        }
+    }
+
+    async fn dummy_clients() -> (sqlx::Pool<sqlx::Postgres>, S3Client) {
+        let pool = PgPoolOptions::new()
+            .connect_lazy("postgres://user@localhost/db")
+            .unwrap();
+        let rp = RegionProviderChain::default_provider().or_else("us-east-1");
+        let shared = aws_config::from_env().region(rp).load().await;
+        (pool, S3Client::new(&shared))
+    }
+
+    async fn write_script(dir: &Path, name: &str, body: &str) -> PathBuf {
+        let path = dir.join(name);
+        tokio::fs::write(&path, format!("#!/bin/sh\n{}", body))
+            .await
+            .unwrap();
+        tokio::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))
+            .await
+            .unwrap();
+        path
+    }
+
+    async fn run(command: &str, input: &Value) -> Result<Value> {
+        let dir = tempdir().unwrap();
+        let document = dir.path().join("in.pdf");
+        tokio::fs::write(&document, b"%PDF").await.unwrap();
+        let (pool, s3) = dummy_clients().await;
+        let request = ExecRequest {
+            document: &document,
+            input,
+            ocr_text: Some("hello"),
+        };
+        handle_exec_stage(
+            &pool,
+            &s3,
+            &dummy_job(),
+            &exec_stage(command),
+            "bucket",
+            request,
+        )
+        .await
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn exec_stage_reads_stdin_and_returns_json() {
+        std::env::set_var("SKIP_DB", "1");
+        let dir = tempdir().unwrap();
+        std::env::set_var("LOCAL_S3_DIR", dir.path());
+        std::env::set_var("SECRET_TOKEN", "leak");
+        let script = write_script(
+            dir.path(),
+            "tool",
+            "test -f \"$EXEC_DOCUMENT\" || exit 3\n\
+             test -z \"$SECRET_TOKEN\" || exit 4\n\
+             input=$(cat)\n\
+             printf '{\"args\": \"%s\", \"envelope\": %s}' \"$*\" \"$input\"",
+        )
+        .await;
+        std::env::set_var("EXEC_ALLOWLIST", script.display().to_string());
+
+        let out = run("tool --fast", &json!({"total": 3})).await.unwrap();
+        assert_eq!(out["args"], "--fast");
+        assert_eq!(out["envelope"]["stage_id"], "tool");
+        assert_eq!(out["envelope"]["input"]["total"], 3);
+        assert_eq!(out["envelope"]["ocr_text"], "hello");
+        std::env::remove_var("SECRET_TOKEN");
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn exec_stage_rejects_programs_outside_allowlist() {
+        std::env::set_var("EXEC_ALLOWLIST", "/usr/local/bin/allowed");
+        let err = run("sh -c true", &json!({})).await.unwrap_err();
+        assert!(err.to_string().contains("not in EXEC_ALLOWLIST"));
+        let err = run("/bin/sh -c true", &json!({})).await.unwrap_err();
+        assert!(err.to_string().contains("not in EXEC_ALLOWLIST"));
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn exec_stage_fails_on_error_exit_and_timeout() {
+        let dir = tempdir().unwrap();
+        let failing = write_script(dir.path(), "failing", "echo broken >&2\nexit 2").await;
+        let slow = write_script(dir.path(), "slow", "sleep 5\necho '{}'").await;
+        std::env::set_var(
+            "EXEC_ALLOWLIST",
+            format!("{},{}", failing.display(), slow.display()),
+        );
+        std::env::set_var("EXEC_TIMEOUT_SECS", "1");
+
+        let err = run("failing", &json!({})).await.unwrap_err();
+        assert!(err.to_string().contains("broken"));
+        let err = run("slow", &json!({})).await.unwrap_err();
+        assert!(err.to_string().contains("timed out"));
+        std::env::remove_var("EXEC_TIMEOUT_SECS");
+    }
+}
@@ -1,7 +1,7 @@
 use actix_web::{web, App, HttpResponse, HttpServer};
 use once_cell::sync::Lazy;
 use prometheus::{
-    Encoder, HistogramVec, IntCounterVec, IntCounter, IntGauge, Registry,
+    Encoder, HistogramVec, IntCounterVec, IntCounter, IntGauge, IntGaugeVec, Registry,
     TextEncoder,
 };
 
@@ -81,6 +81,16 @@ pub static RUNNING_JOBS_GAUGE: Lazy<IntGauge> = Lazy::new(|| {
     gauge
 });
 
+pub static QUEUE_DEPTH_GAUGE: Lazy<IntGaugeVec> = Lazy::new(|| {
+    let opts = prometheus::Opts::new(
+        "queue_depth",
+        "Number of pending jobs per priority",
+    );
+    let gauge = IntGaugeVec::new(opts, &["priority"]).unwrap();
+    REGISTRY.register(Box::new(gauge.clone())).unwrap();
+    gauge
+});
+
 async fn metrics() -> HttpResponse {
     let encoder = TextEncoder::new();
     let metric_families = REGISTRY.gather();
@@ -2,28 +2,163 @@ use serde::Deserialize;
 use serde_json::Value;
 use dotenvy;
 
+use crate::models::OrgSettings;
+use crate::processing::retry::RetryPolicy;
+use crate::worker::condition::StageCondition;
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct PromptTemplate {
     pub name: String,
     pub text: String,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Default)]
 pub struct Stage {
+    /// Unique identifier used to reference this stage from `inputs`.
+    pub id: Option<String>,
     #[serde(rename = "type")]
     pub stage_type: String,
+    /// Ids of the stages whose outputs feed into this one. When omitted the
+    /// stage consumes the output of the stage listed before it.
+    pub inputs: Option<Vec<String>>,
     pub command: Option<String>,
     pub prompt_name: Option<String>,
     pub ocr_engine: Option<String>,
     pub ocr_stage_endpoint: Option<String>,
     pub ocr_stage_key: Option<String>,
     pub config: Option<Value>,
+    /// Condition deciding whether the stage runs; skipped stages are recorded as such.
+    pub when: Option<StageCondition>,
+    /// Retry settings for failed attempts of this stage.
+    pub retry: Option<StageRetry>,
+    /// Timeout in seconds for a single attempt.
+    pub timeout_secs: Option<u64>,
+    /// What to do with the job when the stage fails after all attempts.
+    #[serde(default)]
+    pub on_error: OnError,
+}
+
+/// Retry settings of a stage.
+#[derive(Deserialize, Debug, Clone)]
+pub struct StageRetry {
+    /// Total number of attempts, including the first one.
+    pub max_attempts: u32,
+    /// Base delay between attempts, doubled after each failure.
+    #[serde(default)]
+    pub backoff_ms: u64,
+}
+
+/// Behaviour when a stage fails after exhausting its retries.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OnError {
+    /// Fail the whole job.
+    #[default]
+    Fail,
+    /// Record the error, pass the stage input on and keep going.
+    Continue,
+    /// Record the error and finish the job without running further stages.
+    SkipRest,
+}
+
+impl Stage {
+    /// Name used for logging and stored outputs: the stage id, or its type.
+    pub fn name(&self) -> &str {
+        self.id.as_deref().unwrap_or(&self.stage_type)
+    }
+
+    /// Whether retries and timeouts are applied by the stage's HTTP client
+    /// (AI, webhook and remote OCR engines, also as fallback of `auto`)
+    /// instead of around the whole stage by the worker.
+    pub fn retries_in_client(&self, org_settings: Option<&OrgSettings>) -> bool {
+        self.stage_type == "ai"
+            || self.stage_type == "webhook"
+            || (self.stage_type == "ocr"
+                && ocr::engine_for(self, org_settings).is_ok_and(|e| e.is_remote()))
+    }
+
+    /// Retry policy for the stage, falling back to the client defaults.
+    pub fn retry_policy(&self) -> RetryPolicy {
+        let defaults = RetryPolicy::default();
+        RetryPolicy {
+            max_attempts: self
+                .retry
+                .as_ref()
+                .map_or(defaults.max_attempts, |r| r.max_attempts),
+            backoff_ms: self
+                .retry
+                .as_ref()
+                .map_or(defaults.backoff_ms, |r| r.backoff_ms),
+            timeout: self
+                .timeout_secs
+                .map_or(defaults.timeout, std::time::Duration::from_secs),
+        }
+    }
 }
 
 pub mod ai;
+pub mod condition;
+pub mod dag;
+pub mod deliveries;
+pub mod dry_run;
+pub mod exec;
 pub mod metrics;
 pub mod ocr;
 pub mod report;
+pub mod webhook;
+
+/// Why a job failed: a machine readable reason such as `ocr_failed` and the error message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobFailure {
+    pub reason: String,
+    pub message: String,
+}
+
+impl JobFailure {
+    pub fn new(reason: impl Into<String>, error: &anyhow::Error) -> Self {
+        Self {
+            reason: reason.into(),
+            message: format!("{:#}", error),
+        }
+    }
+
+    /// Failure of a stage, reported as `<stage type>_failed`.
+    pub fn stage(stage: &Stage, error: &anyhow::Error) -> Self {
+        Self::new(format!("{}_failed", stage.stage_type), error)
+    }
+
+    /// The job was stopped because cancellation was requested.
+    pub fn cancelled() -> Self {
+        Self {
+            reason: "cancelled".to_string(),
+            message: "job was cancelled".to_string(),
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.reason == "cancelled"
+    }
+
+    /// The job was stopped because another worker took over its lease.
+    pub fn lease_lost() -> Self {
+        Self {
+            reason: "lease_lost".to_string(),
+            message: "worker lost the lease on the job".to_string(),
+        }
+    }
+
+    pub fn is_lease_lost(&self) -> bool {
+        self.reason == "lease_lost"
+    }
+}
+
+impl std::fmt::Display for JobFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.reason, self.message)
+    }
+}
+
+impl std::error::Error for JobFailure {}
 
 /// Runtime configuration for the worker.
 #[derive(Debug, Clone)]
@@ -135,6 +270,36 @@ pub async fn upload_bytes(
     }
 }
 
+/// Download a blob from S3 or the local filesystem when `LOCAL_S3_DIR` is set.
+#[tracing::instrument(skip(s3))]
+pub async fn download_bytes(
+    s3: &S3Client,
+    bucket: &str,
+    key: &str,
+) -> Result<Vec<u8>, anyhow::Error> {
+    if let Ok(local_dir) = env::var("LOCAL_S3_DIR") {
+        let mut path = PathBuf::from(local_dir);
+        path.push(key);
+        return Ok(tokio::fs::read(path).await?);
+    }
+    let obj = match s3.get_object().bucket(bucket).key(key).send().await {
+        Ok(o) => o,
+        Err(e) => {
+            tracing::error!(error=?e, bucket, key, "s3 download failed");
+            S3_ERROR_COUNTER.with_label_values(&["download"]).inc();
+            return Err(e.into());
+        }
+    };
+    match obj.body.collect().await {
+        Ok(b) => Ok(b.into_bytes().to_vec()),
+        Err(e) => {
+            tracing::error!(error=?e, bucket, key, "s3 download body failed");
+            S3_ERROR_COUNTER.with_label_values(&["download"]).inc();
+            Err(e.into())
+        }
+    }
+}
+
 use crate::models::{JobStageOutput, NewJobStageOutput};
 use sqlx::PgPool;
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -175,6 +340,23 @@ pub async fn save_stage_output(
     Ok(())
 }
 
+/// Load the most recent stored output of a stage, if any.
+pub async fn load_stage_output(
+    pool: &PgPool,
+    s3: &S3Client,
+    job_id: Uuid,
+    stage_name: &str,
+    output_type: &str,
+) -> Result<Option<Vec<u8>>, anyhow::Error> {
+    let Some(output) = JobStageOutput::find_latest(pool, job_id, stage_name, output_type).await?
+    else {
+        return Ok(None);
+    };
+    Ok(Some(
+        download_bytes(s3, &output.s3_bucket, &output.s3_key).await?,
+    ))
+}
+
 /// Log that the worker shuts down after being idle and update metrics.
 pub fn log_idle_shutdown() {
     tracing::info!("Idle timeout reached, shutting down");
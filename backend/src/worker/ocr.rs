@@ -1,12 +1,131 @@
 use crate::models::{AnalysisJob, OrgSettings};
 use crate::processing;
+use crate::processing::formats::{self, DocumentFormat};
+use crate::processing::ocr::TesseractOptions;
+use crate::processing::ocr_engine::{self, OcrEngine, OcrInput, OcrService};
+use crate::processing::ocr_layout::{join_pages, OcrLayout, OcrPage};
 use crate::worker::{metrics::API_ERROR_COUNTER, save_stage_output, Stage};
-use anyhow::Result;
+use anyhow::{Context, Result};
 use aws_sdk_s3::Client as S3Client;
+use futures_util::future::try_join_all;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Deserialize;
 use sqlx::PgPool;
 use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
 use tracing::{error, info};
 
+/// Resolution PDF pages are rendered at when the stage sets no `dpi`.
+const DEFAULT_RENDER_DPI: u32 = 300;
+
+/// Pages rendered and recognised at the same time, shared by all jobs of the
+/// worker. Set with `OCR_PAGE_CONCURRENCY`, defaults to the number of CPUs.
+static PAGE_SLOTS: Lazy<Semaphore> = Lazy::new(|| {
+    let slots = std::env::var("OCR_PAGE_CONCURRENCY")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(2, |n| n.get()));
+    Semaphore::new(slots.max(1))
+});
+
+static LANGUAGES_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\w+(\+\w+)*$").unwrap());
+
+/// `config` of an OCR stage.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct OcrConfig {
+    /// Letters and digits the text layer of a page needs before the `auto`
+    /// engine uses it instead of OCR.
+    pub min_text_chars: usize,
+    /// Minimum share of readable characters in the text layer of a page, see
+    /// [`processing::ocr::text_coverage`].
+    pub min_text_coverage: f64,
+    /// Tesseract language packs, e.g. `deu+eng`.
+    pub languages: Option<String>,
+    /// Tesseract page segmentation mode.
+    pub psm: Option<u8>,
+    /// Resolution PDF pages are rendered at, also passed to tesseract.
+    pub dpi: Option<u32>,
+}
+
+impl Default for OcrConfig {
+    fn default() -> Self {
+        Self {
+            min_text_chars: 20,
+            min_text_coverage: 0.9,
+            languages: None,
+            psm: None,
+            dpi: None,
+        }
+    }
+}
+
+impl OcrConfig {
+    /// Parse the optional `config` object of an OCR stage.
+    pub fn from_stage(stage: &Stage) -> Result<Self> {
+        match &stage.config {
+            Some(config) => serde_json::from_value(config.clone()).context("invalid OCR config"),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Check the ranges of the settings and the format of `languages`.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.min_text_coverage) {
+            return Err("'min_text_coverage' must be between 0 and 1".to_string());
+        }
+        if let Some(languages) = &self.languages {
+            if !LANGUAGES_RE.is_match(languages) {
+                return Err(format!(
+                    "'languages' must be language codes joined by '+', e.g. 'deu+eng', not '{}'",
+                    languages
+                ));
+            }
+        }
+        if self.psm.is_some_and(|psm| psm > 13) {
+            return Err("'psm' must be between 0 and 13".to_string());
+        }
+        if self.dpi.is_some_and(|dpi| !(70..=1200).contains(&dpi)) {
+            return Err("'dpi' must be between 70 and 1200".to_string());
+        }
+        Ok(())
+    }
+
+    fn tesseract_options(&self, dpi: Option<u32>) -> TesseractOptions {
+        TesseractOptions {
+            languages: self.languages.clone(),
+            psm: self.psm,
+            dpi,
+        }
+    }
+}
+
+/// Text of a document together with its page layout.
+#[derive(Debug, Default)]
+struct Recognised {
+    text: String,
+    layout: OcrLayout,
+}
+
+impl Recognised {
+    /// Text without positions, read by `method`.
+    fn from_text(text: String, method: &str) -> Self {
+        Self {
+            layout: OcrLayout::from_text(&text, method),
+            text,
+        }
+    }
+}
+
+/// Run an OCR stage and return the recognised text.
+///
+/// The pages, lines and words of the result are stored as an additional
+/// `ocr_pages` output.
+///
+/// Any failure (unreadable input, external OCR error, tesseract error) is
+/// returned as an error so the job ends up failed with reason `ocr_failed`.
 #[tracing::instrument(skip(pool, s3, job, stage, org_settings, local, txt_path))]
 pub async fn handle_ocr_stage(
     pool: &PgPool,
@@ -17,96 +136,365 @@ pub async fn handle_ocr_stage(
     bucket: &str,
     local: &Path,
     txt_path: &Path,
-) -> Result<bool> {
-    info!(job_id=%job.id, stage=%stage.stage_type, "start ocr stage");
+) -> Result<String> {
+    info!(job_id=%job.id, stage=%stage.name(), "start ocr stage");
     let timer = crate::worker::metrics::STAGE_HISTOGRAM
         .with_label_values(&[stage.stage_type.as_str()])
         .start_timer();
-    // Check if this stage should use an external OCR engine
-    let use_external = stage.ocr_engine.as_deref() == Some("external");
-
-    let text_result = if use_external {
-        let endpoint = stage
-            .ocr_stage_endpoint
-            .clone()
-            .or_else(|| org_settings.and_then(|s| s.ocr_api_endpoint.clone()))
-            .unwrap_or_else(|| std::env::var("OCR_API_URL").unwrap_or_default());
-        let key = stage
-            .ocr_stage_key
-            .clone()
-            .or_else(|| org_settings.and_then(|s| s.ocr_api_key.clone()))
-            .unwrap_or_else(|| std::env::var("OCR_API_KEY").unwrap_or_default());
-
-        let pdf_bytes = match tokio::fs::read(local).await {
-            Ok(b) => b,
-            Err(e) => {
-                error!(job_id=%job.id, "Failed to read input PDF for external OCR: {:?}", e);
-                API_ERROR_COUNTER.with_label_values(&["ocr"]).inc();
-                timer.observe_duration();
-                return Ok(true);
-            }
-        };
-
-        match processing::ocr::run_external_ocr(
-            &endpoint,
-            if key.is_empty() {
-                None
-            } else {
-                Some(key.as_str())
-            },
-            pdf_bytes,
-            local
-                .file_name()
-                .map(|f| f.to_string_lossy())
-                .unwrap_or_else(|| "input.pdf".into())
-                .as_ref(),
-        )
-        .await
-        {
-            Ok(text) => text,
-            Err(e) => {
-                error!(job_id=%job.id, "External OCR failed: {:?}", e);
-                API_ERROR_COUNTER.with_label_values(&["ocr"]).inc();
-                timer.observe_duration();
-                return Ok(true);
-            }
-        }
-    } else {
-        if let Err(e) = processing::ocr::run_ocr(local, txt_path).await {
+    let recognised = match run_ocr_engine(stage, org_settings, local, txt_path).await {
+        Ok(recognised) => recognised,
+        Err(e) => {
             error!(job_id=%job.id, "OCR failed: {:?}", e);
             API_ERROR_COUNTER.with_label_values(&["ocr"]).inc();
             timer.observe_duration();
-            return Ok(true);
-        }
-        match tokio::fs::read_to_string(txt_path).await {
-            Ok(t) => t,
-            Err(e) => {
-                error!(job_id=%job.id, "Failed to read OCR output: {:?}", e);
-                API_ERROR_COUNTER.with_label_values(&["ocr"]).inc();
-                timer.observe_duration();
-                return Ok(true);
-            }
+            return Err(e);
         }
     };
 
-    if let Err(e) = tokio::fs::write(txt_path, &text_result).await {
-        error!(job_id=%job.id, "Failed to write OCR text: {:?}", e);
-    }
     let _ = save_stage_output(
         pool,
         s3,
         job.id,
-        &stage.stage_type,
+        stage.name(),
         "txt",
         bucket,
-        text_result.clone().into_bytes(),
+        recognised.text.clone().into_bytes(),
         "txt",
     )
     .await;
-    let _ = tokio::fs::remove_file(txt_path).await;
-    info!(job_id=%job.id, stage=%stage.stage_type, "finished ocr stage");
+    let _ = save_stage_output(
+        pool,
+        s3,
+        job.id,
+        stage.name(),
+        "ocr_pages",
+        bucket,
+        serde_json::to_vec(&recognised.layout)?,
+        "json",
+    )
+    .await;
+    info!(job_id=%job.id, stage=%stage.name(), "finished ocr stage");
     timer.observe_duration();
-    Ok(false)
+    Ok(recognised.text)
+}
+
+/// Text and page layout of the document at `local` as the OCR stage would
+/// recognise them, without storing anything. Used by test runs of pipelines.
+pub async fn recognise_document(
+    stage: &Stage,
+    org_settings: Option<&OrgSettings>,
+    local: &Path,
+    txt_path: &Path,
+) -> Result<(String, OcrLayout)> {
+    let recognised = run_ocr_engine(stage, org_settings, local, txt_path).await?;
+    Ok((recognised.text, recognised.layout))
+}
+
+/// Obtain the text of the document at `local`, whose format follows from its
+/// extension. PDFs and images go through the configured OCR engine, other
+/// formats are converted directly.
+async fn run_ocr_engine(
+    stage: &Stage,
+    org_settings: Option<&OrgSettings>,
+    local: &Path,
+    txt_path: &Path,
+) -> Result<Recognised> {
+    let format =
+        DocumentFormat::from_filename(&local.to_string_lossy()).unwrap_or(DocumentFormat::Pdf);
+    if format.needs_ocr() {
+        return recognise(stage, org_settings, local, txt_path).await;
+    }
+    let bytes = tokio::fs::read(local)
+        .await
+        .context("Failed to read input document")?;
+    if format == DocumentFormat::Email {
+        let text = email_text(stage, org_settings, &bytes, txt_path).await?;
+        return Ok(Recognised::from_text(text, "email"));
+    }
+    formats::extract_text(format, &bytes)
+        .map(|text| Recognised::from_text(text, "text"))
+        .with_context(|| {
+            format!(
+                "Failed to extract text from {} document",
+                format.extension()
+            )
+        })
+}
+
+/// Text of an email followed by the text of each supported attachment.
+/// PDF and image attachments go through OCR like uploaded documents.
+async fn email_text(
+    stage: &Stage,
+    org_settings: Option<&OrgSettings>,
+    bytes: &[u8],
+    txt_path: &Path,
+) -> Result<String> {
+    let email = formats::parse_email(bytes).context("Failed to read email")?;
+    let mut text = email.text;
+    for (idx, attachment) in email.attachments.iter().enumerate() {
+        let attachment_text = if attachment.format.needs_ocr() {
+            let path =
+                txt_path.with_extension(format!("att{}.{}", idx, attachment.format.extension()));
+            let att_txt_path = txt_path.with_extension(format!("att{}.txt", idx));
+            tokio::fs::write(&path, &attachment.bytes)
+                .await
+                .context("Failed to write email attachment")?;
+            let result = recognise(stage, org_settings, &path, &att_txt_path).await;
+            let _ = tokio::fs::remove_file(&path).await;
+            let _ = tokio::fs::remove_file(&att_txt_path).await;
+            result
+                .with_context(|| format!("OCR of attachment '{}' failed", attachment.filename))?
+                .text
+        } else {
+            formats::extract_text(attachment.format, &attachment.bytes)
+                .with_context(|| format!("Failed to read attachment '{}'", attachment.filename))?
+        };
+        text.push_str(&format!("\n--- Attachment: {} ---\n", attachment.filename));
+        text.push_str(attachment_text.trim_end());
+        text.push('\n');
+    }
+    for name in &email.skipped {
+        info!(attachment = %name, "skipping email attachment of unsupported format");
+    }
+    Ok(text)
+}
+
+/// `ocr_engine` mode that uses the text layer of PDF pages and only OCRs
+/// pages without readable text.
+pub const AUTO_ENGINE: &str = "auto";
+
+/// Engine of stages that leave `ocr_engine` out or set it to `default`.
+fn org_default_engine(org_settings: Option<&OrgSettings>) -> String {
+    org_settings
+        .and_then(|s| s.ocr_engine.clone())
+        .filter(|e| !e.is_empty())
+        .unwrap_or_else(|| ocr_engine::DEFAULT_ENGINE.to_string())
+}
+
+/// Name of the engine `stage` runs with: [`AUTO_ENGINE`] or a registered
+/// engine. Used as label of the OCR metrics.
+pub fn engine_name(stage: &Stage, org_settings: Option<&OrgSettings>) -> String {
+    match stage.ocr_engine.as_deref() {
+        None | Some("default") => org_default_engine(org_settings),
+        Some(name) => name.to_string(),
+    }
+}
+
+/// Engine recognising the documents of `stage`. The `auto` mode OCRs pages
+/// with the `external` engine when the stage has an endpoint, otherwise with
+/// the organization's default engine.
+pub fn engine_for(stage: &Stage, org_settings: Option<&OrgSettings>) -> Result<Arc<dyn OcrEngine>> {
+    let mut name = engine_name(stage, org_settings);
+    if name == AUTO_ENGINE {
+        name = match stage.ocr_stage_endpoint {
+            Some(_) => "external".to_string(),
+            None => org_default_engine(org_settings),
+        };
+    }
+    ocr_engine::get(&name).with_context(|| format!("Unknown OCR engine '{}'", name))
+}
+
+/// Endpoint and key of the stage's or organization's OCR service.
+fn ocr_service(stage: &Stage, org_settings: Option<&OrgSettings>) -> OcrService {
+    let endpoint = stage
+        .ocr_stage_endpoint
+        .clone()
+        .or_else(|| org_settings.and_then(|s| s.ocr_api_endpoint.clone()))
+        .unwrap_or_else(|| std::env::var("OCR_API_URL").unwrap_or_default());
+    let key = stage
+        .ocr_stage_key
+        .clone()
+        .or_else(|| org_settings.and_then(|s| s.ocr_api_key.clone()))
+        .unwrap_or_else(|| std::env::var("OCR_API_KEY").unwrap_or_default());
+    OcrService {
+        endpoint,
+        api_key: Some(key).filter(|k| !k.is_empty()),
+        policy: stage.retry_policy(),
+    }
+}
+
+/// Run the configured OCR engine for `stage` on the PDF or image at `local`.
+///
+/// PDFs are recognised page by page by engines that read images and by the
+/// `auto` engine, other input in one go.
+async fn recognise(
+    stage: &Stage,
+    org_settings: Option<&OrgSettings>,
+    local: &Path,
+    txt_path: &Path,
+) -> Result<Recognised> {
+    let config = OcrConfig::from_stage(stage)?;
+    let engine = engine_for(stage, org_settings)?;
+    let service = ocr_service(stage, org_settings);
+    let is_pdf =
+        DocumentFormat::from_filename(&local.to_string_lossy()) == Some(DocumentFormat::Pdf);
+    if engine_name(stage, org_settings) == AUTO_ENGINE && is_pdf {
+        return recognise_auto(engine.as_ref(), &service, &config, local, txt_path).await;
+    }
+    if is_pdf && engine.needs_images() {
+        let bytes = tokio::fs::read(local)
+            .await
+            .context("Failed to read input document")?;
+        let pages: Vec<u32> = (1..=processing::ocr::pdf_page_count(&bytes)?).collect();
+        info!(pages = pages.len(), "recognising PDF pages");
+        let (texts, pages): (Vec<_>, Vec<_>) = recognise_pages(
+            engine.as_ref(),
+            &service,
+            &config,
+            &bytes,
+            local,
+            txt_path,
+            &pages,
+        )
+        .await?
+        .into_iter()
+        .unzip();
+        Ok(Recognised {
+            text: join_pages(&texts),
+            layout: OcrLayout { pages },
+        })
+    } else {
+        let filename = local
+            .file_name()
+            .map(|f| f.to_string_lossy())
+            .unwrap_or_else(|| "input.pdf".into());
+        let (text, layout) = engine
+            .recognise(&OcrInput {
+                path: local,
+                filename: &filename,
+                output_base: txt_path,
+                options: &config.tesseract_options(config.dpi),
+                service: &service,
+            })
+            .await?;
+        Ok(Recognised { text, layout })
+    }
+}
+
+/// Use the embedded text of every PDF page that has enough readable text and
+/// OCR only the remaining pages with `engine`.
+async fn recognise_auto(
+    engine: &dyn OcrEngine,
+    service: &OcrService,
+    config: &OcrConfig,
+    local: &Path,
+    txt_path: &Path,
+) -> Result<Recognised> {
+    let bytes = tokio::fs::read(local)
+        .await
+        .context("Failed to read input document")?;
+    let mut pages: Vec<(String, OcrPage)> = Vec::new();
+    let mut scanned = Vec::new();
+    for (idx, page_text) in processing::ocr::pdf_page_texts(&bytes)?
+        .into_iter()
+        .enumerate()
+    {
+        let page = idx as u32 + 1;
+        let coverage = processing::ocr::text_coverage(&page_text);
+        let chars = page_text.chars().filter(|c| c.is_alphanumeric()).count();
+        let mut page_layout =
+            if chars >= config.min_text_chars && coverage >= config.min_text_coverage {
+                OcrLayout::from_text(&page_text, "text")
+                    .pages
+                    .into_iter()
+                    .next()
+                    .unwrap_or_default()
+            } else {
+                scanned.push(page);
+                OcrPage::default()
+            };
+        page_layout.page = page;
+        page_layout.coverage = Some(coverage);
+        pages.push((page_text, page_layout));
+    }
+    info!(
+        pages = pages.len(),
+        ocr_pages = scanned.len(),
+        "read PDF text layer"
+    );
+    let recognised =
+        recognise_pages(engine, service, config, &bytes, local, txt_path, &scanned).await?;
+    for (text, page_layout) in recognised {
+        let (page_text, layout) = &mut pages[page_layout.page as usize - 1];
+        *page_text = text;
+        *layout = OcrPage {
+            coverage: layout.coverage,
+            ..page_layout
+        };
+    }
+    let (texts, pages): (Vec<_>, Vec<_>) = pages.into_iter().unzip();
+    Ok(Recognised {
+        text: join_pages(&texts),
+        layout: OcrLayout { pages },
+    })
+}
+
+/// OCR the given pages of a PDF concurrently, limited by the worker's page
+/// slots. Results are returned in the order of `pages`.
+async fn recognise_pages(
+    engine: &dyn OcrEngine,
+    service: &OcrService,
+    config: &OcrConfig,
+    bytes: &[u8],
+    local: &Path,
+    txt_path: &Path,
+    pages: &[u32],
+) -> Result<Vec<(String, OcrPage)>> {
+    try_join_all(pages.iter().map(|page| async move {
+        let _slot = PAGE_SLOTS
+            .acquire()
+            .await
+            .context("OCR page slots closed")?;
+        recognise_page(engine, service, config, bytes, local, txt_path, *page)
+            .await
+            .with_context(|| format!("OCR of page {} failed", page))
+    }))
+    .await
+}
+
+/// OCR a single PDF page: rendered to an image for engines that read images,
+/// otherwise passed on as a PDF of its own.
+async fn recognise_page(
+    engine: &dyn OcrEngine,
+    service: &OcrService,
+    config: &OcrConfig,
+    bytes: &[u8],
+    local: &Path,
+    txt_path: &Path,
+    page: u32,
+) -> Result<(String, OcrPage)> {
+    let base = txt_path.with_extension(format!("page{}", page));
+    let (input, dpi) = if engine.needs_images() {
+        let dpi = config.dpi.unwrap_or(DEFAULT_RENDER_DPI);
+        let image = processing::ocr::render_pdf_page(local, page, dpi, &base)
+            .await
+            .context("Failed to render page")?;
+        (image, Some(dpi))
+    } else {
+        let page_pdf = txt_path.with_extension(format!("page{}.pdf", page));
+        tokio::fs::write(&page_pdf, processing::ocr::pdf_single_page(bytes, page)?)
+            .await
+            .context("Failed to write page")?;
+        (page_pdf, config.dpi)
+    };
+    let result = engine
+        .recognise(&OcrInput {
+            path: &input,
+            filename: &format!("page-{}.pdf", page),
+            output_base: &base,
+            options: &config.tesseract_options(dpi),
+            service,
+        })
+        .await;
+    let _ = tokio::fs::remove_file(&input).await;
+    let (text, layout) = result?;
+    // The page is read on its own, so its layout has at most one page
+    let mut page_layout = layout.pages.into_iter().next().unwrap_or_default();
+    page_layout.page = page;
+    if page_layout.method.is_empty() {
+        page_layout.method = engine.name().to_string();
+    }
+    Ok((text, page_layout))
 }
 
 #[cfg(test)]
@@ -120,6 +508,12 @@ mod tests {
     use tempfile::tempdir;
     use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
 
+    /// Writes "page N" to the image instead of rendering page N.
+    /// Arguments: -f N -l N -r DPI -png -singlefile <input> <prefix>
+    const PDFTOPPM: &str = "#!/bin/sh\nfor a; do out=$a; done\necho \"page $2\" > $out.png";
+
+    const TSV_HEADER: &str = "level\\tpage_num\\tblock_num\\tpar_num\\tline_num\\tword_num\\tleft\\ttop\\twidth\\theight\\tconf\\ttext\\n";
+
     fn dummy_stage() -> Stage {
         Stage {
             stage_type: "ocr".into(),
@@ -129,6 +523,7 @@ mod tests {
             ocr_stage_endpoint: None,
             ocr_stage_key: None,
             config: None,
+            ..Default::default()
         }
     }
 
@@ -140,9 +535,26 @@ mod tests {
             pipeline_id: uuid::Uuid::new_v4(),
             status: "pending".into(),
             created_at: chrono::Utc::now(),
+            ..Default::default()
         }
     }
 
+    /// Put executable `scripts` first on the PATH and return the old PATH.
+    async fn stub_tools(dir: &Path, scripts: &[(&str, &str)]) -> String {
+        let bin_dir = dir.join("bin");
+        tokio::fs::create_dir_all(&bin_dir).await.unwrap();
+        for (name, script) in scripts {
+            let path = bin_dir.join(name);
+            tokio::fs::write(&path, script).await.unwrap();
+            tokio::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))
+                .await
+                .unwrap();
+        }
+        let old_path = std::env::var("PATH").unwrap_or_default();
+        std::env::set_var("PATH", format!("{}:{}", bin_dir.display(), old_path));
+        old_path
+    }
+
     async fn dummy_clients() -> (sqlx::Pool<sqlx::Postgres>, S3Client) {
         let pool = PgPoolOptions::new()
             .connect_lazy("postgres://user@localhost/db")
@@ -174,10 +586,8 @@ mod tests {
         let input = dir.path().join("in.pdf");
         tokio::fs::write(&input, b"pdf").await.unwrap();
         let txt = dir.path().join("out.txt");
-        let res = handle_ocr_stage(&pool, &s3, &job, &stage, None, "bucket", &input, &txt)
-            .await
-            .unwrap();
-        assert!(res);
+        let res = handle_ocr_stage(&pool, &s3, &job, &stage, None, "bucket", &input, &txt).await;
+        assert!(res.is_err());
         assert_eq!(server.received_requests().await.unwrap().len(), 0);
     }
 
@@ -192,27 +602,27 @@ mod tests {
             .respond_with(ResponseTemplate::new(200))
             .mount(&server)
             .await;
-        let bin_dir = dir.path().join("bin");
-        tokio::fs::create_dir(&bin_dir).await.unwrap();
-        let script = bin_dir.join("tesseract");
-        tokio::fs::write(&script, "#!/bin/sh\necho hello > $2")
-            .await
-            .unwrap();
-        tokio::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755))
-            .await
-            .unwrap();
-        let old_path = std::env::var("PATH").unwrap_or_default();
-        std::env::set_var("PATH", format!("{}:{}", bin_dir.display(), old_path));
+        let tesseract = format!(
+            "#!/bin/sh\nprintf '{}5\\t1\\t1\\t1\\t1\\t1\\t10\\t10\\t50\\t20\\t91\\thello\\n' > $2.tsv",
+            TSV_HEADER
+        );
+        stub_tools(
+            dir.path(),
+            &[("pdftoppm", PDFTOPPM), ("tesseract", tesseract.as_str())],
+        )
+        .await;
         let (pool, s3) = dummy_clients().await;
         let job = dummy_job();
         let stage = dummy_stage();
         let input = dir.path().join("in.pdf");
-        tokio::fs::write(&input, b"pdf").await.unwrap();
+        tokio::fs::write(&input, pdf_with_pages(&[""]))
+            .await
+            .unwrap();
         let txt = dir.path().join("out.txt");
         let res = handle_ocr_stage(&pool, &s3, &job, &stage, None, "bucket", &input, &txt)
             .await
             .unwrap();
-        assert!(!res);
+        assert_eq!(res.trim(), "hello");
         assert_eq!(server.received_requests().await.unwrap().len(), 0);
     }
 
@@ -237,6 +647,7 @@ mod tests {
             ocr_stage_endpoint: Some(format!("{}/ocr", server.uri())),
             ocr_stage_key: Some("k".into()),
             config: None,
+            ..Default::default()
         };
         let input = dir.path().join("in.pdf");
         tokio::fs::write(&input, b"pdf").await.unwrap();
@@ -244,7 +655,7 @@ mod tests {
         let res = handle_ocr_stage(&pool, &s3, &job, &stage, None, "bucket", &input, &txt)
             .await
             .unwrap();
-        assert!(!res);
+        assert_eq!(res, "ext");
         assert_eq!(server.received_requests().await.unwrap().len(), 1);
     }
 
@@ -272,6 +683,8 @@ mod tests {
             ocr_api_key: Some("k2".into()),
             prompt_templates: None,
             ai_custom_headers: None,
+            max_concurrent_jobs: None,
+            ocr_engine: None,
         };
         let stage = Stage {
             stage_type: "ocr".into(),
@@ -281,6 +694,7 @@ mod tests {
             ocr_stage_endpoint: None,
             ocr_stage_key: None,
             config: None,
+            ..Default::default()
         };
         let input = dir.path().join("in.pdf");
         tokio::fs::write(&input, b"pdf").await.unwrap();
@@ -297,7 +711,357 @@ mod tests {
         )
         .await
         .unwrap();
-        assert!(!res);
+        assert_eq!(res, "ext2");
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn org_default_engine_sends_json_with_base64_document() {
+        let dir = tempdir().unwrap();
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"text": "Total 5"}"#))
+            .mount(&server)
+            .await;
+        let settings = OrgSettings {
+            org_id: uuid::Uuid::new_v4(),
+            monthly_upload_quota: 100,
+            monthly_analysis_quota: 100,
+            accent_color: "#fff".into(),
+            ai_api_endpoint: None,
+            ai_api_key: None,
+            ocr_api_endpoint: Some(server.uri()),
+            ocr_api_key: Some("k".into()),
+            prompt_templates: None,
+            ai_custom_headers: None,
+            max_concurrent_jobs: None,
+            ocr_engine: Some("external_json".into()),
+        };
+        let stage = dummy_stage();
+        assert_eq!(engine_name(&stage, Some(&settings)), "external_json");
+        assert_eq!(engine_name(&stage, None), "tesseract");
+        assert!(stage.retries_in_client(Some(&settings)));
+        assert!(!stage.retries_in_client(None));
+
+        let input = dir.path().join("in.png");
+        tokio::fs::write(&input, b"png").await.unwrap();
+        let recognised =
+            run_ocr_engine(&stage, Some(&settings), &input, &dir.path().join("out.txt"))
+                .await
+                .unwrap();
+        assert_eq!(recognised.text, "Total 5");
+        assert_eq!(recognised.layout.pages[0].method, "external_json");
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].headers.get("x-api-key").unwrap(), "k");
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"filename": "in.png", "content_type": "image/png", "content": "cG5n"})
+        );
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn ocr_stage_external_http_error_is_failure() {
+        std::env::set_var("SKIP_DB", "1");
+        let dir = tempdir().unwrap();
+        std::env::set_var("LOCAL_S3_DIR", dir.path());
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(502).set_body_string("bad gateway"))
+            .mount(&server)
+            .await;
+        let (pool, s3) = dummy_clients().await;
+        let job = dummy_job();
+        let stage = Stage {
+            stage_type: "ocr".into(),
+            ocr_engine: Some("external".into()),
+            ocr_stage_endpoint: Some(server.uri()),
+            retry: Some(crate::worker::StageRetry {
+                max_attempts: 1,
+                backoff_ms: 0,
+            }),
+            ..Default::default()
+        };
+        let input = dir.path().join("in.pdf");
+        tokio::fs::write(&input, b"pdf").await.unwrap();
+        let txt = dir.path().join("out.txt");
+        let err = handle_ocr_stage(&pool, &s3, &job, &stage, None, "bucket", &input, &txt)
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("502"));
         assert_eq!(server.received_requests().await.unwrap().len(), 1);
     }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn ocr_stage_reads_email_and_recognises_attachments() {
+        std::env::set_var("SKIP_DB", "1");
+        let dir = tempdir().unwrap();
+        std::env::set_var("LOCAL_S3_DIR", dir.path());
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("Total 10.00"))
+            .mount(&server)
+            .await;
+        let (pool, s3) = dummy_clients().await;
+        let job = dummy_job();
+        let stage = Stage {
+            stage_type: "ocr".into(),
+            ocr_engine: Some("external".into()),
+            ocr_stage_endpoint: Some(server.uri()),
+            ..Default::default()
+        };
+        let eml = concat!(
+            "From: billing@supplier.example\r\n",
+            "Subject: Invoice 42\r\n",
+            "Content-Type: multipart/mixed; boundary=\"b\"\r\n\r\n",
+            "--b\r\nContent-Type: text/html\r\n\r\n<p>See <b>attached</b> scan.</p>\r\n",
+            "--b\r\nContent-Type: image/jpeg\r\n",
+            "Content-Disposition: attachment; filename=\"scan.jpg\"\r\n",
+            "Content-Transfer-Encoding: base64\r\n\r\n/9j/4AAQ\r\n",
+            "--b--\r\n"
+        );
+        let input = dir.path().join("in.eml");
+        tokio::fs::write(&input, eml).await.unwrap();
+        let txt = dir.path().join("out.txt");
+        let res = handle_ocr_stage(&pool, &s3, &job, &stage, None, "bucket", &input, &txt)
+            .await
+            .unwrap();
+        assert!(res.contains("Subject: Invoice 42"));
+        assert!(res.contains("See attached scan."));
+        assert!(res.contains("--- Attachment: scan.jpg ---\nTotal 10.00"));
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(String::from_utf8_lossy(&requests[0].body).contains("Content-Type: image/jpeg"));
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn ocr_stage_converts_html_without_ocr() {
+        std::env::set_var("SKIP_DB", "1");
+        let dir = tempdir().unwrap();
+        std::env::set_var("LOCAL_S3_DIR", dir.path());
+        let (pool, s3) = dummy_clients().await;
+        let input = dir.path().join("in.html");
+        tokio::fs::write(
+            &input,
+            "<html><body><h1>Invoice</h1><p>Total: 5</p></body></html>",
+        )
+        .await
+        .unwrap();
+        let txt = dir.path().join("out.txt");
+        let res = handle_ocr_stage(
+            &pool,
+            &s3,
+            &dummy_job(),
+            &dummy_stage(),
+            None,
+            "bucket",
+            &input,
+            &txt,
+        )
+        .await
+        .unwrap();
+        assert!(res.contains("Invoice"));
+        assert!(res.contains("Total: 5"));
+        assert!(!res.contains('<'));
+    }
+
+    /// PDF with one page per entry of `texts`. Pages with empty text have no
+    /// text layer, like scans.
+    fn pdf_with_pages(texts: &[&str]) -> Vec<u8> {
+        use lopdf::content::{Content, Operation};
+        use lopdf::{dictionary, Object, Stream};
+        let mut doc = lopdf::Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+        });
+        let mut kids = Vec::new();
+        for text in texts {
+            let operations = if text.is_empty() {
+                vec![]
+            } else {
+                vec![
+                    Operation::new("BT", vec![]),
+                    Operation::new("Tf", vec!["F1".into(), 12.into()]),
+                    Operation::new("Td", vec![72.into(), 700.into()]),
+                    Operation::new("Tj", vec![Object::string_literal(*text)]),
+                    Operation::new("ET", vec![]),
+                ]
+            };
+            let content = Content { operations };
+            let content_id = doc.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+            let page_id = doc.add_object(dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "Contents" => content_id,
+                "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+                "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+            });
+            kids.push(page_id.into());
+        }
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => kids,
+                "Count" => texts.len() as i64,
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        doc.trailer.set("Root", catalog_id);
+        let mut bytes = Vec::new();
+        doc.save_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn auto_engine_uses_text_layer_and_ocrs_scanned_pages() {
+        std::env::set_var("SKIP_DB", "1");
+        let dir = tempdir().unwrap();
+        std::env::set_var("LOCAL_S3_DIR", dir.path());
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("Signed by the customer"))
+            .mount(&server)
+            .await;
+        let (pool, s3) = dummy_clients().await;
+        let stage = Stage {
+            stage_type: "ocr".into(),
+            ocr_engine: Some("auto".into()),
+            ocr_stage_endpoint: Some(server.uri()),
+            ..Default::default()
+        };
+        let input = dir.path().join("in.pdf");
+        tokio::fs::write(
+            &input,
+            pdf_with_pages(&["Invoice 2024-17 total due 120.50 EUR", ""]),
+        )
+        .await
+        .unwrap();
+        let txt = dir.path().join("out.txt");
+        let recognised = run_ocr_engine(&stage, None, &input, &txt).await.unwrap();
+        assert_eq!(
+            recognised.text,
+            "Invoice 2024-17 total due 120.50 EUR\n\x0c\nSigned by the customer"
+        );
+        let pages = &recognised.layout.pages;
+        let methods: Vec<_> = pages.iter().map(|p| (p.page, p.method.as_str())).collect();
+        assert_eq!(methods, vec![(1, "text"), (2, "external")]);
+        assert_eq!(pages[0].coverage, Some(1.0));
+        assert_eq!(pages[1].lines[0].text, "Signed by the customer");
+
+        // Only the scanned page is sent, as a PDF of its own
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 1);
+        let body = &requests[0].body;
+        let start = body.windows(5).position(|w| w == b"%PDF-").unwrap();
+        let end = body.windows(5).rposition(|w| w == b"%%EOF").unwrap() + 5;
+        let page = lopdf::Document::load_mem(&body[start..end]).unwrap();
+        assert_eq!(page.get_pages().len(), 1);
+
+        let res = handle_ocr_stage(
+            &pool,
+            &s3,
+            &dummy_job(),
+            &stage,
+            None,
+            "bucket",
+            &input,
+            &txt,
+        )
+        .await
+        .unwrap();
+        assert_eq!(res, recognised.text);
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn auto_engine_renders_pages_for_tesseract() {
+        let dir = tempdir().unwrap();
+        let tesseract = "#!/bin/sh\nprintf 'header\\n5\\t1\\t1\\t1\\t1\\t1\\t0\\t0\\t9\\t9\\t90\\tscanned %s\\n' \"$(cat $1)\" > $2.tsv";
+        let old_path = stub_tools(
+            dir.path(),
+            &[("pdftoppm", PDFTOPPM), ("tesseract", tesseract)],
+        )
+        .await;
+        let stage = Stage {
+            stage_type: "ocr".into(),
+            ocr_engine: Some("auto".into()),
+            config: Some(serde_json::json!({"min_text_chars": 1000})),
+            ..Default::default()
+        };
+        let input = dir.path().join("in.pdf");
+        tokio::fs::write(
+            &input,
+            pdf_with_pages(&["Invoice 2024-17 total due 120.50 EUR", ""]),
+        )
+        .await
+        .unwrap();
+        let recognised = run_ocr_engine(&stage, None, &input, &dir.path().join("out.txt"))
+            .await
+            .unwrap();
+        std::env::set_var("PATH", old_path);
+        assert_eq!(recognised.text, "scanned page 1\n\x0c\nscanned page 2");
+        let pages = &recognised.layout.pages;
+        assert_eq!(pages[1].page, 2);
+        assert!(pages.iter().all(|p| p.method == "tesseract"));
+        assert_eq!(pages[1].lines[0].confidence, Some(90.0));
+        let mut leftovers = tokio::fs::read_dir(dir.path()).await.unwrap();
+        while let Some(entry) = leftovers.next_entry().await.unwrap() {
+            assert!(!entry.file_name().to_string_lossy().contains("page"));
+        }
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn local_engine_recognises_pdf_pages_concurrently_in_order() {
+        let dir = tempdir().unwrap();
+        let log = dir.path().join("tesseract.log");
+        // Earlier pages take longer, so they finish last
+        let tesseract = format!(
+            "#!/bin/sh\necho \"$@\" >> {}\nn=$(cut -d' ' -f2 $1)\nsleep 0.$((4 - n))\n\
+             printf 'header\\n5\\t1\\t1\\t1\\t1\\t1\\t0\\t0\\t9\\t9\\t90\\tText of %s\\n' \"$(cat $1)\" > $2.tsv",
+            log.display()
+        );
+        let old_path = stub_tools(
+            dir.path(),
+            &[("pdftoppm", PDFTOPPM), ("tesseract", tesseract.as_str())],
+        )
+        .await;
+        let stage = Stage {
+            stage_type: "ocr".into(),
+            config: Some(serde_json::json!({"languages": "deu+eng", "psm": 6, "dpi": 150})),
+            ..Default::default()
+        };
+        let input = dir.path().join("in.pdf");
+        tokio::fs::write(&input, pdf_with_pages(&["", "", ""]))
+            .await
+            .unwrap();
+        let recognised = run_ocr_engine(&stage, None, &input, &dir.path().join("out.txt"))
+            .await
+            .unwrap();
+        std::env::set_var("PATH", old_path);
+        assert_eq!(
+            recognised.text,
+            "Text of page 1\n\x0c\nText of page 2\n\x0c\nText of page 3"
+        );
+        let numbers: Vec<u32> = recognised.layout.pages.iter().map(|p| p.page).collect();
+        assert_eq!(numbers, vec![1, 2, 3]);
+        let calls = tokio::fs::read_to_string(&log).await.unwrap();
+        assert_eq!(calls.lines().count(), 3);
+        assert!(calls
+            .lines()
+            .all(|l| l.ends_with("-l deu+eng --psm 6 --dpi 150 tsv")));
+    }
 }
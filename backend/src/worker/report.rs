@@ -26,7 +26,7 @@ pub async fn handle_report_stage(
     json_result: &serde_json::Value,
     local_pdf: &Path,
 ) -> Result<()> {
-    info!(job_id=%job.id, stage=%stage.stage_type, "start report stage");
+    info!(job_id=%job.id, stage=%stage.name(), "start report stage");
     let timer = crate::worker::metrics::STAGE_HISTOGRAM
         .with_label_values(&[stage.stage_type.as_str()])
         .start_timer();
@@ -76,7 +76,7 @@ pub async fn handle_report_stage(
             pool,
             s3,
             job.id,
-            &stage.stage_type,
+            stage.name(),
             "pdf",
             bucket,
             Vec::new(),
@@ -88,7 +88,7 @@ pub async fn handle_report_stage(
     }
 
     let _ = local_pdf; // suppress unused
-    info!(job_id=%job.id, stage=%stage.stage_type, "finished report stage");
+    info!(job_id=%job.id, stage=%stage.name(), "finished report stage");
     timer.observe_duration();
     Ok(())
 }
@@ -103,11 +103,11 @@ mod tests {
     use serial_test::serial;
 
     fn stage() -> Stage {
-        Stage { stage_type: "report".into(), command: None, prompt_name: None, ocr_engine: None, ocr_stage_endpoint: None, ocr_stage_key: None, config: None }
+        Stage { stage_type: "report".into(), command: None, prompt_name: None, ocr_engine: None, ocr_stage_endpoint: None, ocr_stage_key: None, config: None, ..Default::default() }
     }
 
     fn job() -> AnalysisJob {
-        AnalysisJob { id: uuid::Uuid::new_v4(), org_id: uuid::Uuid::new_v4(), document_id: uuid::Uuid::new_v4(), pipeline_id: uuid::Uuid::new_v4(), status: String::new(), created_at: chrono::Utc::now() }
+        AnalysisJob { id: uuid::Uuid::new_v4(), org_id: uuid::Uuid::new_v4(), document_id: uuid::Uuid::new_v4(), pipeline_id: uuid::Uuid::new_v4(), status: String::new(), created_at: chrono::Utc::now(), ..Default::default() }
     }
 
     fn doc() -> Document {
@@ -0,0 +1,402 @@
+//! `webhook` stages POST the stage input to an arbitrary HTTP service.
+//!
+//! The request body is a JSON payload with the job, document and stage ids and
+//! the stage input. Depending on the `document` setting the document is added
+//! as a presigned download URL or sent alongside the payload as multipart
+//! form data. The JSON response, optionally narrowed by a JSONPath, becomes
+//! the stage output.
+
+use crate::models::{AnalysisJob, Document};
+use crate::processing::template::render_template;
+use crate::processing::webhook::{send_webhook, WebhookRequest};
+use crate::worker::metrics::API_ERROR_COUNTER;
+use crate::worker::{save_stage_output, Stage};
+use anyhow::{anyhow, bail, Context, Result};
+use aws_sdk_s3::presigning::PresigningConfig;
+use aws_sdk_s3::Client as S3Client;
+use jsonpath_rust::JsonPath;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+use tracing::info;
+
+/// Lifetime of presigned document URLs handed to webhooks.
+const PRESIGN_EXPIRY: Duration = Duration::from_secs(3600);
+
+/// Prefix of the worker environment variables `signing_secret_env` may name,
+/// so pipelines cannot sign requests with other secrets of the worker.
+pub const SIGNING_SECRET_ENV_PREFIX: &str = "WEBHOOK_SECRET_";
+
+/// How the document is made available to the webhook.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DocumentMode {
+    /// Only the JSON payload is sent.
+    #[default]
+    None,
+    /// The document is sent as the `document` part of a multipart request.
+    Bytes,
+    /// A presigned download URL is added to the payload as `document_url`.
+    Url,
+}
+
+/// `config` of a `webhook` stage.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Extra request headers. Values may use `{{job_id}}`, `{{org_id}}`,
+    /// `{{document_id}}`, `{{stage_id}}` and `{{input.*}}` placeholders.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub document: DocumentMode,
+    /// Secret used to sign requests with HMAC-SHA256.
+    pub signing_secret: Option<String>,
+    /// Worker environment variable holding the signing secret, so it does not
+    /// have to be stored in the pipeline. Must start with
+    /// [`SIGNING_SECRET_ENV_PREFIX`].
+    pub signing_secret_env: Option<String>,
+    /// JSONPath selecting the part of the response kept as stage output.
+    pub extract: Option<String>,
+}
+
+impl WebhookConfig {
+    /// Parse the `config` object of a webhook stage.
+    pub fn from_stage(stage: &Stage) -> Result<Self> {
+        let config = stage
+            .config
+            .clone()
+            .ok_or_else(|| anyhow!("webhook stage requires a config with a url"))?;
+        serde_json::from_value(config).context("invalid webhook config")
+    }
+
+    /// Check the URL scheme, the secret variable name and the JSONPath syntax.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.url.starts_with("http://") || self.url.starts_with("https://")) {
+            return Err(format!(
+                "url '{}' must start with http:// or https://",
+                self.url
+            ));
+        }
+        if self.signing_secret.is_some() && self.signing_secret_env.is_some() {
+            return Err("use either signing_secret or signing_secret_env".to_string());
+        }
+        if let Some(name) = &self.signing_secret_env {
+            if !is_signing_secret_env(name) {
+                return Err(format!(
+                    "signing_secret_env '{}' must start with {}",
+                    name, SIGNING_SECRET_ENV_PREFIX
+                ));
+            }
+        }
+        if let Some(path) = &self.extract {
+            jsonpath_rust::parser::parse_json_path(path)
+                .map_err(|e| format!("invalid JSONPath '{}': {}", path, e))?;
+        }
+        Ok(())
+    }
+
+    fn secret(&self) -> Result<Option<String>> {
+        match &self.signing_secret_env {
+            Some(name) if !is_signing_secret_env(name) => {
+                bail!(
+                    "signing_secret_env '{}' must start with {}",
+                    name,
+                    SIGNING_SECRET_ENV_PREFIX
+                )
+            }
+            Some(name) => std::env::var(name)
+                .map(Some)
+                .with_context(|| format!("signing secret variable {} is not set", name)),
+            None => Ok(self.signing_secret.clone()),
+        }
+    }
+}
+
+fn is_signing_secret_env(name: &str) -> bool {
+    name.len() > SIGNING_SECRET_ENV_PREFIX.len() && name.starts_with(SIGNING_SECRET_ENV_PREFIX)
+}
+
+/// Select the configured part of a webhook response.
+///
+/// A single match is returned as is, several matches as an array and no
+/// match as `null`.
+pub fn extract_response(response: &Value, path: Option<&str>) -> Result<Value> {
+    let Some(path) = path else {
+        return Ok(response.clone());
+    };
+    let mut matches = response
+        .query(path)
+        .map_err(|e| anyhow!("invalid JSONPath '{}': {}", path, e))?;
+    Ok(match matches.len() {
+        0 => Value::Null,
+        1 => matches.remove(0).clone(),
+        _ => Value::Array(matches.into_iter().cloned().collect()),
+    })
+}
+
+/// Data of the running job passed to [`handle_webhook_stage`].
+pub struct WebhookStageRequest<'a> {
+    pub document: &'a Document,
+    /// Local copy of the document.
+    pub local_pdf: &'a Path,
+    pub input: &'a Value,
+}
+
+async fn presign_document(s3: &S3Client, bucket: &str, key: &str) -> Result<String> {
+    if std::env::var("LOCAL_S3_DIR").is_ok() {
+        bail!("presigned document URLs are not available with LOCAL_S3_DIR");
+    }
+    let presigned = s3
+        .get_object()
+        .bucket(bucket)
+        .key(key)
+        .presigned(PresigningConfig::expires_in(PRESIGN_EXPIRY)?)
+        .await?;
+    Ok(presigned.uri().to_string())
+}
+
+/// Call the webhook of a `webhook` stage and store the extracted response.
+#[tracing::instrument(skip(pool, s3, job, stage, request))]
+pub async fn handle_webhook_stage(
+    pool: &PgPool,
+    s3: &S3Client,
+    job: &AnalysisJob,
+    stage: &Stage,
+    bucket: &str,
+    request: WebhookStageRequest<'_>,
+) -> Result<Value> {
+    let cfg = WebhookConfig::from_stage(stage)?;
+    cfg.validate().map_err(|e| anyhow!(e))?;
+    let secret = cfg.secret()?;
+
+    let context = json!({
+        "job_id": job.id,
+        "org_id": job.org_id,
+        "document_id": job.document_id,
+        "stage_id": stage.name(),
+        "input": request.input,
+    });
+    let headers: Vec<(String, String)> = cfg
+        .headers
+        .iter()
+        .map(|(name, value)| (name.clone(), render_template(value, &context)))
+        .collect();
+
+    let mut payload = json!({
+        "job_id": job.id,
+        "document_id": job.document_id,
+        "stage_id": stage.name(),
+        "input": request.input,
+    });
+    let mut document_bytes = None;
+    match cfg.document {
+        DocumentMode::None => {}
+        DocumentMode::Url => {
+            payload["document_url"] =
+                Value::String(presign_document(s3, bucket, &request.document.filename).await?);
+        }
+        DocumentMode::Bytes => {
+            document_bytes = Some(
+                tokio::fs::read(request.local_pdf)
+                    .await
+                    .context("failed to read document for webhook stage")?,
+            );
+        }
+    }
+
+    let webhook = WebhookRequest {
+        url: &cfg.url,
+        headers: &headers,
+        payload: &payload,
+        document: document_bytes
+            .as_deref()
+            .map(|bytes| (request.document.display_name.as_str(), bytes)),
+        signing_secret: secret.as_deref(),
+    };
+    let response = match send_webhook(&webhook, &stage.retry_policy()).await {
+        Ok(r) => r,
+        Err(e) => {
+            API_ERROR_COUNTER.with_label_values(&["webhook"]).inc();
+            return Err(e.into());
+        }
+    };
+    let output = extract_response(&response, cfg.extract.as_deref())?;
+
+    let bytes = serde_json::to_vec_pretty(&output)?;
+    save_stage_output(
+        pool,
+        s3,
+        job.id,
+        stage.name(),
+        "json",
+        bucket,
+        bytes,
+        "json",
+    )
+    .await?;
+    info!(job_id=%job.id, stage=%stage.name(), "finished webhook stage");
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::webhook::{sign_payload, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+    use aws_config::meta::region::RegionProviderChain;
+    use serial_test::serial;
+    use sqlx::postgres::PgPoolOptions;
+    use tempfile::tempdir;
+    use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+    fn config(value: Value) -> WebhookConfig {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn validates_url_and_extract_path() {
+        assert!(config(json!({"url": "https://erp.example.com/lookup"}))
+            .validate()
+            .is_ok());
+        assert!(config(json!({"url": "ftp://example.com"}))
+            .validate()
+            .is_err());
+        assert!(config(json!({"url": "http://x", "extract": "$.[["}))
+            .validate()
+            .is_err());
+        assert!(serde_json::from_value::<WebhookConfig>(
+            json!({"url": "http://x", "method": "PUT"})
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn signing_secret_env_needs_the_webhook_prefix() {
+        let env = |name: &str| config(json!({"url": "http://x", "signing_secret_env": name}));
+        assert!(env("WEBHOOK_SECRET_CLASSIFIER").validate().is_ok());
+        assert!(env("JWT_SECRET").validate().is_err());
+        assert!(env("WEBHOOK_SECRET_").validate().is_err());
+        assert!(env("DATABASE_URL").secret().is_err());
+    }
+
+    #[test]
+    fn extracts_single_and_multiple_matches() {
+        let response = json!({"label": "invoice", "items": [{"sku": "a"}, {"sku": "b"}]});
+        assert_eq!(extract_response(&response, None).unwrap(), response);
+        assert_eq!(
+            extract_response(&response, Some("$.label")).unwrap(),
+            json!("invoice")
+        );
+        assert_eq!(
+            extract_response(&response, Some("$.items[*].sku")).unwrap(),
+            json!(["a", "b"])
+        );
+        assert_eq!(
+            extract_response(&response, Some("$.missing")).unwrap(),
+            Value::Null
+        );
+    }
+
+    async fn dummy_clients() -> (sqlx::Pool<sqlx::Postgres>, S3Client) {
+        let pool = PgPoolOptions::new()
+            .connect_lazy("postgres://user@localhost/db")
+            .unwrap();
+        let rp = RegionProviderChain::default_provider().or_else("us-east-1");
+        let shared = aws_config::from_env().region(rp).load().await;
+        let cfg = aws_sdk_s3::config::Builder::from(&shared)
+            .endpoint_url("http://localhost")
+            .force_path_style(true)
+            .build();
+        (pool, S3Client::from_conf(cfg))
+    }
+
+    #[actix_rt::test]
+    #[serial]
+    async fn webhook_stage_sends_signed_request_with_document() {
+        std::env::set_var("SKIP_DB", "1");
+        // The mock server listens on localhost
+        std::env::set_var("WEBHOOK_ALLOW_PRIVATE_NETWORKS", "true");
+        let dir = tempdir().unwrap();
+        std::env::set_var("LOCAL_S3_DIR", dir.path());
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"result": {"label": "invoice"}, "debug": true})),
+            )
+            .mount(&server)
+            .await;
+        let (pool, s3) = dummy_clients().await;
+        let job = AnalysisJob {
+            id: uuid::Uuid::new_v4(),
+            org_id: uuid::Uuid::new_v4(),
+            document_id: uuid::Uuid::new_v4(),
+            status: "in_progress".into(),
+            ..Default::default()
+        };
+        let document = Document {
+            id: job.document_id,
+            org_id: job.org_id,
+            owner_id: uuid::Uuid::new_v4(),
+            filename: "doc.pdf".into(),
+            pages: 1,
+            is_target: true,
+            upload_date: chrono::Utc::now(),
+            expires_at: None,
+            display_name: "Invoice.pdf".into(),
+        };
+        let local_pdf = dir.path().join("in.pdf");
+        std::fs::write(&local_pdf, b"%PDF-1.4").unwrap();
+        let stage: Stage = serde_json::from_value(json!({
+            "id": "classify",
+            "type": "webhook",
+            "config": {
+                "url": server.uri(),
+                "headers": {"X-Org": "{{org_id}}", "X-Vendor": "{{input.vendor}}"},
+                "document": "bytes",
+                "signing_secret": "s3cret",
+                "extract": "$.result.label"
+            }
+        }))
+        .unwrap();
+        let input = json!({"vendor": "ACME"});
+        let request = WebhookStageRequest {
+            document: &document,
+            local_pdf: &local_pdf,
+            input: &input,
+        };
+
+        let output = handle_webhook_stage(&pool, &s3, &job, &stage, "bucket", request)
+            .await
+            .unwrap();
+        assert_eq!(output, json!("invoice"));
+
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 1);
+        let req = &requests[0];
+        let header = |name: &str| req.headers.get(name).unwrap().to_str().unwrap().to_string();
+        assert_eq!(header("X-Org"), job.org_id.to_string());
+        assert_eq!(header("X-Vendor"), "ACME");
+        assert!(header("content-type").starts_with("multipart/form-data"));
+        let body = String::from_utf8_lossy(&req.body);
+        assert!(body.contains("name=\"document\"; filename=\"Invoice.pdf\""));
+        assert!(body.contains("%PDF-1.4"));
+
+        let payload = serde_json::to_vec(&json!({
+            "job_id": job.id,
+            "document_id": job.document_id,
+            "stage_id": "classify",
+            "input": input,
+        }))
+        .unwrap();
+        let timestamp: u64 = header(TIMESTAMP_HEADER).parse().unwrap();
+        assert_eq!(
+            header(SIGNATURE_HEADER),
+            sign_payload("s3cret", timestamp, &payload)
+        );
+    }
+}
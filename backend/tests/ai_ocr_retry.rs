@@ -1,4 +1,6 @@
+use backend::processing::retry::RetryPolicy;
 use backend::processing::{ai_client, ocr};
+use std::time::Duration;
 use serde_json::json;
 use wiremock::{MockServer, Mock, ResponseTemplate};
 use wiremock::matchers::method;
@@ -51,3 +53,91 @@ async fn ocr_client_retries_on_server_error() {
     assert_eq!(text, "ok");
     assert_eq!(server.received_requests().await.unwrap().len(), 3);
 }
+
+#[actix_rt::test]
+async fn ai_client_honours_retry_policy() {
+    let server = MockServer::start().await;
+    let _mock = Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount_as_scoped(&server)
+        .await;
+
+    let policy = RetryPolicy {
+        max_attempts: 2,
+        backoff_ms: 1,
+        timeout: Duration::from_secs(1),
+    };
+    let res = ai_client::run_ai_with_policy(&json!({}), &server.uri(), "", None, &policy).await;
+    assert!(res.is_err());
+    assert_eq!(server.received_requests().await.unwrap().len(), 2);
+}
+
+#[actix_rt::test]
+async fn ocr_client_times_out_per_attempt() {
+    let server = MockServer::start().await;
+    let _mock = Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(500)))
+        .mount_as_scoped(&server)
+        .await;
+
+    let policy = RetryPolicy {
+        max_attempts: 1,
+        backoff_ms: 0,
+        timeout: Duration::from_millis(100),
+    };
+    let res =
+        ocr::run_external_ocr_with_policy(&server.uri(), None, b"pdf".to_vec(), "test.pdf", &policy)
+            .await;
+    assert!(matches!(res, Err(ocr::OcrError::Request(e)) if e.is_timeout()));
+}
+
+#[actix_rt::test]
+async fn webhook_client_retries_and_signs_json_body() {
+    use backend::processing::webhook::{self, WebhookRequest};
+    // The mock server listens on localhost
+    std::env::set_var("WEBHOOK_ALLOW_PRIVATE_NETWORKS", "true");
+    let server = MockServer::start().await;
+    let counter = Arc::new(AtomicUsize::new(0));
+    let c = counter.clone();
+    let _mock = Mock::given(method("POST"))
+        .respond_with(move |_: &wiremock::Request| {
+            let n = c.fetch_add(1, Ordering::SeqCst);
+            if n < 1 {
+                ResponseTemplate::new(503)
+            } else {
+                ResponseTemplate::new(200).set_body_json(json!({"erp_id": 42}))
+            }
+        })
+        .mount_as_scoped(&server)
+        .await;
+
+    let payload = json!({"input": {"vendor": "ACME"}});
+    let headers = vec![("Authorization".to_string(), "Token abc".to_string())];
+    let request = WebhookRequest {
+        url: &server.uri(),
+        headers: &headers,
+        payload: &payload,
+        document: None,
+        signing_secret: Some("secret"),
+    };
+    let policy = RetryPolicy {
+        max_attempts: 2,
+        backoff_ms: 1,
+        timeout: Duration::from_secs(5),
+    };
+    let res = webhook::send_webhook(&request, &policy).await.unwrap();
+    assert_eq!(res["erp_id"], 42);
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 2);
+    let req = &requests[1];
+    let body: serde_json::Value = serde_json::from_slice(&req.body).unwrap();
+    assert_eq!(body, payload);
+    assert_eq!(req.headers.get("Authorization").unwrap(), "Token abc");
+    let header = |name: &str| req.headers.get(name).unwrap().to_str().unwrap().to_string();
+    let ts: u64 = header(webhook::TIMESTAMP_HEADER).parse().unwrap();
+    assert_eq!(
+        header(webhook::SIGNATURE_HEADER),
+        webhook::sign_payload("secret", ts, &req.body)
+    );
+}
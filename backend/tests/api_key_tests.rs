@@ -0,0 +1,170 @@
+use actix_web::test;
+use serde_json::json;
+
+mod test_utils;
+use backend::models::{Document, NewDocument, NewPipeline, Pipeline};
+use test_utils::{create_org, create_user, generate_jwt_token, setup_test_app};
+
+#[actix_rt::test]
+async fn api_keys_are_scoped_rotated_and_revoked() {
+    let Ok((app, pool)) = setup_test_app().await else {
+        return;
+    };
+    let org_id = create_org(&pool, "Key Org").await;
+    let admin_id = create_user(&pool, org_id, "keys@example.com", "org_admin").await;
+    let member_id = create_user(&pool, org_id, "keys-member@example.com", "user").await;
+    let token = generate_jwt_token(admin_id, org_id, "org_admin");
+    let keys_uri = format!("/api/settings/{}/api-keys", org_id);
+
+    let req = test::TestRequest::post()
+        .uri(&keys_uri)
+        .insert_header((
+            "Authorization",
+            format!("Bearer {}", generate_jwt_token(member_id, org_id, "user")),
+        ))
+        .set_json(json!({"name": "ERP", "scopes": ["upload"]}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+
+    let req = test::TestRequest::post()
+        .uri(&keys_uri)
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(json!({"name": "ERP", "scopes": ["admin"]}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+
+    let req = test::TestRequest::post()
+        .uri(&keys_uri)
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(json!({"name": "ERP", "scopes": ["upload", "jobs:read"]}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let created: serde_json::Value = test::read_body_json(resp).await;
+    let key = created["key"].as_str().unwrap().to_string();
+    let key_id = created["id"].as_str().unwrap().to_string();
+    assert!(key.starts_with(created["prefix"].as_str().unwrap()));
+    assert!(created.get("key_hash").is_none());
+
+    let pipeline = Pipeline::create(
+        &pool,
+        NewPipeline {
+            org_id,
+            name: "Pipe".into(),
+            stages: json!([{"type": "ocr"}]),
+        },
+    )
+    .await
+    .unwrap();
+    let document = Document::create(
+        &pool,
+        NewDocument {
+            org_id,
+            owner_id: admin_id,
+            filename: "f.pdf".into(),
+            pages: 1,
+            is_target: true,
+            expires_at: None,
+            display_name: "File.pdf".into(),
+        },
+    )
+    .await
+    .unwrap();
+
+    let analyze = |key: &str| {
+        test::TestRequest::post()
+            .uri(&format!("/api/documents/{}/analyze", document.id))
+            .insert_header(("X-API-Key", key.to_string()))
+            .set_json(json!({"pipeline_id": pipeline.id}))
+            .to_request()
+    };
+    let resp = test::call_service(&app, analyze(&key)).await;
+    assert!(resp.status().is_success());
+    let job: serde_json::Value = test::read_body_json(resp).await;
+    let job_id = job["id"].as_str().unwrap().to_string();
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/jobs/{}/details", job_id))
+        .insert_header(("X-API-Key", key.clone()))
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/jobs/{}/cancel", job_id))
+        .insert_header(("X-API-Key", key.clone()))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/pipelines/{}", org_id))
+        .insert_header(("X-API-Key", key.clone()))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+
+    // Keys do not open endpoints that only accept logged-in users
+    let req = test::TestRequest::get()
+        .uri(&keys_uri)
+        .insert_header(("X-API-Key", key.clone()))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+
+    let (action, audit_key): (String, Option<uuid::Uuid>) = sqlx::query_as(
+        "SELECT action, api_key_id FROM audit_logs WHERE org_id=$1 AND action LIKE 'job_created:%'",
+    )
+    .bind(org_id)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(action, format!("job_created:{}", job_id));
+    assert_eq!(audit_key.unwrap().to_string(), key_id);
+
+    let req = test::TestRequest::post()
+        .uri(&format!("{}/{}/rotate", keys_uri, key_id))
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let rotated: serde_json::Value = test::read_body_json(resp).await;
+    let new_key = rotated["key"].as_str().unwrap().to_string();
+    assert_eq!(rotated["id"], created["id"]);
+    assert_eq!(test::call_service(&app, analyze(&key)).await.status(), 401);
+    assert!(test::call_service(&app, analyze(&new_key))
+        .await
+        .status()
+        .is_success());
+
+    let req = test::TestRequest::get()
+        .uri(&keys_uri)
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let keys: serde_json::Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert!(keys[0]["last_used_at"].is_string());
+    assert!(keys[0].get("key").is_none());
+
+    // Keys stop working when their creator is deactivated
+    let set_active = |active: bool| {
+        sqlx::query("UPDATE users SET is_active=$2 WHERE id=$1")
+            .bind(admin_id)
+            .bind(active)
+            .execute(&pool)
+    };
+    set_active(false).await.unwrap();
+    assert_eq!(
+        test::call_service(&app, analyze(&new_key)).await.status(),
+        401
+    );
+    set_active(true).await.unwrap();
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("{}/{}", keys_uri, key_id))
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let revoked: serde_json::Value = test::read_body_json(resp).await;
+    assert!(revoked["revoked_at"].is_string());
+    assert_eq!(
+        test::call_service(&app, analyze(&new_key)).await.status(),
+        401
+    );
+}
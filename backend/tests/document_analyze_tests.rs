@@ -0,0 +1,153 @@
+use actix_web::test;
+use serde_json::json;
+use uuid::Uuid;
+
+mod test_utils;
+use test_utils::{
+    create_document, create_org, create_pipeline, create_user, generate_jwt_token, setup_test_app,
+};
+
+#[actix_rt::test]
+async fn analyze_existing_document() {
+    let Ok((app, pool)) = setup_test_app().await else {
+        return;
+    };
+    let org_id = create_org(&pool, "Analyze Org").await;
+    let user_id = create_user(&pool, org_id, "analyze@example.com", "org_admin").await;
+    let token = generate_jwt_token(user_id, org_id, "org_admin");
+    let doc = create_document(&pool, org_id, user_id, "invoice").await;
+    let pipeline = create_pipeline(&pool, org_id, "normal").await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/documents/{}/analyze", doc.id))
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(json!({"pipeline_id": pipeline.id}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let job: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(job["document_id"], json!(doc.id));
+    assert_eq!(job["pipeline_id"], json!(pipeline.id));
+    assert_eq!(job["status"], "pending");
+    assert_eq!(job["priority"], "normal");
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/documents/{}/analyze", doc.id))
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(json!({"pipeline_id": pipeline.id, "priority": "high"}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let job: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(job["priority"], "high");
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/documents/{}/analyze", doc.id))
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(json!({"pipeline_id": pipeline.id, "priority": "urgent"}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+
+    let other_org = create_org(&pool, "Analyze Other Org").await;
+    let other_pipeline = create_pipeline(&pool, other_org, "normal").await;
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/documents/{}/analyze", doc.id))
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(json!({"pipeline_id": other_pipeline.id}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+}
+
+#[actix_rt::test]
+async fn batch_analyze_respects_quota() {
+    let Ok((app, pool)) = setup_test_app().await else {
+        return;
+    };
+    let org_id = create_org(&pool, "Batch Org").await;
+    let user_id = create_user(&pool, org_id, "batch@example.com", "org_admin").await;
+    let token = generate_jwt_token(user_id, org_id, "org_admin");
+    let pipeline = create_pipeline(&pool, org_id, "normal").await;
+    let mut ids = Vec::new();
+    for name in ["a", "b", "c"] {
+        ids.push(create_document(&pool, org_id, user_id, name).await.id);
+    }
+
+    sqlx::query("UPDATE org_settings SET monthly_analysis_quota=2 WHERE org_id=$1")
+        .bind(org_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let req = test::TestRequest::post()
+        .uri("/api/documents/analyze")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(json!({"pipeline_id": pipeline.id, "document_ids": ids}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 429);
+
+    sqlx::query("UPDATE org_settings SET monthly_analysis_quota=10 WHERE org_id=$1")
+        .bind(org_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let req = test::TestRequest::post()
+        .uri("/api/documents/analyze")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(json!({"pipeline_id": pipeline.id, "document_ids": ids}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let jobs: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(jobs.as_array().unwrap().len(), 3);
+
+    let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM analysis_jobs WHERE org_id=$1")
+        .bind(org_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 3);
+}
+
+#[actix_rt::test]
+async fn concurrent_batches_cannot_exceed_quota() {
+    let Ok((app, pool)) = setup_test_app().await else {
+        return;
+    };
+    let org_id = create_org(&pool, "Concurrent Batch Org").await;
+    let user_id = create_user(&pool, org_id, "concurrent-batch@example.com", "org_admin").await;
+    let token = generate_jwt_token(user_id, org_id, "org_admin");
+    let pipeline = create_pipeline(&pool, org_id, "normal").await;
+    let mut ids = Vec::new();
+    for name in ["a", "b", "c", "d"] {
+        ids.push(create_document(&pool, org_id, user_id, name).await.id);
+    }
+    sqlx::query("UPDATE org_settings SET monthly_analysis_quota=3 WHERE org_id=$1")
+        .bind(org_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let batch = |docs: &[Uuid]| {
+        test::TestRequest::post()
+            .uri("/api/documents/analyze")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(json!({"pipeline_id": pipeline.id, "document_ids": docs}))
+            .to_request()
+    };
+    let (first, second) = tokio::join!(
+        test::call_service(&app, batch(&ids[..2])),
+        test::call_service(&app, batch(&ids[2..]))
+    );
+    let mut statuses = [first.status().as_u16(), second.status().as_u16()];
+    statuses.sort();
+    assert_eq!(statuses, [200, 429]);
+
+    let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM analysis_jobs WHERE org_id=$1")
+        .bind(org_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 2);
+}
@@ -153,6 +153,46 @@ async fn test_pdf_upload_bad_content_type() {
     assert_eq!(put_mock.received_requests().await.len(), 0);
 }
 
+#[actix_rt::test]
+async fn test_tiff_upload_counts_frames_and_checks_magic_bytes() {
+    let s3_server = MockServer::start().await;
+    let put_mock = Mock::given(method("PUT"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&s3_server)
+        .await;
+
+    let (app, pool) = setup_test_app(&s3_server).await;
+    let org_id = create_org(&pool, "Scan Org").await;
+    let user_id = create_user(&pool, org_id, "scan@example.com", "org_admin").await;
+    let token = generate_jwt_token(user_id, org_id, "org_admin");
+    let upload = |filename: &str, content_type: &str, content: &str| {
+        let boundary = "BOUNDARY";
+        test::TestRequest::post()
+            .uri(&format!("/api/upload?org_id={}", org_id))
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .insert_header((
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", boundary),
+            ))
+            .set_payload(multipart_body(boundary, filename, content_type, content))
+            .to_request()
+    };
+
+    // Two empty image directories, the first pointing to the second
+    let tiff = "II*\0\x08\0\0\0\0\0\x0e\0\0\0\0\0\0\0\0\0";
+    let resp = test::call_service(&app, upload("scan.tiff", "image/tiff", tiff)).await;
+    assert!(resp.status().is_success());
+    let doc: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(doc["pages"], 2);
+    assert_eq!(doc["display_name"], "scan.tiff");
+
+    let resp = test::call_service(&app, upload("photo.png", "image/png", "not a png")).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    let resp = test::call_service(&app, upload("archive.zip", "application/zip", "PK")).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    assert_eq!(put_mock.received_requests().await.len(), 1);
+}
+
 #[actix_rt::test]
 async fn test_cleanup_on_failed_upload() {
     let s3_server = MockServer::start().await;
@@ -339,6 +379,11 @@ async fn reject_when_analysis_quota_exceeded() {
         resp.status(),
         actix_web::http::StatusCode::TOO_MANY_REQUESTS
     );
+    let error: serde_json::Value = test::read_body_json(resp).await;
+    assert!(error["error"]
+        .as_str()
+        .unwrap()
+        .contains("Document uploaded but not queued for analysis"));
 
     let docs: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM documents WHERE org_id=$1")
         .bind(org_id)
@@ -99,6 +99,8 @@ async fn ocr_error_marks_failed() {
     assert!(!pdf_tmp.exists());
     assert!(!txt_tmp.exists());
     assert_eq!(job.status, "failed");
+    assert_eq!(job.failure_reason.as_deref(), Some("ocr_failed"));
+    assert!(job.error.is_some());
     let log_count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM audit_logs WHERE org_id=$1")
         .bind(org_id)
         .fetch_one(&pool)
@@ -182,6 +184,7 @@ async fn ai_invalid_json_marks_failed() {
     assert!(!pdf_tmp.exists());
     assert!(!txt_tmp.exists());
     assert_eq!(job.status, "failed");
+    assert_eq!(job.failure_reason.as_deref(), Some("ai_failed"));
     let log_count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM audit_logs WHERE org_id=$1")
         .bind(org_id)
         .fetch_one(&pool)
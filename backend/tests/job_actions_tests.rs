@@ -0,0 +1,107 @@
+use actix_web::test;
+use serde_json::json;
+
+mod test_utils;
+use test_utils::{
+    create_job, create_org, create_user, fail_job, generate_jwt_token, setup_test_app,
+};
+
+#[actix_rt::test]
+async fn cancel_then_retry_with_resume() {
+    let Ok((app, pool)) = setup_test_app().await else {
+        return;
+    };
+    let org_id = create_org(&pool, "Cancel Org").await;
+    let user_id = create_user(&pool, org_id, "cancel@example.com", "org_admin").await;
+    let token = generate_jwt_token(user_id, org_id, "org_admin");
+    let job = create_job(&pool, org_id, user_id, "normal").await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/jobs/{}/retry", job.id))
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/jobs/{}/cancel", job.id))
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["status"], "cancelled");
+    assert_eq!(body["cancel_requested"], true);
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/jobs/{}/cancel", job.id))
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/jobs/{}/retry?resume=true", job.id))
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["status"], "pending");
+    assert_eq!(body["resume"], true);
+    assert_eq!(body["cancel_requested"], false);
+
+    let (count,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM audit_logs WHERE org_id=$1 AND action IN ($2, $3)")
+            .bind(org_id)
+            .bind(format!("job_cancel:{}", job.id))
+            .bind(format!("job_retry:{}", job.id))
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(count, 2);
+}
+
+#[actix_rt::test]
+async fn rerun_creates_new_job() {
+    let Ok((app, pool)) = setup_test_app().await else {
+        return;
+    };
+    let org_id = create_org(&pool, "Rerun Org").await;
+    let user_id = create_user(&pool, org_id, "rerun@example.com", "org_admin").await;
+    let token = generate_jwt_token(user_id, org_id, "org_admin");
+    let job = create_job(&pool, org_id, user_id, "normal").await;
+    fail_job(&pool, job.id, "ocr_failed", "tesseract failed").await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/jobs/{}/rerun", job.id))
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_ne!(body["id"], json!(job.id));
+    assert_eq!(body["document_id"], json!(job.document_id));
+    assert_eq!(body["status"], "pending");
+
+    sqlx::query("UPDATE org_settings SET monthly_analysis_quota=2 WHERE org_id=$1")
+        .bind(org_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/jobs/{}/rerun", job.id))
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 429);
+
+    let other_org = create_org(&pool, "Other Rerun Org").await;
+    let other_user = create_user(&pool, other_org, "rerun-other@example.com", "org_admin").await;
+    let other_token = generate_jwt_token(other_user, other_org, "org_admin");
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/jobs/{}/rerun", job.id))
+        .insert_header(("Authorization", format!("Bearer {}", other_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401);
+}
@@ -2,8 +2,10 @@ use actix_web::test;
 use serde_json::json;
 
 mod test_utils;
-use backend::models::{AnalysisJob, Document, NewAnalysisJob, NewDocument, NewPipeline, Pipeline};
-use test_utils::{create_org, create_user, generate_jwt_token, setup_test_app};
+use backend::models::{
+    AnalysisJob, Document, JobStageRun, NewAnalysisJob, NewDocument, NewPipeline, Pipeline,
+};
+use test_utils::{create_org, create_user, fail_job, generate_jwt_token, setup_test_app};
 
 #[actix_rt::test]
 async fn list_jobs_includes_names() {
@@ -12,7 +14,7 @@ async fn list_jobs_includes_names() {
     };
     let org_id = create_org(&pool, "Job Org").await;
     let user_id = create_user(&pool, org_id, "user@example.com", "org_admin").await;
-    let _token = generate_jwt_token(user_id, org_id, "org_admin");
+    let token = generate_jwt_token(user_id, org_id, "org_admin");
 
     let pipeline = Pipeline::create(
         &pool,
@@ -53,6 +55,21 @@ async fn list_jobs_includes_names() {
     let req = test::TestRequest::get()
         .uri(&format!("/api/jobs/{}", org_id))
         .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+
+    let other_org = create_org(&pool, "Other Job Org").await;
+    let other_user = create_user(&pool, other_org, "joblister@example.com", "org_admin").await;
+    let other_token = generate_jwt_token(other_user, other_org, "org_admin");
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/jobs/{}", org_id))
+        .insert_header(("Authorization", format!("Bearer {}", other_token)))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/jobs/{}", org_id))
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
     let resp = test::call_service(&app, req).await;
     assert!(resp.status().is_success());
     let list: serde_json::Value = test::read_body_json(resp).await;
@@ -61,3 +78,148 @@ async fn list_jobs_includes_names() {
     assert_eq!(first["document_name"], "File.pdf");
     assert_eq!(first["pipeline_name"], "Pipe");
 }
+
+#[actix_rt::test]
+async fn job_details_include_failure_reason() {
+    let Ok((app, pool)) = setup_test_app().await else {
+        return;
+    };
+    let org_id = create_org(&pool, "Failed Job Org").await;
+    let user_id = create_user(&pool, org_id, "failed@example.com", "org_admin").await;
+    let token = generate_jwt_token(user_id, org_id, "org_admin");
+
+    let pipeline = Pipeline::create(
+        &pool,
+        NewPipeline {
+            org_id,
+            name: "Pipe".into(),
+            stages: json!([{"type": "ocr"}]),
+        },
+    )
+    .await
+    .unwrap();
+    let document = Document::create(
+        &pool,
+        NewDocument {
+            org_id,
+            owner_id: user_id,
+            filename: "f.pdf".into(),
+            pages: 1,
+            is_target: true,
+            expires_at: None,
+            display_name: "File.pdf".into(),
+        },
+    )
+    .await
+    .unwrap();
+    let job = AnalysisJob::create(
+        &pool,
+        NewAnalysisJob {
+            org_id,
+            document_id: document.id,
+            pipeline_id: pipeline.id,
+            status: "in_progress".into(),
+        },
+    )
+    .await
+    .unwrap();
+    fail_job(&pool, job.id, "ocr_failed", "tesseract failed").await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/jobs/{}/details", job.id))
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let details: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(details["status"], "failed");
+    assert_eq!(details["failure_reason"], "ocr_failed");
+    assert_eq!(details["error"], "tesseract failed");
+}
+
+#[actix_rt::test]
+async fn job_details_include_stage_runs() {
+    let Ok((app, pool)) = setup_test_app().await else {
+        return;
+    };
+    let org_id = create_org(&pool, "Stage Run Org").await;
+    let user_id = create_user(&pool, org_id, "runs@example.com", "org_admin").await;
+    let token = generate_jwt_token(user_id, org_id, "org_admin");
+
+    let pipeline = Pipeline::create(
+        &pool,
+        NewPipeline {
+            org_id,
+            name: "Pipe".into(),
+            stages: json!([{"id": "scan", "type": "ocr"}, {"type": "ai"}]),
+        },
+    )
+    .await
+    .unwrap();
+    let document = Document::create(
+        &pool,
+        NewDocument {
+            org_id,
+            owner_id: user_id,
+            filename: "f.pdf".into(),
+            pages: 1,
+            is_target: true,
+            expires_at: None,
+            display_name: "File.pdf".into(),
+        },
+    )
+    .await
+    .unwrap();
+    let job = AnalysisJob::create(
+        &pool,
+        NewAnalysisJob {
+            org_id,
+            document_id: document.id,
+            pipeline_id: pipeline.id,
+            status: "pending".into(),
+        },
+    )
+    .await
+    .unwrap();
+    // Claim jobs like a worker until this one is picked up
+    while let Some(claimed) = AnalysisJob::claim_next(&pool, "test-worker", 60)
+        .await
+        .unwrap()
+    {
+        if claimed.id == job.id {
+            break;
+        }
+    }
+    let run = JobStageRun::start(&pool, job.id, "scan", "ocr").await.unwrap();
+    JobStageRun::finish(&pool, run.id, "failed", 2, Some("tesseract failed"))
+        .await
+        .unwrap();
+    JobStageRun::record(
+        &pool,
+        job.id,
+        "ai",
+        "ai",
+        "skipped",
+        Some("all inputs were skipped"),
+    )
+    .await
+    .unwrap();
+    fail_job(&pool, job.id, "ocr_failed", "tesseract failed").await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/jobs/{}/details", job.id))
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let details: serde_json::Value = test::read_body_json(resp).await;
+    assert!(details["started_at"].is_string());
+    assert!(details["finished_at"].is_string());
+    let runs = details["stage_runs"].as_array().unwrap();
+    assert_eq!(runs.len(), 2);
+    assert_eq!(runs[0]["stage_id"], "scan");
+    assert_eq!(runs[0]["status"], "failed");
+    assert_eq!(runs[0]["attempt"], 2);
+    assert_eq!(runs[0]["error"], "tesseract failed");
+    assert_eq!(runs[1]["status"], "skipped");
+}
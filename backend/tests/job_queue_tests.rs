@@ -0,0 +1,85 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+mod test_utils;
+use backend::models::AnalysisJob;
+use test_utils::{create_job, create_org, create_user, setup_test_app};
+
+async fn expire_lease(pool: &PgPool, id: Uuid, attempts: i32) {
+    sqlx::query(
+        "UPDATE analysis_jobs SET status='in_progress', attempts=$2, lease_owner='dead', \
+         lease_expires_at=NOW() - INTERVAL '1 minute' WHERE id=$1",
+    )
+    .bind(id)
+    .bind(attempts)
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+#[actix_rt::test]
+async fn concurrent_claims_get_distinct_jobs() {
+    let Ok((_app, pool)) = setup_test_app().await else {
+        return;
+    };
+    let org_id = create_org(&pool, "Claim Org").await;
+    let user_id = create_user(&pool, org_id, "claim@example.com", "org_admin").await;
+    create_job(&pool, org_id, user_id, "normal").await;
+    create_job(&pool, org_id, user_id, "normal").await;
+
+    let (a, b) = tokio::join!(
+        AnalysisJob::claim_next(&pool, "worker-a", 60),
+        AnalysisJob::claim_next(&pool, "worker-b", 60)
+    );
+    let a = a.unwrap().expect("job claimed");
+    let b = b.unwrap().expect("job claimed");
+    assert_ne!(a.id, b.id);
+    assert_eq!(a.status, "in_progress");
+    assert_eq!(a.attempts, 1);
+    assert_eq!(a.lease_owner.as_deref(), Some("worker-a"));
+
+    assert!(AnalysisJob::heartbeat(&pool, a.id, "worker-a", 60)
+        .await
+        .unwrap());
+    assert!(!AnalysisJob::heartbeat(&pool, a.id, "worker-b", 60)
+        .await
+        .unwrap());
+    assert!(
+        !AnalysisJob::mark_failed(&pool, a.id, "worker-b", "ocr_failed", "boom")
+            .await
+            .unwrap()
+    );
+    assert!(AnalysisJob::mark_completed(&pool, a.id, "worker-a")
+        .await
+        .unwrap());
+}
+
+#[actix_rt::test]
+async fn expired_leases_are_requeued_or_failed() {
+    let Ok((_app, pool)) = setup_test_app().await else {
+        return;
+    };
+    let org_id = create_org(&pool, "Lease Org").await;
+    let user_id = create_user(&pool, org_id, "lease@example.com", "org_admin").await;
+    let retried = create_job(&pool, org_id, user_id, "normal").await;
+    let lost = create_job(&pool, org_id, user_id, "normal").await;
+    expire_lease(&pool, retried.id, 1).await;
+    expire_lease(&pool, lost.id, 3).await;
+
+    let reaped = AnalysisJob::reap_expired_leases(&pool, 3).await.unwrap();
+    let retried = reaped.iter().find(|j| j.id == retried.id).unwrap();
+    assert_eq!(retried.status, "pending");
+    assert!(retried.lease_owner.is_none());
+    let lost = reaped.iter().find(|j| j.id == lost.id).unwrap();
+    assert_eq!(lost.status, "failed");
+    assert_eq!(lost.failure_reason.as_deref(), Some("worker_lost"));
+    assert!(lost.finished_at.is_some());
+
+    // The worker that lost the lease can no longer finish the job
+    let retried_id = retried.id;
+    assert!(!AnalysisJob::mark_completed(&pool, retried_id, "dead")
+        .await
+        .unwrap());
+    let job = AnalysisJob::find(&pool, retried_id).await.unwrap();
+    assert_eq!(job.status, "pending");
+}
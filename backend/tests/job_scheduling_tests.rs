@@ -0,0 +1,100 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+mod test_utils;
+use backend::models::AnalysisJob;
+use test_utils::{create_job, create_org, create_user, setup_test_app};
+
+async fn create_jobs(pool: &PgPool, org_id: Uuid, priority: &str, count: usize) -> Vec<Uuid> {
+    let user_id = create_user(pool, org_id, &format!("{}@example.com", org_id), "user").await;
+    let mut ids = Vec::new();
+    for _ in 0..count {
+        let job = create_job(pool, org_id, user_id, priority).await;
+        assert_eq!(job.priority, priority);
+        ids.push(job.id);
+    }
+    ids
+}
+
+/// Claim until the queue is empty and return the claimed jobs among `ids` in order.
+async fn claim_all(pool: &PgPool, ids: &[Uuid]) -> Vec<Uuid> {
+    let mut claimed = Vec::new();
+    while let Some(job) = AnalysisJob::claim_next(pool, "worker", 60).await.unwrap() {
+        if ids.contains(&job.id) {
+            claimed.push(job.id);
+        }
+    }
+    claimed
+}
+
+#[actix_rt::test]
+async fn claims_follow_priority_fairness_and_org_limits() {
+    let Ok((_app, pool)) = setup_test_app().await else {
+        return;
+    };
+    let busy_org = create_org(&pool, "Busy Org").await;
+    let quiet_org = create_org(&pool, "Quiet Org").await;
+    let urgent_org = create_org(&pool, "Urgent Org").await;
+    let capped_org = create_org(&pool, "Capped Org").await;
+    sqlx::query("UPDATE org_settings SET max_concurrent_jobs=1 WHERE org_id=$1")
+        .bind(capped_org)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let busy = create_jobs(&pool, busy_org, "normal", 3).await;
+    let quiet = create_jobs(&pool, quiet_org, "normal", 1).await;
+    let urgent = create_jobs(&pool, urgent_org, "high", 1).await;
+    let capped = create_jobs(&pool, capped_org, "bulk", 2).await;
+    let all: Vec<Uuid> = [&busy, &quiet, &urgent, &capped]
+        .into_iter()
+        .flatten()
+        .copied()
+        .collect();
+
+    let claimed = claim_all(&pool, &all).await;
+    assert_eq!(
+        claimed,
+        vec![urgent[0], busy[0], quiet[0], busy[1], busy[2], capped[0]]
+    );
+
+    assert!(AnalysisJob::mark_completed(&pool, capped[0], "worker")
+        .await
+        .unwrap());
+    assert_eq!(claim_all(&pool, &all).await, vec![capped[1]]);
+}
+
+#[actix_rt::test]
+async fn concurrent_claims_respect_org_limit() {
+    let Ok((_app, pool)) = setup_test_app().await else {
+        return;
+    };
+    let org_id = create_org(&pool, "Limited Org").await;
+    sqlx::query("UPDATE org_settings SET max_concurrent_jobs=2 WHERE org_id=$1")
+        .bind(org_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let ids = create_jobs(&pool, org_id, "normal", 6).await;
+
+    let claims = futures_util::future::join_all(
+        (0..4).map(|i| AnalysisJob::claim_next(&pool, if i % 2 == 0 { "a" } else { "b" }, 60)),
+    )
+    .await;
+    let claimed = claims
+        .into_iter()
+        .filter_map(|c| c.unwrap())
+        .filter(|job| ids.contains(&job.id))
+        .count();
+    assert!(claimed <= 2);
+    claim_all(&pool, &ids).await;
+
+    let (running,): (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM analysis_jobs WHERE org_id=$1 AND status='in_progress'",
+    )
+    .bind(org_id)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(running, 2);
+}
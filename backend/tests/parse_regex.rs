@@ -64,6 +64,22 @@ async fn regex_invalid_pattern() {
             ]
         }
     });
+    let err = run_parse_stage(text, Some(&config)).await.unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("parameters.patterns[0].regex: regex parse error"));
+}
+
+#[actix_rt::test]
+async fn regex_matches_cite_ocr_pages() {
+    let text = "Invoice 17\nSubtotal: 10.00\n\x0c\nTotal: 12.00";
+    let config = json!({
+        "strategy": "regexExtraction",
+        "parameters": {
+            "patterns": [{"name": "amount", "regex": r"(?m)^Total:\s+([\d.]+)"}],
+            "includePages": true
+        }
+    });
     let res = run_parse_stage(text, Some(&config)).await.unwrap();
-    assert!(res["bad"][0].as_str().unwrap().contains("Regex Compile Error"));
+    assert_eq!(res["amount"], json!([{"value": "12.00", "page": 2}]));
 }
@@ -49,7 +49,7 @@ async fn test_numeric_summary() {
 }
 
 #[actix_rt::test]
-async fn test_invalid_delimiter_regex_does_not_panic() {
+async fn test_invalid_delimiter_regex_is_rejected() {
     let text = "HEADER\nItem Qty\nApple 1";
     let config = json!({
         "strategy": "SimpleTableExtraction",
@@ -58,6 +58,164 @@ async fn test_invalid_delimiter_regex_does_not_panic() {
             "delimiterRegex": "(*invalid"
         }
     });
-    let res = run_parse_stage(text, Some(&config)).await;
-    assert!(res.is_ok());
+    let err = run_parse_stage(text, Some(&config)).await.unwrap_err();
+    assert!(err.to_string().contains("parameters.delimiterRegex"));
+}
+
+#[actix_rt::test]
+async fn test_malformed_config_fails_instead_of_dumping_lines() {
+    let config = json!({
+        "strategy": "SimpleTableExtraction",
+        "parameters": {"headerKeyword": ["item"]}
+    });
+    let err = run_parse_stage("Item Qty", Some(&config))
+        .await
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("parameters: missing field `headerKeywords`"));
+    let unknown = json!({"strategy": "tableExtraction", "parameters": {}});
+    assert!(run_parse_stage("Item Qty", Some(&unknown)).await.is_err());
+
+    let res = run_parse_stage("Item Qty", None).await.unwrap();
+    assert_eq!(res["strategy_used"], "Default (Lines)");
+}
+
+#[actix_rt::test]
+async fn test_tables_with_locale_amounts() {
+    let text = "Invoice\n\
+        Description          Qty        Amount\n\
+        Consulting             2  1.234,56 EUR\n\
+        Travel                        80,00 EUR\n\
+        Total                       1.314,56 EUR\n\
+        \n\
+        Description          Qty        Amount\n\
+        Discount               1      -10,00 EUR";
+    let config = json!({
+        "strategy": "SimpleTableExtraction",
+        "parameters": {
+            "headerKeywords": ["description", "amount"],
+            "stopKeywords": ["total"],
+            "locale": "de-DE",
+            "numericSummary": true
+        }
+    });
+    let res = run_parse_stage(text, Some(&config)).await.unwrap();
+    assert_eq!(res["status"], "ok");
+    assert_eq!(res["tables"].as_array().unwrap().len(), 2);
+    assert_eq!(res["rows"][1], json!(["Travel", "", "80,00 EUR"]));
+    assert_eq!(
+        res["cells"][0][2],
+        json!({"text": "1.234,56 EUR", "type": "currency", "value": 1234.56, "currency": "EUR"})
+    );
+    assert_eq!(res["numeric_summary"]["amount"]["sum"], 1314.56);
+    assert_eq!(res["tables"][1]["cells"][0][2]["value"], -10.0);
+}
+
+#[actix_rt::test]
+async fn test_schema_extraction_types_and_validates_fields() {
+    let text = "Rechnung\n\
+        Rechnungsnummer: RE-2026-0042\n\
+        Rechnungsdatum:  12.03.2026\n\
+        Zahlungsart\n\
+        überweisung\n\
+        IBAN: DE89 3704 0044 0532 0130 00\n\
+        Gesamtbetrag        1.234,56 EUR";
+    let config = json!({
+        "strategy": "schemaExtraction",
+        "parameters": {
+            "locale": "de-DE",
+            "fields": [
+                {"name": "invoice_number", "regex": r"(RE-\d{4}-\d+)", "required": true},
+                {"name": "invoice_date", "type": "date", "labels": ["Rechnungsdatum", "Datum"]},
+                {"name": "total", "type": "decimal", "labels": ["Gesamtbetrag"], "required": true},
+                {"name": "iban", "type": "iban", "labels": ["IBAN"]},
+                {"name": "payment", "type": "enum", "labels": ["Zahlungsart"],
+                 "values": ["Überweisung", "Lastschrift"]},
+                {"name": "due_date", "type": "date", "labels": ["Fällig"], "required": true},
+                {"name": "customer", "type": "decimal", "regex": r"Rechnungsnummer: (\S+)"}
+            ]
+        }
+    });
+    let res = run_parse_stage(text, Some(&config)).await.unwrap();
+    let fields = &res["fields"];
+    assert_eq!(fields["invoice_number"], "RE-2026-0042");
+    assert_eq!(fields["invoice_date"], "2026-03-12");
+    assert_eq!(fields["total"], 1234.56);
+    assert_eq!(fields["iban"], "DE89370400440532013000");
+    assert_eq!(fields["payment"], "Überweisung");
+    assert!(fields["due_date"].is_null());
+    assert!(fields["customer"].is_null());
+    assert_eq!(
+        res["validation_errors"],
+        json!([
+            {"field": "due_date", "message": "Required field not found"},
+            {"field": "customer", "message": "Not a decimal number", "value": "RE-2026-0042"}
+        ])
+    );
+}
+
+#[actix_rt::test]
+async fn test_anchor_extraction_finds_values_next_to_labels() {
+    let text = "Rechnungsnummer: RE-2026-0042\n\
+        Kunde                 Lieferdatum\n\
+        ACME GmbH             10.03.2026\n\
+        Bemerkung:                          siehe Anlage";
+    let config = json!({
+        "strategy": "anchorExtraction",
+        "parameters": {
+            "fields": [
+                {"name": "invoice_number", "labels": ["Invoice No.", "Rechnungsnummer"]},
+                {"name": "delivery_date", "labels": ["Lieferdatum"], "direction": "below"},
+                {"name": "note", "labels": ["Bemerkung"], "direction": "right", "maxGap": 5},
+                {"name": "order", "labels": ["Bestellnummer"]}
+            ]
+        }
+    });
+    let res = run_parse_stage(text, Some(&config)).await.unwrap();
+    assert_eq!(
+        res,
+        json!({
+            "invoice_number": {"value": "RE-2026-0042", "label": "Rechnungsnummer", "page": 1},
+            "delivery_date": {"value": "10.03.2026", "label": "Lieferdatum", "page": 1}
+        })
+    );
+}
+
+#[actix_rt::test]
+async fn test_composite_runs_steps_under_their_names() {
+    let text = "Invoice INV-7\nItem Qty Price\nApple 1 2\nTotal 2";
+    let config = json!({
+        "strategy": "composite",
+        "parameters": {
+            "steps": [
+                {"name": "keywords", "strategy": "keywordExtraction",
+                 "parameters": {"keywords": ["invoice"]}},
+                {"name": "items", "strategy": "SimpleTableExtraction",
+                 "parameters": {"headerKeywords": ["item", "qty"], "stopKeywords": ["total"]}},
+                {"name": "ids", "strategy": "regexExtraction",
+                 "parameters": {"patterns": [{"name": "invoice", "regex": "(INV-\\d+)"}]}}
+            ]
+        }
+    });
+    let res = run_parse_stage(text, Some(&config)).await.unwrap();
+    assert_eq!(res["keywords"]["invoice"], 1);
+    assert_eq!(res["items"]["rows"].as_array().unwrap().len(), 1);
+    assert_eq!(res["ids"]["invoice"], json!(["INV-7"]));
+
+    let config = json!({
+        "strategy": "composite",
+        "parameters": {
+            "firstMatch": true,
+            "steps": [
+                {"name": "credit_note", "strategy": "regexExtraction",
+                 "parameters": {"patterns": [{"name": "id", "regex": "(CN-\\d+)"}]}},
+                {"name": "invoice", "strategy": "regexExtraction",
+                 "parameters": {"patterns": [{"name": "id", "regex": "(INV-\\d+)"}]}},
+                {"name": "lines", "strategy": "passthrough", "parameters": {}}
+            ]
+        }
+    });
+    let res = run_parse_stage(text, Some(&config)).await.unwrap();
+    assert_eq!(res, json!({"invoice": {"id": ["INV-7"]}}));
 }
@@ -0,0 +1,191 @@
+use actix_web::{http::header, test, web, App};
+use aws_config::meta::region::RegionProviderChain;
+use aws_sdk_s3::Client as S3Client;
+use backend::handlers;
+use backend::models::{Document, NewDocument};
+use backend::worker::dry_run;
+use serde_json::json;
+use sqlx::{postgres::PgPoolOptions, PgPool};
+use uuid::Uuid;
+
+mod test_utils;
+use test_utils::{clear_database, create_org, create_user, generate_jwt_token};
+
+async fn setup_test_app() -> Option<(
+    impl actix_web::dev::Service<
+        actix_http::Request,
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+    >,
+    PgPool,
+)> {
+    dotenvy::from_filename(".env.test").ok();
+    let database_url = std::env::var("DATABASE_URL_TEST")
+        .or_else(|_| std::env::var("DATABASE_URL"))
+        .ok()?;
+    let pool = PgPoolOptions::new()
+        .max_connections(5)
+        .connect(&database_url)
+        .await
+        .expect("Failed to connect to test database");
+    sqlx::migrate!("./migrations")
+        .run(&pool)
+        .await
+        .expect("Failed to run migrations on test DB");
+
+    let region_provider = RegionProviderChain::default_provider().or_else("us-east-1");
+    let shared_config = aws_config::from_env().region(region_provider).load().await;
+    let s3_client = S3Client::new(&shared_config);
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(s3_client))
+            .configure(handlers::init),
+    )
+    .await;
+    Some((app, pool))
+}
+
+fn invoice_stages() -> serde_json::Value {
+    json!([
+        {"id": "ocr", "type": "ocr", "command": "run"},
+        {"id": "ids", "type": "parse", "command": "run", "config": {
+            "strategy": "RegexExtraction",
+            "parameters": {"patterns": [{"name": "invoice", "regex": "(INV-\\d+)"}]}
+        }},
+        {"id": "summary", "type": "ai", "command": "run"}
+    ])
+}
+
+#[actix_rt::test]
+async fn saved_pipeline_runs_on_text_without_creating_jobs() {
+    let Some((app, pool)) = setup_test_app().await else {
+        return;
+    };
+    let org_id = create_org(&pool, "Test Run Org").await;
+    let user_id = create_user(&pool, org_id, "testrun@example.com", "org_admin").await;
+    let token = generate_jwt_token(user_id, org_id, "org_admin");
+
+    let req = test::TestRequest::post()
+        .uri("/api/pipelines")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .set_json(json!({"org_id": org_id, "name": "Invoices", "stages": invoice_stages()}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let created: serde_json::Value = test::read_body_json(resp).await;
+    let pipeline_id = created["id"].as_str().unwrap();
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/pipelines/{}/test", pipeline_id))
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .set_json(json!({"text": "Invoice INV-42"}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["status"], "succeeded");
+    assert_eq!(body["stages"][0]["status"], "not_run");
+    assert_eq!(body["stages"][1]["status"], "succeeded");
+    assert_eq!(body["stages"][1]["output"]["invoice"][0], "INV-42");
+    assert_eq!(body["stages"][2]["status"], "not_run");
+
+    let jobs: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM analysis_jobs WHERE org_id=$1")
+        .bind(org_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(jobs.0, 0);
+
+    clear_database(&pool).await;
+}
+
+#[actix_rt::test]
+async fn unsaved_pipeline_runs_on_stored_document() {
+    let Some((app, pool)) = setup_test_app().await else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    std::env::set_var("LOCAL_S3_DIR", dir.path());
+    let org_id = create_org(&pool, "Unsaved Run Org").await;
+    let other_org = create_org(&pool, "Other Run Org").await;
+    let user_id = create_user(&pool, org_id, "unsaved@example.com", "org_admin").await;
+    let token = generate_jwt_token(user_id, org_id, "org_admin");
+
+    let key = format!("{}.txt", Uuid::new_v4());
+    std::fs::write(dir.path().join(&key), "Invoice INV-7").unwrap();
+    let doc = Document::create(
+        &pool,
+        NewDocument {
+            org_id,
+            owner_id: user_id,
+            filename: key,
+            pages: 1,
+            is_target: false,
+            expires_at: None,
+            display_name: "invoice.txt".into(),
+        },
+    )
+    .await
+    .unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/api/pipelines/test")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .set_json(json!({"org_id": org_id, "stages": invoice_stages(), "document_id": doc.id}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["stages"][0]["output"], "Invoice INV-7");
+    assert_eq!(body["stages"][1]["output"]["invoice"][0], "INV-7");
+
+    let req = test::TestRequest::post()
+        .uri("/api/pipelines/test")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .set_json(json!({
+            "org_id": org_id,
+            "stages": invoice_stages(),
+            "document_id": doc.id,
+            "text": "Invoice INV-7"
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::post()
+        .uri("/api/pipelines/test")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .set_json(json!({"org_id": other_org, "stages": invoice_stages(), "text": "x"}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::post()
+        .uri("/api/pipelines/test")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .set_json(json!({
+            "org_id": org_id,
+            "stages": invoice_stages(),
+            "text": "x".repeat(dry_run::MAX_TEXT_BYTES + 1)
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+    sqlx::query("UPDATE documents SET pages=$2 WHERE id=$1")
+        .bind(doc.id)
+        .bind(dry_run::MAX_PAGES + 1)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let req = test::TestRequest::post()
+        .uri("/api/pipelines/test")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        .set_json(json!({"org_id": org_id, "stages": invoice_stages(), "document_id": doc.id}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+    clear_database(&pool).await;
+}
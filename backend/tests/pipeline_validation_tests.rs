@@ -59,3 +59,157 @@ fn external_ocr_with_non_string_key_rejected() {
     ]);
     assert!(validate_stages(&stages).is_err());
 }
+
+#[test]
+fn stage_inputs_form_valid_dag() {
+    let stages = json!([
+        {"id": "ocr1", "type": "ocr", "command": "run"},
+        {"id": "totals", "type": "parse", "command": "run", "inputs": ["ocr1"]},
+        {"id": "lines", "type": "parse", "command": "run", "inputs": ["ocr1"]},
+        {"id": "merge", "type": "ai", "command": "run", "inputs": ["totals", "lines"]}
+    ]);
+    assert!(validate_stages(&stages).is_ok());
+}
+
+#[test]
+fn stage_ids_limited_to_safe_characters() {
+    for id in [json!("ocr-1_a"), json!("a".repeat(64))] {
+        let stages = json!([{"id": id, "type": "ocr", "command": "run"}]);
+        assert!(validate_stages(&stages).is_ok(), "{}", id);
+    }
+    for id in [
+        json!(""),
+        json!("../../etc"),
+        json!("a/b"),
+        json!("two words"),
+        json!("a".repeat(65)),
+        json!(7),
+    ] {
+        let stages = json!([{"id": id, "type": "ocr", "command": "run"}]);
+        assert!(validate_stages(&stages).is_err(), "{}", id);
+    }
+}
+
+#[test]
+fn unknown_stage_input_rejected() {
+    let stages = json!([
+        {"id": "a", "type": "ocr", "command": "run"},
+        {"id": "b", "type": "parse", "command": "run", "inputs": ["nope"]}
+    ]);
+    assert!(validate_stages(&stages).is_err());
+}
+
+#[test]
+fn cyclic_stage_inputs_rejected() {
+    let stages = json!([
+        {"id": "a", "type": "parse", "command": "run", "inputs": ["b"]},
+        {"id": "b", "type": "parse", "command": "run", "inputs": ["a"]}
+    ]);
+    assert!(validate_stages(&stages).is_err());
+}
+
+#[test]
+fn non_array_inputs_rejected() {
+    let stages = json!([
+        {"id": "a", "type": "parse", "command": "run", "inputs": "b"}
+    ]);
+    assert!(validate_stages(&stages).is_err());
+}
+
+#[test]
+fn stage_condition_accepted() {
+    let stages = json!([
+        {"id": "totals", "type": "parse", "command": "run"},
+        {"id": "ai", "type": "ai", "command": "run", "when": {"path": "$.totals.status", "op": "eq", "value": "ok"}}
+    ]);
+    assert!(validate_stages(&stages).is_ok());
+}
+
+#[test]
+fn stage_condition_with_invalid_path_rejected() {
+    let stages = json!([
+        {"id": "totals", "type": "parse", "command": "run"},
+        {"id": "ai", "type": "ai", "command": "run", "when": {"path": "$.totals[", "value": "ok"}}
+    ]);
+    assert!(validate_stages(&stages).is_err());
+}
+
+#[test]
+fn stage_condition_with_unknown_operator_rejected() {
+    let stages = json!([
+        {"id": "ai", "type": "ai", "command": "run", "when": {"path": "$.a", "op": "gt", "value": 1}}
+    ]);
+    assert!(validate_stages(&stages).is_err());
+}
+
+#[test]
+fn retry_timeout_and_on_error_accepted() {
+    let stages = json!([
+        {"id": "ocr", "type": "ocr", "command": "run", "ocr_engine": "external", "ocr_stage_endpoint": "http://ex",
+         "retry": {"max_attempts": 5, "backoff_ms": 1000}, "timeout_secs": 120},
+        {"id": "enrich", "type": "ai", "command": "run", "on_error": "continue"},
+        {"id": "report", "type": "report", "command": "run", "on_error": "skip_rest"}
+    ]);
+    assert!(validate_stages(&stages).is_ok());
+}
+
+#[test]
+fn invalid_retry_settings_rejected() {
+    let zero_attempts = json!([
+        {"type": "ai", "command": "run", "retry": {"max_attempts": 0}}
+    ]);
+    assert!(validate_stages(&zero_attempts).is_err());
+    let zero_timeout = json!([
+        {"type": "ai", "command": "run", "timeout_secs": 0}
+    ]);
+    assert!(validate_stages(&zero_timeout).is_err());
+    let unknown_on_error = json!([
+        {"type": "ai", "command": "run", "on_error": "ignore"}
+    ]);
+    assert!(validate_stages(&unknown_on_error).is_err());
+}
+
+#[actix_rt::test]
+async fn parse_config_errors_name_the_field() {
+    let stages = json!([
+        {"id": "ocr", "type": "ocr", "command": "run"},
+        {"id": "fields", "type": "parse", "command": "run", "config": {
+            "strategy": "composite",
+            "parameters": {"steps": [
+                {"name": "ids", "strategy": "regexExtraction",
+                 "parameters": {"patterns": [{"name": "id", "regex": "(INV-\\d+"}]}},
+                {"name": "ids", "strategy": "schemaExtraction",
+                 "parameters": {"fields": [{"name": "total", "type": "money", "labels": ["Total"]}]}}
+            ]}
+        }}
+    ]);
+    let resp = validate_stages(&stages).unwrap_err();
+    assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let paths: Vec<&str> = body["details"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|d| d["path"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        paths,
+        [
+            "stages[1].config.parameters.steps[0].parameters.patterns[0].regex",
+            "stages[1].config.parameters.steps[1].parameters.fields[0]"
+        ]
+    );
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .starts_with("Stage 1 (parse): invalid 'config'"));
+
+    let valid = json!([
+        {"id": "fields", "type": "parse", "command": "run", "config": {
+            "strategy": "RegexExtraction",
+            "parameters": {"patterns": [{"name": "id", "regex": "(INV-\\d+)"}]}
+        }}
+    ]);
+    assert!(validate_stages(&valid).is_ok());
+}
@@ -135,3 +135,40 @@ async fn test_update_settings_invalid_ocr_endpoint() {
     let body: serde_json::Value = test::read_body_json(resp).await;
     assert!(body.get("error").is_some());
 }
+
+#[actix_rt::test]
+async fn test_update_settings_ocr_engine_must_be_registered() {
+    let Ok((app, pool)) = setup_test_app().await else {
+        return;
+    };
+    let org_id = create_org(&pool, "OCR Engine Org").await;
+    let user_id = create_user(&pool, org_id, "ocrengine@example.com", "org_admin").await;
+    let token = generate_jwt_token(user_id, org_id, "org_admin");
+
+    for (engine, status) in [
+        ("external_json", actix_web::http::StatusCode::OK),
+        ("unknown", actix_web::http::StatusCode::BAD_REQUEST),
+    ] {
+        let payload = json!({
+            "org_id": org_id,
+            "ocr_engine": engine,
+            "monthly_upload_quota": 10,
+            "monthly_analysis_quota": 10,
+            "accent_color": "#123456"
+        });
+        let req = test::TestRequest::post()
+            .uri("/api/settings")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .set_json(&payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), status);
+    }
+    let row: (Option<String>,) =
+        sqlx::query_as("SELECT ocr_engine FROM org_settings WHERE org_id=$1")
+            .bind(org_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(row.0.as_deref(), Some("external_json"));
+}
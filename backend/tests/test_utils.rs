@@ -1,10 +1,12 @@
 #![allow(dead_code)]
 use actix_web::{test, web, App};
 use backend::handlers;
+use backend::models::{AnalysisJob, Document, NewAnalysisJob, NewDocument, NewPipeline, Pipeline};
 use backend::middleware::jwt::create_jwt;
 use sqlx::{PgPool, postgres::PgPoolOptions};
 use argon2::{Argon2, PasswordHasher};
 use argon2::password_hash::SaltString;
+use serde_json::json;
 use uuid::Uuid;
 
 pub async fn setup_test_app() -> Result<(
@@ -104,3 +106,67 @@ pub async fn clear_database(pool: &PgPool) {
     .await
     .unwrap();
 }
+
+/// Fail `job_id` the way a worker would: take its lease, then mark it failed.
+pub async fn fail_job(pool: &PgPool, job_id: Uuid, reason: &str, error: &str) {
+    sqlx::query("UPDATE analysis_jobs SET lease_owner='test-worker' WHERE id=$1")
+        .bind(job_id)
+        .execute(pool)
+        .await
+        .unwrap();
+    assert!(
+        backend::models::AnalysisJob::mark_failed(pool, job_id, "test-worker", reason, error)
+            .await
+            .unwrap()
+    );
+}
+
+/// Create a single-stage OCR pipeline whose jobs default to `priority`.
+pub async fn create_pipeline(pool: &PgPool, org_id: Uuid, priority: &str) -> Pipeline {
+    Pipeline::create_with_priority(
+        pool,
+        NewPipeline {
+            org_id,
+            name: "Pipe".into(),
+            stages: json!([{"type": "ocr"}]),
+        },
+        priority,
+    )
+    .await
+    .unwrap()
+}
+
+/// Create a one-page target document named `<name>.pdf`.
+pub async fn create_document(pool: &PgPool, org_id: Uuid, user_id: Uuid, name: &str) -> Document {
+    Document::create(
+        pool,
+        NewDocument {
+            org_id,
+            owner_id: user_id,
+            filename: format!("{}.pdf", name),
+            pages: 1,
+            is_target: true,
+            expires_at: None,
+            display_name: format!("{}.pdf", name),
+        },
+    )
+    .await
+    .unwrap()
+}
+
+/// Create a pending job with its own pipeline and document.
+pub async fn create_job(pool: &PgPool, org_id: Uuid, user_id: Uuid, priority: &str) -> AnalysisJob {
+    let pipeline = create_pipeline(pool, org_id, priority).await;
+    let document = create_document(pool, org_id, user_id, "File").await;
+    AnalysisJob::create(
+        pool,
+        NewAnalysisJob {
+            org_id,
+            document_id: document.id,
+            pipeline_id: pipeline.id,
+            status: "pending".into(),
+        },
+    )
+    .await
+    .unwrap()
+}
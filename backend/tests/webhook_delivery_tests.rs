@@ -0,0 +1,203 @@
+use actix_web::test;
+use serde_json::json;
+use serial_test::serial;
+use sqlx::PgPool;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+mod test_utils;
+use backend::models::{NewWebhookEndpoint, WebhookDelivery, WebhookEndpoint};
+use backend::processing::webhook::{sign_payload, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+use backend::worker::deliveries::{deliver_due, DeliveryConfig, EVENT_HEADER};
+use test_utils::{create_job, create_org, create_user, generate_jwt_token, setup_test_app};
+
+/// `deliver_due` sends the due deliveries of every organization, so each test
+/// starts without deliveries left over from other tests.
+async fn clear_deliveries(pool: &PgPool) {
+    sqlx::query("DELETE FROM webhook_deliveries")
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+#[actix_rt::test]
+#[serial]
+async fn job_events_are_delivered_retried_and_redeliverable() {
+    let Ok((app, pool)) = setup_test_app().await else {
+        return;
+    };
+    clear_deliveries(&pool).await;
+    std::env::remove_var("WEBHOOK_ALLOW_PRIVATE_NETWORKS");
+    let server = MockServer::start().await;
+    let counter = Arc::new(AtomicUsize::new(0));
+    let c = counter.clone();
+    Mock::given(method("POST"))
+        .respond_with(move |_: &wiremock::Request| {
+            if c.fetch_add(1, Ordering::SeqCst) == 0 {
+                ResponseTemplate::new(500)
+            } else {
+                ResponseTemplate::new(204)
+            }
+        })
+        .mount(&server)
+        .await;
+
+    let org_id = create_org(&pool, "Webhook Org").await;
+    let admin_id = create_user(&pool, org_id, "hooks@example.com", "org_admin").await;
+    let member_id = create_user(&pool, org_id, "member@example.com", "user").await;
+    let token = generate_jwt_token(admin_id, org_id, "org_admin");
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/settings/{}/webhooks", org_id))
+        .insert_header((
+            "Authorization",
+            format!("Bearer {}", generate_jwt_token(member_id, org_id, "user")),
+        ))
+        .set_json(json!({"url": server.uri(), "event_types": ["job.cancelled"]}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/settings/{}/webhooks", org_id))
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(json!({"url": server.uri(), "event_types": ["job.cancelled"]}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+    // The mock server listens on localhost
+    std::env::set_var("WEBHOOK_ALLOW_PRIVATE_NETWORKS", "true");
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/settings/{}/webhooks", org_id))
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(json!({"url": server.uri(), "event_types": ["job.done"]}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/settings/{}/webhooks", org_id))
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(json!({"url": server.uri(), "secret": "whsec", "event_types": ["job.cancelled"]}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let endpoint: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(endpoint["secret"], "whsec");
+    let webhook_id = endpoint["id"].as_str().unwrap().to_string();
+
+    let job = create_job(&pool, org_id, admin_id, "normal").await;
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/jobs/{}/cancel", job.id))
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+
+    let cfg = DeliveryConfig {
+        max_attempts: 3,
+        backoff: Duration::ZERO,
+        timeout: Duration::from_secs(5),
+    };
+    let deliveries_uri = format!("/api/settings/{}/webhooks/{}/deliveries", org_id, webhook_id);
+    let list_deliveries = || {
+        test::TestRequest::get()
+            .uri(&deliveries_uri)
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request()
+    };
+
+    assert_eq!(deliver_due(&pool, &cfg).await.unwrap(), 1);
+    let log: serde_json::Value =
+        test::read_body_json(test::call_service(&app, list_deliveries()).await).await;
+    assert_eq!(log[0]["status"], "pending");
+    assert_eq!(log[0]["attempts"], 1);
+    assert_eq!(log[0]["response_status"], 500);
+
+    assert_eq!(deliver_due(&pool, &cfg).await.unwrap(), 1);
+    let log: serde_json::Value =
+        test::read_body_json(test::call_service(&app, list_deliveries()).await).await;
+    assert_eq!(log[0]["status"], "delivered");
+    assert_eq!(log[0]["attempts"], 2);
+    assert_eq!(log[0]["payload"]["type"], "job.cancelled");
+    assert_eq!(log[0]["payload"]["data"]["job_id"], json!(job.id));
+
+    let requests = server.received_requests().await.unwrap();
+    let sent = requests.last().unwrap();
+    let header = |name: &str| sent.headers.get(name).unwrap().to_str().unwrap().to_string();
+    assert_eq!(header(EVENT_HEADER), "job.cancelled");
+    let ts: u64 = header(TIMESTAMP_HEADER).parse().unwrap();
+    assert_eq!(header(SIGNATURE_HEADER), sign_payload("whsec", ts, &sent.body));
+
+    let delivery_id = log[0]["id"].as_str().unwrap();
+    let req = test::TestRequest::post()
+        .uri(&format!("{}/{}/redeliver", deliveries_uri, delivery_id))
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let redelivery: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(redelivery["status"], "pending");
+    assert_eq!(redelivery["payload"], log[0]["payload"]);
+    assert_eq!(deliver_due(&pool, &cfg).await.unwrap(), 1);
+    assert_eq!(server.received_requests().await.unwrap().len(), 3);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/settings/{}/webhooks", org_id))
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let endpoints: serde_json::Value =
+        test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(endpoints[0]["secret"], "********");
+}
+
+#[actix_rt::test]
+#[serial]
+async fn concurrent_polls_send_each_delivery_once_to_slow_endpoints() {
+    let Ok((_app, pool)) = setup_test_app().await else {
+        return;
+    };
+    clear_deliveries(&pool).await;
+    std::env::set_var("WEBHOOK_ALLOW_PRIVATE_NETWORKS", "true");
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(204).set_delay(Duration::from_millis(800)))
+        .mount(&server)
+        .await;
+    let org_id = create_org(&pool, "Slow Webhook Org").await;
+    for _ in 0..10 {
+        WebhookEndpoint::create(
+            &pool,
+            NewWebhookEndpoint {
+                org_id,
+                url: server.uri(),
+                secret: "whsec".into(),
+                event_types: vec!["job.completed".into()],
+            },
+        )
+        .await
+        .unwrap();
+    }
+    let deliveries = WebhookDelivery::enqueue(&pool, org_id, "job.completed", &json!({}))
+        .await
+        .unwrap();
+    assert_eq!(deliveries.len(), 10);
+
+    // Sent one after another the batch would take longer than the 7s lease,
+    // so the second poll would claim the deliveries not sent yet again.
+    let cfg = DeliveryConfig {
+        max_attempts: 3,
+        backoff: Duration::ZERO,
+        timeout: Duration::from_secs(1),
+    };
+    let (first, second) = tokio::join!(deliver_due(&pool, &cfg), async {
+        tokio::time::sleep(Duration::from_millis(7500)).await;
+        deliver_due(&pool, &cfg).await
+    });
+    assert_eq!(first.unwrap() + second.unwrap(), 10);
+    assert_eq!(server.received_requests().await.unwrap().len(), 10);
+    for delivery in deliveries {
+        let delivery = WebhookDelivery::find(&pool, delivery.id).await.unwrap();
+        assert_eq!(delivery.status, "delivered");
+        assert_eq!(delivery.attempts, 1);
+    }
+}
@@ -1,9 +1,14 @@
 export interface Stage {
   id: string;
   type: string;
+  inputs?: string[] | null;
+  when?: StageCondition | null;
+  retry?: { max_attempts: number; backoff_ms?: number } | null;
+  timeout_secs?: number | null;
+  on_error?: 'fail' | 'continue' | 'skip_rest';
   command?: string | null;
   prompt_name?: string | null;
-  ocr_engine?: 'default' | 'external';
+  ocr_engine?: string; // 'default', 'auto' or the name of an OCR engine
   ocr_stage_endpoint?: string | null;
   ocr_stage_key?: string | null;
   config?: {
@@ -15,6 +20,12 @@ export interface Stage {
   } | null;
 }
 
+export interface StageCondition {
+  path: string;
+  op?: 'eq' | 'ne' | 'exists' | 'not_exists' | 'contains';
+  value?: any;
+}
+
 export interface RegexPatternConfig {
   id: string;
   name: string;
@@ -22,6 +33,30 @@ export interface RegexPatternConfig {
   captureGroupIndex?: number;
 }
 
+export interface SchemaFieldConfig {
+  id: string;
+  name: string;
+  type: 'string' | 'decimal' | 'date' | 'iban' | 'enum';
+  regex?: string;
+  captureGroupIndex?: number;
+  labels: string[];
+  required: boolean;
+  values: string[];
+  normalize?: string[];
+  _labelsString?: string;
+  _valuesString?: string;
+}
+
+export interface AnchorFieldConfig {
+  id: string;
+  name: string;
+  labels: string[];
+  direction?: 'any' | 'right' | 'below';
+  maxGap?: number | null;
+  maxLinesBelow?: number;
+  _labelsString?: string;
+}
+
 export interface Pipeline {
   id?: string;
   name: string;
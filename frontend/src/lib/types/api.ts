@@ -3,7 +3,7 @@ export interface Stage {
   type: string;
   command?: string | null;
   prompt_name?: string | null;
-  ocr_engine?: 'default' | 'external';
+  ocr_engine?: string; // 'default', 'auto' or the name of an OCR engine
   ocr_stage_endpoint?: string | null;
   ocr_stage_key?: string | null;
   config?: {
@@ -20,8 +20,11 @@ export interface Pipeline {
   org_id: string;
   name: string;
   stages: Stage[];
+  priority?: JobPriority;
 }
 
+export type JobPriority = 'high' | 'normal' | 'bulk';
+
 export interface Document {
   id: string;
   filename: string;
@@ -43,4 +46,46 @@ export interface OrgSettings {
   ocr_api_key?: string | null;
   prompt_templates?: { id?: string; name: string; text: string }[] | null;
   ai_custom_headers?: { id: string; name: string; value: string }[] | null;
+  max_concurrent_jobs?: number | null;
+  ocr_engine?: string | null;
+}
+
+export interface WebhookEndpoint {
+  id: string;
+  org_id: string;
+  url: string;
+  /** Masked as '********' except in the response that created the endpoint. */
+  secret: string;
+  event_types: string[];
+  created_at?: string | null;
+}
+
+export interface WebhookDelivery {
+  id: string;
+  endpoint_id: string;
+  event_type: string;
+  payload: unknown;
+  status: 'pending' | 'delivered' | 'failed';
+  attempts: number;
+  response_status?: number | null;
+  last_error?: string | null;
+  next_attempt_at: string;
+  created_at?: string | null;
+  delivered_at?: string | null;
+}
+
+export interface ApiKey {
+  id: string;
+  org_id: string;
+  name: string;
+  /** First characters of the key, to tell keys apart. */
+  prefix: string;
+  scopes: string[];
+  created_by: string;
+  created_at?: string | null;
+  last_used_at?: string | null;
+  expires_at?: string | null;
+  revoked_at?: string | null;
+  /** Only present in the response that created or rotated the key. */
+  key?: string;
 }